@@ -1,12 +1,24 @@
 //! The infinite canvas that holds all nodes
+//!
+//! The canvas can be looked at through several [`Viewport`]s at once, e.g.
+//! one per monitor. Each viewport pans and zooms independently; nodes are
+//! shared between all of them.
 
-use crate::{CanvasError, Connection, Node, NodeId, Result, Viewport, limits};
-use std::collections::HashMap;
+use crate::ink::{InkStroke, MAX_NODE_STROKES};
+use crate::{
+    CanvasError, Connection, Node, NodeId, NodeType, Result, Viewport, ViewportId, limits,
+};
+use std::collections::{BTreeMap, HashMap};
 
 pub struct Canvas {
     nodes: HashMap<NodeId, Node>,
     connections: Vec<Connection>,
-    viewport: Viewport,
+    /// Viewports into the canvas, ordered by id
+    viewports: BTreeMap<ViewportId, Viewport>,
+    /// Next id handed out by [`Canvas::allocate_node_id`]
+    next_node_id: NodeId,
+    /// Next id handed out by [`Canvas::add_viewport`]
+    next_viewport_id: ViewportId,
 }
 
 impl Canvas {
@@ -14,10 +26,31 @@ impl Canvas {
         Self {
             nodes: HashMap::new(),
             connections: Vec::new(),
-            viewport: Viewport::default(),
+            viewports: BTreeMap::new(),
+            next_node_id: 1,
+            next_viewport_id: 1,
         }
     }
 
+    /// Allocate a node id that is not used by any node on this canvas
+    pub fn allocate_node_id(&mut self) -> Result<NodeId> {
+        while self.nodes.contains_key(&self.next_node_id) {
+            self.bump_next_node_id()?;
+        }
+
+        let id = self.next_node_id;
+        self.bump_next_node_id()?;
+        Ok(id)
+    }
+
+    fn bump_next_node_id(&mut self) -> Result<()> {
+        self.next_node_id = self
+            .next_node_id
+            .checked_add(1)
+            .ok_or_else(|| CanvasError::ResourceLimitExceeded("Node ids exhausted".to_string()))?;
+        Ok(())
+    }
+
     /// Add a node to the canvas (with resource limits)
     pub fn add_node(&mut self, node: Node) -> Result<NodeId> {
         if self.nodes.len() >= limits::MAX_NODES {
@@ -35,10 +68,126 @@ impl Canvas {
         }
 
         let id = node.id;
+        if self.nodes.contains_key(&id) {
+            return Err(CanvasError::DuplicateNode(id));
+        }
+
         self.nodes.insert(id, node);
         Ok(id)
     }
 
+    /// Move a node to a new canvas position (with bounds checking)
+    pub fn move_node(&mut self, id: NodeId, x: f64, y: f64) -> Result<()> {
+        if !Self::is_valid_coordinate(x) || !Self::is_valid_coordinate(y) {
+            return Err(CanvasError::ResourceLimitExceeded(
+                "Node coordinates out of bounds".to_string(),
+            ));
+        }
+
+        let node = self
+            .nodes
+            .get_mut(&id)
+            .ok_or(CanvasError::NodeNotFound(id))?;
+        node.x = x;
+        node.y = y;
+        Ok(())
+    }
+
+    /// Find the node showing the given Wayland surface
+    pub fn surface_node(&self, surface_id: u64) -> Option<&Node> {
+        self.nodes.values().find(
+            |n| matches!(n.node_type, NodeType::Surface { surface_id: id } if id == surface_id),
+        )
+    }
+
+    /// Add a pen stroke, given in canvas coordinates, to an ink node
+    ///
+    /// The stroke joins the ink node `into` if given, which grows to hold
+    /// it; otherwise a new ink node is made around the stroke.
+    pub fn add_ink_stroke(
+        &mut self,
+        mut stroke: InkStroke,
+        into: Option<NodeId>,
+    ) -> Result<NodeId> {
+        let (x0, y0, x1, y1) = stroke
+            .bounds()
+            .ok_or_else(|| CanvasError::InvalidStroke("Stroke has no points".to_string()))?;
+        if !Self::is_valid_coordinate(x0) || !Self::is_valid_coordinate(y0) {
+            return Err(CanvasError::ResourceLimitExceeded(
+                "Stroke coordinates out of bounds".to_string(),
+            ));
+        }
+
+        let Some(id) = into else {
+            let id = self.allocate_node_id()?;
+            stroke.translate(-x0, -y0);
+            let node = Node::new(
+                id,
+                NodeType::Ink {
+                    strokes: vec![stroke],
+                },
+                x0,
+                y0,
+            )
+            .with_size(x1 - x0, y1 - y0);
+            return self.add_node(node);
+        };
+
+        let node = self
+            .nodes
+            .get_mut(&id)
+            .ok_or(CanvasError::NodeNotFound(id))?;
+        let NodeType::Ink { strokes } = &mut node.node_type else {
+            return Err(CanvasError::InvalidStroke(format!(
+                "Node {} is not an ink node",
+                id
+            )));
+        };
+        if strokes.len() >= MAX_NODE_STROKES {
+            return Err(CanvasError::ResourceLimitExceeded(format!(
+                "Maximum strokes per ink node ({}) exceeded",
+                MAX_NODE_STROKES
+            )));
+        }
+
+        // Grow the node, moving its origin if the stroke goes past it
+        let (x, y) = (node.x.min(x0), node.y.min(y0));
+        let (right, bottom) = (
+            (node.x + node.width).max(x1),
+            (node.y + node.height).max(y1),
+        );
+        for existing in strokes.iter_mut() {
+            existing.translate(node.x - x, node.y - y);
+        }
+        stroke.translate(-x, -y);
+        strokes.push(stroke);
+
+        node.x = x;
+        node.y = y;
+        node.width = right - x;
+        node.height = bottom - y;
+        Ok(id)
+    }
+
+    /// The ink node at a canvas point, or within `margin` of it
+    ///
+    /// With overlapping ink nodes, the newest wins.
+    pub fn ink_node_at(&self, x: f64, y: f64, margin: f64) -> Option<NodeId> {
+        self.nodes
+            .values()
+            .filter(|n| matches!(n.node_type, NodeType::Ink { .. }))
+            .filter(|n| {
+                let (x0, y0, x1, y1) = n.bounds();
+                (x0 - margin..=x1 + margin).contains(&x) && (y0 - margin..=y1 + margin).contains(&y)
+            })
+            .map(|n| n.id)
+            .max()
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.nodes.values()
+    }
+
     pub fn get_node(&self, id: NodeId) -> Option<&Node> {
         self.nodes.get(&id)
     }
@@ -74,17 +223,46 @@ impl Canvas {
         Ok(())
     }
 
-    pub fn viewport(&self) -> &Viewport {
-        &self.viewport
+    /// Add a viewport into the canvas (with resource limits)
+    pub fn add_viewport(&mut self, viewport: Viewport) -> Result<ViewportId> {
+        if self.viewports.len() >= limits::MAX_VIEWPORTS {
+            return Err(CanvasError::ResourceLimitExceeded(format!(
+                "Maximum viewports ({}) exceeded",
+                limits::MAX_VIEWPORTS
+            )));
+        }
+
+        let id = self.next_viewport_id;
+        self.next_viewport_id = id.checked_add(1).ok_or_else(|| {
+            CanvasError::ResourceLimitExceeded("Viewport ids exhausted".to_string())
+        })?;
+
+        self.viewports.insert(id, viewport);
+        Ok(id)
+    }
+
+    pub fn remove_viewport(&mut self, id: ViewportId) -> Option<Viewport> {
+        self.viewports.remove(&id)
+    }
+
+    pub fn viewport(&self, id: ViewportId) -> Option<&Viewport> {
+        self.viewports.get(&id)
     }
 
-    pub fn viewport_mut(&mut self) -> &mut Viewport {
-        &mut self.viewport
+    pub fn viewport_mut(&mut self, id: ViewportId) -> Option<&mut Viewport> {
+        self.viewports.get_mut(&id)
     }
 
-    pub fn visible_nodes(&self) -> impl Iterator<Item = &Node> {
-        let vp = &self.viewport;
-        self.nodes.values().filter(|n| vp.contains(n.x, n.y))
+    pub fn viewports(&self) -> impl Iterator<Item = (ViewportId, &Viewport)> {
+        self.viewports.iter().map(|(id, viewport)| (*id, viewport))
+    }
+
+    /// Nodes whose position is inside a viewport
+    pub fn visible_nodes(&self, viewport: ViewportId) -> impl Iterator<Item = &Node> {
+        let vp = self.viewports.get(&viewport);
+        self.nodes
+            .values()
+            .filter(move |n| vp.is_some_and(|vp| vp.contains(n.x, n.y)))
     }
 
     pub fn node_count(&self) -> usize {
@@ -106,3 +284,122 @@ impl Default for Canvas {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn surface_node(id: NodeId, surface_id: u64) -> Node {
+        Node::new(id, NodeType::Surface { surface_id }, 0.0, 0.0)
+    }
+
+    #[test]
+    fn test_allocate_node_id_skips_used_ids() {
+        let mut canvas = Canvas::new();
+        canvas.add_node(surface_node(1, 10)).unwrap();
+        canvas.add_node(surface_node(2, 20)).unwrap();
+
+        let id = canvas.allocate_node_id().unwrap();
+        assert_eq!(id, 3);
+        assert_ne!(canvas.allocate_node_id().unwrap(), id);
+    }
+
+    #[test]
+    fn test_duplicate_node_rejected() {
+        let mut canvas = Canvas::new();
+        canvas.add_node(surface_node(1, 10)).unwrap();
+        assert!(canvas.add_node(surface_node(1, 20)).is_err());
+        assert_eq!(canvas.node_count(), 1);
+    }
+
+    #[test]
+    fn test_move_node_bounds() {
+        let mut canvas = Canvas::new();
+        canvas.add_node(surface_node(1, 10)).unwrap();
+
+        canvas.move_node(1, 100.0, -50.0).unwrap();
+        let node = canvas.get_node(1).unwrap();
+        assert_eq!((node.x, node.y), (100.0, -50.0));
+
+        assert!(canvas.move_node(1, f64::NAN, 0.0).is_err());
+        assert!(
+            canvas
+                .move_node(1, limits::MAX_COORDINATE * 2.0, 0.0)
+                .is_err()
+        );
+        assert!(canvas.move_node(2, 0.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_surface_node_lookup() {
+        let mut canvas = Canvas::new();
+        canvas.add_node(surface_node(1, 10)).unwrap();
+        canvas.add_node(surface_node(2, 20)).unwrap();
+
+        assert_eq!(canvas.surface_node(20).map(|n| n.id), Some(2));
+        assert!(canvas.surface_node(30).is_none());
+    }
+
+    #[test]
+    fn test_ink_strokes() {
+        use crate::ink::InkPoint;
+
+        let stroke = |points: &[(f64, f64)]| {
+            let mut stroke = InkStroke::new([1.0; 4], 2.0);
+            for &(x, y) in points {
+                stroke.push(InkPoint::new(x, y, 1.0, (0.0, 0.0)));
+            }
+            stroke
+        };
+
+        let mut canvas = Canvas::new();
+        let id = canvas
+            .add_ink_stroke(stroke(&[(10.0, 10.0), (20.0, 10.0)]), None)
+            .unwrap();
+        let node = canvas.get_node(id).unwrap();
+        assert_eq!(
+            (node.x, node.y, node.width, node.height),
+            (9.0, 9.0, 12.0, 2.0)
+        );
+        assert_eq!(canvas.ink_node_at(15.0, 15.0, 5.0), Some(id));
+        assert_eq!(canvas.ink_node_at(15.0, 15.0, 1.0), None);
+
+        // A stroke joining the node above and left of it moves its origin,
+        // keeping the first stroke in place on the canvas
+        canvas
+            .add_ink_stroke(stroke(&[(5.0, 5.0), (6.0, 6.0)]), Some(id))
+            .unwrap();
+        let node = canvas.get_node(id).unwrap();
+        assert_eq!(
+            (node.x, node.y, node.width, node.height),
+            (4.0, 4.0, 17.0, 7.0)
+        );
+        let NodeType::Ink { strokes } = &node.node_type else {
+            panic!("expected an ink node");
+        };
+        assert_eq!((strokes[0].points[0].x, strokes[0].points[0].y), (6.0, 6.0));
+        assert_eq!((strokes[1].points[0].x, strokes[1].points[0].y), (1.0, 1.0));
+
+        assert!(canvas.add_ink_stroke(stroke(&[]), None).is_err());
+    }
+
+    #[test]
+    fn test_independent_viewports() {
+        let mut canvas = Canvas::new();
+        canvas.add_node(surface_node(1, 10)).unwrap();
+
+        let left = canvas.add_viewport(Viewport::new(1000.0, 800.0)).unwrap();
+        let right = canvas.add_viewport(Viewport::new(1000.0, 800.0)).unwrap();
+        assert_ne!(left, right);
+
+        canvas.viewport_mut(right).unwrap().pan(5000.0, 0.0);
+        assert_eq!(canvas.viewport(left).unwrap().x, 0.0);
+        assert_eq!(canvas.visible_nodes(left).count(), 1);
+        assert_eq!(canvas.visible_nodes(right).count(), 0);
+
+        assert!(canvas.remove_viewport(left).is_some());
+        assert!(canvas.viewport(left).is_none());
+        assert_eq!(canvas.visible_nodes(left).count(), 0);
+        assert_eq!(canvas.viewports().count(), 1);
+    }
+}
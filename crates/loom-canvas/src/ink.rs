@@ -0,0 +1,168 @@
+//! Ink strokes drawn with a pen
+//!
+//! Strokes are kept as the sampled pen points, in canvas units, with the
+//! pen's pressure and tilt at each. They are stored in
+//! [`NodeType::Ink`](crate::NodeType::Ink) nodes relative to the node
+//! origin, so moving the node moves its ink; renderers draw them at any
+//! zoom from [`InkStroke::dabs`].
+
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of points in a stroke (further points are dropped)
+pub const MAX_STROKE_POINTS: usize = 10_000;
+
+/// Maximum number of strokes in an ink node
+pub const MAX_NODE_STROKES: usize = 1_000;
+
+/// Points closer than this to the previous one are dropped (canvas units)
+const MIN_POINT_SPACING: f64 = 0.5;
+
+/// Share of the width kept at zero pressure
+const MIN_PRESSURE_WIDTH: f64 = 0.2;
+
+/// A pen sample
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct InkPoint {
+    pub x: f64,
+    pub y: f64,
+    /// Pen pressure, from 0 to 1
+    pub pressure: f64,
+    /// Pen tilt from the vertical along both axes, in degrees
+    pub tilt: (f64, f64),
+}
+
+/// A continuous pen stroke
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InkStroke {
+    /// RGBA color
+    pub color: [f32; 4],
+    /// Width at full pressure (canvas units)
+    pub width: f64,
+    pub points: Vec<InkPoint>,
+}
+
+impl InkPoint {
+    pub fn new(x: f64, y: f64, pressure: f64, tilt: (f64, f64)) -> Self {
+        Self {
+            x,
+            y,
+            pressure,
+            tilt,
+        }
+    }
+}
+
+impl InkStroke {
+    pub fn new(color: [f32; 4], width: f64) -> Self {
+        Self {
+            color,
+            width,
+            points: Vec::new(),
+        }
+    }
+
+    /// Add a pen sample, returning whether it was kept
+    ///
+    /// Samples with invalid values, too close to the previous one or
+    /// beyond [`MAX_STROKE_POINTS`] are dropped.
+    pub fn push(&mut self, point: InkPoint) -> bool {
+        if !point.x.is_finite() || !point.y.is_finite() || self.points.len() >= MAX_STROKE_POINTS {
+            return false;
+        }
+        if let Some(last) = self.points.last()
+            && (point.x - last.x).hypot(point.y - last.y) < MIN_POINT_SPACING
+        {
+            return false;
+        }
+
+        let pressure = if point.pressure.is_finite() {
+            point.pressure.clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        self.points.push(InkPoint { pressure, ..point });
+        true
+    }
+
+    /// Radius of the stroke at a point
+    pub fn radius(&self, point: &InkPoint) -> f64 {
+        let pressure = MIN_PRESSURE_WIDTH + (1.0 - MIN_PRESSURE_WIDTH) * point.pressure;
+        self.width * pressure / 2.0
+    }
+
+    /// Bounding box of the stroke, including its width, as
+    /// `(min_x, min_y, max_x, max_y)`
+    pub fn bounds(&self) -> Option<(f64, f64, f64, f64)> {
+        self.points.iter().fold(None, |bounds, point| {
+            let r = self.radius(point);
+            let (x0, y0, x1, y1) = (point.x - r, point.y - r, point.x + r, point.y + r);
+            Some(match bounds {
+                None => (x0, y0, x1, y1),
+                Some((a, b, c, d)) => (a.min(x0), b.min(y0), c.max(x1), d.max(y1)),
+            })
+        })
+    }
+
+    /// Move every point
+    pub fn translate(&mut self, dx: f64, dy: f64) {
+        for point in &mut self.points {
+            point.x += dx;
+            point.y += dy;
+        }
+    }
+
+    /// Discs covering the stroke, as `(x, y, radius)`
+    ///
+    /// Discs are spaced at most `spacing` apart along the stroke, with the
+    /// pressure interpolated between samples. Pass a spacing in canvas
+    /// units matching the zoom, so strokes stay smooth when zoomed in
+    /// without producing needless discs when zoomed out.
+    pub fn dabs(&self, spacing: f64) -> impl Iterator<Item = (f64, f64, f64)> + '_ {
+        let spacing = spacing.max(MIN_POINT_SPACING);
+        let first = self
+            .points
+            .first()
+            .map(|point| (point.x, point.y, self.radius(point)));
+
+        let segments = self.points.windows(2).flat_map(move |pair| {
+            let (a, b) = (&pair[0], &pair[1]);
+            let (ra, rb) = (self.radius(a), self.radius(b));
+            let length = (b.x - a.x).hypot(b.y - a.y);
+            let steps = (length / spacing).ceil().max(1.0) as usize;
+            (1..=steps).map(move |step| {
+                let t = step as f64 / steps as f64;
+                let lerp = |from: f64, to: f64| from * (1.0 - t) + to * t;
+                (lerp(a.x, b.x), lerp(a.y, b.y), lerp(ra, rb))
+            })
+        });
+
+        first.into_iter().chain(segments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stroke_points_and_dabs() {
+        let mut stroke = InkStroke::new([1.0; 4], 4.0);
+        assert!(stroke.push(InkPoint::new(0.0, 0.0, 1.0, (0.0, 0.0))));
+        // Too close to the previous point
+        assert!(!stroke.push(InkPoint::new(0.1, 0.0, 1.0, (0.0, 0.0))));
+        assert!(!stroke.push(InkPoint::new(f64::NAN, 0.0, 1.0, (0.0, 0.0))));
+        assert!(stroke.push(InkPoint::new(10.0, 0.0, 0.0, (30.0, 0.0))));
+
+        let (x0, y0, x1, y1) = stroke.bounds().unwrap();
+        assert_eq!((x0, y0, y1), (-2.0, -2.0, 2.0));
+        assert!((x1 - 10.4).abs() < 1e-9);
+
+        let dabs: Vec<_> = stroke.dabs(2.5).collect();
+        assert_eq!(dabs.len(), 5);
+        assert_eq!(dabs[0], (0.0, 0.0, 2.0));
+        assert_eq!((dabs[2].0, dabs[2].1), (5.0, 0.0));
+        assert_eq!(dabs[4], (10.0, 0.0, stroke.radius(&stroke.points[1])));
+
+        assert_eq!(InkStroke::new([1.0; 4], 4.0).bounds(), None);
+    }
+}
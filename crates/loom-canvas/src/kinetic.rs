@@ -0,0 +1,244 @@
+//! Kinetic (inertial) panning
+//!
+//! Touchpad scrolls keep gliding after the fingers lift, slowing down with
+//! an exponential friction. This is a pure state machine: the compositor
+//! feeds it scroll deltas and drives [`KineticPan::tick`] from a timer.
+
+use std::collections::VecDeque;
+
+/// Default velocity decay rate (per second)
+pub const DEFAULT_FRICTION: f64 = 4.0;
+
+/// Speed below which a glide stops (pixels per second)
+const MIN_VELOCITY: f64 = 30.0;
+
+/// Speed cap, so a single noisy sample can't fling the canvas away
+const MAX_VELOCITY: f64 = 20_000.0;
+
+/// Only samples this recent are used to estimate the release velocity (ms)
+const SAMPLE_WINDOW_MS: u32 = 100;
+
+/// Maximum number of samples kept
+const MAX_SAMPLES: usize = 8;
+
+/// A scroll delta and the time it was received
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    time_msec: u32,
+    dx: f64,
+    dy: f64,
+}
+
+/// Inertial pan state
+#[derive(Debug, Clone)]
+pub struct KineticPan {
+    /// Velocity decay rate (per second)
+    friction: f64,
+    /// Recent scroll samples
+    samples: VecDeque<Sample>,
+    /// Current glide velocity (pixels per second)
+    velocity: (f64, f64),
+    /// Whether a glide is in progress
+    active: bool,
+}
+
+impl Default for KineticPan {
+    fn default() -> Self {
+        Self::new(DEFAULT_FRICTION)
+    }
+}
+
+impl KineticPan {
+    /// Create a kinetic pan with the given friction (per second)
+    pub fn new(friction: f64) -> Self {
+        let friction = if friction.is_finite() && friction > 0.0 {
+            friction
+        } else {
+            DEFAULT_FRICTION
+        };
+
+        Self {
+            friction,
+            samples: VecDeque::with_capacity(MAX_SAMPLES),
+            velocity: (0.0, 0.0),
+            active: false,
+        }
+    }
+
+    /// Record a scroll delta while the fingers are down
+    ///
+    /// Stops any glide in progress.
+    pub fn push(&mut self, time_msec: u32, dx: f64, dy: f64) {
+        if !dx.is_finite() || !dy.is_finite() {
+            return;
+        }
+
+        self.active = false;
+        self.velocity = (0.0, 0.0);
+
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(Sample { time_msec, dx, dy });
+    }
+
+    /// The fingers lifted: start gliding with the recent scroll velocity
+    ///
+    /// Returns whether a glide started. Lifting the fingers after holding
+    /// them still does not glide.
+    pub fn release(&mut self, time_msec: u32) -> bool {
+        let recent: Vec<Sample> = self
+            .samples
+            .drain(..)
+            .filter(|s| time_msec.wrapping_sub(s.time_msec) <= SAMPLE_WINDOW_MS)
+            .collect();
+
+        let (Some(first), Some(last)) = (recent.first(), recent.last()) else {
+            return false;
+        };
+
+        let span = last.time_msec.wrapping_sub(first.time_msec);
+        if span == 0 {
+            return false;
+        }
+
+        // The first sample's delta happened before the window started
+        let (dx, dy) = recent[1..]
+            .iter()
+            .fold((0.0, 0.0), |(x, y), s| (x + s.dx, y + s.dy));
+        let seconds = span as f64 / 1000.0;
+        let (vx, vy) = (dx / seconds, dy / seconds);
+
+        let speed = vx.hypot(vy);
+        if speed < MIN_VELOCITY {
+            return false;
+        }
+
+        let scale = (MAX_VELOCITY / speed).min(1.0);
+        self.velocity = (vx * scale, vy * scale);
+        self.active = true;
+        true
+    }
+
+    /// Stop gliding and forget recorded samples
+    pub fn stop(&mut self) {
+        self.active = false;
+        self.velocity = (0.0, 0.0);
+        self.samples.clear();
+    }
+
+    /// Whether a glide is in progress
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Current glide velocity (pixels per second)
+    pub fn velocity(&self) -> (f64, f64) {
+        self.velocity
+    }
+
+    /// Advance the glide by `dt` seconds
+    ///
+    /// Returns the distance travelled, or `None` once the glide is over.
+    pub fn tick(&mut self, dt: f64) -> Option<(f64, f64)> {
+        if !self.active {
+            return None;
+        }
+        if !dt.is_finite() || dt <= 0.0 {
+            return Some((0.0, 0.0));
+        }
+
+        // Integral of v * e^(-k t) over the step
+        let decay = (-self.friction * dt).exp();
+        let travelled = (1.0 - decay) / self.friction;
+        let delta = (self.velocity.0 * travelled, self.velocity.1 * travelled);
+
+        self.velocity = (self.velocity.0 * decay, self.velocity.1 * decay);
+        if self.velocity.0.hypot(self.velocity.1) < MIN_VELOCITY {
+            self.stop();
+        }
+
+        Some(delta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fling(kinetic: &mut KineticPan) {
+        for i in 0..5 {
+            kinetic.push(1000 + i * 10, 10.0, 0.0);
+        }
+    }
+
+    #[test]
+    fn test_release_starts_glide() {
+        let mut kinetic = KineticPan::default();
+        fling(&mut kinetic);
+
+        assert!(kinetic.release(1045));
+        assert!(kinetic.is_active());
+
+        // 40 pixels over 40ms
+        let (vx, vy) = kinetic.velocity();
+        assert!((vx - 1000.0).abs() < 1e-6);
+        assert_eq!(vy, 0.0);
+    }
+
+    #[test]
+    fn test_glide_decelerates_and_stops() {
+        let mut kinetic = KineticPan::default();
+        fling(&mut kinetic);
+        kinetic.release(1045);
+
+        let mut total = 0.0;
+        let mut last_step = f64::MAX;
+        while let Some((dx, _)) = kinetic.tick(1.0 / 60.0) {
+            assert!(dx <= last_step);
+            last_step = dx;
+            total += dx;
+        }
+
+        assert!(!kinetic.is_active());
+        // Total glide approaches v / friction
+        assert!(total > 200.0 && total <= 250.0, "total = {total}");
+    }
+
+    #[test]
+    fn test_no_glide_without_motion() {
+        let mut kinetic = KineticPan::default();
+        assert!(!kinetic.release(0));
+
+        // A single sample has no measurable velocity
+        kinetic.push(0, 50.0, 0.0);
+        assert!(!kinetic.release(5));
+
+        // Fingers held still before lifting
+        fling(&mut kinetic);
+        assert!(!kinetic.release(2000));
+        assert_eq!(kinetic.tick(0.016), None);
+    }
+
+    #[test]
+    fn test_push_cancels_glide() {
+        let mut kinetic = KineticPan::default();
+        fling(&mut kinetic);
+        kinetic.release(1045);
+
+        kinetic.push(1100, 1.0, 1.0);
+        assert!(!kinetic.is_active());
+        assert_eq!(kinetic.tick(0.016), None);
+    }
+
+    #[test]
+    fn test_velocity_capped() {
+        let mut kinetic = KineticPan::default();
+        kinetic.push(0, 0.0, 0.0);
+        kinetic.push(1, 0.0, 1_000_000.0);
+
+        assert!(kinetic.release(1));
+        let (vx, vy) = kinetic.velocity();
+        assert!(vx.hypot(vy) <= MAX_VELOCITY + 1e-6);
+    }
+}
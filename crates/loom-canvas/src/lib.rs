@@ -5,16 +5,21 @@
 //! - Node-based content organization
 //! - Connections between nodes
 //! - Spatial memory layout
+//! - Pen annotations
 
 pub mod canvas;
 pub mod connection;
+pub mod ink;
+pub mod kinetic;
 pub mod node;
 pub mod viewport;
 
 pub use canvas::Canvas;
 pub use connection::Connection;
+pub use ink::{InkPoint, InkStroke};
+pub use kinetic::KineticPan;
 pub use node::{Node, NodeId, NodeType};
-pub use viewport::Viewport;
+pub use viewport::{Viewport, ViewportId};
 
 use thiserror::Error;
 
@@ -23,9 +28,15 @@ pub enum CanvasError {
     #[error("Node not found: {0}")]
     NodeNotFound(NodeId),
 
+    #[error("Node already exists: {0}")]
+    DuplicateNode(NodeId),
+
     #[error("Invalid connection: {0}")]
     InvalidConnection(String),
 
+    #[error("Invalid ink stroke: {0}")]
+    InvalidStroke(String),
+
     #[error("Resource limit exceeded: {0}")]
     ResourceLimitExceeded(String),
 }
@@ -36,6 +47,8 @@ pub mod limits {
     pub const MAX_NODES: usize = 10_000;
     /// Maximum number of connections allowed
     pub const MAX_CONNECTIONS: usize = 100_000;
+    /// Maximum number of viewports (one per output, at most)
+    pub const MAX_VIEWPORTS: usize = 64;
     /// Maximum canvas coordinate (prevents floating point issues)
     pub const MAX_COORDINATE: f64 = 1_000_000.0;
     /// Minimum canvas coordinate
@@ -5,7 +5,9 @@
 //! - A generated UI element (from AI)
 //! - A data visualization
 //! - A group of other nodes
+//! - Ink drawn with a pen
 
+use crate::ink::InkStroke;
 use serde::{Deserialize, Serialize};
 
 pub type NodeId = u64;
@@ -22,6 +24,8 @@ pub enum NodeType {
     Note { text: String },
     /// An image or media
     Media { path: String },
+    /// Pen strokes, relative to the node origin
+    Ink { strokes: Vec<InkStroke> },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
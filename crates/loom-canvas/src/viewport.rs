@@ -7,6 +7,9 @@
 use crate::limits;
 use serde::{Deserialize, Serialize};
 
+/// Identifier of a viewport on a [`Canvas`](crate::Canvas)
+pub type ViewportId = u32;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Viewport {
     /// Center X position in canvas coordinates
@@ -52,6 +55,9 @@ impl Viewport {
     }
 
     /// Zoom in/out centered on a point (with bounds checking)
+    ///
+    /// The center is given in canvas coordinates and keeps its screen
+    /// position, so zooming "around the cursor" feels anchored.
     pub fn zoom_at(&mut self, factor: f64, center_x: f64, center_y: f64) {
         // Validate input
         if !factor.is_finite() || !center_x.is_finite() || !center_y.is_finite() {
@@ -61,8 +67,9 @@ impl Viewport {
         let old_zoom = self.zoom;
         self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
 
-        // Adjust position to zoom towards the center point
-        let zoom_ratio = self.zoom / old_zoom;
+        // Adjust position to zoom towards the center point: its distance to
+        // the viewport center shrinks as the zoom grows
+        let zoom_ratio = old_zoom / self.zoom;
         let new_x = center_x - (center_x - self.x) * zoom_ratio;
         let new_y = center_y - (center_y - self.y) * zoom_ratio;
 
@@ -109,3 +116,53 @@ impl Default for Viewport {
         Self::new(1920.0, 1080.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: (f64, f64), b: (f64, f64)) {
+        assert!(
+            (a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9,
+            "{a:?} != {b:?}"
+        );
+    }
+
+    #[test]
+    fn test_zoom_at_keeps_center_fixed() {
+        let mut viewport = Viewport::new(1920.0, 1080.0);
+        viewport.pan(300.0, -120.0);
+
+        let anchor = viewport.screen_to_canvas(400.0, 200.0);
+        viewport.zoom_at(2.0, anchor.0, anchor.1);
+
+        assert_eq!(viewport.zoom, 2.0);
+        assert_close(
+            viewport.canvas_to_screen(anchor.0, anchor.1),
+            (400.0, 200.0),
+        );
+    }
+
+    #[test]
+    fn test_zoom_clamped() {
+        let mut viewport = Viewport::default();
+        viewport.zoom_at(1000.0, 0.0, 0.0);
+        assert_eq!(viewport.zoom, MAX_ZOOM);
+
+        viewport.zoom_at(f64::NAN, 0.0, 0.0);
+        assert_eq!(viewport.zoom, MAX_ZOOM);
+    }
+
+    #[test]
+    fn test_screen_canvas_roundtrip() {
+        let mut viewport = Viewport::new(1280.0, 720.0);
+        viewport.pan(50.0, 75.0);
+        viewport.zoom_at(0.5, 10.0, 10.0);
+
+        let canvas = viewport.screen_to_canvas(100.0, 600.0);
+        assert_close(
+            viewport.canvas_to_screen(canvas.0, canvas.1),
+            (100.0, 600.0),
+        );
+    }
+}
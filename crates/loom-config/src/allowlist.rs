@@ -0,0 +1,30 @@
+//! Allowlists of client executables
+//!
+//! Privileged protocols (screen capture, clipboard access) are only
+//! offered to clients whose executable is listed by absolute path.
+
+use std::path::Path;
+use tracing::warn;
+
+/// Whether an allowlist contains an executable
+///
+/// Entries must match the whole path; bare names never match.
+pub(crate) fn allows(allowed_clients: &[String], executable: &Path) -> bool {
+    allowed_clients.iter().any(|allowed| {
+        let allowed = Path::new(allowed);
+        allowed.is_absolute() && executable == allowed
+    })
+}
+
+/// Warn about the entries of a section's allowlist that are not absolute
+/// paths, which are ignored
+pub(crate) fn warn_ignored(section: &str, allowed_clients: &[String]) {
+    for allowed in allowed_clients {
+        if !Path::new(allowed).is_absolute() {
+            warn!(
+                "Ignoring [{}] client {:?}: not an absolute path",
+                section, allowed
+            );
+        }
+    }
+}
@@ -0,0 +1,67 @@
+//! Screen capture configuration
+//!
+//! Capturing the screen or a window (screenshots, screen recording,
+//! screen sharing) is denied by default. The `[capture]` section lists the
+//! programs allowed to capture, by the absolute path of their executable:
+//!
+//! ```toml
+//! [capture]
+//! allowed_clients = ["/usr/bin/grim", "/usr/libexec/xdg-desktop-portal-wlr"]
+//! ```
+//!
+//! Bare names are ignored, with a warning when the config is loaded: any
+//! program can be copied or built under a listed name, in a directory its
+//! author can write to. A path is only as trustworthy as the directory it
+//! lives in, so list executables in system directories.
+
+use crate::allowlist;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CaptureConfig {
+    /// Absolute paths of the executables allowed to capture outputs and
+    /// windows
+    #[serde(default)]
+    pub allowed_clients: Vec<String>,
+}
+
+impl CaptureConfig {
+    /// Whether a client running an executable may capture
+    ///
+    /// Entries must match the whole path; bare names never match.
+    pub fn allows(&self, executable: &Path) -> bool {
+        allowlist::allows(&self.allowed_clients, executable)
+    }
+
+    /// Warn about entries that are not absolute paths, which are ignored
+    pub fn warn_ignored(&self) {
+        allowlist::warn_ignored("capture", &self.allowed_clients);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_config_parse() {
+        let config: CaptureConfig = toml::from_str(
+            r#"
+            allowed_clients = ["grim", "/usr/libexec/xdg-desktop-portal-wlr"]
+            "#,
+        )
+        .unwrap();
+
+        // Bare names match nothing, wherever the executable lives
+        assert!(!config.allows(Path::new("/usr/bin/grim")));
+        assert!(!config.allows(Path::new("/tmp/grim")));
+        assert!(config.allows(Path::new("/usr/libexec/xdg-desktop-portal-wlr")));
+        assert!(!config.allows(Path::new("/tmp/xdg-desktop-portal-wlr")));
+        assert!(!config.allows(Path::new("/usr/libexec/xdg-desktop-portal-wlrx")));
+
+        let config: CaptureConfig = toml::from_str("").unwrap();
+        assert_eq!(config, CaptureConfig::default());
+        assert!(!config.allows(Path::new("/usr/bin/grim")));
+    }
+}
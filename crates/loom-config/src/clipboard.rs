@@ -0,0 +1,65 @@
+//! Clipboard access configuration
+//!
+//! Clipboard managers and tools like `wl-copy` read and set the clipboard
+//! and primary selection through `zwlr_data_control_manager_v1`, whatever
+//! has the keyboard focus. That is hidden from clients by default; the
+//! `[clipboard]` section lists the programs allowed to use it, by the
+//! absolute path of their executable:
+//!
+//! ```toml
+//! [clipboard]
+//! allowed_clients = ["/usr/bin/wl-copy", "/usr/bin/wl-paste", "/usr/bin/cliphist"]
+//! ```
+//!
+//! As for screen capture, bare names are ignored with a warning. Clients
+//! still get the selection of their own through `wl_data_device` when they
+//! have the keyboard focus.
+
+use crate::allowlist;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClipboardConfig {
+    /// Absolute paths of the executables allowed to read and set the
+    /// clipboard without the keyboard focus
+    #[serde(default)]
+    pub allowed_clients: Vec<String>,
+}
+
+impl ClipboardConfig {
+    /// Whether a client running an executable may access the clipboard
+    ///
+    /// Entries must match the whole path; bare names never match.
+    pub fn allows(&self, executable: &Path) -> bool {
+        allowlist::allows(&self.allowed_clients, executable)
+    }
+
+    /// Warn about entries that are not absolute paths, which are ignored
+    pub fn warn_ignored(&self) {
+        allowlist::warn_ignored("clipboard", &self.allowed_clients);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clipboard_config_parse() {
+        let config: ClipboardConfig = toml::from_str(
+            r#"
+            allowed_clients = ["wl-paste", "/usr/bin/cliphist"]
+            "#,
+        )
+        .unwrap();
+
+        assert!(config.allows(Path::new("/usr/bin/cliphist")));
+        assert!(!config.allows(Path::new("/home/user/bin/cliphist")));
+        assert!(!config.allows(Path::new("/usr/bin/wl-paste")));
+
+        let config: ClipboardConfig = toml::from_str("").unwrap();
+        assert_eq!(config, ClipboardConfig::default());
+        assert!(!config.allows(Path::new("/usr/bin/cliphist")));
+    }
+}
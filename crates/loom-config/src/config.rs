@@ -1,6 +1,10 @@
 //! Main configuration struct
 
-use crate::{ConfigError, Result, keybindings::Keybinding, theme::Theme};
+use crate::{
+    ConfigError, Result, capture::CaptureConfig, clipboard::ClipboardConfig,
+    gestures::GestureBinding, idle::IdleConfig, input::InputConfig, keybindings::Keybinding,
+    switches::SwitchConfig, theme::Theme,
+};
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::path::Path;
@@ -24,9 +28,37 @@ pub struct Config {
     #[serde(default)]
     pub theme: Theme,
 
+    /// Keyboard layout and input device settings
+    #[serde(default)]
+    pub input: InputConfig,
+
+    /// Lid and tablet-mode switch actions
+    #[serde(default)]
+    pub switches: SwitchConfig,
+
+    /// Clients allowed to capture the screen
+    #[serde(default)]
+    pub capture: CaptureConfig,
+
+    /// Clients allowed to access the clipboard without the keyboard focus
+    #[serde(default)]
+    pub clipboard: ClipboardConfig,
+
+    /// Idle timeouts
+    #[serde(default)]
+    pub idle: IdleConfig,
+
     /// Keybindings
     #[serde(default)]
     pub keybindings: Vec<Keybinding>,
+
+    /// Touchpad gesture bindings (the defaults if unset)
+    #[serde(default = "GestureBinding::defaults")]
+    pub gestures: Vec<GestureBinding>,
+
+    /// Output arrangement
+    #[serde(default)]
+    pub outputs: Vec<OutputConfig>,
 }
 
 // Manual Debug impl to avoid leaking sensitive data in logs
@@ -37,10 +69,17 @@ impl fmt::Debug for Config {
             .field("canvas", &self.canvas)
             .field("ai", &self.ai)
             .field("theme", &"[...]")
+            .field("input", &self.input)
+            .field("switches", &self.switches)
+            .field("capture", &self.capture)
+            .field("clipboard", &self.clipboard)
+            .field("idle", &self.idle)
             .field(
                 "keybindings",
                 &format!("[{} bindings]", self.keybindings.len()),
             )
+            .field("gestures", &self.gestures)
+            .field("outputs", &self.outputs)
             .finish()
     }
 }
@@ -55,9 +94,18 @@ pub struct GeneralConfig {
     #[serde(default = "default_terminal")]
     pub terminal: String,
 
-    /// Default launcher command
+    /// Launcher command, or the desktop file id of an installed launcher app
     #[serde(default)]
     pub launcher: Option<String>,
+
+    /// Time allowed between the keys of a chord, in milliseconds
+    #[serde(default = "default_chord_timeout")]
+    pub chord_timeout_ms: u64,
+
+    /// Ask clients to leave window decorations to the compositor, which
+    /// frames their nodes with the theme's borders and a title strip
+    #[serde(default = "default_true")]
+    pub server_side_decorations: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -74,6 +122,10 @@ pub struct CanvasConfig {
     #[serde(default = "default_pan_sensitivity")]
     pub pan_sensitivity: f64,
 
+    /// Keep panning after touchpad fingers lift
+    #[serde(default = "default_true")]
+    pub kinetic_panning: bool,
+
     /// Show grid in background
     #[serde(default = "default_true")]
     pub show_grid: bool,
@@ -81,6 +133,31 @@ pub struct CanvasConfig {
     /// Grid spacing in pixels
     #[serde(default = "default_grid_spacing")]
     pub grid_spacing: f64,
+
+    /// How outputs look at the canvas
+    #[serde(default)]
+    pub viewports: ViewportMode,
+}
+
+/// How the canvas is shown on multiple outputs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ViewportMode {
+    /// Every output has its own viewport, panned and zoomed independently
+    #[default]
+    PerOutput,
+    /// A single viewport spans all outputs, following their arrangement
+    Spanning,
+}
+
+/// Placement of an output in the layout
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutputConfig {
+    /// Connector name (e.g., "DP-1", "HDMI-A-1")
+    pub name: String,
+
+    /// Position of the top-left corner in the layout, in logical pixels
+    pub position: (i32, i32),
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -104,6 +181,10 @@ pub struct AiConfig {
     /// Local model path
     #[serde(default)]
     pub local_model_path: Option<String>,
+
+    /// Program showing the AI prompt, run by the `ai_prompt` action
+    #[serde(default)]
+    pub prompt_command: Option<String>,
 }
 
 // Manual Debug impl to redact API key
@@ -115,6 +196,7 @@ impl fmt::Debug for AiConfig {
             .field("api_key", &self.api_key.as_ref().map(|_| "[REDACTED]"))
             .field("use_local", &self.use_local)
             .field("local_model_path", &self.local_model_path)
+            .field("prompt_command", &self.prompt_command)
             .finish()
     }
 }
@@ -180,7 +262,11 @@ impl Config {
             ));
         }
 
-        toml::from_str(&content).map_err(|e| ConfigError::ParseError(e.to_string()))
+        let config: Self =
+            toml::from_str(&content).map_err(|e| ConfigError::ParseError(e.to_string()))?;
+        config.capture.warn_ignored();
+        config.clipboard.warn_ignored();
+        Ok(config)
     }
 
     /// Save config to file
@@ -209,7 +295,14 @@ impl Default for Config {
             canvas: CanvasConfig::default(),
             ai: AiConfig::default(),
             theme: Theme::default(),
+            input: InputConfig::default(),
+            switches: SwitchConfig::default(),
+            capture: CaptureConfig::default(),
+            clipboard: ClipboardConfig::default(),
+            idle: IdleConfig::default(),
             keybindings: Keybinding::defaults(),
+            gestures: GestureBinding::defaults(),
+            outputs: Vec::new(),
         }
     }
 }
@@ -220,6 +313,8 @@ impl Default for GeneralConfig {
             debug: false,
             terminal: default_terminal(),
             launcher: None,
+            chord_timeout_ms: default_chord_timeout(),
+            server_side_decorations: true,
         }
     }
 }
@@ -230,8 +325,10 @@ impl Default for CanvasConfig {
             initial_zoom: default_zoom(),
             zoom_sensitivity: default_zoom_sensitivity(),
             pan_sensitivity: default_pan_sensitivity(),
+            kinetic_panning: true,
             show_grid: true,
             grid_spacing: default_grid_spacing(),
+            viewports: ViewportMode::default(),
         }
     }
 }
@@ -244,6 +341,7 @@ impl Default for AiConfig {
             api_key: None,
             use_local: false,
             local_model_path: None,
+            prompt_command: None,
         }
     }
 }
@@ -252,6 +350,10 @@ fn default_terminal() -> String {
     "foot".to_string()
 }
 
+fn default_chord_timeout() -> u64 {
+    1000
+}
+
 fn default_zoom() -> f64 {
     1.0
 }
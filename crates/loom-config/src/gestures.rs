@@ -0,0 +1,107 @@
+//! Touchpad gesture configuration
+//!
+//! Gestures are bound by kind and finger count:
+//!
+//! ```toml
+//! [[gestures]]
+//! gesture = "swipe"
+//! fingers = 3
+//! action = "pan"
+//! ```
+//!
+//! Without a `[[gestures]]` section the [defaults](GestureBinding::defaults)
+//! apply; `gestures = []` binds none, sending all gestures to clients.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GestureBinding {
+    /// Kind of gesture
+    pub gesture: GestureKind,
+    /// Number of fingers (any number if unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingers: Option<u32>,
+    /// Action to perform
+    pub action: GestureAction,
+}
+
+/// Touchpad gesture kinds, as recognized by libinput
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GestureKind {
+    /// Fingers moving together
+    Swipe,
+    /// Fingers moving apart or together
+    Pinch,
+    /// Fingers resting on the touchpad
+    Hold,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GestureAction {
+    /// Pan the canvas along with the fingers
+    Pan,
+    /// Zoom the canvas around the cursor as the fingers pinch
+    Zoom,
+    /// Cycle the focus through the windows, swiping left for the next
+    CycleFocus,
+    /// Stop the canvas gliding after a pan; while nothing glides, the
+    /// gesture goes to clients
+    StopInertia,
+}
+
+impl GestureBinding {
+    pub fn new(gesture: GestureKind, fingers: Option<u32>, action: GestureAction) -> Self {
+        Self {
+            gesture,
+            fingers,
+            action,
+        }
+    }
+
+    /// Default gesture bindings
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self::new(GestureKind::Swipe, Some(3), GestureAction::Pan),
+            Self::new(GestureKind::Swipe, Some(4), GestureAction::CycleFocus),
+            Self::new(GestureKind::Pinch, None, GestureAction::Zoom),
+            Self::new(GestureKind::Hold, None, GestureAction::StopInertia),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gesture_bindings_parse() {
+        #[derive(Deserialize)]
+        struct Gestures {
+            gestures: Vec<GestureBinding>,
+        }
+
+        let parsed: Gestures = toml::from_str(
+            r#"
+            [[gestures]]
+            gesture = "swipe"
+            fingers = 3
+            action = "cycle_focus"
+
+            [[gestures]]
+            gesture = "pinch"
+            action = "zoom"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            parsed.gestures,
+            vec![
+                GestureBinding::new(GestureKind::Swipe, Some(3), GestureAction::CycleFocus),
+                GestureBinding::new(GestureKind::Pinch, None, GestureAction::Zoom),
+            ]
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! Idle configuration
+//!
+//! The `[idle]` section sets what happens after a while without input:
+//! running a screen locker, and turning the outputs off (DPMS). Either
+//! timeout is disabled when 0. Visible idle inhibitors (a video player on
+//! screen) hold both off.
+//!
+//! ```toml
+//! [idle]
+//! lock_timeout_secs = 300
+//! lock_command = "swaylock -f"
+//! dpms_timeout_secs = 600
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdleConfig {
+    /// Seconds without input before running `lock_command`, 0 to never
+    #[serde(default)]
+    pub lock_timeout_secs: u64,
+
+    /// Command locking the session once idle (e.g. `swaylock -f`); double
+    /// quotes group an argument containing spaces
+    #[serde(default)]
+    pub lock_command: Option<String>,
+
+    /// Seconds without input before turning the outputs off, 0 to never
+    #[serde(default)]
+    pub dpms_timeout_secs: u64,
+}
+
+impl IdleConfig {
+    /// Idle time before locking, `None` if disabled or without a command
+    pub fn lock_timeout(&self) -> Option<Duration> {
+        self.lock_command.as_ref()?;
+        (self.lock_timeout_secs > 0).then(|| Duration::from_secs(self.lock_timeout_secs))
+    }
+
+    /// Idle time before turning the outputs off, `None` if disabled
+    pub fn dpms_timeout(&self) -> Option<Duration> {
+        (self.dpms_timeout_secs > 0).then(|| Duration::from_secs(self.dpms_timeout_secs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_config_parse() {
+        let config: IdleConfig = toml::from_str(
+            r#"
+            lock_timeout_secs = 300
+            lock_command = "swaylock -f"
+            dpms_timeout_secs = 600
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.lock_command.as_deref(), Some("swaylock -f"));
+        assert_eq!(config.lock_timeout(), Some(Duration::from_secs(300)));
+        assert_eq!(config.dpms_timeout(), Some(Duration::from_secs(600)));
+
+        let config: IdleConfig = toml::from_str("lock_timeout_secs = 300").unwrap();
+        assert_eq!(config.lock_timeout(), None);
+
+        let config: IdleConfig = toml::from_str("").unwrap();
+        assert_eq!(config, IdleConfig::default());
+        assert_eq!(config.dpms_timeout(), None);
+    }
+}
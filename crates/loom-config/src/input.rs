@@ -0,0 +1,262 @@
+//! Input device configuration for LoomWM
+//!
+//! The `[input]` section sets the XKB keymap and key repeat of the
+//! keyboard, and libinput options of devices:
+//!
+//! ```toml
+//! [input]
+//! xkb_layout = "us,de"
+//! xkb_options = "grp:alt_shift_toggle,caps:escape"
+//! repeat_delay = 300
+//! repeat_rate = 30
+//!
+//! [[input.devices]]
+//! type = "touchpad"
+//! tap_to_click = true
+//! natural_scroll = true
+//!
+//! [[input.devices]]
+//! name = "Logitech G502"
+//! accel_profile = "flat"
+//! accel_speed = -0.3
+//! ```
+//!
+//! Device sections match devices by name or type (or all devices, with
+//! neither). Every matching section applies the options it sets, those
+//! matching by name last; options no section sets keep the device's
+//! defaults.
+
+use serde::{Deserialize, Serialize};
+
+/// Maximum key repeat rate, in repeats per second
+pub const MAX_REPEAT_RATE: i32 = 1000;
+
+/// Maximum delay before a held key repeats, in milliseconds
+pub const MAX_REPEAT_DELAY: i32 = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputConfig {
+    /// XKB rules file (empty for the default)
+    #[serde(default)]
+    pub xkb_rules: String,
+
+    /// XKB keyboard model
+    #[serde(default)]
+    pub xkb_model: String,
+
+    /// XKB layouts, comma separated
+    #[serde(default)]
+    pub xkb_layout: String,
+
+    /// XKB variants, one per layout
+    #[serde(default)]
+    pub xkb_variant: String,
+
+    /// XKB options, comma separated
+    #[serde(default)]
+    pub xkb_options: Option<String>,
+
+    /// Delay before a held key repeats, in milliseconds
+    #[serde(default = "default_repeat_delay")]
+    pub repeat_delay: i32,
+
+    /// Key repeats per second (0 disables repeat)
+    #[serde(default = "default_repeat_rate")]
+    pub repeat_rate: i32,
+
+    /// Device options
+    #[serde(default)]
+    pub devices: Vec<DeviceConfig>,
+}
+
+/// Type of an input device, for matching device sections
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceType {
+    Keyboard,
+    Pointer,
+    Touchpad,
+    Touch,
+    Tablet,
+}
+
+/// Pointer acceleration profile
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccelProfile {
+    /// Constant acceleration
+    Flat,
+    /// Acceleration depending on the speed
+    Adaptive,
+}
+
+/// How a touchpad or pointer scrolls
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScrollMethod {
+    /// No scrolling from motion
+    None,
+    /// Two fingers moving on a touchpad
+    TwoFinger,
+    /// One finger along the edge of a touchpad
+    Edge,
+    /// Moving while a button is held
+    OnButtonDown,
+}
+
+/// Options for the devices a section matches
+///
+/// Options left unset keep the device's defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeviceConfig {
+    /// Device name to match, as reported by libinput
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+
+    /// Device type to match
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub device_type: Option<DeviceType>,
+
+    /// Tapping a touchpad clicks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tap_to_click: Option<bool>,
+
+    /// Content follows the fingers when scrolling
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub natural_scroll: Option<bool>,
+
+    /// Acceleration profile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accel_profile: Option<AccelProfile>,
+
+    /// Pointer speed, from -1.0 (slowest) to 1.0 (fastest)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub accel_speed: Option<f64>,
+
+    /// Swap the buttons for left-handed use
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub left_handed: Option<bool>,
+
+    /// Ignore a touchpad while typing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disable_while_typing: Option<bool>,
+
+    /// Scroll method
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scroll_method: Option<ScrollMethod>,
+}
+
+impl DeviceConfig {
+    /// Whether the section applies to a device
+    pub fn matches(&self, name: &str, device_type: DeviceType) -> bool {
+        self.name.as_deref().is_none_or(|n| n == name)
+            && self.device_type.is_none_or(|t| t == device_type)
+    }
+
+    /// Take the options another section sets
+    pub fn merge(&mut self, other: &DeviceConfig) {
+        self.tap_to_click = other.tap_to_click.or(self.tap_to_click);
+        self.natural_scroll = other.natural_scroll.or(self.natural_scroll);
+        self.accel_profile = other.accel_profile.or(self.accel_profile);
+        self.accel_speed = other.accel_speed.or(self.accel_speed);
+        self.left_handed = other.left_handed.or(self.left_handed);
+        self.disable_while_typing = other.disable_while_typing.or(self.disable_while_typing);
+        self.scroll_method = other.scroll_method.or(self.scroll_method);
+    }
+}
+
+impl InputConfig {
+    /// Options for a device, from every section matching it
+    ///
+    /// Sections matching by name apply after the others, so they win. The
+    /// pointer speed is clamped to its range.
+    pub fn device_settings(&self, name: &str, device_type: DeviceType) -> DeviceConfig {
+        let mut settings = DeviceConfig::default();
+        let matching = self
+            .devices
+            .iter()
+            .filter(|device| device.matches(name, device_type));
+
+        for device in matching.clone().filter(|device| device.name.is_none()) {
+            settings.merge(device);
+        }
+        for device in matching.filter(|device| device.name.is_some()) {
+            settings.merge(device);
+        }
+
+        settings.accel_speed = settings.accel_speed.map(|speed| speed.clamp(-1.0, 1.0));
+        settings
+    }
+
+    /// Key repeat delay and rate, clamped to their limits
+    pub fn repeat_info(&self) -> (i32, i32) {
+        (
+            self.repeat_delay.clamp(0, MAX_REPEAT_DELAY),
+            self.repeat_rate.clamp(0, MAX_REPEAT_RATE),
+        )
+    }
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        Self {
+            xkb_rules: String::new(),
+            xkb_model: String::new(),
+            xkb_layout: String::new(),
+            xkb_variant: String::new(),
+            xkb_options: None,
+            repeat_delay: default_repeat_delay(),
+            repeat_rate: default_repeat_rate(),
+            devices: Vec::new(),
+        }
+    }
+}
+
+fn default_repeat_delay() -> i32 {
+    200
+}
+
+fn default_repeat_rate() -> i32 {
+    25
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_settings_merge() {
+        let config: InputConfig = toml::from_str(
+            r#"
+            repeat_rate = 5000
+
+            [[devices]]
+            name = "Fancy Touchpad"
+            natural_scroll = false
+            accel_speed = 3.0
+
+            [[devices]]
+            type = "touchpad"
+            tap_to_click = true
+            natural_scroll = true
+
+            [[devices]]
+            left_handed = true
+            "#,
+        )
+        .unwrap();
+
+        let touchpad = config.device_settings("Fancy Touchpad", DeviceType::Touchpad);
+        assert_eq!(touchpad.tap_to_click, Some(true));
+        // The section naming the device wins
+        assert_eq!(touchpad.natural_scroll, Some(false));
+        assert_eq!(touchpad.accel_speed, Some(1.0));
+        assert_eq!(touchpad.left_handed, Some(true));
+
+        let mouse = config.device_settings("Mouse", DeviceType::Pointer);
+        assert_eq!(mouse.tap_to_click, None);
+        assert_eq!(mouse.left_handed, Some(true));
+
+        assert_eq!(config.repeat_info(), (200, MAX_REPEAT_RATE));
+    }
+}
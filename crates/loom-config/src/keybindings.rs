@@ -7,10 +7,24 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Keybinding {
-    /// Key combination (e.g., "Super+Return", "Super+Shift+Q")
+    /// Key combination (e.g., "Super+Return", "Super+Shift+Q"), or a chord
+    /// of combinations pressed one after the other (e.g., "Super+K, Super+C")
     pub key: String,
     /// Action to perform
     pub action: KeybindingAction,
+    /// Binding mode the binding belongs to (the default mode if unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    /// Fire when the key is released, if no other key was pressed since
+    /// (e.g., tapping "Super_L" alone)
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub on_release: bool,
+    /// Keep working while the session is locked
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub allow_when_locked: bool,
+    /// Repeat the action while the key is held
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub repeat: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,11 +46,19 @@ pub enum KeybindingAction {
     ResetView,
     /// Open AI command input
     AiPrompt,
+    /// Draw on the canvas background with a tablet pen, or stop drawing
+    ToggleAnnotation,
     /// Launch a desktop application by its .desktop file name (safe)
     /// Example: "firefox", "org.gnome.Calculator"
     LaunchApp { app_id: String },
     /// Run a script from ~/.config/loom-wm/scripts/ (restricted)
     RunScript { script_name: String },
+    /// Switch to a binding mode (only that mode's bindings are active)
+    EnterMode { mode: String },
+    /// Return to the default binding mode
+    ExitMode,
+    /// Reload the config file
+    ReloadConfig,
     /// Quit the compositor
     Quit,
 }
@@ -62,9 +84,19 @@ impl Keybinding {
         Self {
             key: key.into(),
             action,
+            mode: None,
+            on_release: false,
+            allow_when_locked: false,
+            repeat: false,
         }
     }
 
+    /// Put the binding in a binding mode
+    pub fn in_mode(mut self, mode: impl Into<String>) -> Self {
+        self.mode = Some(mode.into());
+        self
+    }
+
     /// Default keybindings
     pub fn defaults() -> Vec<Self> {
         vec![
@@ -73,6 +105,7 @@ impl Keybinding {
             Self::new("Super+Q", KeybindingAction::Close),
             Self::new("Super+F", KeybindingAction::Fullscreen),
             Self::new("Super+Space", KeybindingAction::AiPrompt),
+            Self::new("Super+I", KeybindingAction::ToggleAnnotation),
             Self::new("Super+0", KeybindingAction::ResetView),
             Self::new(
                 "Super+Plus",
@@ -86,11 +119,16 @@ impl Keybinding {
                     direction: ZoomDirection::Out,
                 },
             ),
+            Self::new("Super+Shift+R", KeybindingAction::ReloadConfig),
             Self::new("Super+Shift+Q", KeybindingAction::Quit),
         ]
     }
 }
 
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
 /// Security module for validating and executing actions safely
 pub mod security {
     use crate::{ConfigError, Result};
@@ -195,6 +233,48 @@ pub mod security {
 #[cfg(test)]
 mod tests {
     use super::security::*;
+    use super::{Keybinding, KeybindingAction};
+
+    #[test]
+    fn test_binding_options_parse() {
+        #[derive(serde::Deserialize)]
+        struct Bindings {
+            keybindings: Vec<Keybinding>,
+        }
+
+        let bindings: Bindings = toml::from_str(
+            r#"
+            [[keybindings]]
+            key = "Super+R"
+            action = { type = "enter_mode", mode = "resize" }
+
+            [[keybindings]]
+            key = "Left"
+            mode = "resize"
+            repeat = true
+            action = { type = "pan", direction = "left" }
+
+            [[keybindings]]
+            key = "Super_L"
+            on_release = true
+            action = { type = "ai_prompt" }
+            "#,
+        )
+        .unwrap();
+
+        let [enter, pan, tap] = &bindings.keybindings[..] else {
+            panic!("expected three bindings");
+        };
+        assert!(matches!(&enter.action, KeybindingAction::EnterMode { mode } if mode == "resize"));
+        assert!(enter.mode.is_none() && !enter.on_release && !enter.repeat);
+        assert_eq!(pan.mode.as_deref(), Some("resize"));
+        assert!(pan.repeat && !pan.allow_when_locked);
+        assert!(tap.on_release);
+
+        // Unset options are left out when saving
+        let saved = toml::to_string(&Keybinding::new("Super+Q", KeybindingAction::Quit)).unwrap();
+        assert!(!saved.contains("on_release") && !saved.contains("mode"));
+    }
 
     #[test]
     fn test_valid_script_names() {
@@ -2,16 +2,33 @@
 //!
 //! Handles all configuration:
 //! - User preferences
-//! - Keybindings
+//! - Keybindings and touchpad gestures
 //! - Theme settings
+//! - Keyboard layout and input devices
+//! - Lid and tablet-mode switches
+//! - Screen capture and clipboard permissions
+//! - Idle timeouts (screen locker, outputs off)
 //! - AI service configuration
 
+mod allowlist;
+pub mod capture;
+pub mod clipboard;
 pub mod config;
+pub mod gestures;
+pub mod idle;
+pub mod input;
 pub mod keybindings;
+pub mod switches;
 pub mod theme;
 
+pub use capture::CaptureConfig;
+pub use clipboard::ClipboardConfig;
 pub use config::Config;
+pub use gestures::{GestureAction, GestureBinding, GestureKind};
+pub use idle::IdleConfig;
+pub use input::{DeviceConfig, DeviceType, InputConfig};
 pub use keybindings::{Keybinding, KeybindingAction};
+pub use switches::{LidAction, SwitchConfig};
 pub use theme::Theme;
 
 use thiserror::Error;
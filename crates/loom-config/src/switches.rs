@@ -0,0 +1,125 @@
+//! Lid and tablet-mode switch configuration
+//!
+//! The `[switches]` section sets what closing the lid does, and how touch
+//! input changes while a convertible is in tablet mode:
+//!
+//! ```toml
+//! [switches]
+//! lid_close = "command"
+//! lid_command = "systemctl suspend"
+//!
+//! [switches.tablet_mode]
+//! touch_margin = 24.0
+//! multi_touch_navigation = false
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+/// Maximum extra touch area around windows, in pixels
+pub const MAX_TOUCH_MARGIN: f64 = 64.0;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SwitchConfig {
+    /// What closing the lid does
+    #[serde(default)]
+    pub lid_close: LidAction,
+
+    /// Command run when the lid closes, with `lid_close = "command"`
+    /// (e.g. a screen locker or `systemctl suspend`); double quotes group
+    /// an argument containing spaces
+    #[serde(default)]
+    pub lid_command: Option<String>,
+
+    /// Touch input in tablet mode
+    #[serde(default)]
+    pub tablet_mode: TabletModeConfig,
+}
+
+/// Action on closing the lid
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LidAction {
+    /// Do nothing
+    Ignore,
+    /// Turn the internal display off while another output is connected,
+    /// moving its nodes to the remaining outputs
+    #[default]
+    DisableOutput,
+    /// Run `lid_command`
+    Command,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TabletModeConfig {
+    /// Change touch input while in tablet mode
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Distance around windows within which touches still land on them,
+    /// in pixels
+    #[serde(default = "default_touch_margin")]
+    pub touch_margin: f64,
+
+    /// Pan and pinch-zoom the canvas with two fingers anywhere, windows
+    /// included
+    #[serde(default = "default_true")]
+    pub multi_touch_navigation: bool,
+}
+
+impl TabletModeConfig {
+    /// Touch margin, clamped to a sane range
+    pub fn touch_margin(&self) -> f64 {
+        if self.touch_margin.is_finite() {
+            self.touch_margin.clamp(0.0, MAX_TOUCH_MARGIN)
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Default for TabletModeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            touch_margin: default_touch_margin(),
+            multi_touch_navigation: true,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_touch_margin() -> f64 {
+    16.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_switch_config_parse() {
+        let config: SwitchConfig = toml::from_str(
+            r#"
+            lid_close = "command"
+            lid_command = "systemctl suspend"
+
+            [tablet_mode]
+            touch_margin = 1000.0
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.lid_close, LidAction::Command);
+        assert_eq!(config.lid_command.as_deref(), Some("systemctl suspend"));
+        assert!(config.tablet_mode.enabled);
+        assert!(config.tablet_mode.multi_touch_navigation);
+        assert_eq!(config.tablet_mode.touch_margin(), MAX_TOUCH_MARGIN);
+
+        let config: SwitchConfig = toml::from_str("").unwrap();
+        assert_eq!(config, SwitchConfig::default());
+        assert_eq!(config.lid_close, LidAction::DisableOutput);
+    }
+}
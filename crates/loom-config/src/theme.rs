@@ -111,3 +111,38 @@ fn default_font() -> String {
 fn default_font_size() -> f32 {
     14.0
 }
+
+/// Parse a `#rrggbb` or `#rrggbbaa` color into RGBA components from 0 to 1
+pub fn parse_color(hex: &str) -> Option<[f32; 4]> {
+    let digits = hex.strip_prefix('#')?;
+    if !matches!(digits.len(), 6 | 8) || !digits.is_ascii() {
+        return None;
+    }
+
+    let mut color = [1.0; 4];
+    for (component, i) in color.iter_mut().zip((0..digits.len()).step_by(2)) {
+        *component = u8::from_str_radix(&digits[i..i + 2], 16).ok()? as f32 / 255.0;
+    }
+    Some(color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#ff0000"), Some([1.0, 0.0, 0.0, 1.0]));
+        assert_eq!(parse_color("#00ff0000"), Some([0.0, 1.0, 0.0, 0.0]));
+        assert_eq!(parse_color("ff0000"), None);
+        assert_eq!(parse_color("#ff00"), None);
+        assert_eq!(parse_color("#gg0000"), None);
+        assert_eq!(parse_color("#ÿÿÿ"), None);
+
+        // Every default color parses
+        let theme = Theme::default();
+        for color in [&theme.background, &theme.text, &theme.accent] {
+            assert!(parse_color(color).is_some());
+        }
+    }
+}
@@ -12,116 +12,186 @@
 //! - **GBM**: Generic Buffer Manager for buffer allocation
 //! - **libinput**: Input device handling
 //!
+//! The event loop runs with [`LoomState`] as its data, like the other
+//! backends, so sources registered by the compositor (socket, timers) are
+//! dispatched. Device state lives in a [`DrmBackend`] shared by the DRM,
+//! udev and session sources.
+//!
+//! # Frame Scheduling
+//!
+//! Each output renders through a [`DrmCompositor`] and is paced by its
+//! [`FrameScheduler`] instead of a fixed timer: a redraw is queued after a
+//! dispatch that changed something on screen (see
+//! [`LoomState::queue_redraw`]) and only renders once the previous frame
+//! was flipped. Frames without damage are not submitted; the output then
+//! waits for the estimated vblank, which keeps clients throttled to the
+//! refresh rate. An idle compositor doesn't render at all.
+//!
+//! # Hotplug
+//!
+//! Connector changes rescan the GPU's connectors: new monitors become
+//! outputs right of the existing ones, and unplugged ones are removed from
+//! the layout (see [`crate::outputs`]).
+//!
+//! # Lid
+//!
+//! While the lid is closed, internal displays can be turned off and taken
+//! out of the layout as long as another output is connected (see
+//! [`crate::input::Switches`]); they come back when the lid opens or the
+//! last other output is unplugged.
+//!
+//! # Idle
+//!
+//! Outputs turned off for idleness (see [`crate::idle`]) are powered down,
+//! their CRTCs kept, and stay in the layout; the next input powers them
+//! back up with a full redraw.
+//!
+//! # Session
+//!
+//! When the session is paused (VT switch away), libinput is suspended,
+//! the DRM devices are released and nothing renders. On activation the
+//! devices are taken back, their connectors rescanned and every output is
+//! fully redrawn. `Ctrl+Alt+F1..F12` switches VTs through the session.
+//!
 //! # Security Notes
 //!
 //! - Requires appropriate permissions (seat access, input group)
 //! - Uses libseat for proper privilege separation
 //! - Device access is managed through the session
 
-use crate::perf::{FrameTimer, TARGET_FRAME_TIME_60FPS};
+use crate::backend::scheduler::FrameScheduler;
+use crate::capture;
+use crate::input::process_input_event;
+use crate::outputs::is_internal_output;
+use crate::perf::FrameTimer;
+use crate::render;
 use crate::state::LoomState;
 use crate::{CoreError, Result};
+use loom_config::Config;
 use smithay::{
     backend::{
-        allocator::gbm::{GbmAllocator, GbmBufferFlags, GbmDevice},
-        drm::{DrmDevice, DrmDeviceFd, DrmEvent, DrmEventMetadata, DrmNode, NodeType},
+        allocator::{
+            Fourcc,
+            gbm::{GbmAllocator, GbmBufferFlags, GbmDevice},
+        },
+        drm::{
+            DrmDevice, DrmDeviceFd, DrmEvent, DrmEventMetadata, DrmNode, NodeType,
+            compositor::{DrmCompositor, FrameFlags},
+            exporter::gbm::GbmFramebufferExporter,
+        },
+        egl::{EGLContext, EGLDisplay},
+        input::InputEvent,
         libinput::{LibinputInputBackend, LibinputSessionInterface},
-        renderer::damage::OutputDamageTracker,
+        renderer::{gles::GlesTexture, glow::GlowRenderer},
         session::{Event as SessionEvent, Session, libseat::LibSeatSession},
         udev::{UdevBackend, UdevEvent},
     },
     output::{Mode, Output, PhysicalProperties, Subpixel},
     reexports::{
         calloop::{
-            EventLoop, LoopHandle, RegistrationToken,
+            EventLoop, RegistrationToken,
             timer::{TimeoutAction, Timer},
         },
         drm::control::{ModeTypeFlags, connector, crtc},
         input::Libinput,
         rustix::fs::OFlags,
-        wayland_server::Display,
+        wayland_server::{Display, backend::GlobalId},
     },
     utils::{DeviceFd, Transform},
 };
 use smithay_drm_extras::drm_scanner::{DrmScanEvent, DrmScanner};
-use std::{collections::HashMap, path::Path, time::Duration};
+use std::{cell::RefCell, collections::HashMap, path::Path, rc::Rc, time::Duration};
 use tracing::{debug, error, info, warn};
 
 /// Background color (dark gray) - RGBA as f32 [0.0, 1.0]
-#[allow(dead_code)]
 const BACKGROUND_COLOR: [f32; 4] = [0.1, 0.1, 0.1, 1.0];
 
+/// Color formats for the primary plane, in order of preference
+const SUPPORTED_COLOR_FORMATS: [Fourcc; 2] = [Fourcc::Argb8888, Fourcc::Xrgb8888];
+
+/// How long the global of a removed output lingers before it is destroyed
+const OUTPUT_GLOBAL_LINGER: Duration = Duration::from_secs(10);
+
 /// Log performance stats every N frames
 const PERF_LOG_INTERVAL: u64 = 300;
 
+/// DRM compositor rendering into GBM buffers
+type GbmDrmCompositor =
+    DrmCompositor<GbmAllocator<DrmDeviceFd>, GbmFramebufferExporter<DrmDeviceFd>, (), DrmDeviceFd>;
+
+/// Backend state shared by the event sources
+type SharedBackend = Rc<RefCell<DrmBackend>>;
+
 /// State for a single GPU device
 struct GpuData {
     /// DRM device
-    #[allow(dead_code)]
     drm: DrmDevice,
-    /// DRM device file descriptor
-    #[allow(dead_code)]
-    drm_fd: DrmDeviceFd,
     /// GBM device for buffer allocation
-    #[allow(dead_code)]
     gbm: GbmDevice<DrmDeviceFd>,
     /// GBM allocator
-    #[allow(dead_code)]
     allocator: GbmAllocator<DrmDeviceFd>,
+    /// Renderer for this GPU's outputs
+    renderer: GlowRenderer,
     /// DRM scanner for connector/CRTC management
-    #[allow(dead_code)]
     drm_scanner: DrmScanner,
+    /// Outputs driven by this GPU
+    outputs: HashMap<crtc::Handle, OutputData>,
     /// Token for event loop registration
-    #[allow(dead_code)]
     token: RegistrationToken,
 }
 
 /// State for a single output (monitor)
 struct OutputData {
     /// The Smithay output
-    #[allow(dead_code)]
     output: Output,
-    /// CRTC for this output
-    #[allow(dead_code)]
-    crtc: crtc::Handle,
-    /// Damage tracker for efficient rendering
-    #[allow(dead_code)]
-    damage_tracker: OutputDamageTracker,
+    /// wl_output global advertising the output
+    global: GlobalId,
+    /// Compositor for the CRTC's planes
+    compositor: GbmDrmCompositor,
+    /// Frame pacing
+    scheduler: FrameScheduler,
+    /// Pending estimated vblank timer, after a frame without damage
+    estimated_vblank: Option<RegistrationToken>,
+    /// Whether the output is off and out of the layout (lid closed)
+    disabled: bool,
+    /// Whether the output is powered down for idleness, in the layout
+    powered_off: bool,
 }
 
 /// DRM backend state
-struct DrmState {
-    /// Compositor state
-    loom_state: LoomState,
+struct DrmBackend {
     /// Session for device access
     session: LibSeatSession,
+    /// libinput context, suspended while the session is paused
+    libinput: Libinput,
     /// Primary GPU node
     #[allow(dead_code)]
     primary_gpu: DrmNode,
     /// Per-GPU data
     gpus: HashMap<DrmNode, GpuData>,
-    /// Per-output data
-    outputs: HashMap<crtc::Handle, OutputData>,
-    /// Frame timer
+    /// Frame timer (measures render time)
     frame_timer: FrameTimer,
     /// Frame counter
     frame_count: u64,
 }
 
-impl DrmState {
-    /// Check if compositor should keep running
-    fn running(&self) -> bool {
-        self.loom_state.running
+impl DrmBackend {
+    /// Look up an output by GPU and CRTC
+    fn output_mut(&mut self, node: DrmNode, crtc: crtc::Handle) -> Option<&mut OutputData> {
+        self.gpus.get_mut(&node)?.outputs.get_mut(&crtc)
+    }
+
+    /// Number of outputs over all GPUs
+    fn output_count(&self) -> usize {
+        self.gpus.values().map(|gpu| gpu.outputs.len()).sum()
     }
 }
 
 /// Run the compositor using the DRM backend
-pub fn run() -> Result<()> {
+pub fn run(config: &Config) -> Result<()> {
     info!("Starting DRM backend...");
 
-    // Create the event loop with LoomState as the data type
-    // We'll use a wrapper to handle both DRM state and LoomState
-    let mut event_loop: EventLoop<DrmState> =
+    let mut event_loop: EventLoop<LoomState> =
         EventLoop::try_new().map_err(|e| CoreError::EventLoop(e.to_string()))?;
     let loop_handle = event_loop.handle();
 
@@ -130,22 +200,17 @@ pub fn run() -> Result<()> {
         .map_err(|e| CoreError::Session(format!("Failed to create session: {e}")))?;
     info!("Session created: {:?}", session.seat());
 
-    // Create Wayland display for LoomState
-    let display: Display<LoomState> = Display::new()
+    // Create Wayland display and compositor state
+    let mut display: Display<LoomState> = Display::new()
         .map_err(|e| CoreError::BackendInit(format!("Failed to create display: {e}")))?;
 
-    // We need a separate event loop handle for LoomState
-    // Create a simple event loop just for getting a handle
-    let loom_event_loop: EventLoop<LoomState> =
-        EventLoop::try_new().map_err(|e| CoreError::EventLoop(e.to_string()))?;
-
-    // Create compositor state
-    let loom_state = LoomState::new(display, loom_event_loop.handle())
+    let mut state = LoomState::new(&display, loop_handle.clone(), config)
         .map_err(|e| CoreError::BackendInit(format!("Failed to create state: {e}")))?;
 
-    // Create display for socket
-    let mut socket_display: Display<LoomState> = Display::new()
-        .map_err(|e| CoreError::BackendInit(format!("Failed to create display: {e}")))?;
+    let socket_name = state
+        .register_socket(&mut display)
+        .map_err(|e| CoreError::BackendInit(format!("Failed to register socket: {e}")))?;
+    info!("Wayland socket: {}", socket_name);
 
     // Initialize udev backend
     let udev_backend = UdevBackend::new(session.seat())
@@ -165,131 +230,107 @@ pub fn run() -> Result<()> {
         .ok_or_else(|| CoreError::BackendInit("No primary GPU found".to_string()))?;
     info!("Primary GPU: {:?}", primary_gpu);
 
-    // Create DRM state
-    let mut state = DrmState {
-        loom_state,
+    // Initialize libinput on the session's seat
+    let mut libinput_context =
+        Libinput::new_with_udev(LibinputSessionInterface::from(session.clone()));
+    libinput_context
+        .udev_assign_seat(&session.seat())
+        .map_err(|()| CoreError::Input("Failed to assign libinput seat".to_string()))?;
+
+    let backend: SharedBackend = Rc::new(RefCell::new(DrmBackend {
         session,
+        libinput: libinput_context.clone(),
         primary_gpu,
         gpus: HashMap::new(),
-        outputs: HashMap::new(),
         frame_timer: FrameTimer::new(),
         frame_count: 0,
-    };
-
-    // Register socket
-    let socket_name = state
-        .loom_state
-        .register_socket(&mut socket_display)
-        .map_err(|e| CoreError::BackendInit(format!("Failed to register socket: {e}")))?;
-    info!("Wayland socket: {}", socket_name);
+    }));
 
     // Insert session event source
+    let shared = backend.clone();
     loop_handle
-        .insert_source(notifier, |event, _, state| {
-            handle_session_event(event, state);
+        .insert_source(notifier, move |event, _, state| {
+            handle_session_event(&shared, state, event);
         })
         .map_err(|e| CoreError::EventLoop(format!("Failed to insert session source: {e}")))?;
 
-    // Initialize libinput
-    let libinput_context =
-        Libinput::new_with_udev(LibinputSessionInterface::from(state.session.clone()));
-    let libinput_backend = LibinputInputBackend::new(libinput_context.clone());
-
+    // Forward input events to the compositor
     loop_handle
-        .insert_source(libinput_backend, |event, _, _state| {
-            // TODO: Forward input events to state
-            debug!("Input event: {:?}", event);
-        })
+        .insert_source(
+            LibinputInputBackend::new(libinput_context),
+            |event, _, state| {
+                match &event {
+                    InputEvent::DeviceAdded { device } => {
+                        state.add_libinput_device(device.clone());
+                    }
+                    InputEvent::DeviceRemoved { device } => {
+                        state.remove_libinput_device(device);
+                    }
+                    _ => {}
+                }
+                process_input_event(state, event);
+            },
+        )
         .map_err(|e| CoreError::EventLoop(format!("Failed to insert libinput source: {e}")))?;
 
     // Initialize GPUs from udev
     for (dev_id, path) in udev_backend.device_list() {
-        if let Err(e) = init_gpu(&mut state, &loop_handle, dev_id, path) {
+        if let Err(e) = init_gpu(&backend, &mut state, dev_id, path) {
             error!("Failed to initialize GPU {:?}: {}", path, e);
         }
     }
 
-    // Clone handle for udev closure
-    let udev_loop_handle = loop_handle.clone();
-
     // Insert udev event source for hotplug
+    let shared = backend.clone();
     loop_handle
         .insert_source(udev_backend, move |event, _, state| {
-            handle_udev_event(event, state, &udev_loop_handle);
+            handle_udev_event(&shared, state, event);
         })
         .map_err(|e| CoreError::EventLoop(format!("Failed to insert udev source: {e}")))?;
 
-    // Set up frame timer
-    let timer = Timer::immediate();
-    loop_handle
-        .insert_source(timer, |_, _, state| {
-            // Render all outputs
-            let crtcs: Vec<_> = state.outputs.keys().copied().collect();
-            for crtc in crtcs {
-                if let Err(e) = render_output(state, crtc) {
-                    error!("Failed to render output: {}", e);
-                }
-            }
-
-            // Schedule next frame
-            TimeoutAction::ToDuration(TARGET_FRAME_TIME_60FPS)
-        })
-        .map_err(|e| CoreError::EventLoop(format!("Failed to insert frame timer: {e}")))?;
-
     info!("Entering main event loop");
     info!(
         "To connect a client, run: WAYLAND_DISPLAY={} <client>",
         socket_name
     );
 
-    // Main loop
-    while state.running() {
-        state.frame_timer.begin_frame();
-
-        // Dispatch events
+    // Main loop: rendering is paced by vblanks, so block until events arrive
+    while state.running {
         event_loop
-            .dispatch(Some(Duration::from_millis(1)), &mut state)
+            .dispatch(None, &mut state)
             .map_err(|e| CoreError::EventLoop(format!("Event loop error: {e}")))?;
 
         // Process Wayland clients
-        socket_display
-            .dispatch_clients(&mut state.loom_state)
+        display
+            .dispatch_clients(&mut state)
             .map_err(|e| CoreError::EventLoop(format!("Dispatch error: {e}")))?;
+        state.refresh();
 
-        // Flush clients
-        socket_display.flush_clients().ok();
-
-        // Record frame time
-        let is_stutter = state.frame_timer.end_frame();
-        if is_stutter {
-            let stats = state.frame_timer.stats();
-            warn!(
-                "Frame stutter: {:?} (target: {:?})",
-                stats.last_frame_time,
-                state.frame_timer.target_frame_time()
-            );
+        // VT switches requested by a keybinding
+        if let Some(vt) = state.vt_switch.take() {
+            change_vt(&backend, vt);
         }
 
-        // Periodic logging
-        state.frame_count += 1;
-        if state.frame_count.is_multiple_of(PERF_LOG_INTERVAL) {
-            let stats = state.frame_timer.stats();
-            info!(
-                "Performance: {:.1} FPS, avg frame: {:?}, stutters: {}, clients: {}, outputs: {}",
-                stats.fps,
-                stats.avg_frame_time,
-                stats.stutter_count,
-                state.loom_state.client_count(),
-                state.outputs.len()
-            );
+        // Internal displays follow the lid, and all outputs idleness
+        sync_internal_outputs(&backend, &mut state);
+        sync_output_power(&backend, &mut state);
+
+        // Render outputs that aren't waiting for a page flip, if anything
+        // changed on screen
+        if std::mem::take(&mut state.redraw_needed) {
+            queue_redraw_all(&backend, &mut state);
         }
+
+        // Flush clients
+        display.flush_clients().ok();
     }
 
     // Final stats
-    let stats = state.frame_timer.stats();
+    let backend = backend.borrow();
+    let stats = backend.frame_timer.stats();
     info!(
-        "DRM backend shutting down. Final stats: {:.1} FPS avg, {} stutters",
-        stats.fps, stats.stutter_count
+        "DRM backend shutting down. Final stats: {} frames, avg render: {:?}, {} stutters",
+        backend.frame_count, stats.avg_frame_time, stats.stutter_count
     );
 
     Ok(())
@@ -297,8 +338,8 @@ pub fn run() -> Result<()> {
 
 /// Initialize a GPU device
 fn init_gpu(
-    state: &mut DrmState,
-    loop_handle: &LoopHandle<DrmState>,
+    backend: &SharedBackend,
+    state: &mut LoomState,
     dev_id: libc::dev_t,
     path: &Path,
 ) -> Result<()> {
@@ -306,7 +347,8 @@ fn init_gpu(
         .map_err(|e| CoreError::BackendInit(format!("Invalid DRM node: {e}")))?;
 
     // Open the device through the session
-    let fd = state
+    let fd = backend
+        .borrow_mut()
         .session
         .open(
             path,
@@ -321,7 +363,7 @@ fn init_gpu(
         .map_err(|e| CoreError::BackendInit(format!("Failed to create DRM device: {e}")))?;
 
     // Create GBM device
-    let gbm = GbmDevice::new(drm_fd.clone())
+    let gbm = GbmDevice::new(drm_fd)
         .map_err(|e| CoreError::BackendInit(format!("Failed to create GBM device: {e}")))?;
 
     // Create allocator
@@ -330,56 +372,91 @@ fn init_gpu(
         GbmBufferFlags::RENDERING | GbmBufferFlags::SCANOUT,
     );
 
+    // Create the renderer on top of the GBM device
+    // SAFETY: the GBM device is owned by the same GpuData as the renderer
+    // and outlives the EGL display created from it
+    let egl_display = unsafe { EGLDisplay::new(gbm.clone()) }
+        .map_err(|e| CoreError::Renderer(format!("Failed to create EGL display: {e}")))?;
+    let egl_context = EGLContext::new(&egl_display)
+        .map_err(|e| CoreError::Renderer(format!("Failed to create EGL context: {e}")))?;
+    // SAFETY: the context was just created and isn't current on any thread
+    let renderer = unsafe { GlowRenderer::new(egl_context) }
+        .map_err(|e| CoreError::Renderer(format!("Failed to create renderer: {e}")))?;
+
     // Register DRM event source
-    let token = loop_handle
+    let shared = backend.clone();
+    let token = state
+        .loop_handle
         .insert_source(drm_notifier, move |event, metadata, state| {
-            handle_drm_event(event, metadata, state, node);
+            handle_drm_event(&shared, state, node, event, metadata);
         })
         .map_err(|e| CoreError::EventLoop(format!("Failed to insert DRM source: {e}")))?;
 
-    // Create DRM scanner
-    let mut drm_scanner = DrmScanner::new();
-
-    // Scan for connectors and process results
-    for event in drm_scanner
-        .scan_connectors(&drm)
-        .map_err(|e| CoreError::BackendInit(format!("Failed to scan connectors: {e}")))?
-    {
-        match event {
-            DrmScanEvent::Connected { connector, crtc } => {
-                if let Some(crtc) = crtc
-                    && let Err(e) = init_output(state, connector, crtc)
-                {
-                    error!("Failed to init output: {}", e);
-                }
-            }
-            DrmScanEvent::Disconnected { crtc, .. } => {
-                if let Some(crtc) = crtc {
-                    state.outputs.remove(&crtc);
-                }
-            }
-        }
-    }
-
     // Store GPU data
-    state.gpus.insert(
+    backend.borrow_mut().gpus.insert(
         node,
         GpuData {
             drm,
-            drm_fd,
             gbm,
             allocator,
-            drm_scanner,
+            renderer,
+            drm_scanner: DrmScanner::new(),
+            outputs: HashMap::new(),
             token,
         },
     );
 
+    scan_connectors(backend, state, node);
+
     info!("GPU initialized: {:?}", path);
     Ok(())
 }
 
+/// Scan a GPU's connectors, creating and removing outputs accordingly
+fn scan_connectors(backend: &SharedBackend, state: &mut LoomState, node: DrmNode) {
+    let mut backend = backend.borrow_mut();
+    let Some(gpu) = backend.gpus.get_mut(&node) else {
+        return;
+    };
+
+    let events = match gpu.drm_scanner.scan_connectors(&gpu.drm) {
+        Ok(events) => events,
+        Err(e) => {
+            warn!("Failed to scan connectors on {:?}: {}", node, e);
+            return;
+        }
+    };
+
+    for event in events {
+        match event {
+            DrmScanEvent::Connected {
+                connector,
+                crtc: Some(crtc),
+            } => {
+                if let Err(e) = init_output(gpu, state, node, connector, crtc) {
+                    error!("Failed to init output: {}", e);
+                }
+            }
+            DrmScanEvent::Disconnected {
+                crtc: Some(crtc), ..
+            } => {
+                if let Some(output_data) = gpu.outputs.remove(&crtc) {
+                    remove_output(state, output_data);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
 /// Initialize an output (monitor)
-fn init_output(state: &mut DrmState, connector: connector::Info, crtc: crtc::Handle) -> Result<()> {
+fn init_output(
+    gpu: &mut GpuData,
+    state: &mut LoomState,
+    node: DrmNode,
+    connector: connector::Info,
+    crtc: crtc::Handle,
+) -> Result<()> {
     // Get connector name
     let name = format!(
         "{}-{}",
@@ -389,7 +466,7 @@ fn init_output(state: &mut DrmState, connector: connector::Info, crtc: crtc::Han
     info!("Initializing output: {}", name);
 
     // Find the preferred mode
-    let mode = connector
+    let drm_mode = connector
         .modes()
         .iter()
         .find(|m| m.mode_type().contains(ModeTypeFlags::PREFERRED))
@@ -397,8 +474,13 @@ fn init_output(state: &mut DrmState, connector: connector::Info, crtc: crtc::Han
         .copied()
         .ok_or_else(|| CoreError::BackendInit("No mode available".to_string()))?;
 
-    let (w, h) = mode.size();
-    info!("Mode: {}x{} @ {}Hz", w, h, mode.vrefresh());
+    let (w, h) = drm_mode.size();
+    info!("Mode: {}x{} @ {}Hz", w, h, drm_mode.vrefresh());
+
+    let surface = gpu
+        .drm
+        .create_surface(crtc, drm_mode, &[connector.handle()])
+        .map_err(|e| CoreError::BackendInit(format!("Failed to create DRM surface: {e}")))?;
 
     // Create Smithay output
     let output = Output::new(
@@ -411,32 +493,42 @@ fn init_output(state: &mut DrmState, connector: connector::Info, crtc: crtc::Han
         },
     );
 
-    let smithay_mode = Mode {
-        size: (w as i32, h as i32).into(),
-        refresh: (mode.vrefresh() * 1000) as i32,
-    };
-
-    output.change_current_state(
-        Some(smithay_mode),
-        Some(Transform::Normal),
-        None,
-        Some((0, 0).into()),
-    );
-    output.set_preferred(smithay_mode);
+    let mode = Mode::from(drm_mode);
+    let position = state.next_output_position(&name);
+    output.change_current_state(Some(mode), Some(Transform::Normal), None, Some(position));
+    output.set_preferred(mode);
 
-    // Add output to space
-    state.loom_state.space.map_output(&output, (0, 0));
+    let render_formats = gpu.renderer.egl_context().dmabuf_render_formats().clone();
 
-    // Create damage tracker
-    let damage_tracker = OutputDamageTracker::from_output(&output);
+    let compositor = DrmCompositor::new(
+        &output,
+        surface,
+        None,
+        gpu.allocator.clone(),
+        GbmFramebufferExporter::new(gpu.gbm.clone(), Some(node).into()),
+        SUPPORTED_COLOR_FORMATS,
+        render_formats,
+        gpu.drm.cursor_size(),
+        Some(gpu.gbm.clone()),
+    )
+    .map_err(|e| CoreError::Renderer(format!("Failed to create DRM compositor: {e}")))?;
+
+    // Advertise the output and add it to the space
+    let global = output.create_global::<LoomState>(&state.display_handle);
+    state.space.map_output(&output, position);
+    state.update_views();
 
     // Store output data
-    state.outputs.insert(
+    gpu.outputs.insert(
         crtc,
         OutputData {
             output,
-            crtc,
-            damage_tracker,
+            global,
+            compositor,
+            scheduler: FrameScheduler::new(mode.refresh),
+            estimated_vblank: None,
+            disabled: false,
+            powered_off: false,
         },
     );
 
@@ -444,40 +536,254 @@ fn init_output(state: &mut DrmState, connector: connector::Info, crtc: crtc::Han
     Ok(())
 }
 
+/// Remove an output from the layout and withdraw its global
+fn remove_output(state: &mut LoomState, output_data: OutputData) {
+    info!("Removing output: {}", output_data.output.name());
+
+    if let Some(token) = output_data.estimated_vblank {
+        state.loop_handle.remove(token);
+    }
+
+    // Disabled outputs are already out of the layout
+    if output_data.disabled {
+        return;
+    }
+    state.remove_output(&output_data.output);
+    withdraw_global(state, output_data.global);
+}
+
+/// Turn internal displays off or on as the lid requires
+///
+/// They are only turned off while another output is connected.
+fn sync_internal_outputs(backend: &SharedBackend, state: &mut LoomState) {
+    let mut guard = backend.borrow_mut();
+    if !guard.session.is_active() {
+        return;
+    }
+
+    let has_external = guard
+        .gpus
+        .values()
+        .flat_map(|gpu| gpu.outputs.values())
+        .any(|output_data| !is_internal_output(&output_data.output.name()));
+    let disable = state.lid_disables_internal_outputs() && has_external;
+
+    for output_data in guard
+        .gpus
+        .values_mut()
+        .flat_map(|gpu| gpu.outputs.values_mut())
+    {
+        if output_data.disabled == disable || !is_internal_output(&output_data.output.name()) {
+            continue;
+        }
+
+        if disable {
+            disable_output(state, output_data);
+        } else {
+            enable_output(state, output_data);
+        }
+    }
+}
+
+/// Turn an output off and take it out of the layout, keeping its CRTC
+fn disable_output(state: &mut LoomState, output_data: &mut OutputData) {
+    info!("Disabling output: {}", output_data.output.name());
+
+    if let Some(token) = output_data.estimated_vblank.take() {
+        state.loop_handle.remove(token);
+    }
+    output_data.scheduler.reset();
+    if let Err(e) = output_data.compositor.clear() {
+        warn!("Failed to turn output off: {}", e);
+    }
+
+    state.remove_output(&output_data.output);
+    withdraw_global(state, output_data.global.clone());
+    output_data.disabled = true;
+}
+
+/// Put a disabled output back into the layout
+///
+/// The next frame turns it back on.
+fn enable_output(state: &mut LoomState, output_data: &mut OutputData) {
+    let output = &output_data.output;
+    info!("Enabling output: {}", output.name());
+
+    let position = state.next_output_position(&output.name());
+    output.change_current_state(None, None, None, Some(position));
+    output_data.global = output.create_global::<LoomState>(&state.display_handle);
+    state.space.map_output(output, position);
+    state.update_views();
+
+    output_data.compositor.reset_buffers();
+    output_data.disabled = false;
+}
+
+/// Power outputs down while idle, and back up on input
+fn sync_output_power(backend: &SharedBackend, state: &mut LoomState) {
+    let mut guard = backend.borrow_mut();
+    if !guard.session.is_active() {
+        return;
+    }
+
+    let off = state.idle.outputs_off();
+    for output_data in guard
+        .gpus
+        .values_mut()
+        .flat_map(|gpu| gpu.outputs.values_mut())
+    {
+        if output_data.powered_off == off || output_data.disabled {
+            continue;
+        }
+
+        if off {
+            info!("Powering output down: {}", output_data.output.name());
+            if let Some(token) = output_data.estimated_vblank.take() {
+                state.loop_handle.remove(token);
+            }
+            output_data.scheduler.reset();
+            if let Err(e) = output_data.compositor.clear() {
+                warn!("Failed to power output down: {}", e);
+            }
+        } else {
+            info!("Powering output up: {}", output_data.output.name());
+            output_data.compositor.reset_buffers();
+            state.queue_redraw();
+        }
+        output_data.powered_off = off;
+    }
+}
+
+/// Withdraw an output's global from clients
+fn withdraw_global(state: &mut LoomState, global: GlobalId) {
+    // Clients may be binding the global right now; disable it first so
+    // no new binds happen, and destroy it once they had time to notice
+    state
+        .display_handle
+        .disable_global::<LoomState>(global.clone());
+    let timer = Timer::from_duration(OUTPUT_GLOBAL_LINGER);
+    if let Err(e) = state.loop_handle.insert_source(timer, move |_, _, state| {
+        state
+            .display_handle
+            .remove_global::<LoomState>(global.clone());
+        TimeoutAction::Drop
+    }) {
+        warn!("Failed to schedule output global removal: {}", e);
+    }
+}
+
 /// Handle session events (VT switching)
-fn handle_session_event(event: SessionEvent, _state: &mut DrmState) {
+fn handle_session_event(backend: &SharedBackend, state: &mut LoomState, event: SessionEvent) {
     match event {
         SessionEvent::PauseSession => {
             info!("Session paused (VT switch away)");
-            // TODO: Pause rendering, release devices
+            pause_session(backend, state);
         }
         SessionEvent::ActivateSession => {
             info!("Session activated (VT switch back)");
-            // TODO: Resume rendering, reclaim devices
+            activate_session(backend, state);
+        }
+    }
+}
+
+/// Release input and DRM devices and stop rendering
+fn pause_session(backend: &SharedBackend, state: &mut LoomState) {
+    let mut backend = backend.borrow_mut();
+    backend.libinput.suspend();
+
+    for gpu in backend.gpus.values_mut() {
+        gpu.drm.pause();
+
+        // Frames in flight will never be flipped
+        for output_data in gpu.outputs.values_mut() {
+            if let Some(token) = output_data.estimated_vblank.take() {
+                state.loop_handle.remove(token);
+            }
+            output_data.scheduler.reset();
+        }
+    }
+
+    // Don't keep gliding while nobody sees it
+    state.stop_kinetic_pan();
+}
+
+/// Take the devices back and redraw every output from scratch
+fn activate_session(backend: &SharedBackend, state: &mut LoomState) {
+    let nodes: Vec<DrmNode> = {
+        let mut guard = backend.borrow_mut();
+        if guard.libinput.resume().is_err() {
+            error!("Failed to resume libinput");
+        }
+
+        for (node, gpu) in guard.gpus.iter_mut() {
+            if let Err(e) = gpu.drm.activate(false) {
+                error!("Failed to activate GPU {:?}: {}", node, e);
+                continue;
+            }
+
+            // Another DRM master may have changed the hardware state, and
+            // the old buffer contents can't be trusted
+            for output_data in gpu.outputs.values_mut() {
+                if let Err(e) = output_data.compositor.reset_state() {
+                    warn!("Failed to reset output state: {}", e);
+                }
+                output_data.compositor.reset_buffers();
+                // Powered down again after the first frame if still idle
+                output_data.powered_off = false;
+            }
         }
+
+        guard.gpus.keys().copied().collect()
+    };
+
+    // Monitors may have been plugged while we were away
+    for node in nodes {
+        scan_connectors(backend, state, node);
+    }
+
+    queue_redraw_all(backend, state);
+}
+
+/// Switch to another VT
+fn change_vt(backend: &SharedBackend, vt: i32) {
+    info!("Switching to VT {}", vt);
+    if let Err(e) = backend.borrow_mut().session.change_vt(vt) {
+        error!("Failed to switch to VT {}: {}", vt, e);
     }
 }
 
 /// Handle udev events (device hotplug)
-fn handle_udev_event(event: UdevEvent, state: &mut DrmState, loop_handle: &LoopHandle<DrmState>) {
+fn handle_udev_event(backend: &SharedBackend, state: &mut LoomState, event: UdevEvent) {
     match event {
         UdevEvent::Added { device_id, path } => {
             info!("GPU added: {:?}", path);
-            if let Err(e) = init_gpu(state, loop_handle, device_id, &path) {
+            if let Err(e) = init_gpu(backend, state, device_id, &path) {
                 error!("Failed to init hotplugged GPU: {}", e);
             }
         }
         UdevEvent::Changed { device_id } => {
+            // Connectors were plugged or unplugged; while the session is
+            // paused the rescan happens on activation
             debug!("GPU changed: {:?}", device_id);
-            // TODO: Handle connector changes
+            if backend.borrow().session.is_active()
+                && let Ok(node) = DrmNode::from_dev_id(device_id)
+            {
+                scan_connectors(backend, state, node);
+            }
         }
         UdevEvent::Removed { device_id } => {
-            if let Ok(node) = DrmNode::from_dev_id(device_id) {
-                info!("GPU removed: {:?}", node);
-                if let Some(_gpu_data) = state.gpus.remove(&node) {
-                    // Remove associated outputs
-                    // Token is automatically removed when GpuData is dropped
-                }
+            let Ok(node) = DrmNode::from_dev_id(device_id) else {
+                return;
+            };
+
+            info!("GPU removed: {:?}", node);
+            let Some(gpu) = backend.borrow_mut().gpus.remove(&node) else {
+                return;
+            };
+
+            state.loop_handle.remove(gpu.token);
+            for (_, output_data) in gpu.outputs {
+                remove_output(state, output_data);
             }
         }
     }
@@ -485,29 +791,215 @@ fn handle_udev_event(event: UdevEvent, state: &mut DrmState, loop_handle: &LoopH
 
 /// Handle DRM events (page flip, vblank)
 fn handle_drm_event(
+    backend: &SharedBackend,
+    state: &mut LoomState,
+    node: DrmNode,
     event: DrmEvent,
     _metadata: &mut Option<DrmEventMetadata>,
-    state: &mut DrmState,
-    gpu_node: DrmNode,
 ) {
     match event {
         DrmEvent::VBlank(crtc) => {
-            // VBlank occurred, we can submit the next frame
-            if let Some(_output_data) = state.outputs.get_mut(&crtc) {
-                // TODO: Submit pending frame
+            let render = {
+                let mut backend = backend.borrow_mut();
+                let Some(output_data) = backend.output_mut(node, crtc) else {
+                    return;
+                };
+
+                // The queued frame is on screen now
+                if let Err(e) = output_data.compositor.frame_submitted() {
+                    warn!("Failed to mark frame as presented: {}", e);
+                }
+                output_data.scheduler.vblank()
+            };
+
+            if render {
+                render_output(backend, state, node, crtc);
             }
         }
         DrmEvent::Error(e) => {
-            error!("DRM error on {:?}: {}", gpu_node, e);
+            error!("DRM error on {:?}: {}", node, e);
         }
     }
 }
 
-/// Render a single output
-fn render_output(_state: &mut DrmState, _crtc: crtc::Handle) -> Result<()> {
-    // TODO: Implement actual rendering
-    // This requires setting up the DRM compositor with surfaces
-    // and performing the render similar to winit backend
+/// Queue a redraw on every output and render those that are ready
+fn queue_redraw_all(backend: &SharedBackend, state: &mut LoomState) {
+    let ready: Vec<(DrmNode, crtc::Handle)> = {
+        let mut backend = backend.borrow_mut();
+        if !backend.session.is_active() {
+            return;
+        }
 
-    Ok(())
+        backend
+            .gpus
+            .iter_mut()
+            .flat_map(|(node, gpu)| {
+                gpu.outputs
+                    .iter_mut()
+                    .filter(|(_, output_data)| !output_data.disabled && !output_data.powered_off)
+                    .filter_map(|(crtc, output_data)| {
+                        output_data
+                            .scheduler
+                            .queue_redraw()
+                            .then_some((*node, *crtc))
+                    })
+            })
+            .collect()
+    };
+
+    for (node, crtc) in ready {
+        render_output(backend, state, node, crtc);
+    }
+}
+
+/// Render a single output and queue the frame for the next page flip
+fn render_output(
+    backend: &SharedBackend,
+    state: &mut LoomState,
+    node: DrmNode,
+    crtc: crtc::Handle,
+) {
+    let mut guard = backend.borrow_mut();
+    let output_count = guard.output_count();
+    let DrmBackend {
+        session,
+        gpus,
+        frame_timer,
+        frame_count,
+        ..
+    } = &mut *guard;
+
+    if !session.is_active() {
+        return;
+    }
+    let Some(gpu) = gpus.get_mut(&node) else {
+        return;
+    };
+    let Some(output_data) = gpu.outputs.get_mut(&crtc) else {
+        return;
+    };
+    if output_data.disabled || output_data.powered_off || !output_data.scheduler.should_render() {
+        return;
+    }
+
+    frame_timer.begin_frame();
+
+    // Collect render elements from the canvas (viewport transform applied)
+    let elements = render::output_elements(&mut gpu.renderer, &output_data.output, state);
+
+    // Render with damage tracking; empty frames aren't submitted
+    let has_damage = output_data
+        .compositor
+        .render_frame(
+            &mut gpu.renderer,
+            &elements,
+            BACKGROUND_COLOR,
+            FrameFlags::DEFAULT,
+        )
+        .map(|frame| !frame.is_empty);
+
+    let submitted = match has_damage {
+        Ok(true) => match output_data.compositor.queue_frame(()) {
+            Ok(()) => true,
+            Err(e) => {
+                error!("Failed to queue frame: {}", e);
+                output_data.scheduler.frame_failed();
+                return;
+            }
+        },
+        Ok(false) => false,
+        Err(e) => {
+            error!("Failed to render output: {}", e);
+            output_data.scheduler.frame_failed();
+            return;
+        }
+    };
+
+    // Record render time
+    if frame_timer.end_frame() {
+        warn!(
+            "Slow frame: {:?} (target: {:?})",
+            frame_timer.stats().last_frame_time,
+            frame_timer.target_frame_time()
+        );
+    }
+
+    if submitted {
+        output_data.scheduler.frame_submitted();
+    } else {
+        output_data.scheduler.frame_skipped();
+        schedule_estimated_vblank(backend, state, output_data, node, crtc);
+    }
+
+    // Copy what screen capture clients wait for
+    capture::render_captures::<_, GlesTexture>(
+        &mut gpu.renderer,
+        state,
+        &output_data.output,
+        BACKGROUND_COLOR,
+    );
+
+    send_frame_callbacks(state, &output_data.output);
+
+    // Periodic logging
+    *frame_count += 1;
+    if frame_count.is_multiple_of(PERF_LOG_INTERVAL) {
+        let stats = frame_timer.stats();
+        info!(
+            "Performance: avg render: {:?}, stutters: {}, clients: {}, outputs: {}",
+            stats.avg_frame_time,
+            stats.stutter_count,
+            state.client_count(),
+            output_count
+        );
+    }
+}
+
+/// Wake up when the vblank of a skipped frame would have happened
+fn schedule_estimated_vblank(
+    backend: &SharedBackend,
+    state: &LoomState,
+    output_data: &mut OutputData,
+    node: DrmNode,
+    crtc: crtc::Handle,
+) {
+    let shared = backend.clone();
+    let timer = Timer::from_duration(output_data.scheduler.refresh_interval());
+
+    let result = state.loop_handle.insert_source(timer, move |_, _, state| {
+        let render = {
+            let mut backend = shared.borrow_mut();
+            let Some(output_data) = backend.output_mut(node, crtc) else {
+                return TimeoutAction::Drop;
+            };
+            output_data.estimated_vblank = None;
+            output_data.scheduler.estimated_vblank()
+        };
+
+        if render {
+            render_output(&shared, state, node, crtc);
+        }
+        TimeoutAction::Drop
+    });
+
+    match result {
+        Ok(token) => output_data.estimated_vblank = Some(token),
+        Err(e) => {
+            warn!("Failed to schedule estimated vblank: {}", e);
+            output_data.scheduler.frame_failed();
+        }
+    }
+}
+
+/// Send frame callbacks to the windows, layer and lock surfaces shown on an
+/// output
+fn send_frame_callbacks(state: &mut LoomState, output: &Output) {
+    let time = state.clock.now();
+    state.space.elements().for_each(|window| {
+        window.send_frame(output, time, Some(Duration::ZERO), |_, _| {
+            Some(output.clone())
+        });
+    });
+    state.send_layer_frames(output, time);
+    state.send_lock_frames(output, time);
 }
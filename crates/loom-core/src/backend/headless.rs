@@ -0,0 +1,612 @@
+//! Headless backend for CI and integration tests
+//!
+//! This backend runs the full compositor event loop without a GPU or a
+//! host display. Outputs are virtual, rendered on the CPU with Smithay's
+//! pixman renderer into offscreen buffers, and frames can be read back
+//! or dumped to PNG.
+//!
+//! # Usage in tests
+//!
+//! ```ignore
+//! let mut backend = HeadlessBackend::new(HeadlessConfig::default())?;
+//! let stream = backend.connect_client()?; // or connect to backend.socket_name()
+//! backend.dispatch(Some(Duration::from_millis(10)))?;
+//! backend.render()?;
+//! let frame = backend.frame(0)?;
+//! assert_eq!(frame.pixel(10, 10), Some([25, 25, 25, 255]));
+//! ```
+//!
+//! # Environment
+//!
+//! When started through [`run`] (`LOOM_BACKEND=headless`), the outputs are
+//! read from `LOOM_HEADLESS_OUTPUTS` as a comma separated list of sizes,
+//! e.g. `1920x1080,1280x720`.
+
+use crate::capture;
+use crate::perf::{FrameTimer, TARGET_FRAME_TIME_60FPS};
+use crate::render;
+use crate::state::{ClientState, LoomState};
+use crate::{CoreError, Result};
+use loom_config::Config;
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            Bind, ExportMem, Offscreen, damage::OutputDamageTracker, pixman::PixmanRenderer,
+        },
+    },
+    output::{Mode, Output, PhysicalProperties, Subpixel},
+    reexports::{calloop::EventLoop, pixman::Image, wayland_server::Display},
+    utils::{Buffer, Physical, Rectangle, Size, Transform},
+};
+use std::{io::BufWriter, os::unix::net::UnixStream, path::Path, sync::Arc, time::Duration};
+use tracing::{debug, error, info, warn};
+
+/// Background color (dark gray) - RGBA as f32 [0.0, 1.0]
+const BACKGROUND_COLOR: [f32; 4] = [0.1, 0.1, 0.1, 1.0];
+
+/// Environment variable holding the virtual output sizes for [`run`]
+pub const OUTPUTS_ENV_VAR: &str = "LOOM_HEADLESS_OUTPUTS";
+
+/// Default virtual output size
+const DEFAULT_OUTPUT_SIZE: (i32, i32) = (1920, 1080);
+
+/// Maximum number of virtual outputs (keeps CPU rendering cheap)
+const MAX_OUTPUTS: usize = 8;
+
+/// Pixel format of the offscreen buffers (R, G, B, A byte order)
+const BUFFER_FORMAT: Fourcc = Fourcc::Abgr8888;
+
+/// Log performance stats every N frames
+const PERF_LOG_INTERVAL: u64 = 300;
+
+/// Configuration for the headless backend
+#[derive(Debug, Clone)]
+pub struct HeadlessConfig {
+    /// Size of each virtual output in pixels
+    pub outputs: Vec<Size<i32, Physical>>,
+    /// Refresh rate advertised for the virtual outputs (in mHz)
+    pub refresh: i32,
+    /// Whether to create a listening socket in `XDG_RUNTIME_DIR`
+    ///
+    /// Tests that connect clients through [`HeadlessBackend::connect_client`]
+    /// can disable this to run without a runtime directory.
+    pub create_socket: bool,
+    /// Compositor configuration
+    pub settings: Config,
+}
+
+impl Default for HeadlessConfig {
+    fn default() -> Self {
+        Self {
+            outputs: vec![DEFAULT_OUTPUT_SIZE.into()],
+            refresh: 60_000,
+            create_socket: true,
+            settings: Config::default(),
+        }
+    }
+}
+
+impl HeadlessConfig {
+    /// Build a config from [`OUTPUTS_ENV_VAR`], falling back to defaults
+    pub fn from_env(settings: &Config) -> Self {
+        let mut config = Self {
+            settings: settings.clone(),
+            ..Self::default()
+        };
+
+        if let Ok(spec) = std::env::var(OUTPUTS_ENV_VAR) {
+            match parse_output_sizes(&spec) {
+                Ok(outputs) => config.outputs = outputs,
+                Err(e) => warn!("Ignoring {}: {}", OUTPUTS_ENV_VAR, e),
+            }
+        }
+
+        config
+    }
+
+    /// Use a single virtual output of the given size
+    pub fn with_output_size(mut self, width: i32, height: i32) -> Self {
+        self.outputs = vec![(width, height).into()];
+        self
+    }
+
+    /// Config of the tests: a single output of the given size and no
+    /// listening socket
+    #[cfg(test)]
+    pub(crate) fn for_tests(width: i32, height: i32) -> Self {
+        Self {
+            create_socket: false,
+            ..Self::default()
+        }
+        .with_output_size(width, height)
+    }
+}
+
+/// Parse a list of output sizes like `1920x1080,1280x720`
+pub fn parse_output_sizes(spec: &str) -> Result<Vec<Size<i32, Physical>>> {
+    let mut sizes = Vec::new();
+
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (w, h) = entry
+            .split_once('x')
+            .ok_or_else(|| CoreError::BackendInit(format!("Invalid output size: {entry}")))?;
+
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<i32>()
+                .ok()
+                .filter(|v| (1..=crate::security::MAX_BUFFER_WIDTH as i32).contains(v))
+                .ok_or_else(|| CoreError::BackendInit(format!("Invalid output size: {entry}")))
+        };
+
+        sizes.push((parse(w)?, parse(h)?).into());
+    }
+
+    if sizes.is_empty() {
+        return Err(CoreError::BackendInit("No output sizes given".to_string()));
+    }
+    if sizes.len() > MAX_OUTPUTS {
+        return Err(CoreError::BackendInit(format!(
+            "Too many outputs (max {MAX_OUTPUTS})"
+        )));
+    }
+
+    Ok(sizes)
+}
+
+/// A frame read back from a virtual output
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// Width in pixels
+    pub width: u32,
+    /// Height in pixels
+    pub height: u32,
+    /// Pixel data, tightly packed RGBA8 rows
+    pub data: Vec<u8>,
+}
+
+impl Frame {
+    /// Get the RGBA value of a pixel
+    pub fn pixel(&self, x: u32, y: u32) -> Option<[u8; 4]> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        let offset = ((y * self.width + x) * 4) as usize;
+        self.data
+            .get(offset..offset + 4)
+            .map(|p| [p[0], p[1], p[2], p[3]])
+    }
+
+    /// Encode the frame as a PNG file
+    pub fn write_png(&self, path: &Path) -> Result<()> {
+        let file = std::fs::File::create(path)
+            .map_err(|e| CoreError::Renderer(format!("Failed to create {path:?}: {e}")))?;
+
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.data))
+            .map_err(|e| CoreError::Renderer(format!("Failed to encode PNG: {e}")))
+    }
+}
+
+/// A virtual output and its offscreen buffer
+struct HeadlessOutput {
+    /// The Smithay output
+    output: Output,
+    /// Offscreen buffer the output is rendered into
+    buffer: Image<'static, 'static>,
+    /// Damage tracker for the output
+    damage_tracker: OutputDamageTracker,
+}
+
+/// Headless compositor instance
+///
+/// Owns the event loop, display and compositor state so tests can drive
+/// the compositor step by step.
+pub struct HeadlessBackend {
+    /// Event loop driving all sources
+    event_loop: EventLoop<'static, LoomState>,
+    /// Wayland display (clients are dispatched from here)
+    display: Display<LoomState>,
+    /// Compositor state
+    state: LoomState,
+    /// CPU renderer
+    renderer: PixmanRenderer,
+    /// Virtual outputs
+    outputs: Vec<HeadlessOutput>,
+    /// Frame timer
+    frame_timer: FrameTimer,
+    /// Frame counter
+    frame_count: u64,
+}
+
+impl HeadlessBackend {
+    /// Create a headless compositor with the given virtual outputs
+    pub fn new(config: HeadlessConfig) -> Result<Self> {
+        if config.outputs.is_empty() || config.outputs.len() > MAX_OUTPUTS {
+            return Err(CoreError::BackendInit(format!(
+                "Headless backend needs 1 to {MAX_OUTPUTS} outputs"
+            )));
+        }
+
+        let event_loop: EventLoop<LoomState> =
+            EventLoop::try_new().map_err(|e| CoreError::EventLoop(e.to_string()))?;
+
+        let mut display: Display<LoomState> = Display::new()
+            .map_err(|e| CoreError::BackendInit(format!("Failed to create display: {e}")))?;
+
+        let mut state = LoomState::new(&display, event_loop.handle(), &config.settings)
+            .map_err(|e| CoreError::BackendInit(format!("Failed to create state: {e}")))?;
+
+        if config.create_socket {
+            state
+                .register_socket(&mut display)
+                .map_err(|e| CoreError::BackendInit(format!("Failed to register socket: {e}")))?;
+        }
+
+        let mut renderer = PixmanRenderer::new()
+            .map_err(|e| CoreError::Renderer(format!("Failed to create pixman renderer: {e}")))?;
+
+        // Lay the outputs out left to right
+        let mut outputs = Vec::with_capacity(config.outputs.len());
+        for (index, size) in config.outputs.iter().copied().enumerate() {
+            let output = create_output(index, size, config.refresh);
+            let position = state.next_output_position(&output.name());
+            output.change_current_state(None, None, None, Some(position));
+            output.create_global::<LoomState>(&state.display_handle);
+            state.space.map_output(&output, position);
+
+            let buffer_size: Size<i32, Buffer> = (size.w, size.h).into();
+            let buffer = renderer
+                .create_buffer(BUFFER_FORMAT, buffer_size)
+                .map_err(|e| CoreError::Renderer(format!("Failed to create buffer: {e}")))?;
+
+            let damage_tracker = OutputDamageTracker::from_output(&output);
+            debug!(
+                "Virtual output created: {} ({}x{})",
+                output.name(),
+                size.w,
+                size.h
+            );
+
+            outputs.push(HeadlessOutput {
+                output,
+                buffer,
+                damage_tracker,
+            });
+        }
+
+        state.update_views();
+
+        info!(
+            "Headless backend initialized with {} output(s)",
+            outputs.len()
+        );
+
+        Ok(Self {
+            event_loop,
+            display,
+            state,
+            renderer,
+            outputs,
+            frame_timer: FrameTimer::new(),
+            frame_count: 0,
+        })
+    }
+
+    /// Backend of the tests, see [`HeadlessConfig::for_tests`]
+    #[cfg(test)]
+    pub(crate) fn for_tests(width: i32, height: i32) -> Self {
+        Self::new(HeadlessConfig::for_tests(width, height))
+            .expect("Failed to create headless backend")
+    }
+
+    /// Compositor state
+    pub fn state(&self) -> &LoomState {
+        &self.state
+    }
+
+    /// Mutable compositor state
+    pub fn state_mut(&mut self) -> &mut LoomState {
+        &mut self.state
+    }
+
+    /// Name of the listening socket, if one was created
+    pub fn socket_name(&self) -> Option<&str> {
+        self.state.socket_name.as_deref()
+    }
+
+    /// Iterate over the virtual outputs
+    pub fn outputs(&self) -> impl Iterator<Item = &Output> {
+        self.outputs.iter().map(|o| &o.output)
+    }
+
+    /// Connect an in-process client over a socket pair
+    ///
+    /// Returns the client end of the connection. This does not need a
+    /// listening socket, which makes it convenient in sandboxed CI.
+    pub fn connect_client(&mut self) -> Result<UnixStream> {
+        let (server, client) = UnixStream::pair()
+            .map_err(|e| CoreError::BackendInit(format!("Failed to create socket pair: {e}")))?;
+
+        let client_state = Arc::new(ClientState::new(&server));
+        self.state
+            .display_handle
+            .insert_client(server, client_state)
+            .map_err(|e| CoreError::BackendInit(format!("Failed to insert client: {e}")))?;
+
+        Ok(client)
+    }
+
+    /// Dispatch pending events and client requests, then flush replies
+    pub fn dispatch(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.event_loop
+            .dispatch(timeout, &mut self.state)
+            .map_err(|e| CoreError::EventLoop(format!("Event loop error: {e}")))?;
+
+        self.display
+            .dispatch_clients(&mut self.state)
+            .map_err(|e| CoreError::EventLoop(format!("Dispatch error: {e}")))?;
+        self.state.refresh();
+
+        self.display.flush_clients().ok();
+        Ok(())
+    }
+
+    /// Render all virtual outputs and send frame callbacks
+    pub fn render(&mut self) -> Result<()> {
+        for index in 0..self.outputs.len() {
+            self.render_output(index)?;
+        }
+
+        self.display.flush_clients().ok();
+        Ok(())
+    }
+
+    /// Read back the last rendered frame of an output
+    pub fn frame(&mut self, index: usize) -> Result<Frame> {
+        let HeadlessOutput { output, buffer, .. } = self
+            .outputs
+            .get_mut(index)
+            .ok_or_else(|| CoreError::Renderer(format!("No output with index {index}")))?;
+
+        let size = output_size(output);
+        let region = Rectangle::from_size((size.w, size.h).into());
+
+        let framebuffer = self
+            .renderer
+            .bind(buffer)
+            .map_err(|e| CoreError::Renderer(format!("Failed to bind buffer: {e}")))?;
+
+        let mapping = self
+            .renderer
+            .copy_framebuffer(&framebuffer, region, BUFFER_FORMAT)
+            .map_err(|e| CoreError::Renderer(format!("Failed to copy framebuffer: {e}")))?;
+
+        let data = self
+            .renderer
+            .map_texture(&mapping)
+            .map_err(|e| CoreError::Renderer(format!("Failed to map framebuffer: {e}")))?
+            .to_vec();
+
+        Ok(Frame {
+            width: size.w as u32,
+            height: size.h as u32,
+            data,
+        })
+    }
+
+    /// Dump the last rendered frame of an output to a PNG file
+    pub fn save_png(&mut self, index: usize, path: &Path) -> Result<()> {
+        self.frame(index)?.write_png(path)
+    }
+
+    /// Render a single output into its offscreen buffer
+    fn render_output(&mut self, index: usize) -> Result<()> {
+        let HeadlessOutput {
+            output,
+            buffer,
+            damage_tracker,
+        } = &mut self.outputs[index];
+
+        let elements = render::output_elements(&mut self.renderer, output, &self.state);
+
+        let mut framebuffer = self
+            .renderer
+            .bind(buffer)
+            .map_err(|e| CoreError::Renderer(format!("Failed to bind buffer: {e}")))?;
+
+        damage_tracker
+            .render_output(
+                &mut self.renderer,
+                &mut framebuffer,
+                0, // Offscreen buffers are not swapped, always redraw fully
+                &elements,
+                BACKGROUND_COLOR,
+            )
+            .map_err(|e| CoreError::Renderer(format!("Render output failed: {e:?}")))?;
+
+        // Copy what screen capture clients wait for
+        capture::render_captures::<_, Image<'static, 'static>>(
+            &mut self.renderer,
+            &mut self.state,
+            output,
+            BACKGROUND_COLOR,
+        );
+
+        // Send frame callbacks to clients
+        let time = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        self.state.space.elements().for_each(|window| {
+            window.send_frame(output, time, Some(Duration::ZERO), |_, _| {
+                Some(output.clone())
+            });
+        });
+        self.state.send_layer_frames(output, time);
+        self.state.send_lock_frames(output, time);
+
+        Ok(())
+    }
+}
+
+/// Run the compositor using the headless backend
+pub fn run(config: &Config) -> Result<()> {
+    info!("Starting headless backend...");
+
+    let mut backend = HeadlessBackend::new(HeadlessConfig::from_env(config))?;
+
+    if let Some(socket_name) = backend.socket_name() {
+        info!(
+            "To connect a client, run: WAYLAND_DISPLAY={} <client>",
+            socket_name
+        );
+    }
+
+    while backend.state.running {
+        backend.frame_timer.begin_frame();
+
+        backend.dispatch(Some(TARGET_FRAME_TIME_60FPS))?;
+
+        if let Err(e) = backend.render() {
+            error!("Render error: {}", e);
+        }
+
+        backend.frame_timer.end_frame();
+
+        backend.frame_count += 1;
+        if backend.frame_count.is_multiple_of(PERF_LOG_INTERVAL) {
+            let stats = backend.frame_timer.stats();
+            info!(
+                "Performance: {:.1} FPS, avg frame: {:?}, clients: {}",
+                stats.fps,
+                stats.avg_frame_time,
+                backend.state.client_count()
+            );
+        }
+    }
+
+    info!("Headless backend shutting down");
+    Ok(())
+}
+
+/// Create a virtual output
+fn create_output(index: usize, size: Size<i32, Physical>, refresh: i32) -> Output {
+    let mode = Mode { size, refresh };
+
+    let physical_properties = PhysicalProperties {
+        size: (0, 0).into(), // Virtual outputs have no physical size
+        subpixel: Subpixel::Unknown,
+        make: "LoomWM".into(),
+        model: "Headless".into(),
+    };
+
+    let output = Output::new(format!("headless-{index}"), physical_properties);
+    output.change_current_state(Some(mode), Some(Transform::Normal), None, None);
+    output.set_preferred(mode);
+
+    output
+}
+
+/// Current pixel size of an output
+fn output_size(output: &Output) -> Size<i32, Physical> {
+    output
+        .current_mode()
+        .map(|mode| mode.size)
+        .unwrap_or_else(|| DEFAULT_OUTPUT_SIZE.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::test_client::TestClient;
+    use wayland_client::protocol::wl_shm::WlShm;
+
+    #[test]
+    fn test_parse_output_sizes() {
+        let sizes = parse_output_sizes("1920x1080, 1280x720").unwrap();
+        assert_eq!(
+            sizes,
+            vec![Size::from((1920, 1080)), Size::from((1280, 720))]
+        );
+
+        assert!(parse_output_sizes("").is_err());
+        assert!(parse_output_sizes("1920").is_err());
+        assert!(parse_output_sizes("0x1080").is_err());
+        assert!(parse_output_sizes("99999x1080").is_err());
+    }
+
+    #[test]
+    fn test_renders_background() {
+        let mut backend = HeadlessBackend::for_tests(64, 48);
+        backend.dispatch(Some(Duration::ZERO)).unwrap();
+        backend.render().unwrap();
+
+        let frame = backend.frame(0).unwrap();
+        assert_eq!((frame.width, frame.height), (64, 48));
+
+        let [r, g, b, a] = frame.pixel(32, 24).unwrap();
+        assert_eq!(a, 255);
+        assert!(r == g && g == b);
+        assert!((24..=27).contains(&r));
+        assert_eq!(frame.pixel(64, 0), None);
+    }
+
+    #[test]
+    fn test_independent_output_views() {
+        let config = HeadlessConfig {
+            outputs: vec![(64, 48).into(), (32, 48).into()],
+            ..HeadlessConfig::for_tests(64, 48)
+        };
+        let mut backend = HeadlessBackend::new(config).unwrap();
+        let state = backend.state_mut();
+        assert_eq!(state.views.len(), 2);
+
+        // The second output continues the canvas right of the first
+        let right_edge = state.global_to_canvas((63.0, 10.0).into());
+        let next = state.global_to_canvas((64.0, 10.0).into());
+        assert_eq!(next.x - right_edge.x, 1.0);
+
+        // Panning over the second output leaves the first one alone
+        let left = state.global_to_canvas((10.0, 10.0).into());
+        state.pan_view_at((80.0, 10.0).into(), 20.0, 0.0);
+        assert_eq!(state.global_to_canvas((10.0, 10.0).into()), left);
+        assert_eq!(state.global_to_canvas((64.0, 10.0).into()).x, next.x - 20.0);
+    }
+
+    #[test]
+    fn test_connect_client() {
+        let mut backend = HeadlessBackend::for_tests(64, 48);
+        let _client = backend.connect_client().unwrap();
+        backend.dispatch(Some(Duration::ZERO)).unwrap();
+    }
+
+    #[test]
+    fn test_redraw_only_on_changes() {
+        let mut backend = HeadlessBackend::for_tests(64, 48);
+        let mut client = TestClient::connect(&mut backend);
+        let shm: WlShm = client.bind(1);
+        let buffer = client.shm_buffer(&shm, 20, 20, 0xff00ff00);
+        let (surface, _, _) = client.map_toplevel(&mut backend, &buffer);
+
+        // Requests that don't change anything on screen
+        backend.state_mut().redraw_needed = false;
+        client.roundtrip(&mut backend).unwrap();
+        assert!(!backend.state().redraw_needed);
+
+        surface.commit();
+        client.roundtrip(&mut backend).unwrap();
+        assert!(backend.state().redraw_needed);
+
+        let state = backend.state_mut();
+        state.redraw_needed = false;
+        state.pan_view_at((10.0, 10.0).into(), 5.0, 0.0);
+        assert!(state.redraw_needed);
+    }
+}
@@ -3,18 +3,41 @@
 //! Supports:
 //! - DRM/KMS for real hardware (TTY) - enabled with `drm` feature
 //! - Winit for development (nested in X11/Wayland) - enabled with `winit` feature
+//! - Headless for CI and integration tests (CPU rendering, no display) -
+//!   enabled with `headless` feature
 
 use crate::{CoreError, Result};
+use loom_config::Config;
 
-#[cfg(any(feature = "backend-drm", feature = "backend-winit"))]
+#[cfg(any(
+    feature = "backend-drm",
+    feature = "backend-winit",
+    feature = "backend-headless"
+))]
 use tracing::info;
 
 #[cfg(feature = "backend-drm")]
 pub mod drm;
 
+#[cfg(feature = "backend-headless")]
+pub mod headless;
+
+pub mod scheduler;
+
+#[cfg(all(test, feature = "backend-headless"))]
+pub(crate) mod test_client;
+
+#[cfg(all(test, feature = "backend-headless"))]
+pub(crate) mod test_input;
+
 #[cfg(feature = "backend-winit")]
 pub mod winit;
 
+/// Environment variable used to force a specific backend
+///
+/// Currently only `headless` is recognized, since it is never auto-detected.
+pub const BACKEND_ENV_VAR: &str = "LOOM_BACKEND";
+
 /// Available backend types
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BackendType {
@@ -24,11 +47,21 @@ pub enum BackendType {
     /// Winit - for development/testing in a window
     #[cfg(feature = "backend-winit")]
     Winit,
+    /// Headless - virtual outputs rendered on the CPU, for CI and tests
+    #[cfg(feature = "backend-headless")]
+    Headless,
 }
 
 impl BackendType {
     /// Auto-detect the best backend for the current environment
     pub fn autodetect() -> Result<Self> {
+        // Headless is only used when explicitly requested
+        #[cfg(feature = "backend-headless")]
+        if std::env::var(BACKEND_ENV_VAR).is_ok_and(|backend| backend == "headless") {
+            info!("{}=headless set, using headless backend", BACKEND_ENV_VAR);
+            return Ok(BackendType::Headless);
+        }
+
         // If we're running inside an existing display server, prefer Winit
         #[cfg(feature = "backend-winit")]
         if std::env::var("DISPLAY").is_ok() || std::env::var("WAYLAND_DISPLAY").is_ok() {
@@ -49,19 +82,22 @@ impl BackendType {
     }
 }
 
-/// Run the compositor with the specified backend
-pub fn run(backend: BackendType) -> Result<()> {
+/// Run the compositor with the specified backend and configuration
+pub fn run(backend: BackendType, config: &Config) -> Result<()> {
     match backend {
         #[cfg(feature = "backend-drm")]
-        BackendType::Drm => drm::run(),
+        BackendType::Drm => drm::run(config),
 
         #[cfg(feature = "backend-winit")]
-        BackendType::Winit => winit::run(),
+        BackendType::Winit => winit::run(config),
+
+        #[cfg(feature = "backend-headless")]
+        BackendType::Headless => headless::run(config),
     }
 }
 
 /// Run the compositor with auto-detected backend
-pub fn run_auto() -> Result<()> {
+pub fn run_auto(config: &Config) -> Result<()> {
     let backend = BackendType::autodetect()?;
-    run(backend)
+    run(backend, config)
 }
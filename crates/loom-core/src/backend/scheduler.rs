@@ -0,0 +1,248 @@
+//! Frame scheduling state machine
+//!
+//! Decides when an output renders, independently of any GPU API so it can
+//! be unit tested. A backend feeds it redraw requests, the outcome of each
+//! render, and (estimated) vblanks; the scheduler answers whether the
+//! output should render now.
+//!
+//! ```text
+//!          queue_redraw              frame_submitted
+//!   Idle ───────────────► Queued ───────────────────► WaitingForVBlank
+//!    ▲                      │                               │
+//!    │                      │ frame_skipped                 │ vblank
+//!    │                      ▼                               │
+//!    │       WaitingForEstimatedVBlank                      │
+//!    │                      │ estimated_vblank              │
+//!    └──────────────────────┴───────────────────────────────┘
+//!               (or back to Queued if a redraw came in meanwhile)
+//! ```
+//!
+//! A frame without damage is not submitted, so no page flip happens. The
+//! backend then waits for the time the vblank would have happened before
+//! rendering again, which keeps clients throttled to the refresh rate.
+
+use std::time::Duration;
+
+/// Refresh rate assumed for modes that don't report one (in mHz)
+const FALLBACK_REFRESH: i32 = 60_000;
+
+/// Scheduling state of an output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrameState {
+    /// Nothing to do until a redraw is queued
+    #[default]
+    Idle,
+    /// A redraw is queued and the output may render right away
+    Queued,
+    /// A frame was submitted and is waiting for its page flip
+    WaitingForVBlank {
+        /// A redraw was queued while waiting
+        redraw_needed: bool,
+    },
+    /// Nothing was submitted; waiting until the vblank would have happened
+    WaitingForEstimatedVBlank {
+        /// A redraw was queued while waiting
+        redraw_needed: bool,
+    },
+}
+
+/// Per-output frame scheduler
+#[derive(Debug, Clone)]
+pub struct FrameScheduler {
+    state: FrameState,
+    refresh_interval: Duration,
+}
+
+impl FrameScheduler {
+    /// Create a scheduler for a mode with the given refresh rate (in mHz)
+    pub fn new(refresh: i32) -> Self {
+        let refresh = if refresh > 0 {
+            refresh
+        } else {
+            FALLBACK_REFRESH
+        };
+
+        Self {
+            state: FrameState::Idle,
+            refresh_interval: Duration::from_nanos(1_000_000_000_000 / refresh as u64),
+        }
+    }
+
+    /// Current state
+    #[inline]
+    pub fn state(&self) -> FrameState {
+        self.state
+    }
+
+    /// Time between two vblanks
+    #[inline]
+    pub fn refresh_interval(&self) -> Duration {
+        self.refresh_interval
+    }
+
+    /// Request a new frame
+    ///
+    /// Returns whether the output should render now. While a frame is in
+    /// flight the redraw is remembered and happens on the next vblank.
+    pub fn queue_redraw(&mut self) -> bool {
+        match &mut self.state {
+            FrameState::Idle | FrameState::Queued => {
+                self.state = FrameState::Queued;
+                true
+            }
+            FrameState::WaitingForVBlank { redraw_needed }
+            | FrameState::WaitingForEstimatedVBlank { redraw_needed } => {
+                *redraw_needed = true;
+                false
+            }
+        }
+    }
+
+    /// Whether the output should render now
+    #[inline]
+    pub fn should_render(&self) -> bool {
+        self.state == FrameState::Queued
+    }
+
+    /// A rendered frame was queued for a page flip
+    pub fn frame_submitted(&mut self) {
+        self.state = FrameState::WaitingForVBlank {
+            redraw_needed: false,
+        };
+    }
+
+    /// The frame had no damage and was not submitted
+    ///
+    /// The backend should call [`Self::estimated_vblank`] after
+    /// [`Self::refresh_interval`].
+    pub fn frame_skipped(&mut self) {
+        self.state = FrameState::WaitingForEstimatedVBlank {
+            redraw_needed: false,
+        };
+    }
+
+    /// Rendering or submission failed; the next redraw starts over
+    pub fn frame_failed(&mut self) {
+        self.state = FrameState::Idle;
+    }
+
+    /// The submitted frame was flipped
+    ///
+    /// Returns whether the output should render again right away.
+    pub fn vblank(&mut self) -> bool {
+        match self.state {
+            FrameState::WaitingForVBlank { redraw_needed } => self.finish_frame(redraw_needed),
+            _ => false,
+        }
+    }
+
+    /// The estimated vblank of a skipped frame passed
+    ///
+    /// Returns whether the output should render again right away.
+    pub fn estimated_vblank(&mut self) -> bool {
+        match self.state {
+            FrameState::WaitingForEstimatedVBlank { redraw_needed } => {
+                self.finish_frame(redraw_needed)
+            }
+            _ => false,
+        }
+    }
+
+    /// Forget any frame in flight (e.g. the session was paused)
+    pub fn reset(&mut self) {
+        self.state = FrameState::Idle;
+    }
+
+    fn finish_frame(&mut self, redraw_needed: bool) -> bool {
+        self.state = if redraw_needed {
+            FrameState::Queued
+        } else {
+            FrameState::Idle
+        };
+        redraw_needed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refresh_interval() {
+        assert_eq!(
+            FrameScheduler::new(60_000).refresh_interval(),
+            Duration::from_nanos(16_666_666)
+        );
+        assert_eq!(
+            FrameScheduler::new(144_000).refresh_interval(),
+            Duration::from_nanos(6_944_444)
+        );
+        assert_eq!(
+            FrameScheduler::new(0).refresh_interval(),
+            FrameScheduler::new(FALLBACK_REFRESH).refresh_interval()
+        );
+    }
+
+    #[test]
+    fn test_submit_and_vblank() {
+        let mut scheduler = FrameScheduler::new(60_000);
+        assert!(!scheduler.should_render());
+
+        assert!(scheduler.queue_redraw());
+        assert!(scheduler.should_render());
+
+        scheduler.frame_submitted();
+        assert!(!scheduler.should_render());
+
+        // Nothing new happened: go idle on vblank
+        assert!(!scheduler.vblank());
+        assert_eq!(scheduler.state(), FrameState::Idle);
+    }
+
+    #[test]
+    fn test_redraw_deferred_to_vblank() {
+        let mut scheduler = FrameScheduler::new(60_000);
+        scheduler.queue_redraw();
+        scheduler.frame_submitted();
+
+        // Redraws while a flip is pending don't render immediately
+        assert!(!scheduler.queue_redraw());
+        assert!(!scheduler.queue_redraw());
+        assert!(!scheduler.should_render());
+
+        assert!(scheduler.vblank());
+        assert!(scheduler.should_render());
+    }
+
+    #[test]
+    fn test_skipped_frame_waits_for_estimated_vblank() {
+        let mut scheduler = FrameScheduler::new(60_000);
+        scheduler.queue_redraw();
+        scheduler.frame_skipped();
+
+        assert!(!scheduler.queue_redraw());
+
+        // A real vblank doesn't apply to a skipped frame
+        assert!(!scheduler.vblank());
+        assert!(scheduler.estimated_vblank());
+        assert!(scheduler.should_render());
+
+        scheduler.frame_skipped();
+        assert!(!scheduler.estimated_vblank());
+        assert_eq!(scheduler.state(), FrameState::Idle);
+    }
+
+    #[test]
+    fn test_failure_and_reset() {
+        let mut scheduler = FrameScheduler::new(60_000);
+        scheduler.queue_redraw();
+        scheduler.frame_failed();
+        assert_eq!(scheduler.state(), FrameState::Idle);
+        assert!(scheduler.queue_redraw());
+
+        scheduler.frame_submitted();
+        scheduler.reset();
+        assert_eq!(scheduler.state(), FrameState::Idle);
+        assert!(!scheduler.vblank());
+    }
+}
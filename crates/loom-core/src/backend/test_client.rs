@@ -0,0 +1,650 @@
+//! In-process Wayland clients driving the headless backend in tests
+//!
+//! A [`TestClient`] talks to the compositor over a socket pair from
+//! [`HeadlessBackend::connect_client`], and records the events the tests
+//! look at in its [`ClientData`]. [`TestClient::roundtrip`] dispatches both
+//! sides until the compositor has handled every request sent so far.
+
+use crate::backend::headless::HeadlessBackend;
+use std::{
+    fs::File,
+    io::{ErrorKind, Write},
+    os::{
+        fd::{AsFd, FromRawFd},
+        unix::fs::FileExt,
+    },
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+use wayland_client::{
+    Connection, Dispatch, DispatchError, EventQueue, Proxy, QueueHandle,
+    backend::{WaylandError, protocol::ProtocolError},
+    delegate_noop, event_created_child,
+    protocol::{
+        wl_buffer, wl_callback, wl_compositor, wl_data_device, wl_data_device_manager,
+        wl_data_offer, wl_data_source, wl_output, wl_pointer, wl_registry, wl_seat, wl_shm,
+        wl_shm_pool, wl_surface,
+    },
+};
+use wayland_protocols::{
+    ext::{
+        foreign_toplevel_list::v1::client::{
+            ext_foreign_toplevel_handle_v1::{self, ExtForeignToplevelHandleV1},
+            ext_foreign_toplevel_list_v1::{self, ExtForeignToplevelListV1},
+        },
+        image_capture_source::v1::client::{
+            ext_foreign_toplevel_image_capture_source_manager_v1, ext_image_capture_source_v1,
+        },
+        image_copy_capture::v1::client::{
+            ext_image_copy_capture_frame_v1, ext_image_copy_capture_manager_v1,
+            ext_image_copy_capture_session_v1,
+        },
+        session_lock::v1::client::{
+            ext_session_lock_manager_v1, ext_session_lock_surface_v1, ext_session_lock_v1,
+        },
+    },
+    xdg::shell::client::{xdg_popup, xdg_positioner, xdg_surface, xdg_toplevel, xdg_wm_base},
+};
+use wayland_protocols_wlr::{
+    layer_shell::v1::client::{zwlr_layer_shell_v1, zwlr_layer_surface_v1},
+    screencopy::v1::client::{zwlr_screencopy_frame_v1, zwlr_screencopy_manager_v1},
+};
+
+/// Dispatch rounds after which a roundtrip is considered stuck
+const MAX_ROUNDS: usize = 100;
+
+/// A Wayland client connected to a headless compositor
+pub(crate) struct TestClient {
+    connection: Connection,
+    queue: EventQueue<ClientData>,
+    registry: wl_registry::WlRegistry,
+    /// Events received so far
+    pub data: ClientData,
+}
+
+/// Events received by a test client
+#[derive(Debug, Default)]
+pub(crate) struct ClientData {
+    /// Advertised globals: name, interface and version
+    globals: Vec<(u32, String, u32)>,
+    /// Last configure of a layer surface: serial, width and height
+    pub layer_configure: Option<(u32, u32, u32)>,
+    /// Whether a layer surface was closed
+    pub layer_closed: bool,
+    /// Serial of the last configure of an xdg surface
+    pub xdg_configure: Option<u32>,
+    /// Last configure of an xdg toplevel: width, height and whether it is
+    /// fullscreen
+    pub toplevel_configure: Option<(i32, i32, bool)>,
+    /// Last configure of an xdg popup: x, y, width and height
+    pub popup_configure: Option<(i32, i32, i32, i32)>,
+    /// Token of the last `xdg_popup.repositioned`
+    pub repositioned: Option<u32>,
+    /// Popups that were dismissed
+    pub popups_done: Vec<xdg_popup::XdgPopup>,
+    /// Serial of the last pointer button event
+    pub button_serial: Option<u32>,
+    /// Content a data source sends when asked
+    pub source_content: Vec<u8>,
+    /// Events a data source received, by name
+    pub source_events: Vec<&'static str>,
+    /// Handles of the listed toplevels
+    pub toplevels: Vec<ExtForeignToplevelHandleV1>,
+    /// Size of the buffers a screencopy frame or capture session takes
+    pub capture_size: Option<(u32, u32)>,
+    /// Whether a capture session stopped
+    pub capture_stopped: bool,
+    /// Outcome of the last copy: whether it succeeded
+    pub copied: Option<bool>,
+    /// Whether the session was locked for this client
+    pub locked: bool,
+    /// Whether the lock was refused or ended
+    pub lock_finished: bool,
+    /// Last configure of a lock surface: serial, width and height
+    pub lock_configure: Option<(u32, u32, u32)>,
+}
+
+/// A shm buffer of a test client, filled with one color
+pub(crate) struct ShmBuffer {
+    pub buffer: wl_buffer::WlBuffer,
+    file: File,
+    width: u32,
+}
+
+impl TestClient {
+    /// Connect a client and fetch the globals
+    pub fn connect(backend: &mut HeadlessBackend) -> Self {
+        let stream = backend.connect_client().expect("Failed to connect client");
+        let connection = Connection::from_socket(stream).expect("Failed to create connection");
+        let queue = connection.new_event_queue();
+        let registry = connection.display().get_registry(&queue.handle(), ());
+
+        let mut client = Self {
+            connection,
+            queue,
+            registry,
+            data: ClientData::default(),
+        };
+        client.roundtrip(backend).expect("Failed to fetch globals");
+        client
+    }
+
+    /// Handle to create objects with
+    pub fn handle(&self) -> QueueHandle<ClientData> {
+        self.queue.handle()
+    }
+
+    /// Whether the compositor advertises a global
+    pub fn has_global(&self, interface: &str) -> bool {
+        self.data
+            .globals
+            .iter()
+            .any(|(_, global, _)| global == interface)
+    }
+
+    /// Bind a global, at most at a version
+    pub fn bind<I>(&self, version: u32) -> I
+    where
+        I: Proxy + 'static,
+        ClientData: Dispatch<I, ()>,
+    {
+        let interface = I::interface().name;
+        let &(name, _, advertised) = self
+            .data
+            .globals
+            .iter()
+            .find(|(_, global, _)| global == interface)
+            .unwrap_or_else(|| panic!("No {interface} global"));
+        self.registry
+            .bind(name, version.min(advertised), &self.handle(), ())
+    }
+
+    /// Create an ARGB8888 shm buffer filled with a color
+    pub fn shm_buffer(&self, shm: &wl_shm::WlShm, width: u32, height: u32, argb: u32) -> ShmBuffer {
+        // SAFETY: the name is a valid C string
+        let fd = unsafe { libc::memfd_create(c"loom-test".as_ptr(), libc::MFD_CLOEXEC) };
+        assert!(fd >= 0, "Failed to create shm file");
+        // SAFETY: the descriptor was just created and is owned here only
+        let mut file = unsafe { File::from_raw_fd(fd) };
+        let pixels = argb.to_le_bytes().repeat((width * height) as usize);
+        file.write_all(&pixels).unwrap();
+
+        let pool = shm.create_pool(file.as_fd(), pixels.len() as i32, &self.handle(), ());
+        let buffer = pool.create_buffer(
+            0,
+            width as i32,
+            height as i32,
+            width as i32 * 4,
+            wl_shm::Format::Argb8888,
+            &self.handle(),
+            (),
+        );
+        pool.destroy();
+        ShmBuffer {
+            buffer,
+            file,
+            width,
+        }
+    }
+
+    /// Map an xdg toplevel showing a buffer
+    pub fn map_toplevel(
+        &mut self,
+        backend: &mut HeadlessBackend,
+        buffer: &ShmBuffer,
+    ) -> (
+        wl_surface::WlSurface,
+        xdg_surface::XdgSurface,
+        xdg_toplevel::XdgToplevel,
+    ) {
+        let compositor: wl_compositor::WlCompositor = self.bind(4);
+        let wm_base: xdg_wm_base::XdgWmBase = self.bind(1);
+        let surface = compositor.create_surface(&self.handle(), ());
+        let xdg_surface = wm_base.get_xdg_surface(&surface, &self.handle(), ());
+        let toplevel = xdg_surface.get_toplevel(&self.handle(), ());
+        surface.commit();
+        self.roundtrip(backend).unwrap();
+
+        let serial = self.data.xdg_configure.expect("No initial configure");
+        xdg_surface.ack_configure(serial);
+        surface.attach(Some(&buffer.buffer), 0, 0);
+        surface.commit();
+        self.roundtrip(backend).unwrap();
+        (surface, xdg_surface, toplevel)
+    }
+
+    /// Create a positioner for a popup of a size, anchored to the bottom
+    /// left of a rectangle and growing down and right
+    pub fn positioner(
+        &self,
+        size: (i32, i32),
+        anchor_rect: (i32, i32, i32, i32),
+    ) -> xdg_positioner::XdgPositioner {
+        let wm_base: xdg_wm_base::XdgWmBase = self.bind(3);
+        let positioner = wm_base.create_positioner(&self.handle(), ());
+        positioner.set_size(size.0, size.1);
+        let (x, y, width, height) = anchor_rect;
+        positioner.set_anchor_rect(x, y, width, height);
+        positioner.set_anchor(xdg_positioner::Anchor::BottomLeft);
+        positioner.set_gravity(xdg_positioner::Gravity::BottomRight);
+        positioner
+    }
+
+    /// Map an xdg popup of a parent showing a buffer, with an explicit grab
+    /// for the serial of an input event if given
+    pub fn map_popup(
+        &mut self,
+        backend: &mut HeadlessBackend,
+        parent: &xdg_surface::XdgSurface,
+        positioner: &xdg_positioner::XdgPositioner,
+        buffer: &ShmBuffer,
+        grab: Option<(&wl_seat::WlSeat, u32)>,
+    ) -> (
+        wl_surface::WlSurface,
+        xdg_surface::XdgSurface,
+        xdg_popup::XdgPopup,
+    ) {
+        let compositor: wl_compositor::WlCompositor = self.bind(4);
+        let wm_base: xdg_wm_base::XdgWmBase = self.bind(3);
+        let surface = compositor.create_surface(&self.handle(), ());
+        let xdg_surface = wm_base.get_xdg_surface(&surface, &self.handle(), ());
+        let popup = xdg_surface.get_popup(Some(parent), positioner, &self.handle(), ());
+        if let Some((seat, serial)) = grab {
+            popup.grab(seat, serial);
+        }
+        surface.commit();
+        self.roundtrip(backend).unwrap();
+
+        let serial = self.data.xdg_configure.expect("No initial configure");
+        xdg_surface.ack_configure(serial);
+        surface.attach(Some(&buffer.buffer), 0, 0);
+        surface.commit();
+        self.roundtrip(backend).unwrap();
+        (surface, xdg_surface, popup)
+    }
+
+    /// Send the pending requests and dispatch both sides until the
+    /// compositor has handled them
+    ///
+    /// Fails once the compositor reported a protocol error.
+    pub fn roundtrip(&mut self, backend: &mut HeadlessBackend) -> Result<(), DispatchError> {
+        let done = Arc::new(AtomicBool::new(false));
+        self.connection
+            .display()
+            .sync(&self.queue.handle(), done.clone());
+
+        for _ in 0..MAX_ROUNDS {
+            self.connection.flush()?;
+            backend
+                .dispatch(Some(Duration::ZERO))
+                .expect("Failed to dispatch compositor");
+            if let Some(guard) = self.queue.prepare_read() {
+                match guard.read() {
+                    Ok(_) => {}
+                    Err(WaylandError::Io(e)) if e.kind() == ErrorKind::WouldBlock => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            self.queue.dispatch_pending(&mut self.data)?;
+            if done.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+        }
+        panic!("Roundtrip did not complete");
+    }
+
+    /// Protocol error the compositor reported, if any
+    pub fn protocol_error(&self) -> Option<ProtocolError> {
+        self.connection.protocol_error()
+    }
+}
+
+impl ShmBuffer {
+    /// Color of a pixel, as RGBA like [`Frame::pixel`](super::headless::Frame::pixel)
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let mut bgra = [0; 4];
+        let offset = u64::from((y * self.width + x) * 4);
+        self.file.read_exact_at(&mut bgra, offset).unwrap();
+        [bgra[2], bgra[1], bgra[0], bgra[3]]
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, ()> for ClientData {
+    fn event(
+        data: &mut Self,
+        _: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_registry::Event::Global {
+                name,
+                interface,
+                version,
+            } => data.globals.push((name, interface, version)),
+            wl_registry::Event::GlobalRemove { name } => {
+                data.globals.retain(|(global, _, _)| *global != name);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_callback::WlCallback, Arc<AtomicBool>> for ClientData {
+    fn event(
+        _: &mut Self,
+        _: &wl_callback::WlCallback,
+        event: wl_callback::Event,
+        done: &Arc<AtomicBool>,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_callback::Event::Done { .. } = event {
+            done.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Dispatch<zwlr_layer_surface_v1::ZwlrLayerSurfaceV1, ()> for ClientData {
+    fn event(
+        data: &mut Self,
+        _: &zwlr_layer_surface_v1::ZwlrLayerSurfaceV1,
+        event: zwlr_layer_surface_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_layer_surface_v1::Event::Configure {
+                serial,
+                width,
+                height,
+            } => data.layer_configure = Some((serial, width, height)),
+            zwlr_layer_surface_v1::Event::Closed => data.layer_closed = true,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<xdg_wm_base::XdgWmBase, ()> for ClientData {
+    fn event(
+        _: &mut Self,
+        wm_base: &xdg_wm_base::XdgWmBase,
+        event: xdg_wm_base::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let xdg_wm_base::Event::Ping { serial } = event {
+            wm_base.pong(serial);
+        }
+    }
+}
+
+impl Dispatch<xdg_surface::XdgSurface, ()> for ClientData {
+    fn event(
+        data: &mut Self,
+        _: &xdg_surface::XdgSurface,
+        event: xdg_surface::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let xdg_surface::Event::Configure { serial } = event {
+            data.xdg_configure = Some(serial);
+        }
+    }
+}
+
+impl Dispatch<xdg_toplevel::XdgToplevel, ()> for ClientData {
+    fn event(
+        data: &mut Self,
+        _: &xdg_toplevel::XdgToplevel,
+        event: xdg_toplevel::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let xdg_toplevel::Event::Configure {
+            width,
+            height,
+            states,
+        } = event
+        {
+            let fullscreen = states.chunks_exact(4).any(|state| {
+                u32::from_ne_bytes(state.try_into().unwrap())
+                    == xdg_toplevel::State::Fullscreen as u32
+            });
+            data.toplevel_configure = Some((width, height, fullscreen));
+        }
+    }
+}
+
+impl Dispatch<xdg_popup::XdgPopup, ()> for ClientData {
+    fn event(
+        data: &mut Self,
+        popup: &xdg_popup::XdgPopup,
+        event: xdg_popup::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            xdg_popup::Event::Configure {
+                x,
+                y,
+                width,
+                height,
+            } => data.popup_configure = Some((x, y, width, height)),
+            xdg_popup::Event::Repositioned { token } => data.repositioned = Some(token),
+            xdg_popup::Event::PopupDone => data.popups_done.push(popup.clone()),
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_pointer::WlPointer, ()> for ClientData {
+    fn event(
+        data: &mut Self,
+        _: &wl_pointer::WlPointer,
+        event: wl_pointer::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_pointer::Event::Button { serial, .. } = event {
+            data.button_serial = Some(serial);
+        }
+    }
+}
+
+impl Dispatch<wl_data_source::WlDataSource, ()> for ClientData {
+    fn event(
+        data: &mut Self,
+        _: &wl_data_source::WlDataSource,
+        event: wl_data_source::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let name = match event {
+            wl_data_source::Event::Target { .. } => "target",
+            wl_data_source::Event::Send { fd, .. } => {
+                File::from(fd).write_all(&data.source_content).unwrap();
+                "send"
+            }
+            wl_data_source::Event::Cancelled => "cancelled",
+            wl_data_source::Event::DndDropPerformed => "dnd_drop_performed",
+            wl_data_source::Event::DndFinished => "dnd_finished",
+            wl_data_source::Event::Action { .. } => "action",
+            _ => return,
+        };
+        data.source_events.push(name);
+    }
+}
+
+impl Dispatch<wl_data_device::WlDataDevice, ()> for ClientData {
+    fn event(
+        _: &mut Self,
+        _: &wl_data_device::WlDataDevice,
+        _: wl_data_device::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+
+    event_created_child!(ClientData, wl_data_device::WlDataDevice, [
+        wl_data_device::EVT_DATA_OFFER_OPCODE => (wl_data_offer::WlDataOffer, ()),
+    ]);
+}
+
+impl Dispatch<ExtForeignToplevelListV1, ()> for ClientData {
+    fn event(
+        data: &mut Self,
+        _: &ExtForeignToplevelListV1,
+        event: ext_foreign_toplevel_list_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let ext_foreign_toplevel_list_v1::Event::Toplevel { toplevel } = event {
+            data.toplevels.push(toplevel);
+        }
+    }
+
+    event_created_child!(ClientData, ExtForeignToplevelListV1, [
+        ext_foreign_toplevel_list_v1::EVT_TOPLEVEL_OPCODE => (ExtForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<ExtForeignToplevelHandleV1, ()> for ClientData {
+    fn event(
+        data: &mut Self,
+        handle: &ExtForeignToplevelHandleV1,
+        event: ext_foreign_toplevel_handle_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let ext_foreign_toplevel_handle_v1::Event::Closed = event {
+            data.toplevels.retain(|toplevel| toplevel != handle);
+        }
+    }
+}
+
+impl Dispatch<zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1, ()> for ClientData {
+    fn event(
+        data: &mut Self,
+        _: &zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
+        event: zwlr_screencopy_frame_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            zwlr_screencopy_frame_v1::Event::Buffer { width, height, .. } => {
+                data.capture_size = Some((width, height));
+            }
+            zwlr_screencopy_frame_v1::Event::Ready { .. } => data.copied = Some(true),
+            zwlr_screencopy_frame_v1::Event::Failed => data.copied = Some(false),
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ext_image_copy_capture_session_v1::ExtImageCopyCaptureSessionV1, ()> for ClientData {
+    fn event(
+        data: &mut Self,
+        _: &ext_image_copy_capture_session_v1::ExtImageCopyCaptureSessionV1,
+        event: ext_image_copy_capture_session_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_image_copy_capture_session_v1::Event::BufferSize { width, height } => {
+                data.capture_size = Some((width, height));
+            }
+            ext_image_copy_capture_session_v1::Event::Stopped => data.capture_stopped = true,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ext_image_copy_capture_frame_v1::ExtImageCopyCaptureFrameV1, ()> for ClientData {
+    fn event(
+        data: &mut Self,
+        _: &ext_image_copy_capture_frame_v1::ExtImageCopyCaptureFrameV1,
+        event: ext_image_copy_capture_frame_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_image_copy_capture_frame_v1::Event::Ready => data.copied = Some(true),
+            ext_image_copy_capture_frame_v1::Event::Failed { .. } => data.copied = Some(false),
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ext_session_lock_v1::ExtSessionLockV1, ()> for ClientData {
+    fn event(
+        data: &mut Self,
+        _: &ext_session_lock_v1::ExtSessionLockV1,
+        event: ext_session_lock_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            ext_session_lock_v1::Event::Locked => data.locked = true,
+            ext_session_lock_v1::Event::Finished => data.lock_finished = true,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ext_session_lock_surface_v1::ExtSessionLockSurfaceV1, ()> for ClientData {
+    fn event(
+        data: &mut Self,
+        _: &ext_session_lock_surface_v1::ExtSessionLockSurfaceV1,
+        event: ext_session_lock_surface_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let ext_session_lock_surface_v1::Event::Configure {
+            serial,
+            width,
+            height,
+        } = event
+        {
+            data.lock_configure = Some((serial, width, height));
+        }
+    }
+}
+
+// Objects without events the tests look at
+delegate_noop!(ClientData: ignore wl_compositor::WlCompositor);
+delegate_noop!(ClientData: ignore wl_surface::WlSurface);
+delegate_noop!(ClientData: ignore zwlr_layer_shell_v1::ZwlrLayerShellV1);
+delegate_noop!(ClientData: ignore wl_output::WlOutput);
+delegate_noop!(ClientData: ignore wl_seat::WlSeat);
+delegate_noop!(ClientData: ignore wl_data_device_manager::WlDataDeviceManager);
+delegate_noop!(ClientData: ignore wl_data_offer::WlDataOffer);
+delegate_noop!(ClientData: ignore xdg_positioner::XdgPositioner);
+delegate_noop!(ClientData: ignore wl_shm::WlShm);
+delegate_noop!(ClientData: ignore wl_shm_pool::WlShmPool);
+delegate_noop!(ClientData: ignore wl_buffer::WlBuffer);
+delegate_noop!(ClientData: ignore zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1);
+delegate_noop!(ClientData: ignore ext_foreign_toplevel_image_capture_source_manager_v1::ExtForeignToplevelImageCaptureSourceManagerV1);
+delegate_noop!(ClientData: ignore ext_image_capture_source_v1::ExtImageCaptureSourceV1);
+delegate_noop!(ClientData: ignore ext_image_copy_capture_manager_v1::ExtImageCopyCaptureManagerV1);
+delegate_noop!(ClientData: ignore ext_session_lock_manager_v1::ExtSessionLockManagerV1);
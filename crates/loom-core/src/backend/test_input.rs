@@ -0,0 +1,163 @@
+//! Synthetic pointer input for tests
+//!
+//! [`TestInput`] is an input backend that only produces pointer motion and
+//! button events, fed through [`process_input_event`] like real devices so
+//! that focus, grabs and serials behave as they do in a session.
+
+use crate::{input::process_input_event, state::LoomState};
+use smithay::{
+    backend::input::{
+        ButtonState, Device, DeviceCapability, Event, InputBackend, InputEvent, PointerButtonEvent,
+        PointerMotionEvent, UnusedEvent,
+    },
+    utils::{Logical, Monotonic, Point, Time},
+};
+use std::path::PathBuf;
+
+/// Input backend of the synthetic events
+#[derive(Debug)]
+pub(crate) enum TestInput {}
+
+/// The one pointer device of [`TestInput`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct TestPointer;
+
+/// Relative motion of the test pointer
+pub(crate) struct Motion {
+    time: u64,
+    delta: Point<f64, Logical>,
+}
+
+/// Button press or release of the test pointer
+pub(crate) struct Button {
+    time: u64,
+    button: u32,
+    state: ButtonState,
+}
+
+/// Move the pointer to a global location
+pub(crate) fn move_pointer(state: &mut LoomState, location: Point<f64, Logical>) {
+    let event = Motion {
+        time: now(state),
+        delta: location - state.pointer_location,
+    };
+    process_input_event::<TestInput>(state, InputEvent::PointerMotion { event });
+}
+
+/// Press or release a button (like `BTN_LEFT`)
+pub(crate) fn press_button(state: &mut LoomState, button: u32, pressed: bool) {
+    let event = Button {
+        time: now(state),
+        button,
+        state: if pressed {
+            ButtonState::Pressed
+        } else {
+            ButtonState::Released
+        },
+    };
+    process_input_event::<TestInput>(state, InputEvent::PointerButton { event });
+}
+
+/// Timestamp of a new event, in microseconds
+fn now(state: &LoomState) -> u64 {
+    let time: Time<Monotonic> = state.clock.now();
+    std::time::Duration::from(time).as_micros() as u64
+}
+
+impl InputBackend for TestInput {
+    type Device = TestPointer;
+    type KeyboardKeyEvent = UnusedEvent;
+    type PointerAxisEvent = UnusedEvent;
+    type PointerButtonEvent = Button;
+    type PointerMotionEvent = Motion;
+    type PointerMotionAbsoluteEvent = UnusedEvent;
+    type GestureSwipeBeginEvent = UnusedEvent;
+    type GestureSwipeUpdateEvent = UnusedEvent;
+    type GestureSwipeEndEvent = UnusedEvent;
+    type GesturePinchBeginEvent = UnusedEvent;
+    type GesturePinchUpdateEvent = UnusedEvent;
+    type GesturePinchEndEvent = UnusedEvent;
+    type GestureHoldBeginEvent = UnusedEvent;
+    type GestureHoldEndEvent = UnusedEvent;
+    type TouchDownEvent = UnusedEvent;
+    type TouchUpEvent = UnusedEvent;
+    type TouchMotionEvent = UnusedEvent;
+    type TouchCancelEvent = UnusedEvent;
+    type TouchFrameEvent = UnusedEvent;
+    type TabletToolAxisEvent = UnusedEvent;
+    type TabletToolProximityEvent = UnusedEvent;
+    type TabletToolTipEvent = UnusedEvent;
+    type TabletToolButtonEvent = UnusedEvent;
+    type SwitchToggleEvent = UnusedEvent;
+    type SpecialEvent = ();
+}
+
+impl Device for TestPointer {
+    fn id(&self) -> String {
+        "test-pointer".into()
+    }
+
+    fn name(&self) -> String {
+        "Test pointer".into()
+    }
+
+    fn has_capability(&self, capability: DeviceCapability) -> bool {
+        capability == DeviceCapability::Pointer
+    }
+
+    fn usb_id(&self) -> Option<(u32, u32)> {
+        None
+    }
+
+    fn syspath(&self) -> Option<PathBuf> {
+        None
+    }
+}
+
+impl Event<TestInput> for Motion {
+    fn time(&self) -> u64 {
+        self.time
+    }
+
+    fn device(&self) -> TestPointer {
+        TestPointer
+    }
+}
+
+impl PointerMotionEvent<TestInput> for Motion {
+    fn delta_x(&self) -> f64 {
+        self.delta.x
+    }
+
+    fn delta_y(&self) -> f64 {
+        self.delta.y
+    }
+
+    fn delta_x_unaccel(&self) -> f64 {
+        self.delta.x
+    }
+
+    fn delta_y_unaccel(&self) -> f64 {
+        self.delta.y
+    }
+}
+
+impl Event<TestInput> for Button {
+    fn time(&self) -> u64 {
+        self.time
+    }
+
+    fn device(&self) -> TestPointer {
+        TestPointer
+    }
+}
+
+impl PointerButtonEvent<TestInput> for Button {
+    fn button_code(&self) -> u32 {
+        self.button
+    }
+
+    fn state(&self) -> ButtonState {
+        self.state
+    }
+}
@@ -15,19 +15,18 @@
 //! - Pre-allocated element vector to avoid per-frame allocations
 //! - Frame timing with stutter detection
 
+use crate::capture;
 use crate::input::process_input_event;
 use crate::perf::{FrameTimer, TARGET_FRAME_TIME_60FPS};
+use crate::render;
 use crate::state::LoomState;
 use crate::{CoreError, Result};
+use loom_config::Config;
 use smithay::{
     backend::{
-        renderer::{
-            damage::OutputDamageTracker, element::surface::WaylandSurfaceRenderElement,
-            glow::GlowRenderer,
-        },
+        renderer::{damage::OutputDamageTracker, gles::GlesTexture, glow::GlowRenderer},
         winit::{self, WinitEvent, WinitGraphicsBackend},
     },
-    desktop::space::SpaceRenderElements,
     output::{Mode, Output, PhysicalProperties, Subpixel},
     reexports::{calloop::EventLoop, wayland_server::Display},
     utils::{Physical, Size, Transform},
@@ -42,7 +41,7 @@ const BACKGROUND_COLOR: [f32; 4] = [0.1, 0.1, 0.1, 1.0];
 const PERF_LOG_INTERVAL: u64 = 300; // Every 5 seconds at 60 FPS
 
 /// Run the compositor using the Winit backend
-pub fn run() -> Result<()> {
+pub fn run(config: &Config) -> Result<()> {
     info!("Starting Winit backend...");
 
     // Create the event loop with LoomState as the data type
@@ -50,18 +49,13 @@ pub fn run() -> Result<()> {
         EventLoop::try_new().map_err(|e| CoreError::EventLoop(e.to_string()))?;
 
     // Create Wayland display
-    let display: Display<LoomState> = Display::new()
+    let mut display: Display<LoomState> = Display::new()
         .map_err(|e| CoreError::BackendInit(format!("Failed to create display: {e}")))?;
 
     // Create compositor state
-    let mut state = LoomState::new(display, event_loop.handle())
+    let mut state = LoomState::new(&display, event_loop.handle(), config)
         .map_err(|e| CoreError::BackendInit(format!("Failed to create state: {e}")))?;
 
-    // Create another display for socket registration
-    // (the first one was consumed by LoomState::new)
-    let mut display: Display<LoomState> = Display::new()
-        .map_err(|e| CoreError::BackendInit(format!("Failed to create display: {e}")))?;
-
     // Register Wayland socket
     let socket_name = state
         .register_socket(&mut display)
@@ -79,10 +73,14 @@ pub fn run() -> Result<()> {
 
     // Create output for this backend
     let output = create_output(size);
+    output.create_global::<LoomState>(&state.display_handle);
     debug!("Output created: {:?}", output.name());
 
     // Add output to space
-    state.space.map_output(&output, (0, 0));
+    let position = state.next_output_position(&output.name());
+    output.change_current_state(None, None, None, Some(position));
+    state.space.map_output(&output, position);
+    state.update_views();
 
     // Create damage tracker for efficient rendering
     let mut damage_tracker = OutputDamageTracker::from_output(&output);
@@ -94,10 +92,11 @@ pub fn run() -> Result<()> {
     let mut frame_count: u64 = 0;
 
     // Insert Winit event source into the event loop
+    let winit_output = output.clone();
     event_loop
         .handle()
         .insert_source(winit_event_source, move |event, _, state| {
-            handle_winit_event(event, state);
+            handle_winit_event(event, &winit_output, state);
         })
         .map_err(|e| CoreError::EventLoop(format!("Failed to insert Winit source: {e}")))?;
 
@@ -125,6 +124,7 @@ pub fn run() -> Result<()> {
         display
             .dispatch_clients(&mut state)
             .map_err(|e| CoreError::EventLoop(format!("Dispatch error: {e}")))?;
+        state.refresh();
 
         // Render frame
         if let Err(e) = render_frame(&mut backend, &output, &mut damage_tracker, &mut state) {
@@ -172,14 +172,25 @@ pub fn run() -> Result<()> {
 
 /// Handle Winit window events
 #[inline]
-fn handle_winit_event(event: WinitEvent, state: &mut LoomState) {
+fn handle_winit_event(event: WinitEvent, output: &Output, state: &mut LoomState) {
     match event {
         WinitEvent::Resized { size, scale_factor } => {
             debug!(
                 "Window resized to {}x{} (scale: {})",
                 size.w, size.h, scale_factor
             );
-            // TODO: Update output mode
+
+            // The output follows the window, and its view with it
+            let mode = Mode {
+                size,
+                refresh: 60_000,
+            };
+            output.change_current_state(Some(mode), None, None, None);
+            output.set_preferred(mode);
+            if let Some(position) = state.space.output_geometry(output).map(|g| g.loc) {
+                state.space.map_output(output, position);
+            }
+            state.update_views();
         }
         WinitEvent::Focus(focused) => {
             debug!("Window focus: {}", focused);
@@ -237,14 +248,8 @@ fn render_frame(
     damage_tracker: &mut OutputDamageTracker,
     state: &mut LoomState,
 ) -> Result<()> {
-    // Collect render elements from the space
-    let scale = output.current_scale().fractional_scale() as f32;
-    let elements: Vec<
-        SpaceRenderElements<GlowRenderer, WaylandSurfaceRenderElement<GlowRenderer>>,
-    > = state
-        .space
-        .render_elements_for_output(backend.renderer(), output, scale)
-        .map_err(|e| CoreError::Renderer(format!("Failed to get render elements: {e:?}")))?;
+    // Collect render elements from the canvas (viewport transform applied)
+    let elements = render::output_elements(backend.renderer(), output, state);
 
     // Bind the renderer and get framebuffer
     let (renderer, mut framebuffer) = backend
@@ -271,6 +276,14 @@ fn render_frame(
                 .submit(damage)
                 .map_err(|e| CoreError::Renderer(format!("Failed to submit frame: {e}")))?;
 
+            // Copy what screen capture clients wait for
+            capture::render_captures::<_, GlesTexture>(
+                backend.renderer(),
+                state,
+                output,
+                BACKGROUND_COLOR,
+            );
+
             // Send frame callbacks to clients
             let time = std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
@@ -280,6 +293,8 @@ fn render_frame(
                     Some(output.clone())
                 });
             });
+            state.send_layer_frames(output, time);
+            state.send_lock_frames(output, time);
         }
         Err(e) => {
             warn!("Render output failed: {:?}", e);
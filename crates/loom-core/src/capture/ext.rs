@@ -0,0 +1,469 @@
+//! `ext-image-copy-capture-v1` and `ext-image-capture-source-v1`
+//!
+//! Sources are outputs, and windows through their
+//! `ext-foreign-toplevel-list-v1` handles. Sessions offer shm buffers only.
+//! Every copy after a session's first waits for damage. Cursor sessions
+//! aren't supported: their capture session stops right away.
+
+use super::{
+    CaptureFrame, CaptureManagerData, CaptureSource, PendingCapture, SHM_FORMATS, SessionKey,
+    buffer_fits,
+};
+use crate::state::LoomState;
+use crate::types::WindowId;
+use smithay::{
+    output::Output,
+    reexports::{
+        wayland_protocols::ext::{
+            image_capture_source::v1::server::{
+                ext_foreign_toplevel_image_capture_source_manager_v1::{
+                    self, ExtForeignToplevelImageCaptureSourceManagerV1,
+                },
+                ext_image_capture_source_v1::ExtImageCaptureSourceV1,
+                ext_output_image_capture_source_manager_v1::{
+                    self, ExtOutputImageCaptureSourceManagerV1,
+                },
+            },
+            image_copy_capture::v1::server::{
+                ext_image_copy_capture_cursor_session_v1::{
+                    self, ExtImageCopyCaptureCursorSessionV1,
+                },
+                ext_image_copy_capture_frame_v1::{
+                    self, ExtImageCopyCaptureFrameV1, FailureReason,
+                },
+                ext_image_copy_capture_manager_v1::{self, ExtImageCopyCaptureManagerV1, Options},
+                ext_image_copy_capture_session_v1::{self, ExtImageCopyCaptureSessionV1},
+            },
+        },
+        wayland_server::{
+            Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource, WEnum,
+            backend::ClientId,
+            protocol::{wl_buffer::WlBuffer, wl_output},
+        },
+    },
+    utils::{Physical, Rectangle, Size},
+    wayland::foreign_toplevel_list::ForeignToplevelHandle,
+};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// User data of a capture source, `None` if it doesn't exist
+#[derive(Debug)]
+pub(super) struct SourceData(Option<CaptureSource>);
+
+/// User data of a capture session
+#[derive(Debug)]
+pub(super) struct SessionData {
+    /// Captured source, `None` once the session stopped
+    source: Option<CaptureSource>,
+    /// Size of the source in the last buffer constraints
+    size: Size<i32, Physical>,
+    paint_cursors: bool,
+    /// Whether a frame of the session exists
+    has_frame: bool,
+}
+
+/// User data of a capture frame
+#[derive(Debug)]
+pub(super) struct FrameData {
+    session: ExtImageCopyCaptureSessionV1,
+    buffer: Option<WlBuffer>,
+    captured: bool,
+}
+
+impl GlobalDispatch<ExtOutputImageCaptureSourceManagerV1, ()> for LoomState {
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ExtOutputImageCaptureSourceManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ExtOutputImageCaptureSourceManagerV1, ()> for LoomState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _manager: &ExtOutputImageCaptureSourceManagerV1,
+        request: ext_output_image_capture_source_manager_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let ext_output_image_capture_source_manager_v1::Request::CreateSource {
+            source,
+            output,
+        } = request
+        {
+            let output = Output::from_resource(&output)
+                .map(|output| CaptureSource::Output(output.downgrade()));
+            data_init.init(source, SourceData(output));
+        }
+    }
+}
+
+impl GlobalDispatch<ExtForeignToplevelImageCaptureSourceManagerV1, ()> for LoomState {
+    fn bind(
+        _state: &mut Self,
+        _handle: &DisplayHandle,
+        _client: &Client,
+        resource: New<ExtForeignToplevelImageCaptureSourceManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        data_init.init(resource, ());
+    }
+}
+
+impl Dispatch<ExtForeignToplevelImageCaptureSourceManagerV1, ()> for LoomState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _manager: &ExtForeignToplevelImageCaptureSourceManagerV1,
+        request: ext_foreign_toplevel_image_capture_source_manager_v1::Request,
+        _data: &(),
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let ext_foreign_toplevel_image_capture_source_manager_v1::Request::CreateSource {
+            source,
+            toplevel_handle,
+        } = request
+        {
+            let window = ForeignToplevelHandle::from_resource(&toplevel_handle)
+                .filter(|handle| !handle.is_closed())
+                .and_then(|handle| handle.user_data().get::<WindowId>().copied())
+                .map(CaptureSource::Window);
+            data_init.init(source, SourceData(window));
+        }
+    }
+}
+
+impl Dispatch<ExtImageCaptureSourceV1, SourceData> for LoomState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        _source: &ExtImageCaptureSourceV1,
+        _request: <ExtImageCaptureSourceV1 as Resource>::Request,
+        _data: &SourceData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+    }
+}
+
+impl GlobalDispatch<ExtImageCopyCaptureManagerV1, ()> for LoomState {
+    fn bind(
+        state: &mut Self,
+        _handle: &DisplayHandle,
+        client: &Client,
+        resource: New<ExtImageCopyCaptureManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let allowed = state.capture_allowed(client);
+        data_init.init(resource, CaptureManagerData { allowed });
+    }
+}
+
+impl Dispatch<ExtImageCopyCaptureManagerV1, CaptureManagerData> for LoomState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        manager: &ExtImageCopyCaptureManagerV1,
+        request: ext_image_copy_capture_manager_v1::Request,
+        data: &CaptureManagerData,
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        match request {
+            ext_image_copy_capture_manager_v1::Request::CreateSession {
+                session,
+                source,
+                options,
+            } => {
+                let source = source
+                    .data::<SourceData>()
+                    .and_then(|source| source.0.clone())
+                    .filter(|_| data.allowed);
+                let size = source
+                    .as_ref()
+                    .and_then(|source| state.capture_source_size(source))
+                    .map(|(size, _)| size);
+                let paint_cursors = match options {
+                    WEnum::Value(options) => options.contains(Options::PaintCursors),
+                    WEnum::Unknown(_) => false,
+                };
+
+                let session = data_init.init(
+                    session,
+                    Mutex::new(SessionData {
+                        source: size.and(source),
+                        size: size.unwrap_or_default(),
+                        paint_cursors,
+                        has_frame: false,
+                    }),
+                );
+                if let WEnum::Unknown(options) = options {
+                    manager.post_error(
+                        ext_image_copy_capture_manager_v1::Error::InvalidOption,
+                        format!("unknown options {options:#x}"),
+                    );
+                    return;
+                }
+
+                match size {
+                    Some(size) => send_constraints(&session, size),
+                    None => session.stopped(),
+                }
+            }
+            ext_image_copy_capture_manager_v1::Request::CreatePointerCursorSession {
+                session,
+                ..
+            } => {
+                data_init.init(session, AtomicBool::new(false));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ExtImageCopyCaptureCursorSessionV1, AtomicBool> for LoomState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        cursor_session: &ExtImageCopyCaptureCursorSessionV1,
+        request: ext_image_copy_capture_cursor_session_v1::Request,
+        has_session: &AtomicBool,
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let ext_image_copy_capture_cursor_session_v1::Request::GetCaptureSession { session } =
+            request
+        {
+            let session = data_init.init(
+                session,
+                Mutex::new(SessionData {
+                    source: None,
+                    size: Size::default(),
+                    paint_cursors: false,
+                    has_frame: false,
+                }),
+            );
+            if has_session.swap(true, Ordering::Relaxed) {
+                cursor_session.post_error(
+                    ext_image_copy_capture_cursor_session_v1::Error::DuplicateSession,
+                    "capture session already created",
+                );
+                return;
+            }
+            session.stopped();
+        }
+    }
+}
+
+impl Dispatch<ExtImageCopyCaptureSessionV1, Mutex<SessionData>> for LoomState {
+    fn request(
+        _state: &mut Self,
+        _client: &Client,
+        session: &ExtImageCopyCaptureSessionV1,
+        request: ext_image_copy_capture_session_v1::Request,
+        data: &Mutex<SessionData>,
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let ext_image_copy_capture_session_v1::Request::CreateFrame { frame } = request {
+            data_init.init(
+                frame,
+                Mutex::new(FrameData {
+                    session: session.clone(),
+                    buffer: None,
+                    captured: false,
+                }),
+            );
+
+            let Ok(mut data) = data.lock() else {
+                return;
+            };
+            if data.has_frame {
+                session.post_error(
+                    ext_image_copy_capture_session_v1::Error::DuplicateFrame,
+                    "the session already has a frame",
+                );
+                return;
+            }
+            data.has_frame = true;
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        session: &ExtImageCopyCaptureSessionV1,
+        _data: &Mutex<SessionData>,
+    ) {
+        state.capture.end_session(&session.id());
+    }
+}
+
+impl Dispatch<ExtImageCopyCaptureFrameV1, Mutex<FrameData>> for LoomState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        frame: &ExtImageCopyCaptureFrameV1,
+        request: ext_image_copy_capture_frame_v1::Request,
+        data: &Mutex<FrameData>,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let Ok(mut data) = data.lock() else {
+            return;
+        };
+        if data.captured && !matches!(request, ext_image_copy_capture_frame_v1::Request::Destroy) {
+            frame.post_error(
+                ext_image_copy_capture_frame_v1::Error::AlreadyCaptured,
+                "frame already captured",
+            );
+            return;
+        }
+
+        match request {
+            ext_image_copy_capture_frame_v1::Request::AttachBuffer { buffer } => {
+                data.buffer = Some(buffer);
+            }
+            // Valid damage is ignored, the whole buffer is copied anyway
+            ext_image_copy_capture_frame_v1::Request::DamageBuffer {
+                x,
+                y,
+                width,
+                height,
+            } if x < 0 || y < 0 || width <= 0 || height <= 0 => {
+                frame.post_error(
+                    ext_image_copy_capture_frame_v1::Error::InvalidBufferDamage,
+                    "invalid buffer damage",
+                );
+            }
+            ext_image_copy_capture_frame_v1::Request::Capture => {
+                let Some(buffer) = data.buffer.clone() else {
+                    frame.post_error(
+                        ext_image_copy_capture_frame_v1::Error::NoBuffer,
+                        "no buffer attached",
+                    );
+                    return;
+                };
+                data.captured = true;
+
+                let Some((source, size, paint_cursor)) = data
+                    .session
+                    .data::<Mutex<SessionData>>()
+                    .and_then(|session| session.lock().ok())
+                    .and_then(|session| {
+                        let source = session.source.clone()?;
+                        Some((source, session.size, session.paint_cursors))
+                    })
+                else {
+                    frame.failed(FailureReason::Stopped);
+                    return;
+                };
+                if !buffer_fits(&buffer, size) {
+                    frame.failed(FailureReason::BufferConstraints);
+                    return;
+                }
+
+                state.capture.queue(PendingCapture {
+                    frame: CaptureFrame::Ext(frame.clone()),
+                    source,
+                    buffer,
+                    size,
+                    region: Rectangle::from_size(size),
+                    paint_cursor,
+                    with_damage: true,
+                    session: SessionKey::Ext(data.session.id()),
+                });
+                // Copied after the next render
+                state.queue_redraw();
+            }
+            _ => {}
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        frame: &ExtImageCopyCaptureFrameV1,
+        data: &Mutex<FrameData>,
+    ) {
+        state.capture.cancel(&CaptureFrame::Ext(frame.clone()));
+
+        let session = data.lock().ok().map(|data| data.session.clone());
+        if let Some(session) = session
+            && let Some(Ok(mut session)) = session
+                .data::<Mutex<SessionData>>()
+                .map(|session| session.lock())
+        {
+            session.has_frame = false;
+        }
+    }
+}
+
+/// Send the buffer constraints of a session
+fn send_constraints(session: &ExtImageCopyCaptureSessionV1, size: Size<i32, Physical>) {
+    session.buffer_size(size.w as u32, size.h as u32);
+    for format in SHM_FORMATS {
+        session.shm_format(format);
+    }
+    session.done();
+}
+
+/// Send new buffer constraints after the source of a frame's session
+/// changed size
+pub(super) fn resize_session(frame: &ExtImageCopyCaptureFrameV1, size: Size<i32, Physical>) {
+    let Some(session) = frame_session(frame) else {
+        return;
+    };
+    let Some(Ok(mut data)) = session.data::<Mutex<SessionData>>().map(|data| data.lock()) else {
+        return;
+    };
+    if data.source.is_some() && data.size != size {
+        data.size = size;
+        send_constraints(&session, size);
+    }
+}
+
+/// Send the metadata of a copy
+pub(super) fn frame_ready(
+    frame: &ExtImageCopyCaptureFrameV1,
+    damage: &[Rectangle<i32, Physical>],
+    time: Duration,
+) {
+    frame.transform(wl_output::Transform::Normal);
+    for rect in damage {
+        frame.damage(rect.loc.x, rect.loc.y, rect.size.w, rect.size.h);
+    }
+    let seconds = time.as_secs();
+    frame.presentation_time((seconds >> 32) as u32, seconds as u32, time.subsec_nanos());
+    frame.ready();
+}
+
+/// Report a failed copy, stopping the session if its source is gone
+pub(super) fn frame_failed(frame: &ExtImageCopyCaptureFrameV1, reason: FailureReason) {
+    if reason == FailureReason::Stopped
+        && let Some(session) = frame_session(frame)
+        && let Some(Ok(mut data)) = session.data::<Mutex<SessionData>>().map(|data| data.lock())
+        && data.source.take().is_some()
+    {
+        session.stopped();
+    }
+    frame.failed(reason);
+}
+
+/// Session of a frame
+fn frame_session(frame: &ExtImageCopyCaptureFrameV1) -> Option<ExtImageCopyCaptureSessionV1> {
+    let data = frame.data::<Mutex<FrameData>>()?.lock().ok()?;
+    Some(data.session.clone())
+}
@@ -0,0 +1,822 @@
+//! Screen capture
+//!
+//! Clients capture outputs through `wlr-screencopy-unstable-v1`, and
+//! outputs or single windows through `ext-image-copy-capture-v1`, whose
+//! window sources are the handles of `ext-foreign-toplevel-list-v1`.
+//!
+//! Capturing is a privilege: only clients whose executable is allowed by
+//! the `[capture]` section of the config may capture (see
+//! [`crate::security::peer_executable`]). Other clients can bind the
+//! globals, but their frames fail and their sessions stop.
+//!
+//! Copies wait for the next render, then are rendered again offscreen and
+//! copied into the client's shm buffer. An output is captured as shown, at
+//! its scale; a window is captured alone at 1:1 (a buffer pixel per
+//! pixel), whatever the zoom of the view showing it. The cursor is painted
+//! on request, when its image is a client surface.
+//!
+//! While the session is locked, outputs are captured showing the lock
+//! screen, and copies of windows wait for the unlock.
+//!
+//! Damage is tracked per capture session, apart from the backends: copies
+//! that ask for damage wait until the source changed since the session's
+//! last copy, then report the changed regions.
+
+mod ext;
+mod wlr;
+
+use crate::render::LoomRenderElement;
+use crate::security::MAX_PENDING_CAPTURES;
+use crate::state::{ClientState, LoomState};
+use crate::types::WindowId;
+use crate::{CoreError, Result};
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            ExportMem, ImportAll, ImportMem, Offscreen, Renderer,
+            damage::OutputDamageTracker,
+            element::{
+                AsRenderElements, Element, Kind, RenderElement,
+                surface::render_elements_from_surface_tree,
+            },
+        },
+    },
+    input::pointer::{CursorImageStatus, CursorImageSurfaceData},
+    output::{Output, WeakOutput},
+    reexports::{
+        wayland_protocols::ext::{
+            image_capture_source::v1::server::{
+                ext_foreign_toplevel_image_capture_source_manager_v1::ExtForeignToplevelImageCaptureSourceManagerV1,
+                ext_output_image_capture_source_manager_v1::ExtOutputImageCaptureSourceManagerV1,
+            },
+            image_copy_capture::v1::server::{
+                ext_image_copy_capture_frame_v1::{ExtImageCopyCaptureFrameV1, FailureReason},
+                ext_image_copy_capture_manager_v1::ExtImageCopyCaptureManagerV1,
+            },
+        },
+        wayland_protocols_wlr::screencopy::v1::server::{
+            zwlr_screencopy_frame_v1::ZwlrScreencopyFrameV1,
+            zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+        },
+        wayland_server::{
+            Client, DisplayHandle, Resource,
+            backend::ObjectId,
+            protocol::{wl_buffer::WlBuffer, wl_shm},
+        },
+    },
+    utils::{Buffer, Logical, Physical, Point, Rectangle, Scale, Size, Transform},
+    wayland::{
+        compositor::with_states,
+        shm::{with_buffer_contents, with_buffer_contents_mut},
+    },
+};
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::warn;
+
+/// Format captures are rendered and read back in
+const CAPTURE_FORMAT: Fourcc = Fourcc::Argb8888;
+
+/// Shm formats accepted for capture buffers, in the layout of
+/// [`CAPTURE_FORMAT`]
+const SHM_FORMATS: [wl_shm::Format; 2] = [wl_shm::Format::Xrgb8888, wl_shm::Format::Argb8888];
+
+/// Background of window captures, around and behind the window
+const WINDOW_BACKGROUND: [f32; 4] = [0.0, 0.0, 0.0, 0.0];
+
+/// Copies waiting for a render, and the damage of capture sessions
+#[derive(Debug, Default)]
+pub struct CaptureState {
+    pending: Vec<PendingCapture>,
+    damage: HashMap<SessionKey, SessionDamage>,
+}
+
+/// What a client captures
+#[derive(Debug, Clone)]
+enum CaptureSource {
+    Output(WeakOutput),
+    Window(WindowId),
+}
+
+/// Frame object of a copy, of either protocol
+#[derive(Debug, Clone, PartialEq)]
+enum CaptureFrame {
+    Wlr(ZwlrScreencopyFrameV1),
+    Ext(ExtImageCopyCaptureFrameV1),
+}
+
+/// Capture session whose damage is tracked
+///
+/// screencopy has no sessions; its copies of an output through the same
+/// manager are taken as one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum SessionKey {
+    Wlr { manager: ObjectId, output: String },
+    Ext(ObjectId),
+}
+
+/// A copy waiting for the next render
+#[derive(Debug)]
+struct PendingCapture {
+    frame: CaptureFrame,
+    source: CaptureSource,
+    buffer: WlBuffer,
+    /// Size of the source when the copy was requested
+    size: Size<i32, Physical>,
+    /// Part of the source copied into the buffer
+    region: Rectangle<i32, Physical>,
+    paint_cursor: bool,
+    /// Wait for damage before copying, and report it
+    with_damage: bool,
+    session: SessionKey,
+}
+
+/// Damage tracker of a session, for a source size and scale
+#[derive(Debug)]
+struct SessionDamage {
+    size: Size<i32, Physical>,
+    scale: f64,
+    tracker: OutputDamageTracker,
+}
+
+/// User data of the capture managers
+#[derive(Debug)]
+struct CaptureManagerData {
+    /// Whether the client may capture
+    allowed: bool,
+}
+
+impl CaptureState {
+    /// Create the capture globals
+    pub fn new(display_handle: &DisplayHandle) -> Self {
+        display_handle
+            .create_global::<LoomState, ZwlrScreencopyManagerV1, _>(wlr::MANAGER_VERSION, ());
+        display_handle.create_global::<LoomState, ExtOutputImageCaptureSourceManagerV1, _>(1, ());
+        display_handle
+            .create_global::<LoomState, ExtForeignToplevelImageCaptureSourceManagerV1, _>(1, ());
+        display_handle.create_global::<LoomState, ExtImageCopyCaptureManagerV1, _>(1, ());
+        Self::default()
+    }
+
+    /// Queue a copy for the next render
+    fn queue(&mut self, capture: PendingCapture) {
+        if self.pending.len() >= MAX_PENDING_CAPTURES {
+            warn!(
+                "Failing capture: max pending captures ({}) reached",
+                MAX_PENDING_CAPTURES
+            );
+            capture.frame.failed(FailureReason::Unknown);
+            return;
+        }
+        self.pending.push(capture);
+    }
+
+    /// Forget the copy of a destroyed frame
+    fn cancel(&mut self, frame: &CaptureFrame) {
+        self.pending.retain(|capture| capture.frame != *frame);
+    }
+
+    /// Forget the damage of a destroyed session or screencopy manager
+    fn end_session(&mut self, id: &ObjectId) {
+        self.damage.retain(|key, _| match key {
+            SessionKey::Wlr { manager, .. } => manager != id,
+            SessionKey::Ext(session) => session != id,
+        });
+    }
+
+    /// Damage of a session's source since its last copy
+    ///
+    /// A new session, or a source that changed size or scale, is damaged
+    /// entirely.
+    fn session_damage<E: Element>(
+        &mut self,
+        key: &SessionKey,
+        size: Size<i32, Physical>,
+        scale: f64,
+        elements: &[E],
+    ) -> Vec<Rectangle<i32, Physical>> {
+        let new = || SessionDamage {
+            size,
+            scale,
+            tracker: OutputDamageTracker::new(size, scale, Transform::Normal),
+        };
+        let session = self.damage.entry(key.clone()).or_insert_with(new);
+        if session.size != size || session.scale != scale {
+            *session = new();
+        }
+
+        match session.tracker.damage_output(1, elements) {
+            Ok((Some(damage), _)) => damage.clone(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl CaptureFrame {
+    fn alive(&self) -> bool {
+        match self {
+            Self::Wlr(frame) => frame.is_alive(),
+            Self::Ext(frame) => frame.is_alive(),
+        }
+    }
+
+    /// Report a successful copy
+    fn ready(&self, damage: &[Rectangle<i32, Physical>], time: Duration) {
+        match self {
+            Self::Wlr(frame) => wlr::frame_ready(frame, damage, time),
+            Self::Ext(frame) => ext::frame_ready(frame, damage, time),
+        }
+    }
+
+    /// Report a failed copy
+    fn failed(&self, reason: FailureReason) {
+        match self {
+            Self::Wlr(frame) => frame.failed(),
+            Self::Ext(frame) => ext::frame_failed(frame, reason),
+        }
+    }
+}
+
+impl LoomState {
+    /// Whether a client may capture outputs and windows
+    fn capture_allowed(&self, client: &Client) -> bool {
+        let executable = client
+            .get_data::<ClientState>()
+            .and_then(|data| data.executable.as_deref());
+        let allowed = executable.is_some_and(|executable| self.capture_config.allows(executable));
+        if !allowed {
+            warn!(
+                "Denying screen capture to {}",
+                executable.map_or("an unknown client".into(), |e| e.display().to_string())
+            );
+        }
+        allowed
+    }
+
+    /// Size of a capture source in buffer pixels, and its scale
+    fn capture_source_size(&self, source: &CaptureSource) -> Option<(Size<i32, Physical>, f64)> {
+        match source {
+            CaptureSource::Output(output) => {
+                let output = output.upgrade()?;
+                let scale = output.current_scale().fractional_scale();
+                let size = self
+                    .space
+                    .output_geometry(&output)?
+                    .size
+                    .to_physical_precise_round(scale);
+                Some((size, scale))
+            }
+            CaptureSource::Window(id) => {
+                let size = self.windows.get(*id)?.window.geometry().size;
+                (size.w > 0 && size.h > 0).then(|| ((size.w, size.h).into(), 1.0))
+            }
+        }
+    }
+
+    /// Pointer location relative to a capture source, if over it
+    fn capture_cursor_location(
+        &self,
+        source: &CaptureSource,
+        scale: f64,
+    ) -> Option<Point<f64, Physical>> {
+        match source {
+            CaptureSource::Output(output) => {
+                let geometry = self.space.output_geometry(&output.upgrade()?)?.to_f64();
+                geometry
+                    .contains(self.pointer_location)
+                    .then(|| (self.pointer_location - geometry.loc).to_physical(scale))
+            }
+            // Over the window's node, in its buffer pixels
+            CaptureSource::Window(id) => {
+                if self.window_under(self.pointer_location) != Some(*id)
+                    || self.windows[*id].fullscreen.is_some()
+                {
+                    return None;
+                }
+                let node = self.window_node_rect(*id)?;
+                let local = self.global_to_canvas(self.pointer_location) - node.loc;
+                Some(local.to_physical(scale))
+            }
+        }
+    }
+}
+
+/// Copy the pending captures shown on an output, after it was rendered
+///
+/// Captures of windows are copied after the render of any output.
+/// `background` is the backend's clear color, behind output captures.
+pub fn render_captures<R, T>(
+    renderer: &mut R,
+    state: &mut LoomState,
+    output: &Output,
+    background: [f32; 4],
+) where
+    R: Renderer + ImportAll + ImportMem + ExportMem + Offscreen<T>,
+    R::TextureId: Send + Clone + 'static,
+{
+    if state.capture.pending.is_empty() {
+        return;
+    }
+
+    let time = Duration::from(state.clock.now());
+    for capture in std::mem::take(&mut state.capture.pending) {
+        if !capture.frame.alive() {
+            continue;
+        }
+        match render_capture(renderer, state, &capture, output, background, time) {
+            Ok(true) => {}
+            Ok(false) => state.capture.pending.push(capture),
+            Err(reason) => capture.frame.failed(reason),
+        }
+    }
+}
+
+/// Copy a capture into its buffer
+///
+/// Returns whether it was copied, `false` while it waits for its output to
+/// be rendered or for damage.
+fn render_capture<R, T>(
+    renderer: &mut R,
+    state: &mut LoomState,
+    capture: &PendingCapture,
+    output: &Output,
+    background: [f32; 4],
+    time: Duration,
+) -> std::result::Result<bool, FailureReason>
+where
+    R: Renderer + ImportAll + ImportMem + ExportMem + Offscreen<T>,
+    R::TextureId: Send + Clone + 'static,
+{
+    if let CaptureSource::Output(source) = &capture.source {
+        match source.upgrade() {
+            None => return Err(FailureReason::Stopped),
+            Some(source) if source != *output => return Ok(false),
+            Some(_) => {}
+        }
+    }
+
+    if state.session_locked && matches!(capture.source, CaptureSource::Window(_)) {
+        return Ok(false);
+    }
+
+    let Some((size, scale)) = state.capture_source_size(&capture.source) else {
+        return Err(FailureReason::Stopped);
+    };
+    if size != capture.size {
+        if let CaptureFrame::Ext(frame) = &capture.frame {
+            ext::resize_session(frame, size);
+        }
+        return Err(FailureReason::BufferConstraints);
+    }
+
+    let elements = capture_elements(renderer, state, capture, scale);
+    let damage = state
+        .capture
+        .session_damage(&capture.session, size, scale, &elements);
+    if capture.with_damage && damage.is_empty() {
+        return Ok(false);
+    }
+
+    let clear_color = match capture.source {
+        CaptureSource::Output(_) => background,
+        CaptureSource::Window(_) => WINDOW_BACKGROUND,
+    };
+    if let Err(e) = render_to_shm::<R, T, _>(
+        renderer,
+        size,
+        scale,
+        capture.region,
+        &elements,
+        clear_color,
+        &capture.buffer,
+    ) {
+        warn!("Screen capture failed: {}", e);
+        return Err(FailureReason::Unknown);
+    }
+
+    // Damage of the copied region, relative to the buffer
+    let damage: Vec<_> = damage
+        .into_iter()
+        .filter(|_| capture.with_damage)
+        .filter_map(|rect| rect.intersection(capture.region))
+        .map(|rect| Rectangle::new(rect.loc - capture.region.loc, rect.size))
+        .collect();
+    capture.frame.ready(&damage, time);
+    Ok(true)
+}
+
+/// Build the elements of a capture source, front to back
+fn capture_elements<R>(
+    renderer: &mut R,
+    state: &LoomState,
+    capture: &PendingCapture,
+    scale: f64,
+) -> Vec<LoomRenderElement<R>>
+where
+    R: Renderer + ImportAll + ImportMem,
+    R::TextureId: Send + Clone + 'static,
+{
+    let mut elements = Vec::new();
+    if capture.paint_cursor
+        && let Some(location) = state.capture_cursor_location(&capture.source, scale)
+    {
+        elements.extend(cursor_elements(renderer, state, location, scale));
+    }
+
+    match &capture.source {
+        CaptureSource::Output(output) => {
+            if let Some(output) = output.upgrade() {
+                elements.extend(crate::render::output_elements(renderer, &output, state));
+            }
+        }
+        // The window alone, unzoomed, its geometry at the buffer's origin
+        CaptureSource::Window(id) => {
+            let window = &state.windows[*id].window;
+            let location = Point::<i32, Logical>::from((0, 0)) - window.geometry().loc;
+            elements.extend(window.render_elements::<LoomRenderElement<R>>(
+                renderer,
+                location.to_physical(1),
+                Scale::from(1.0),
+                1.0,
+            ));
+        }
+    }
+    elements
+}
+
+/// Build the elements of the cursor at a location, if a client set its
+/// image
+fn cursor_elements<R>(
+    renderer: &mut R,
+    state: &LoomState,
+    location: Point<f64, Physical>,
+    scale: f64,
+) -> Vec<LoomRenderElement<R>>
+where
+    R: Renderer + ImportAll + ImportMem,
+    R::TextureId: Send + Clone + 'static,
+{
+    let CursorImageStatus::Surface(surface) = &state.cursor_status else {
+        return Vec::new();
+    };
+    let hotspot = with_states(surface, |states| {
+        states
+            .data_map
+            .get::<CursorImageSurfaceData>()
+            .and_then(|data| data.lock().ok().map(|data| data.hotspot))
+    })
+    .unwrap_or_default();
+
+    let location = location - hotspot.to_f64().to_physical(scale);
+    render_elements_from_surface_tree(
+        renderer,
+        surface,
+        location.to_i32_round(),
+        Scale::from(scale),
+        1.0,
+        Kind::Cursor,
+    )
+}
+
+/// Render elements offscreen and copy a region of them into a shm buffer
+fn render_to_shm<R, T, E>(
+    renderer: &mut R,
+    size: Size<i32, Physical>,
+    scale: f64,
+    region: Rectangle<i32, Physical>,
+    elements: &[E],
+    clear_color: [f32; 4],
+    buffer: &WlBuffer,
+) -> Result<()>
+where
+    R: Renderer + ExportMem + Offscreen<T>,
+    E: RenderElement<R>,
+{
+    let mut target = renderer
+        .create_buffer(CAPTURE_FORMAT, (size.w, size.h).into())
+        .map_err(|e| CoreError::Renderer(format!("Failed to create capture buffer: {e}")))?;
+    let mut framebuffer = renderer
+        .bind(&mut target)
+        .map_err(|e| CoreError::Renderer(format!("Failed to bind capture buffer: {e}")))?;
+
+    OutputDamageTracker::new(size, scale, Transform::Normal)
+        .render_output(renderer, &mut framebuffer, 0, elements, clear_color)
+        .map_err(|e| CoreError::Renderer(format!("Failed to render capture: {e:?}")))?;
+
+    let region = Rectangle::<i32, Buffer>::new(
+        (region.loc.x, region.loc.y).into(),
+        (region.size.w, region.size.h).into(),
+    );
+    let mapping = renderer
+        .copy_framebuffer(&framebuffer, region, CAPTURE_FORMAT)
+        .map_err(|e| CoreError::Renderer(format!("Failed to copy capture: {e}")))?;
+    let pixels = renderer
+        .map_texture(&mapping)
+        .map_err(|e| CoreError::Renderer(format!("Failed to map capture: {e}")))?;
+
+    copy_to_shm(buffer, pixels, region.size)
+}
+
+/// Copy tightly packed rows of pixels into a shm buffer
+///
+/// Fails if the buffer doesn't have the size of the pixels, or its rows
+/// don't fit in its pool. An empty copy does nothing.
+fn copy_to_shm(buffer: &WlBuffer, pixels: &[u8], size: Size<i32, Buffer>) -> Result<()> {
+    let (Ok(width), Ok(rows)) = (usize::try_from(size.w), usize::try_from(size.h)) else {
+        return Err(CoreError::Renderer("Invalid capture size".into()));
+    };
+    if width == 0 || rows == 0 {
+        return Ok(());
+    }
+    let row = width
+        .checked_mul(4)
+        .ok_or_else(|| CoreError::Renderer("Invalid capture size".into()))?;
+    if row
+        .checked_mul(rows)
+        .is_none_or(|total| pixels.len() < total)
+    {
+        return Err(CoreError::Renderer("Capture readback is too short".into()));
+    }
+
+    let copied = with_buffer_contents_mut(buffer, |ptr, len, data| {
+        if data.width != size.w || data.height != size.h {
+            return false;
+        }
+        let (Ok(offset), Ok(stride)) = (usize::try_from(data.offset), usize::try_from(data.stride))
+        else {
+            return false;
+        };
+        // End of the last row
+        let end = stride
+            .checked_mul(rows - 1)
+            .and_then(|last| last.checked_add(offset))
+            .and_then(|last| last.checked_add(row));
+        if stride < row || end.is_none_or(|end| end > len) {
+            return false;
+        }
+        for (y, line) in pixels.chunks_exact(row).take(rows).enumerate() {
+            // SAFETY: the row lies within the pool, checked above. The
+            // client may write to its pool meanwhile, which only garbles
+            // its own copy.
+            unsafe {
+                std::ptr::copy_nonoverlapping(line.as_ptr(), ptr.add(offset + y * stride), row)
+            };
+        }
+        true
+    })
+    .map_err(|e| CoreError::Renderer(format!("Cannot access capture buffer: {e}")))?;
+
+    if copied {
+        Ok(())
+    } else {
+        Err(CoreError::Renderer(
+            "Capture buffer changed size or stride".into(),
+        ))
+    }
+}
+
+/// Whether a client buffer can take a copy of a size
+fn buffer_fits(buffer: &WlBuffer, size: Size<i32, Physical>) -> bool {
+    with_buffer_contents(buffer, |_, _, data| {
+        SHM_FORMATS.contains(&data.format)
+            && data.width == size.w
+            && data.height == size.h
+            && data.stride >= size.w * 4
+    })
+    .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{
+        headless::{Frame, HeadlessBackend, HeadlessConfig},
+        test_client::TestClient,
+    };
+    use loom_config::Config;
+    use smithay::backend::renderer::{
+        element::{Id, solid::SolidColorRenderElement},
+        utils::CommitCounter,
+    };
+    use wayland_client::protocol::{wl_output::WlOutput, wl_shm::WlShm};
+    use wayland_protocols::ext::{
+        foreign_toplevel_list::v1::client::ext_foreign_toplevel_list_v1::ExtForeignToplevelListV1,
+        image_capture_source::v1::client::ext_foreign_toplevel_image_capture_source_manager_v1::ExtForeignToplevelImageCaptureSourceManagerV1,
+        image_copy_capture::v1::client::ext_image_copy_capture_manager_v1::{
+            ExtImageCopyCaptureManagerV1, Options,
+        },
+        session_lock::v1::client::ext_session_lock_manager_v1::ExtSessionLockManagerV1,
+    };
+    use wayland_protocols_wlr::screencopy::v1::client::zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1;
+
+    const GREEN: u32 = 0xff00ff00;
+
+    /// A compositor allowing the test binary to capture, or nothing
+    fn capture_backend(allowed: bool) -> HeadlessBackend {
+        let mut settings = Config::default();
+        if allowed {
+            let executable = std::env::current_exe().unwrap();
+            settings.capture.allowed_clients = vec![executable.display().to_string()];
+        }
+        let config = HeadlessConfig {
+            settings,
+            ..HeadlessConfig::for_tests(640, 480)
+        };
+        HeadlessBackend::new(config).unwrap()
+    }
+
+    fn is_green(pixel: [u8; 4]) -> bool {
+        pixel[..3] == [0, 255, 0]
+    }
+
+    /// Bounds of the green pixels of a frame: top-left and bottom-right
+    fn green_bounds(frame: &Frame) -> Option<((u32, u32), (u32, u32))> {
+        let green: Vec<_> = (0..frame.height)
+            .flat_map(|y| (0..frame.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| frame.pixel(x, y).is_some_and(is_green))
+            .collect();
+        let min = green
+            .iter()
+            .copied()
+            .reduce(|a, b| (a.0.min(b.0), a.1.min(b.1)))?;
+        let max = green
+            .iter()
+            .copied()
+            .reduce(|a, b| (a.0.max(b.0), a.1.max(b.1)))?;
+        Some((min, max))
+    }
+
+    #[test]
+    fn test_session_damage() {
+        let mut capture = CaptureState::default();
+        let key = SessionKey::Ext(ObjectId::null());
+        let size = Size::from((100, 100));
+        let element = SolidColorRenderElement::new(
+            Id::new(),
+            Rectangle::new((10, 10).into(), (20, 20).into()),
+            CommitCounter::default(),
+            [1.0, 0.0, 0.0, 1.0],
+            Kind::Unspecified,
+        );
+        let elements = [element];
+
+        // A new session is damaged entirely, then only by changes
+        let full = Rectangle::from_size(size);
+        assert_eq!(
+            capture.session_damage(&key, size, 1.0, &elements),
+            vec![full]
+        );
+        assert!(
+            capture
+                .session_damage(&key, size, 1.0, &elements)
+                .is_empty()
+        );
+        assert_eq!(
+            capture.session_damage(&key, size, 1.0, &[] as &[SolidColorRenderElement]),
+            vec![Rectangle::new((10, 10).into(), (20, 20).into())]
+        );
+
+        // So is a resized source, and a session that ended and started again
+        let size = Size::from((50, 50));
+        assert_eq!(
+            capture.session_damage(&key, size, 1.0, &elements),
+            vec![Rectangle::from_size(size)]
+        );
+        capture.end_session(&ObjectId::null());
+        assert!(capture.damage.is_empty());
+    }
+
+    #[test]
+    fn test_capture_refused() {
+        let mut backend = capture_backend(false);
+        let mut client = TestClient::connect(&mut backend);
+        let output: WlOutput = client.bind(4);
+        let screencopy: ZwlrScreencopyManagerV1 = client.bind(3);
+
+        screencopy.capture_output(0, &output, &client.handle(), ());
+        client.roundtrip(&mut backend).unwrap();
+        assert_eq!(client.data.capture_size, None);
+        assert_eq!(client.data.copied, Some(false));
+    }
+
+    #[test]
+    fn test_capture_region() {
+        let mut backend = capture_backend(true);
+        let mut client = TestClient::connect(&mut backend);
+        let output: WlOutput = client.bind(4);
+        let shm: WlShm = client.bind(1);
+        let screencopy: ZwlrScreencopyManagerV1 = client.bind(3);
+        let window = client.shm_buffer(&shm, 100, 80, GREEN);
+        let _toplevel = client.map_toplevel(&mut backend, &window);
+
+        // A region across the window's top-left corner
+        backend.render().unwrap();
+        let ((x, y), _) = green_bounds(&backend.frame(0).unwrap()).unwrap();
+        let (x, y) = (x - 10, y - 10);
+        let frame = screencopy.capture_output_region(
+            0,
+            &output,
+            x as i32,
+            y as i32,
+            40,
+            30,
+            &client.handle(),
+            (),
+        );
+        client.roundtrip(&mut backend).unwrap();
+        assert_eq!(client.data.capture_size, Some((40, 30)));
+
+        let buffer = client.shm_buffer(&shm, 40, 30, 0);
+        frame.copy(&buffer.buffer);
+        client.roundtrip(&mut backend).unwrap();
+        backend.render().unwrap();
+        client.roundtrip(&mut backend).unwrap();
+        assert_eq!(client.data.copied, Some(true));
+
+        let output_frame = backend.frame(0).unwrap();
+        for (dx, dy) in (0..30).flat_map(|dy| (0..40).map(move |dx| (dx, dy))) {
+            let expected = output_frame.pixel(x + dx, y + dy).unwrap();
+            assert_eq!(buffer.pixel(dx, dy)[..3], expected[..3], "at {dx},{dy}");
+        }
+        assert!(is_green(buffer.pixel(20, 20)));
+        assert!(!is_green(buffer.pixel(0, 0)));
+    }
+
+    #[test]
+    fn test_window_capture_unzoomed() {
+        let mut backend = capture_backend(true);
+        let mut client = TestClient::connect(&mut backend);
+        let shm: WlShm = client.bind(1);
+        let _toplevels: ExtForeignToplevelListV1 = client.bind(1);
+        let sources: ExtForeignToplevelImageCaptureSourceManagerV1 = client.bind(1);
+        let capture: ExtImageCopyCaptureManagerV1 = client.bind(1);
+        let window = client.shm_buffer(&shm, 100, 80, GREEN);
+        let _toplevel = client.map_toplevel(&mut backend, &window);
+        assert_eq!(client.data.toplevels.len(), 1);
+
+        // Shown twice as large on the output
+        let state = backend.state_mut();
+        state.zoom_view_at(2.0, (320.0, 240.0).into());
+        let viewport = state.canvas.viewport(state.views[0].viewport).unwrap();
+        assert_eq!(viewport.zoom, 2.0);
+
+        let source = sources.create_source(&client.data.toplevels[0], &client.handle(), ());
+        let session = capture.create_session(&source, Options::empty(), &client.handle(), ());
+        client.roundtrip(&mut backend).unwrap();
+        assert_eq!(client.data.capture_size, Some((100, 80)));
+
+        let buffer = client.shm_buffer(&shm, 100, 80, 0);
+        let frame = session.create_frame(&client.handle(), ());
+        frame.attach_buffer(&buffer.buffer);
+        frame.damage_buffer(0, 0, 100, 80);
+        frame.capture();
+        client.roundtrip(&mut backend).unwrap();
+        backend.render().unwrap();
+        client.roundtrip(&mut backend).unwrap();
+        assert_eq!(client.data.copied, Some(true));
+        for (x, y) in [(0, 0), (99, 0), (50, 40), (0, 79), (99, 79)] {
+            assert!(is_green(buffer.pixel(x, y)), "at {x},{y}");
+        }
+    }
+
+    #[test]
+    fn test_window_capture_waits_while_locked() {
+        let mut backend = capture_backend(true);
+        let mut client = TestClient::connect(&mut backend);
+        let shm: WlShm = client.bind(1);
+        let _toplevels: ExtForeignToplevelListV1 = client.bind(1);
+        let sources: ExtForeignToplevelImageCaptureSourceManagerV1 = client.bind(1);
+        let capture: ExtImageCopyCaptureManagerV1 = client.bind(1);
+        let window = client.shm_buffer(&shm, 100, 80, GREEN);
+        let _toplevel = client.map_toplevel(&mut backend, &window);
+
+        let mut locker = TestClient::connect(&mut backend);
+        let manager: ExtSessionLockManagerV1 = locker.bind(1);
+        let lock = manager.lock(&locker.handle(), ());
+        locker.roundtrip(&mut backend).unwrap();
+        backend.render().unwrap();
+        locker.roundtrip(&mut backend).unwrap();
+        assert!(locker.data.locked);
+
+        let source = sources.create_source(&client.data.toplevels[0], &client.handle(), ());
+        let session = capture.create_session(&source, Options::empty(), &client.handle(), ());
+        let buffer = client.shm_buffer(&shm, 100, 80, 0);
+        let frame = session.create_frame(&client.handle(), ());
+        frame.attach_buffer(&buffer.buffer);
+        frame.capture();
+        client.roundtrip(&mut backend).unwrap();
+        backend.render().unwrap();
+        client.roundtrip(&mut backend).unwrap();
+        assert_eq!(client.data.copied, None);
+        assert!(!is_green(buffer.pixel(50, 40)));
+
+        // Copied once unlocked
+        lock.unlock_and_destroy();
+        locker.roundtrip(&mut backend).unwrap();
+        backend.render().unwrap();
+        client.roundtrip(&mut backend).unwrap();
+        assert_eq!(client.data.copied, Some(true));
+        assert!(is_green(buffer.pixel(50, 40)));
+    }
+}
@@ -0,0 +1,258 @@
+//! `wlr-screencopy-unstable-v1`
+//!
+//! Copies of an output, or of a region of it, into shm buffers (no
+//! `linux_dmabuf` buffers are offered). `copy_with_damage` waits until the
+//! output changed since the last copy through the same manager.
+
+use super::{
+    CaptureFrame, CaptureManagerData, CaptureSource, PendingCapture, SessionKey, buffer_fits,
+};
+use crate::state::LoomState;
+use smithay::{
+    output::{Output, WeakOutput},
+    reexports::{
+        wayland_protocols_wlr::screencopy::v1::server::{
+            zwlr_screencopy_frame_v1::{self, Flags, ZwlrScreencopyFrameV1},
+            zwlr_screencopy_manager_v1::{self, ZwlrScreencopyManagerV1},
+        },
+        wayland_server::{
+            Client, DataInit, Dispatch, DisplayHandle, GlobalDispatch, New, Resource,
+            backend::{ClientId, ObjectId},
+            protocol::{wl_output::WlOutput, wl_shm},
+        },
+    },
+    utils::{Logical, Physical, Rectangle, Size},
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// Version of the screencopy manager global
+pub(super) const MANAGER_VERSION: u32 = 3;
+
+/// User data of a screencopy frame
+#[derive(Debug)]
+pub(super) struct FrameData {
+    /// What the frame copies, `None` if it can't be captured
+    capture: Option<FrameCapture>,
+    /// Whether a copy was requested
+    used: AtomicBool,
+}
+
+/// Output region copied by a screencopy frame
+#[derive(Debug)]
+struct FrameCapture {
+    output: WeakOutput,
+    output_name: String,
+    /// Size of the output when the frame was created
+    size: Size<i32, Physical>,
+    region: Rectangle<i32, Physical>,
+    paint_cursor: bool,
+    manager: ObjectId,
+}
+
+impl GlobalDispatch<ZwlrScreencopyManagerV1, ()> for LoomState {
+    fn bind(
+        state: &mut Self,
+        _handle: &DisplayHandle,
+        client: &Client,
+        resource: New<ZwlrScreencopyManagerV1>,
+        _global_data: &(),
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let allowed = state.capture_allowed(client);
+        data_init.init(resource, CaptureManagerData { allowed });
+    }
+}
+
+impl Dispatch<ZwlrScreencopyManagerV1, CaptureManagerData> for LoomState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        manager: &ZwlrScreencopyManagerV1,
+        request: zwlr_screencopy_manager_v1::Request,
+        data: &CaptureManagerData,
+        _dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        let (frame, overlay_cursor, output, region) = match request {
+            zwlr_screencopy_manager_v1::Request::CaptureOutput {
+                frame,
+                overlay_cursor,
+                output,
+            } => (frame, overlay_cursor, output, None),
+            zwlr_screencopy_manager_v1::Request::CaptureOutputRegion {
+                frame,
+                overlay_cursor,
+                output,
+                x,
+                y,
+                width,
+                height,
+            } => (
+                frame,
+                overlay_cursor,
+                output,
+                Some(Rectangle::new((x, y).into(), (width, height).into())),
+            ),
+            _ => return,
+        };
+
+        let capture = data
+            .allowed
+            .then(|| state.frame_capture(manager, &output, region, overlay_cursor != 0))
+            .flatten();
+        let frame = data_init.init(
+            frame,
+            FrameData {
+                capture,
+                used: AtomicBool::new(false),
+            },
+        );
+
+        match frame
+            .data::<FrameData>()
+            .and_then(|data| data.capture.as_ref())
+        {
+            Some(capture) => {
+                let size = capture.region.size;
+                frame.buffer(
+                    wl_shm::Format::Xrgb8888,
+                    size.w as u32,
+                    size.h as u32,
+                    size.w as u32 * 4,
+                );
+                if frame.version() >= 3 {
+                    frame.buffer_done();
+                }
+            }
+            None => frame.failed(),
+        }
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        manager: &ZwlrScreencopyManagerV1,
+        _data: &CaptureManagerData,
+    ) {
+        state.capture.end_session(&manager.id());
+    }
+}
+
+impl Dispatch<ZwlrScreencopyFrameV1, FrameData> for LoomState {
+    fn request(
+        state: &mut Self,
+        _client: &Client,
+        frame: &ZwlrScreencopyFrameV1,
+        request: zwlr_screencopy_frame_v1::Request,
+        data: &FrameData,
+        _dhandle: &DisplayHandle,
+        _data_init: &mut DataInit<'_, Self>,
+    ) {
+        let (buffer, with_damage) = match request {
+            zwlr_screencopy_frame_v1::Request::Copy { buffer } => (buffer, false),
+            zwlr_screencopy_frame_v1::Request::CopyWithDamage { buffer } => (buffer, true),
+            _ => return,
+        };
+
+        if data.used.swap(true, Ordering::Relaxed) {
+            frame.post_error(
+                zwlr_screencopy_frame_v1::Error::AlreadyUsed,
+                "frame already copied",
+            );
+            return;
+        }
+        let Some(capture) = &data.capture else {
+            frame.failed();
+            return;
+        };
+        if !buffer_fits(&buffer, capture.region.size) {
+            frame.post_error(
+                zwlr_screencopy_frame_v1::Error::InvalidBuffer,
+                "buffer doesn't match the frame's format and size",
+            );
+            return;
+        }
+
+        state.capture.queue(PendingCapture {
+            frame: CaptureFrame::Wlr(frame.clone()),
+            source: CaptureSource::Output(capture.output.clone()),
+            buffer,
+            size: capture.size,
+            region: capture.region,
+            paint_cursor: capture.paint_cursor,
+            with_damage,
+            session: SessionKey::Wlr {
+                manager: capture.manager.clone(),
+                output: capture.output_name.clone(),
+            },
+        });
+        // Copied after the next render
+        state.queue_redraw();
+    }
+
+    fn destroyed(
+        state: &mut Self,
+        _client: ClientId,
+        frame: &ZwlrScreencopyFrameV1,
+        _data: &FrameData,
+    ) {
+        state.capture.cancel(&CaptureFrame::Wlr(frame.clone()));
+    }
+}
+
+impl LoomState {
+    /// What a new screencopy frame copies, `None` for an unknown output or
+    /// a region outside it
+    fn frame_capture(
+        &self,
+        manager: &ZwlrScreencopyManagerV1,
+        output: &WlOutput,
+        region: Option<Rectangle<i32, Logical>>,
+        paint_cursor: bool,
+    ) -> Option<FrameCapture> {
+        let output = Output::from_resource(output)?;
+        let (size, scale) = self.capture_source_size(&CaptureSource::Output(output.downgrade()))?;
+
+        let full = Rectangle::from_size(size);
+        let region = match region {
+            Some(region) if region.size.w > 0 && region.size.h > 0 => region
+                .to_f64()
+                .to_physical(scale)
+                .to_i32_round()
+                .intersection(full)?,
+            Some(_) => return None,
+            None => full,
+        };
+
+        Some(FrameCapture {
+            output: output.downgrade(),
+            output_name: output.name(),
+            size,
+            region,
+            paint_cursor,
+            manager: manager.id(),
+        })
+    }
+}
+
+/// Send the damage and the presentation time of a copy
+pub(super) fn frame_ready(
+    frame: &ZwlrScreencopyFrameV1,
+    damage: &[Rectangle<i32, Physical>],
+    time: Duration,
+) {
+    frame.flags(Flags::empty());
+    if frame.version() >= 2 {
+        for rect in damage {
+            frame.damage(
+                rect.loc.x as u32,
+                rect.loc.y as u32,
+                rect.size.w as u32,
+                rect.size.h as u32,
+            );
+        }
+    }
+    let seconds = time.as_secs();
+    frame.ready((seconds >> 32) as u32, seconds as u32, time.subsec_nanos());
+}
@@ -0,0 +1,332 @@
+//! Server-side node frames
+//!
+//! Windows whose client agreed to server-side decorations (see the
+//! `xdg_decoration` handler) are framed by the compositor: a border of the
+//! theme's `border_width` around the window, a title strip above it
+//! showing the node's label, and the theme's `corner_radius` on the outer
+//! corners. The frame is drawn in `node_border_focused` while the window
+//! has keyboard focus and in `node_border` otherwise; it is part of the
+//! node, so it pans and zooms with it, but lies outside the node's
+//! rectangle, which stays the window geometry.
+//!
+//! Dragging the title strip moves the node, and dragging the border, or
+//! just outside it, resizes the node from that edge (see
+//! [`LoomState::frame_under`]). Fullscreen windows have no frame.
+
+mod title;
+
+pub use title::{TitleBitmap, render_title};
+
+use crate::grabs::ResizeEdge;
+use crate::state::LoomState;
+use crate::types::{WindowFlags, WindowId};
+use fontdue::Font;
+use loom_config::Theme;
+use loom_config::theme::parse_color;
+use smithay::{
+    desktop::{Window, space::SpaceElement},
+    reexports::wayland_protocols::xdg::decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode,
+    utils::{Logical, Point, Rectangle},
+    wayland::{compositor::with_states, shell::xdg::XdgToplevelSurfaceData},
+};
+
+/// Maximum border width, in canvas units
+const MAX_BORDER_WIDTH: f64 = 16.0;
+
+/// Range of title font sizes, in canvas units
+const FONT_SIZE_RANGE: (f64, f64) = (6.0, 48.0);
+
+/// Space above and below the title text, in canvas units
+pub const TITLE_PADDING: f64 = 5.0;
+
+/// Width of the resize handles outside the border, in screen pixels
+const HANDLE_WIDTH: f64 = 6.0;
+
+/// Frame colors used when the theme's don't parse
+const FALLBACK_COLORS: [[f32; 4]; 3] = [
+    [0.18, 0.18, 0.27, 1.0],
+    [0.39, 0.4, 0.95, 1.0],
+    [0.89, 0.91, 0.94, 1.0],
+];
+
+/// Frame dimensions and colors, from the theme
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameStyle {
+    /// Border width around the window, in canvas units
+    pub border: f64,
+    /// Height of the title strip above the window, in canvas units
+    pub title_height: f64,
+    /// Radius of the outer corners, in canvas units
+    pub radius: f64,
+    /// Title font size, in canvas units
+    pub font_size: f64,
+    /// Frame color of unfocused windows
+    pub color: [f32; 4],
+    /// Frame color of the focused window
+    pub focused_color: [f32; 4],
+    /// Title color
+    pub text_color: [f32; 4],
+}
+
+/// Part of a frame under a point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameHit {
+    /// The title strip, dragging moves the node
+    Title,
+    /// Border or resize handle, dragging resizes from these edges
+    Edge(ResizeEdge),
+}
+
+/// Frame style and the title font
+pub struct Decorations {
+    pub style: FrameStyle,
+    /// Font family the font was loaded for
+    font_family: String,
+    font: Option<Font>,
+}
+
+impl FrameStyle {
+    pub fn from_theme(theme: &Theme) -> Self {
+        let finite = |value: f32, fallback: f64| {
+            if value.is_finite() {
+                value as f64
+            } else {
+                fallback
+            }
+        };
+
+        let border = finite(theme.border_width, 0.0).clamp(0.0, MAX_BORDER_WIDTH);
+        let font_size = finite(theme.font_size, 14.0).clamp(FONT_SIZE_RANGE.0, FONT_SIZE_RANGE.1);
+        let title_height = (font_size + 2.0 * TITLE_PADDING).round();
+        let radius = finite(theme.corner_radius, 0.0).clamp(0.0, title_height);
+        let color = |hex: &str, fallback: [f32; 4]| parse_color(hex).unwrap_or(fallback);
+
+        Self {
+            border,
+            title_height,
+            radius,
+            font_size,
+            color: color(&theme.node_border, FALLBACK_COLORS[0]),
+            focused_color: color(&theme.node_border_focused, FALLBACK_COLORS[1]),
+            text_color: color(&theme.text, FALLBACK_COLORS[2]),
+        }
+    }
+
+    /// Outer rectangle of the frame around a window's content
+    pub fn frame_rect(&self, content: Rectangle<f64, Logical>) -> Rectangle<f64, Logical> {
+        Rectangle::new(
+            content.loc - Point::from((self.border, self.title_height)),
+            (
+                content.size.w + 2.0 * self.border,
+                content.size.h + self.title_height + self.border,
+            )
+                .into(),
+        )
+    }
+
+    /// Radius of the bottom corners
+    ///
+    /// The window itself is square and drawn over the frame, so the bottom
+    /// corners are only rounded as far as its corners stay inside them.
+    pub fn bottom_radius(&self) -> f64 {
+        self.radius
+            .min(self.border * (2.0 + std::f64::consts::SQRT_2))
+    }
+}
+
+impl Decorations {
+    pub fn from_theme(theme: &Theme) -> Self {
+        Self {
+            style: FrameStyle::from_theme(theme),
+            font_family: theme.font_family.clone(),
+            font: title::load_font(&theme.font_family),
+        }
+    }
+
+    /// Follow a theme change, loading the font again only if its family
+    /// changed
+    pub fn set_theme(&mut self, theme: &Theme) {
+        self.style = FrameStyle::from_theme(theme);
+        if self.font_family != theme.font_family {
+            self.font_family = theme.font_family.clone();
+            self.font = title::load_font(&theme.font_family);
+        }
+    }
+
+    /// Font of titles, if any was found
+    pub fn font(&self) -> Option<&Font> {
+        self.font.as_ref()
+    }
+}
+
+// Fonts don't implement Debug
+impl std::fmt::Debug for Decorations {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Decorations")
+            .field("style", &self.style)
+            .field("font_family", &self.font_family)
+            .field("font", &self.font.is_some())
+            .finish()
+    }
+}
+
+/// Part of a frame under a canvas point
+///
+/// `handle` is the width of the resize handles outside the frame. The top
+/// edge is the top border width of the title strip; the rest of the strip
+/// is the title.
+pub fn frame_hit(
+    style: &FrameStyle,
+    content: Rectangle<f64, Logical>,
+    point: Point<f64, Logical>,
+    handle: f64,
+) -> Option<FrameHit> {
+    let frame = style.frame_rect(content);
+    let reach = Rectangle::new(
+        frame.loc - Point::from((handle, handle)),
+        (frame.size.w + 2.0 * handle, frame.size.h + 2.0 * handle).into(),
+    );
+    if !reach.contains(point) || content.contains(point) {
+        return None;
+    }
+
+    let mut edges = ResizeEdge::empty();
+    if point.x < content.loc.x {
+        edges |= ResizeEdge::LEFT;
+    } else if point.x >= content.loc.x + content.size.w {
+        edges |= ResizeEdge::RIGHT;
+    }
+    if point.y < frame.loc.y + style.border {
+        edges |= ResizeEdge::TOP;
+    } else if point.y >= content.loc.y + content.size.h {
+        edges |= ResizeEdge::BOTTOM;
+    }
+
+    Some(if edges.is_empty() {
+        FrameHit::Title
+    } else {
+        FrameHit::Edge(edges)
+    })
+}
+
+impl LoomState {
+    /// Whether a window is drawn with a frame
+    pub fn has_frame(&self, id: WindowId) -> bool {
+        let Some(managed) = self.windows.get(id) else {
+            return false;
+        };
+        if managed.fullscreen.is_some() {
+            return false;
+        }
+
+        managed.window.toplevel().is_some_and(|toplevel| {
+            with_states(toplevel.wl_surface(), |states| {
+                states
+                    .data_map
+                    .get::<XdgToplevelSurfaceData>()
+                    .and_then(|data| data.lock().ok().map(|data| data.current.decoration_mode))
+                    == Some(Some(Mode::ServerSide))
+            })
+        })
+    }
+
+    /// Frame color of a window, following its focus
+    pub fn frame_color(&self, id: WindowId) -> [f32; 4] {
+        let style = &self.decorations.style;
+        let focused = self
+            .windows
+            .get(id)
+            .is_some_and(|managed| managed.flags.contains(WindowFlags::FOCUSED));
+        if focused {
+            style.focused_color
+        } else {
+            style.color
+        }
+    }
+
+    /// Title shown in a window's frame: its node's label, which follows
+    /// the toplevel title
+    pub fn frame_title(&self, id: WindowId) -> Option<&str> {
+        let node = self.canvas.get_node(self.windows.get(id)?.node_id)?;
+        node.label.as_deref().filter(|label| !label.is_empty())
+    }
+
+    /// Whether a window's frame covers a canvas point
+    pub(crate) fn frame_covers(&self, window: &Window, point: Point<f64, Logical>) -> bool {
+        let Some(id) = self.window_id(window) else {
+            return false;
+        };
+        self.has_frame(id)
+            && self.window_node_rect(id).is_some_and(|content| {
+                frame_hit(&self.decorations.style, content, point, 0.0).is_some()
+            })
+    }
+
+    /// Topmost window frame at a global point, with the part hit
+    ///
+    /// Windows in front, and panels, hide the frames behind them. The
+    /// resize handles keep the same width on screen at any zoom.
+    pub fn frame_under(&self, point: Point<f64, Logical>) -> Option<(WindowId, FrameHit)> {
+        let fullscreen = self
+            .output_at(point)
+            .and_then(|output| self.fullscreen_window_on(&output));
+        if fullscreen.is_some() || self.layer_covers(point) {
+            return None;
+        }
+
+        let canvas_point = self.global_to_canvas(point);
+        let zoom = self
+            .viewport_at(point)
+            .map_or(1.0, |viewport| viewport.zoom);
+        let handle = HANDLE_WIDTH / zoom;
+
+        for window in self.space.elements().rev() {
+            let Some(id) = self.window_id(window) else {
+                continue;
+            };
+            if self.has_frame(id)
+                && let Some(content) = self.window_node_rect(id)
+                && let Some(hit) = frame_hit(&self.decorations.style, content, canvas_point, handle)
+            {
+                return Some((id, hit));
+            }
+
+            if let Some(origin) = self.window_render_origin(window)
+                && window.is_in_input_region(&(canvas_point - origin))
+            {
+                return None;
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_hit() {
+        let style = FrameStyle::from_theme(&Theme::default());
+        assert_eq!((style.border, style.title_height), (2.0, 24.0));
+
+        let content = Rectangle::new((0.0, 0.0).into(), (100.0, 100.0).into());
+        let hit = |x: f64, y: f64| frame_hit(&style, content, Point::from((x, y)), 4.0);
+
+        assert_eq!(hit(50.0, -12.0), Some(FrameHit::Title));
+        assert_eq!(hit(50.0, 50.0), None);
+        assert_eq!(hit(-1.0, 50.0), Some(FrameHit::Edge(ResizeEdge::LEFT)));
+        assert_eq!(hit(-5.0, 50.0), Some(FrameHit::Edge(ResizeEdge::LEFT)));
+        assert_eq!(hit(-7.0, 50.0), None);
+        assert_eq!(
+            hit(101.0, 101.0),
+            Some(FrameHit::Edge(ResizeEdge::BOTTOM_RIGHT))
+        );
+        assert_eq!(hit(50.0, -25.0), Some(FrameHit::Edge(ResizeEdge::TOP)));
+        assert_eq!(hit(-1.0, -25.0), Some(FrameHit::Edge(ResizeEdge::TOP_LEFT)));
+        assert_eq!(hit(50.0, -29.0), None);
+
+        // The bottom corners stay clear of the window's square corners
+        assert!(style.bottom_radius() < style.radius);
+    }
+}
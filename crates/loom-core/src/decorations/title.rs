@@ -0,0 +1,234 @@
+//! Title text of node frames
+//!
+//! Titles are drawn with the theme's font family, found among the font
+//! files in the `fonts` directory of every XDG data directory by name
+//! (`Inter` matches `Inter.ttf` and `Inter-Regular.otf`), or else with a
+//! common sans-serif font. Without any font, frames have no titles.
+
+use crate::desktop::data_dirs;
+use fontdue::{Font, FontSettings};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Fonts tried when the theme's font isn't installed
+const FALLBACK_FONTS: [&str; 3] = ["DejaVu Sans", "Noto Sans", "Liberation Sans"];
+
+/// Maximum size of a font file that is read
+const MAX_FONT_FILE_SIZE: u64 = 32 * 1024 * 1024;
+
+/// Maximum depth of subdirectories searched below a fonts directory
+const MAX_FONTS_DEPTH: usize = 4;
+
+/// Maximum number of characters of a title that are laid out
+const MAX_TITLE_CHARS: usize = 256;
+
+/// Character ending titles that don't fit
+const ELLIPSIS: char = '…';
+
+/// A rasterized title, premultiplied ARGB with 4 bytes per pixel
+#[derive(Debug)]
+pub struct TitleBitmap {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+    /// Whether the title was cut to fit
+    pub cut: bool,
+}
+
+/// Load the font of a family, or a fallback font
+///
+/// Searching the font directories is slow, so this is only done when the
+/// theme changes.
+pub fn load_font(family: &str) -> Option<Font> {
+    let mut files = HashMap::new();
+    for dir in data_dirs() {
+        collect_font_files(&dir.join("fonts"), 0, &mut files);
+    }
+
+    for name in std::iter::once(family).chain(FALLBACK_FONTS) {
+        let name = font_key(name);
+        let Some(path) = files
+            .get(&name)
+            .or_else(|| files.get(&format!("{name}regular")))
+        else {
+            continue;
+        };
+        if let Some(font) = read_font(path) {
+            info!("Using font {:?} for titles", path);
+            return Some(font);
+        }
+    }
+
+    warn!("No font found for titles (tried {:?})", family);
+    None
+}
+
+/// Font files below a directory, by [`font_key`] of their name
+///
+/// The first file found for a key is kept.
+fn collect_font_files(dir: &Path, depth: usize, files: &mut HashMap<String, PathBuf>) {
+    if depth > MAX_FONTS_DEPTH {
+        return;
+    }
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+            collect_font_files(&path, depth + 1, files);
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("ttf") || ext.eq_ignore_ascii_case("otf"))
+            && let Some(stem) = path.file_stem().and_then(|stem| stem.to_str())
+        {
+            files.entry(font_key(stem)).or_insert(path);
+        }
+    }
+}
+
+/// Read and parse a font file, refusing oversized ones
+fn read_font(path: &Path) -> Option<Font> {
+    let size = std::fs::metadata(path).ok()?.len();
+    if size > MAX_FONT_FILE_SIZE {
+        warn!("Ignoring oversized font file {:?}", path);
+        return None;
+    }
+
+    let data = std::fs::read(path).ok()?;
+    Font::from_bytes(data, FontSettings::default())
+        .map_err(|e| warn!("Invalid font file {:?}: {}", path, e))
+        .ok()
+}
+
+/// Font name compared to file names: lowercase letters and digits only
+fn font_key(name: &str) -> String {
+    name.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Rasterize a title at a size in pixels, at most `max_width` pixels wide
+///
+/// Titles that don't fit are cut and end with an ellipsis. Returns `None`
+/// if nothing fits.
+pub fn render_title(
+    font: &Font,
+    text: &str,
+    px: f32,
+    color: [f32; 4],
+    max_width: usize,
+) -> Option<TitleBitmap> {
+    let line = font.horizontal_line_metrics(px)?;
+    let ascent = line.ascent.ceil();
+    let height = (ascent - line.descent.floor()) as usize;
+
+    let chars: Vec<char> = text
+        .chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_TITLE_CHARS)
+        .collect();
+    let advances: Vec<f32> = chars
+        .iter()
+        .map(|&c| font.metrics(c, px).advance_width)
+        .collect();
+    let ellipsis = font.metrics(ELLIPSIS, px).advance_width;
+
+    let (count, cut) = fit_title(&advances, ellipsis, max_width as f32);
+    let mut shown: Vec<char> = chars[..count].to_vec();
+    if cut {
+        shown.push(ELLIPSIS);
+    }
+    let width: f32 = advances[..count].iter().sum::<f32>() + if cut { ellipsis } else { 0.0 };
+    let width = (width.ceil() as usize).min(max_width);
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    // Glyph coverage, then colored with premultiplied alpha
+    let mut coverage = vec![0u8; width * height];
+    let mut pen = 0.0f32;
+    for c in shown {
+        let (metrics, bitmap) = font.rasterize(c, px);
+        let left = pen.round() as i64 + metrics.xmin as i64;
+        let top = ascent as i64 - metrics.height as i64 - metrics.ymin as i64;
+        pen += metrics.advance_width;
+
+        for (row, line) in bitmap.chunks(metrics.width.max(1)).enumerate() {
+            let y = top + row as i64;
+            if y < 0 || y >= height as i64 {
+                continue;
+            }
+            for (column, &value) in line.iter().enumerate() {
+                let x = left + column as i64;
+                if x < 0 || x >= width as i64 {
+                    continue;
+                }
+                let pixel = &mut coverage[y as usize * width + x as usize];
+                *pixel = (*pixel).max(value);
+            }
+        }
+    }
+
+    let [r, g, b, a] = color;
+    let pixels = coverage
+        .into_iter()
+        .flat_map(|value| {
+            let alpha = a * value as f32 / 255.0;
+            let channel = |c: f32| (c * alpha * 255.0).round() as u8;
+            // Little endian ARGB
+            [
+                channel(b),
+                channel(g),
+                channel(r),
+                (alpha * 255.0).round() as u8,
+            ]
+        })
+        .collect();
+
+    Some(TitleBitmap {
+        width,
+        height,
+        pixels,
+        cut,
+    })
+}
+
+/// Number of characters of a title shown within a width, and whether the
+/// title is cut and needs an ellipsis
+fn fit_title(advances: &[f32], ellipsis: f32, max_width: f32) -> (usize, bool) {
+    if advances.iter().sum::<f32>() <= max_width {
+        return (advances.len(), false);
+    }
+
+    let mut width = ellipsis;
+    let count = advances
+        .iter()
+        .take_while(|&&advance| {
+            width += advance;
+            width <= max_width
+        })
+        .count();
+    (count, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_title() {
+        let advances = [10.0, 10.0, 10.0, 10.0];
+        assert_eq!(fit_title(&advances, 8.0, 40.0), (4, false));
+        assert_eq!(fit_title(&advances, 8.0, 39.0), (3, true));
+        assert_eq!(fit_title(&advances, 8.0, 27.0), (1, true));
+        assert_eq!(fit_title(&advances, 8.0, 5.0), (0, true));
+        assert_eq!(fit_title(&[], 8.0, 0.0), (0, false));
+
+        assert_eq!(font_key("DejaVu Sans"), "dejavusans");
+        assert_eq!(font_key("Inter-Regular"), "interregular");
+    }
+}
@@ -0,0 +1,951 @@
+//! XDG desktop entries
+//!
+//! Installed applications are found through the `.desktop` files in the
+//! `applications` directory of every XDG data directory. The
+//! [`DesktopIndex`] keeps the launchable ones under their desktop file id
+//! (`org.gnome.Calculator` for `org.gnome.Calculator.desktop`, `kde-foo`
+//! for `kde/foo.desktop`); a file in a more important directory hides
+//! files with the same id in the others, even when it is `Hidden`. Ids
+//! that don't pass [`security::validate_app_id`] are left out, so every
+//! indexed app can be launched by [`LoomState::launch_app`].
+//!
+//! Free text like "calculator", "the web browser" or "firefx" is resolved
+//! to an app by fuzzy matching its names (localized and not), generic name,
+//! keywords and categories (see [`DesktopIndex::resolve`]). Entries marked
+//! `NoDisplay` are only found by their exact id.
+//!
+//! The index is built on a worker thread, since the directories can hold
+//! thousands of files, and swapped in when done: at startup, and shortly
+//! after the directories change (they are watched with inotify), so freshly
+//! installed applications can be launched right away.
+
+use crate::state::LoomState;
+use loom_config::keybindings::security;
+use smithay::reexports::calloop::{
+    Interest, Mode, PostAction,
+    channel::{self, Event},
+    generic::Generic,
+    timer::{TimeoutAction, Timer},
+};
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, mpsc};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Maximum size of a `.desktop` file that is read
+const MAX_DESKTOP_FILE_SIZE: u64 = 64 * 1024;
+
+/// Maximum number of desktop entries indexed
+const MAX_DESKTOP_ENTRIES: usize = 10_000;
+
+/// Depth of subdirectories searched below an `applications` directory
+const MAX_APPLICATIONS_DEPTH: usize = 4;
+
+/// Delay between a change to the directories and the rescan, so a package
+/// installing many files causes a single one
+const RESCAN_DELAY: Duration = Duration::from_millis(500);
+
+/// Lowest score of a fuzzy match that resolves a query
+const MIN_MATCH_SCORE: u32 = 25;
+
+/// Words dropped from the start of a query ("open the calculator")
+const QUERY_STOP_WORDS: [&str; 4] = ["the", "a", "an", "my"];
+
+/// The keys of a desktop entry needed to find and launch it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DesktopEntry {
+    /// Application name (`Name`), localized if possible
+    pub name: Option<String>,
+    /// Untranslated name, if the name is localized
+    pub untranslated_name: Option<String>,
+    /// Generic name like "Web Browser" (`GenericName`), localized if
+    /// possible
+    pub generic_name: Option<String>,
+    /// Search keywords (`Keywords`), localized and untranslated
+    pub keywords: Vec<String>,
+    /// Menu categories (`Categories`)
+    pub categories: Vec<String>,
+    /// Icon name or path (`Icon`)
+    pub icon: Option<String>,
+    /// Command line with field codes (`Exec`)
+    pub exec: String,
+    /// Working directory (`Path`)
+    pub working_dir: Option<PathBuf>,
+    /// Whether the entry is kept out of menus (`NoDisplay`)
+    pub no_display: bool,
+}
+
+/// An installed application
+#[derive(Debug, Clone)]
+pub struct DesktopApp {
+    /// Desktop file id, a valid app id
+    pub id: String,
+    /// Path of the `.desktop` file
+    pub path: PathBuf,
+    /// Contents of the entry
+    pub entry: DesktopEntry,
+}
+
+impl DesktopApp {
+    /// Name to show for the app
+    pub fn display_name(&self) -> &str {
+        self.entry.name.as_deref().unwrap_or(&self.id)
+    }
+
+    /// How well a (normalized) query matches the app, 0 for not at all
+    fn score(&self, query: &str) -> u32 {
+        let entry = &self.entry;
+        // The last part of reverse-DNS ids ("calculator" in
+        // "org.gnome.Calculator") is often the plain name
+        let short_id = self.id.rsplit('.').next().unwrap_or(&self.id);
+
+        let names = [entry.name.as_deref(), entry.untranslated_name.as_deref()]
+            .into_iter()
+            .flatten()
+            .chain([self.id.as_str(), short_id])
+            .map(|text| text_score(query, text));
+        let generic = entry
+            .generic_name
+            .iter()
+            .map(|text| text_score(query, text) * 4 / 5);
+        let keywords = entry
+            .keywords
+            .iter()
+            .map(|text| text_score(query, text) * 7 / 10);
+        // Categories are whole words like "Calculator" or "WebBrowser"
+        let categories = entry
+            .categories
+            .iter()
+            .filter(|category| category.eq_ignore_ascii_case(query))
+            .map(|_| 50);
+
+        names
+            .chain(generic)
+            .chain(keywords)
+            .chain(categories)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Installed applications, by desktop file id
+#[derive(Debug, Default)]
+pub struct DesktopIndex {
+    /// Launchable apps, sorted by id
+    apps: Vec<DesktopApp>,
+}
+
+impl DesktopIndex {
+    /// Index the applications of the XDG data directories, for the
+    /// current locale
+    pub fn scan() -> Self {
+        Self::scan_dirs(&data_dirs(), &locale_variants(&current_locale()))
+    }
+
+    /// Index the applications of data directories, most important first
+    ///
+    /// `locales` are the locale names to prefer for localized keys, best
+    /// first (see [`locale_variants`]).
+    pub fn scan_dirs(dirs: &[PathBuf], locales: &[String]) -> Self {
+        let mut files = Vec::new();
+        for dir in dirs {
+            collect_desktop_files(&dir.join("applications"), &mut files);
+        }
+
+        let mut seen = std::collections::HashSet::new();
+        let mut apps = Vec::new();
+        for (id, path) in files {
+            // The first file with an id wins, launchable or not
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+            if apps.len() >= MAX_DESKTOP_ENTRIES {
+                warn!("Too many desktop entries, indexing {}", MAX_DESKTOP_ENTRIES);
+                break;
+            }
+            if let Err(e) = security::validate_app_id(&id) {
+                debug!("Skipping desktop entry {:?}: {}", path, e);
+                continue;
+            }
+
+            let entry = read_desktop_file(&path)
+                .and_then(|contents| parse_desktop_entry(&contents, locales));
+            if let Some(entry) = entry {
+                apps.push(DesktopApp { id, path, entry });
+            }
+        }
+
+        apps.sort_by(|a, b| a.id.cmp(&b.id));
+        Self { apps }
+    }
+
+    /// Number of indexed apps
+    pub fn len(&self) -> usize {
+        self.apps.len()
+    }
+
+    /// Whether no apps are indexed
+    pub fn is_empty(&self) -> bool {
+        self.apps.is_empty()
+    }
+
+    /// Indexed apps, sorted by id
+    pub fn apps(&self) -> &[DesktopApp] {
+        &self.apps
+    }
+
+    /// App with a desktop file id
+    pub fn get(&self, id: &str) -> Option<&DesktopApp> {
+        self.apps
+            .binary_search_by(|app| app.id.as_str().cmp(id))
+            .ok()
+            .map(|index| &self.apps[index])
+    }
+
+    /// Apps matching a query, best first
+    ///
+    /// Entries marked `NoDisplay` are left out.
+    pub fn search(&self, query: &str) -> Vec<&DesktopApp> {
+        let query = normalize_query(query);
+        if query.is_empty() {
+            return Vec::new();
+        }
+
+        let mut matches: Vec<(u32, &DesktopApp)> = self
+            .apps
+            .iter()
+            .filter(|app| !app.entry.no_display)
+            .map(|app| (app.score(&query), app))
+            .filter(|&(score, _)| score >= MIN_MATCH_SCORE)
+            .collect();
+        // Better scores first, then shorter (more exact) names
+        matches.sort_by(|(a_score, a), (b_score, b)| {
+            b_score
+                .cmp(a_score)
+                .then_with(|| a.display_name().len().cmp(&b.display_name().len()))
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        matches.into_iter().map(|(_, app)| app).collect()
+    }
+
+    /// Resolve free text, like the app of a launch intent, to an app
+    ///
+    /// An exact desktop file id (in any case) wins; otherwise the best
+    /// fuzzy match, if it is good enough.
+    pub fn resolve(&self, query: &str) -> Option<&DesktopApp> {
+        let trimmed = query.trim();
+        self.get(trimmed)
+            .or_else(|| {
+                self.apps
+                    .iter()
+                    .find(|app| app.id.eq_ignore_ascii_case(trimmed))
+            })
+            .or_else(|| self.search(query).into_iter().next())
+    }
+}
+
+impl LoomState {
+    /// Resolve free text to the id of an installed app
+    ///
+    /// Used for `Intent::Launch` and the launcher setting; the id is valid
+    /// for [`LoomState::launch_app`].
+    pub fn resolve_app(&self, query: &str) -> Option<String> {
+        self.apps.resolve(query).map(|app| app.id.clone())
+    }
+
+    /// Start indexing the applications directories on a worker thread,
+    /// and watch them to index them again when they change
+    pub fn start_desktop_scanner(&mut self) {
+        let watcher = match DesktopWatcher::new() {
+            Ok(watcher) => Some(Arc::new(watcher)),
+            Err(e) => {
+                warn!("Cannot watch desktop entries: {}", e);
+                None
+            }
+        };
+
+        let (index_sender, index_channel) = channel::channel();
+        let (requests, worker_requests) = mpsc::channel();
+        let worker_watcher = watcher.clone();
+        let spawned = std::thread::Builder::new()
+            .name("desktop-scanner".to_string())
+            .spawn(move || scan_worker(worker_requests, index_sender, worker_watcher));
+        if let Err(e) = spawned {
+            warn!("Cannot start desktop scanner, indexing now: {}", e);
+            self.apps = DesktopIndex::scan();
+            return;
+        }
+
+        let result = self
+            .loop_handle
+            .insert_source(index_channel, |event, _, state| {
+                if let Event::Msg(index) = event {
+                    state.apps = index;
+                    info!("Indexed {} desktop entries", state.apps.len());
+                }
+            });
+        if let Err(e) = result {
+            warn!("Cannot receive desktop entries, indexing now: {}", e);
+            self.apps = DesktopIndex::scan();
+            return;
+        }
+
+        self.desktop_scanner = Some(DesktopScanner {
+            requests,
+            rescan_pending: false,
+        });
+        self.request_desktop_scan();
+
+        let Some(watcher) = watcher else {
+            return;
+        };
+        let result = self.loop_handle.insert_source(
+            Generic::new(watcher, Interest::READ, Mode::Level),
+            |_, watcher, state| {
+                watcher.drain();
+                state.schedule_desktop_rescan();
+                Ok(PostAction::Continue)
+            },
+        );
+        if let Err(e) = result {
+            warn!("Cannot watch desktop entries: {}", e);
+        }
+    }
+
+    /// Have the worker thread index the directories again
+    fn request_desktop_scan(&mut self) {
+        if let Some(scanner) = &self.desktop_scanner
+            && scanner.requests.send(()).is_err()
+        {
+            warn!("Desktop scanner stopped");
+            self.desktop_scanner = None;
+        }
+    }
+
+    /// Index the directories again after a short delay, unless a rescan is
+    /// already scheduled
+    fn schedule_desktop_rescan(&mut self) {
+        let Some(scanner) = &mut self.desktop_scanner else {
+            return;
+        };
+        if scanner.rescan_pending {
+            return;
+        }
+        scanner.rescan_pending = true;
+
+        let timer = Timer::from_duration(RESCAN_DELAY);
+        let result = self.loop_handle.insert_source(timer, |_, _, state| {
+            if let Some(scanner) = &mut state.desktop_scanner {
+                scanner.rescan_pending = false;
+            }
+            debug!("Desktop entries changed, indexing them again");
+            state.request_desktop_scan();
+            TimeoutAction::Drop
+        });
+        if let Err(e) = result {
+            warn!("Cannot schedule desktop entry rescan: {}", e);
+            if let Some(scanner) = &mut self.desktop_scanner {
+                scanner.rescan_pending = false;
+            }
+        }
+    }
+}
+
+/// Handle of the worker thread indexing the applications directories
+#[derive(Debug)]
+pub struct DesktopScanner {
+    /// Wakes the worker up to index the directories again
+    requests: mpsc::Sender<()>,
+    /// Whether a rescan is scheduled
+    rescan_pending: bool,
+}
+
+/// Index the directories on request, until the compositor goes away
+///
+/// Requests made during a scan are coalesced into a single next scan.
+/// Directories are watched again before every scan, since new
+/// subdirectories need watches of their own.
+fn scan_worker(
+    requests: mpsc::Receiver<()>,
+    indexes: channel::Sender<DesktopIndex>,
+    watcher: Option<Arc<DesktopWatcher>>,
+) {
+    while requests.recv().is_ok() {
+        while requests.try_recv().is_ok() {}
+
+        if let Some(watcher) = &watcher {
+            watcher.watch(&data_dirs());
+        }
+        if indexes.send(DesktopIndex::scan()).is_err() {
+            break;
+        }
+    }
+}
+
+/// inotify instance watching the applications directories
+#[derive(Debug)]
+struct DesktopWatcher {
+    fd: OwnedFd,
+}
+
+impl DesktopWatcher {
+    /// Events that change the set of desktop files
+    const APPLICATIONS_MASK: u32 = libc::IN_CREATE
+        | libc::IN_DELETE
+        | libc::IN_CLOSE_WRITE
+        | libc::IN_MOVED_FROM
+        | libc::IN_MOVED_TO
+        | libc::IN_ONLYDIR;
+
+    /// Events of a data directory that create its applications directory
+    const DATA_DIR_MASK: u32 = libc::IN_CREATE | libc::IN_MOVED_TO | libc::IN_ONLYDIR;
+
+    fn new() -> io::Result<Self> {
+        // SAFETY: inotify_init1 has no memory safety requirements
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the descriptor was just created and is owned here only
+        Ok(Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+        })
+    }
+
+    /// Watch data directories and their applications directories
+    ///
+    /// Watching a directory again is harmless, so this is repeated after
+    /// every change to pick up new directories.
+    fn watch(&self, data_dirs: &[PathBuf]) {
+        for dir in data_dirs {
+            self.add_watch(dir, Self::DATA_DIR_MASK);
+            let mut dirs = Vec::new();
+            collect_dirs(&dir.join("applications"), 0, &mut dirs);
+            for dir in dirs {
+                self.add_watch(&dir, Self::APPLICATIONS_MASK);
+            }
+        }
+    }
+
+    fn add_watch(&self, dir: &Path, mask: u32) {
+        let Ok(path) = CString::new(dir.as_os_str().as_bytes()) else {
+            return;
+        };
+        // SAFETY: `path` is a valid C string for the duration of the call
+        unsafe { libc::inotify_add_watch(self.fd.as_raw_fd(), path.as_ptr(), mask) };
+    }
+
+    /// Discard the pending events; their details don't matter since the
+    /// whole index is rebuilt
+    fn drain(&self) {
+        let mut buffer = [0u8; 4096];
+        loop {
+            // SAFETY: `buffer` is valid for writes of its length
+            let read = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    buffer.as_mut_ptr().cast(),
+                    buffer.len(),
+                )
+            };
+            if read <= 0 {
+                break;
+            }
+        }
+    }
+}
+
+impl AsFd for DesktopWatcher {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+/// A directory and its subdirectories, down to the depth limit
+fn collect_dirs(dir: &Path, depth: usize, dirs: &mut Vec<PathBuf>) {
+    if depth > MAX_APPLICATIONS_DEPTH || !dir.is_dir() {
+        return;
+    }
+    dirs.push(dir.to_path_buf());
+
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+            collect_dirs(&entry.path(), depth + 1, dirs);
+        }
+    }
+}
+
+/// `.desktop` files below an applications directory, with their desktop
+/// file ids
+///
+/// Files are sorted, so the index doesn't depend on directory order.
+fn collect_desktop_files(applications: &Path, files: &mut Vec<(String, PathBuf)>) {
+    let mut dirs = Vec::new();
+    collect_dirs(applications, 0, &mut dirs);
+
+    let mut found = Vec::new();
+    for dir in dirs {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "desktop")
+                && let Some(id) = desktop_file_id(applications, &path)
+            {
+                found.push((id, path));
+            }
+        }
+    }
+
+    found.sort();
+    files.extend(found);
+}
+
+/// Desktop file id of a file below an applications directory
+///
+/// The path relative to the directory, with `/` replaced by `-` and
+/// without the `.desktop` extension.
+fn desktop_file_id(applications: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(applications).ok()?.with_extension("");
+    let parts: Option<Vec<&str>> = relative.iter().map(|part| part.to_str()).collect();
+    Some(parts?.join("-"))
+}
+
+/// Find the `.desktop` file of an application in the XDG data directories
+pub fn desktop_file_path(app_id: &str) -> Option<PathBuf> {
+    let file_name = format!("{app_id}.desktop");
+    data_dirs()
+        .into_iter()
+        .map(|dir| dir.join("applications").join(&file_name))
+        .find(|path| path.is_file())
+}
+
+/// XDG data directories, most important first
+pub fn data_dirs() -> Vec<PathBuf> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")));
+
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|dirs| !dirs.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+
+    data_home
+        .into_iter()
+        .chain(
+            data_dirs
+                .split(':')
+                .map(PathBuf::from)
+                .filter(|dir| dir.is_absolute()),
+        )
+        .collect()
+}
+
+/// Read a `.desktop` file, refusing oversized ones
+pub fn read_desktop_file(path: &Path) -> Option<String> {
+    let size = std::fs::metadata(path).ok()?.len();
+    if size > MAX_DESKTOP_FILE_SIZE {
+        warn!("Ignoring oversized desktop file {:?}", path);
+        return None;
+    }
+    std::fs::read_to_string(path).ok()
+}
+
+/// Locale of messages, from the environment
+fn current_locale() -> String {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default()
+}
+
+/// Names to look up localized keys with, best first
+///
+/// `de_DE.UTF-8@euro` gives `de_DE@euro`, `de_DE`, `de@euro` and `de`, as
+/// the Desktop Entry Specification describes.
+pub fn locale_variants(locale: &str) -> Vec<String> {
+    let (locale, modifier) = match locale.split_once('@') {
+        Some((locale, modifier)) => (locale, Some(modifier)),
+        None => (locale, None),
+    };
+    let locale = locale.split('.').next().unwrap_or_default();
+    if locale.is_empty() || locale == "C" || locale == "POSIX" {
+        return Vec::new();
+    }
+    let (lang, country) = match locale.split_once('_') {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (locale, None),
+    };
+
+    let mut variants = Vec::new();
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+        variants.push(format!("{lang}_{country}@{modifier}"));
+    }
+    if let Some(country) = country {
+        variants.push(format!("{lang}_{country}"));
+    }
+    if let Some(modifier) = modifier {
+        variants.push(format!("{lang}@{modifier}"));
+    }
+    variants.push(lang.to_string());
+    variants
+}
+
+/// Parse the `[Desktop Entry]` group of a `.desktop` file
+///
+/// Entries that are hidden, not applications or have no `Exec` key yield
+/// `None`. Localized keys are taken for the best of `locales`.
+pub fn parse_desktop_entry(contents: &str, locales: &[String]) -> Option<DesktopEntry> {
+    let mut in_entry = false;
+    let mut entry = DesktopEntry::default();
+    // Locale rank of the localized values taken so far (lower is better)
+    let unlocalized = locales.len();
+    let (mut name_rank, mut generic_rank, mut keywords_rank) = (usize::MAX, usize::MAX, usize::MAX);
+    let mut untranslated_keywords = Vec::new();
+
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_entry = line == "[Desktop Entry]";
+            continue;
+        }
+        if !in_entry || line.starts_with('#') {
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let (key, rank) = match key.trim().split_once('[') {
+            Some((key, locale)) => {
+                let rank = locale
+                    .strip_suffix(']')
+                    .and_then(|locale| locales.iter().position(|l| l == locale));
+                match rank {
+                    Some(rank) => (key, rank),
+                    None => continue,
+                }
+            }
+            None => (key.trim(), unlocalized),
+        };
+        let value = unescape_value(value.trim());
+
+        match (key, value.as_str()) {
+            ("Type", kind) if kind != "Application" => return None,
+            ("Hidden", "true") => return None,
+            ("NoDisplay", no_display) => entry.no_display = no_display == "true",
+            ("Name", _) => {
+                if rank == unlocalized {
+                    entry.untranslated_name = Some(value.clone());
+                }
+                if rank < name_rank {
+                    name_rank = rank;
+                    entry.name = Some(value);
+                }
+            }
+            ("GenericName", _) if rank < generic_rank => {
+                generic_rank = rank;
+                entry.generic_name = Some(value);
+            }
+            ("Keywords", _) => {
+                let keywords = split_list(&value);
+                if rank == unlocalized {
+                    untranslated_keywords = keywords.clone();
+                }
+                if rank < keywords_rank {
+                    keywords_rank = rank;
+                    entry.keywords = keywords;
+                }
+            }
+            ("Categories", _) => entry.categories = split_list(&value),
+            ("Icon", _) if !value.is_empty() => entry.icon = Some(value),
+            ("Exec", _) if rank == unlocalized => entry.exec = value,
+            ("Path", _) if !value.is_empty() => entry.working_dir = Some(value.into()),
+            _ => {}
+        }
+    }
+
+    if entry.untranslated_name == entry.name {
+        entry.untranslated_name = None;
+    }
+    for keyword in untranslated_keywords {
+        if !entry.keywords.contains(&keyword) {
+            entry.keywords.push(keyword);
+        }
+    }
+
+    (!entry.exec.is_empty()).then_some(entry)
+}
+
+/// Split a `;`-separated list value
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(';')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Undo the escapes of a desktop entry string value (`\s`, `\n`, `\t`,
+/// `\r` and `\\`)
+pub fn unescape_value(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => unescaped.push(' '),
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => {
+                // Other escapes (like `\"` in Exec) are for the next level
+                if other != '\\' {
+                    unescaped.push('\\');
+                }
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+
+    unescaped
+}
+
+/// Lowercase a query and drop leading articles
+fn normalize_query(query: &str) -> String {
+    let query = query.trim().to_lowercase();
+    let mut words: &[&str] = &query.split_whitespace().collect::<Vec<_>>();
+    while let [first, rest @ ..] = words
+        && !rest.is_empty()
+        && QUERY_STOP_WORDS.contains(first)
+    {
+        words = rest;
+    }
+    words.join(" ")
+}
+
+/// How well a normalized query matches some text, from 0 to 100
+fn text_score(query: &str, text: &str) -> u32 {
+    let text = text.to_lowercase();
+    let mut words = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty());
+
+    if text == query {
+        100
+    } else if text.starts_with(query) {
+        80
+    } else if words.clone().any(|word| word.starts_with(query)) {
+        70
+    } else if text.contains(query) {
+        50
+    } else if query.chars().count() >= 4
+        && words.any(|word| edit_distance(word, query) <= 1 + query.len() / 8)
+    {
+        40
+    } else if query.chars().count() >= 3 && is_subsequence(query, &text) {
+        30
+    } else {
+        0
+    }
+}
+
+/// Whether the characters of a query appear in order in some text
+fn is_subsequence(query: &str, text: &str) -> bool {
+    let mut text = text.chars();
+    query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .all(|c| text.any(|t| t == c))
+}
+
+/// Levenshtein distance between two words
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, &b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != b);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(id: &str, entry: &str) -> DesktopApp {
+        DesktopApp {
+            id: id.to_string(),
+            path: PathBuf::from(format!("{id}.desktop")),
+            entry: parse_desktop_entry(entry, &[]).unwrap(),
+        }
+    }
+
+    fn index() -> DesktopIndex {
+        let mut apps = vec![
+            app(
+                "firefox",
+                "[Desktop Entry]\nType=Application\nName=Firefox\n\
+                 GenericName=Web Browser\nKeywords=Internet;WWW;\n\
+                 Categories=Network;WebBrowser;\nExec=firefox %u\n",
+            ),
+            app(
+                "org.gnome.Calculator",
+                "[Desktop Entry]\nType=Application\nName=Calculator\n\
+                 Categories=Utility;Calculator;\nExec=gnome-calculator\n",
+            ),
+            app(
+                "org.gnome.Characters",
+                "[Desktop Entry]\nType=Application\nName=Characters\n\
+                 Categories=Utility;\nExec=gnome-characters\n",
+            ),
+            app(
+                "mimeinfo-helper",
+                "[Desktop Entry]\nType=Application\nName=Calc Helper\n\
+                 NoDisplay=true\nExec=helper\n",
+            ),
+        ];
+        apps.sort_by(|a, b| a.id.cmp(&b.id));
+        DesktopIndex { apps }
+    }
+
+    #[test]
+    fn test_parse_desktop_entry() {
+        let entry = "\
+[Desktop Entry]
+Type=Application
+Name=Files
+Name[de]=Dateien
+Name[de_DE]=Dateien (DE)
+GenericName=File Manager
+GenericName[de]=Dateiverwaltung
+Keywords=folder;manager;
+Keywords[de]=Ordner;
+Exec=nautilus --new-window %U
+Exec[de]=ignored
+Path=/tmp
+
+[Desktop Action new-window]
+Exec=nautilus --other
+";
+        let locales = locale_variants("de_DE.UTF-8");
+        let entry = parse_desktop_entry(entry, &locales).unwrap();
+        assert_eq!(entry.name.as_deref(), Some("Dateien (DE)"));
+        assert_eq!(entry.untranslated_name.as_deref(), Some("Files"));
+        assert_eq!(entry.generic_name.as_deref(), Some("Dateiverwaltung"));
+        assert_eq!(entry.keywords, ["Ordner", "folder", "manager"]);
+        assert_eq!(entry.exec, "nautilus --new-window %U");
+        assert_eq!(entry.working_dir, Some(PathBuf::from("/tmp")));
+    }
+
+    #[test]
+    fn test_parse_desktop_entry_rejects_hidden_and_links() {
+        let hidden = "[Desktop Entry]\nType=Application\nHidden=true\nExec=app\n";
+        assert_eq!(parse_desktop_entry(hidden, &[]), None);
+
+        let link = "[Desktop Entry]\nType=Link\nURL=https://example.com\n";
+        assert_eq!(parse_desktop_entry(link, &[]), None);
+
+        let no_exec = "[Desktop Entry]\nType=Application\n";
+        assert_eq!(parse_desktop_entry(no_exec, &[]), None);
+    }
+
+    #[test]
+    fn test_locale_variants() {
+        assert_eq!(
+            locale_variants("sr_RS.UTF-8@latin"),
+            ["sr_RS@latin", "sr_RS", "sr@latin", "sr"]
+        );
+        assert_eq!(locale_variants("fr"), ["fr"]);
+        assert!(locale_variants("C.UTF-8").is_empty());
+        assert!(locale_variants("").is_empty());
+    }
+
+    #[test]
+    fn test_resolve() {
+        let index = index();
+        let resolve = |query| index.resolve(query).map(|app| app.id.as_str());
+
+        assert_eq!(resolve("firefox"), Some("firefox"));
+        assert_eq!(resolve("the calculator"), Some("org.gnome.Calculator"));
+        assert_eq!(resolve("web browser"), Some("firefox"));
+        assert_eq!(resolve("WebBrowser"), Some("firefox"));
+        assert_eq!(resolve("internet"), Some("firefox"));
+        // Typos and abbreviations
+        assert_eq!(resolve("firefx"), Some("firefox"));
+        assert_eq!(resolve("ffox"), Some("firefox"));
+        assert_eq!(resolve("calc"), Some("org.gnome.Calculator"));
+        // Exact ids, even of entries kept out of menus
+        assert_eq!(
+            resolve("ORG.GNOME.CHARACTERS"),
+            Some("org.gnome.Characters")
+        );
+        assert_eq!(resolve("mimeinfo-helper"), Some("mimeinfo-helper"));
+        assert_eq!(resolve("calc helper"), None);
+
+        assert_eq!(resolve("spreadsheet"), None);
+        assert_eq!(resolve(""), None);
+    }
+
+    #[test]
+    fn test_scan_dirs() {
+        let root = std::env::temp_dir().join(format!("loom-desktop-{}", std::process::id()));
+        let (home, system) = (root.join("home"), root.join("system"));
+        let write = |dir: &Path, file: &str, contents: &str| {
+            let path = dir.join("applications").join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        };
+
+        let app =
+            |name: &str| format!("[Desktop Entry]\nType=Application\nName={name}\nExec=app\n");
+        write(&system, "editor.desktop", &app("Editor"));
+        write(&system, "kde/konsole.desktop", &app("Konsole"));
+        write(&system, "removed.desktop", &app("Removed"));
+        write(&system, "bad id!.desktop", &app("Bad"));
+        write(&home, "editor.desktop", &app("My Editor"));
+        write(
+            &home,
+            "removed.desktop",
+            "[Desktop Entry]\nType=Application\nHidden=true\n",
+        );
+
+        let index = DesktopIndex::scan_dirs(&[home, system], &[]);
+        std::fs::remove_dir_all(&root).unwrap();
+
+        let ids: Vec<&str> = index.apps().iter().map(|app| app.id.as_str()).collect();
+        assert_eq!(ids, ["editor", "kde-konsole"]);
+        assert_eq!(index.get("editor").unwrap().display_name(), "My Editor");
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("firefox", "firefox"), 0);
+        assert_eq!(edit_distance("firefox", "firefx"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+}
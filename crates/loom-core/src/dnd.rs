@@ -0,0 +1,651 @@
+//! Drag and drop
+//!
+//! Clients drag between each other through `wl_data_device`; the
+//! compositor draws the drag icon, which follows the pointer through the
+//! canvas transform like the nodes under it, so it zooms with them.
+//!
+//! A drag with a data source is relayed through a compositor drag: the
+//! targets get the compositor's offers, and their requests are passed on
+//! to the source. Smithay cancels client drags that no client accepted,
+//! while the relay lets the canvas be a drop target of its own.
+//!
+//! Dropping onto the canvas background makes a node of the content: a
+//! media node for a dropped file (`text/uri-list`) and a note for dropped
+//! text. The canvas copies the content, reading it from the source client
+//! itself, and gives up on anything larger than
+//! [`MAX_CLIPBOARD_SIZE`](crate::security::MAX_CLIPBOARD_SIZE) or slower
+//! than [`DROP_READ_TIMEOUT`].
+
+use crate::security::MAX_CLIPBOARD_SIZE;
+use crate::state::LoomState;
+use loom_canvas::{Node, NodeType};
+use smithay::{
+    reexports::{
+        calloop::{
+            Interest, Mode, PostAction, RegistrationToken,
+            generic::Generic,
+            timer::{TimeoutAction, Timer},
+        },
+        wayland_server::{
+            Resource,
+            protocol::{
+                wl_data_device,
+                wl_data_device_manager::DndAction,
+                wl_data_source::{self, WlDataSource},
+                wl_surface::WlSurface,
+            },
+        },
+    },
+    utils::{Logical, Point, Serial},
+    wayland::{
+        compositor::{self, SurfaceAttributes, with_states},
+        selection::data_device::{DND_ICON_ROLE, start_dnd, with_source_metadata},
+    },
+};
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::fd::{AsFd, FromRawFd, OwnedFd};
+use std::rc::Rc;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Mime types of dropped content made into nodes, by preference
+const DROP_MIME_TYPES: [&str; 4] = [
+    "text/uri-list",
+    "text/plain;charset=utf-8",
+    "UTF8_STRING",
+    "text/plain",
+];
+
+/// Time a drag source has to send all of its dropped content
+///
+/// A source that keeps the pipe open without writing would otherwise keep
+/// its reader around for good.
+pub const DROP_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Size of nodes made from dropped content, in canvas units
+const DROPPED_NODE_SIZE: (f64, f64) = (300.0, 200.0);
+
+/// Maximum length of the label of a dropped note, in characters
+const MAX_NOTE_LABEL_CHARS: usize = 64;
+
+/// Drag in progress
+#[derive(Debug, Default)]
+pub struct DndState {
+    /// Data source of the drag, `None` within a single client
+    pub source: Option<WlDataSource>,
+    /// Icon following the pointer
+    pub icon: Option<DndIcon>,
+    /// Source of a drop on a client, until the client finished with it
+    pub dropped: Option<WlDataSource>,
+}
+
+/// Icon surface of a drag, and its offset from the pointer
+#[derive(Debug)]
+pub struct DndIcon {
+    pub surface: WlSurface,
+    pub offset: Point<i32, Logical>,
+}
+
+impl LoomState {
+    /// Track a drag started by a client
+    ///
+    /// The source of an earlier drop stays around for its target.
+    pub fn start_dnd(&mut self, source: Option<WlDataSource>, icon: Option<WlSurface>) {
+        self.dnd.source = source;
+        self.dnd.icon = icon.map(|surface| DndIcon {
+            surface,
+            offset: Point::from((0, 0)),
+        });
+    }
+
+    /// Forget the drag once dropped
+    pub fn end_dnd(&mut self) {
+        self.dnd.source = None;
+        self.dnd.icon = None;
+    }
+
+    /// Relay a client's drag with a data source through a compositor drag
+    ///
+    /// Returns whether the drag started, which it only does in response to
+    /// a button press that still holds the pointer.
+    pub fn start_source_drag(
+        &mut self,
+        device: &wl_data_device::WlDataDevice,
+        source: WlDataSource,
+        icon: Option<WlSurface>,
+        serial: Serial,
+    ) -> bool {
+        let Some(pointer) = self.seat.get_pointer().filter(|p| p.has_grab(serial)) else {
+            return false;
+        };
+        if let Some(icon) = &icon
+            && compositor::give_role(icon, DND_ICON_ROLE).is_err()
+        {
+            device.post_error(
+                wl_data_device::Error::Role,
+                "Given surface already has an other role",
+            );
+            return true;
+        }
+        let Ok(metadata) = with_source_metadata(&source, |metadata| metadata.clone()) else {
+            return true;
+        };
+
+        self.start_dnd(Some(source), icon);
+        let seat = self.seat.clone();
+        let display_handle = self.display_handle.clone();
+        start_dnd(
+            &display_handle,
+            &seat,
+            self,
+            serial,
+            pointer.grab_start_data(),
+            None,
+            metadata,
+        );
+        true
+    }
+
+    /// Pass a target's accepted mime type on to the source
+    pub fn dnd_accepted(&mut self, mime_type: Option<String>) {
+        if let Some(source) = &self.dnd.source {
+            source.target(mime_type);
+        }
+    }
+
+    /// Pass the action chosen with a target on to the source
+    pub fn dnd_action(&mut self, action: DndAction) {
+        if let Some(source) = &self.dnd.source
+            && source.version() >= wl_data_source::EVT_ACTION_SINCE
+        {
+            source.action(action);
+        }
+    }
+
+    /// Have the source send its content to a target
+    pub fn dnd_send(&mut self, mime_type: String, fd: OwnedFd) {
+        if let Some(source) = self.dnd.source.as_ref().or(self.dnd.dropped.as_ref()) {
+            source.send(mime_type, fd.as_fd());
+        }
+    }
+
+    /// Drop the relayed drag on the client under the pointer
+    ///
+    /// The grab cancels the drop right after when the client didn't accept
+    /// it, so the source only hears of the drop once that had its chance.
+    pub fn dnd_dropped(&mut self) {
+        self.dnd.icon = None;
+        let Some(source) = self.dnd.source.take() else {
+            return;
+        };
+
+        self.dnd.dropped = Some(source.clone());
+        self.loop_handle.insert_idle(move |state| {
+            if state.dnd.dropped.as_ref() == Some(&source)
+                && source.version() >= wl_data_source::EVT_DND_DROP_PERFORMED_SINCE
+            {
+                source.dnd_drop_performed();
+            }
+        });
+    }
+
+    /// Tell the source that nobody accepted its drop
+    pub fn dnd_cancelled(&mut self) {
+        if let Some(source) = self.dnd.dropped.take() {
+            source.cancelled();
+        }
+    }
+
+    /// Tell the source that the target is done with the dropped content
+    pub fn dnd_finished(&mut self) {
+        if let Some(source) = self.dnd.dropped.take() {
+            finish_drop(&source, true);
+        }
+    }
+
+    /// Move the drag icon by the offset of a commit
+    pub fn dnd_icon_commit(&mut self, surface: &WlSurface) {
+        let Some(icon) = self
+            .dnd
+            .icon
+            .as_mut()
+            .filter(|icon| &icon.surface == surface)
+        else {
+            return;
+        };
+
+        let delta = with_states(surface, |states| {
+            states
+                .cached_state
+                .get::<SurfaceAttributes>()
+                .current()
+                .buffer_delta
+                .take()
+        });
+        if let Some(delta) = delta {
+            icon.offset += delta;
+        }
+    }
+
+    /// Take the drop of a drag released over the canvas background
+    ///
+    /// Must be called before the release reaches the drag, which would
+    /// otherwise cancel the source since no client accepted the drop. The
+    /// canvas accepts a copy of the content, which is read in the
+    /// background and made into a node where it was dropped.
+    pub fn drop_on_canvas(&mut self, point: Point<f64, Logical>) {
+        let Some(source) = self.dnd.source.clone() else {
+            return;
+        };
+        if self.window_under(point).is_some() || self.layer_covers(point) {
+            return;
+        }
+
+        let Ok(Some((mime_type, actions))) = with_source_metadata(&source, |metadata| {
+            drop_mime_type(&metadata.mime_types)
+                .map(|mime_type| (mime_type.to_string(), metadata.dnd_action))
+        }) else {
+            debug!("Nothing to make a node of in the drop");
+            return;
+        };
+        // Nodes are copies, a source that only moves its content keeps it
+        let has_actions = source.version() >= wl_data_source::EVT_ACTION_SINCE;
+        if has_actions && !actions.contains(DndAction::Copy) {
+            debug!("Drop can't be copied onto the canvas");
+            return;
+        }
+
+        // The canvas is the target from here on, the drag just ends
+        self.dnd.source = None;
+        source.target(Some(mime_type.clone()));
+        if has_actions {
+            source.action(DndAction::Copy);
+        }
+        if source.version() >= wl_data_source::EVT_DND_DROP_PERFORMED_SINCE {
+            source.dnd_drop_performed();
+        }
+
+        let location = self.global_to_canvas(point);
+        if let Err(e) = self.receive_drop(&source, mime_type, location) {
+            warn!("Cannot receive dropped content: {}", e);
+            source.cancelled();
+        }
+    }
+
+    /// Ask the source for its content and read it as it comes
+    ///
+    /// The source hears whether a node was made of it once the read is
+    /// over, which it is after [`DROP_READ_TIMEOUT`] at the latest.
+    fn receive_drop(
+        &mut self,
+        source: &WlDataSource,
+        mime_type: String,
+        location: Point<f64, Logical>,
+    ) -> io::Result<()> {
+        let (read_end, write_end) = pipe()?;
+        source.send(mime_type.clone(), write_end.as_fd());
+        drop(write_end);
+
+        // Removed by whichever of the read and the timeout ends first
+        let timeout = Rc::new(Cell::new(None::<RegistrationToken>));
+        let read_timeout = timeout.clone();
+        let mut content = Vec::new();
+        let read_source = source.clone();
+        let read = self
+            .loop_handle
+            .insert_source(
+                Generic::new(File::from(read_end), Interest::READ, Mode::Level),
+                move |_, file, state| {
+                    let mut added = false;
+                    let action = read_dropped(file, &mut content, |content| {
+                        added = state.add_dropped_node(&mime_type, content, location);
+                    });
+                    if action == PostAction::Remove {
+                        finish_drop(&read_source, added);
+                        if let Some(token) = read_timeout.take() {
+                            state.loop_handle.remove(token);
+                        }
+                    }
+                    Ok(action)
+                },
+            )
+            .map_err(|e| io::Error::other(e.to_string()))?;
+
+        let timeout_source = source.clone();
+        let timer = Timer::from_duration(DROP_READ_TIMEOUT);
+        let result = self.loop_handle.insert_source(timer, move |_, _, state| {
+            warn!(
+                "Ignoring drop: content not sent within {:?}",
+                DROP_READ_TIMEOUT
+            );
+            finish_drop(&timeout_source, false);
+            state.loop_handle.remove(read);
+            TimeoutAction::Drop
+        });
+        match result {
+            Ok(token) => timeout.set(Some(token)),
+            Err(e) => {
+                self.loop_handle.remove(read);
+                return Err(io::Error::other(e.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Make a node of dropped content at a canvas location
+    ///
+    /// Returns whether the node was added.
+    fn add_dropped_node(
+        &mut self,
+        mime_type: &str,
+        content: &[u8],
+        location: Point<f64, Logical>,
+    ) -> bool {
+        let Some((node_type, label)) = dropped_content(mime_type, content) else {
+            debug!("Dropped {} content is empty or invalid", mime_type);
+            return false;
+        };
+
+        let result = self.canvas.allocate_node_id().and_then(|id| {
+            let node = Node::new(id, node_type, location.x, location.y)
+                .with_size(DROPPED_NODE_SIZE.0, DROPPED_NODE_SIZE.1)
+                .with_label(label);
+            self.canvas.add_node(node)
+        });
+        match result {
+            Ok(id) => {
+                debug!("Dropped {} content as node {}", mime_type, id);
+                self.queue_redraw();
+                true
+            }
+            Err(e) => {
+                warn!("Cannot add node for dropped content: {}", e);
+                false
+            }
+        }
+    }
+}
+
+/// Tell the source of a drop whether its target took the content
+fn finish_drop(source: &WlDataSource, done: bool) {
+    if !done {
+        source.cancelled();
+    } else if source.version() >= wl_data_source::EVT_DND_FINISHED_SINCE {
+        source.dnd_finished();
+    }
+}
+
+/// Read the dropped content available in a pipe
+///
+/// Calls `done` with the whole content once the source closed the pipe.
+/// Returns whether to keep reading.
+fn read_dropped(file: &File, content: &mut Vec<u8>, done: impl FnOnce(&[u8])) -> PostAction {
+    let mut buffer = [0u8; 8192];
+    loop {
+        match (&*file).read(&mut buffer) {
+            Ok(0) => {
+                done(content);
+                return PostAction::Remove;
+            }
+            Ok(read) if content.len() + read > MAX_CLIPBOARD_SIZE => {
+                warn!(
+                    "Ignoring drop: content exceeds {} bytes",
+                    MAX_CLIPBOARD_SIZE
+                );
+                return PostAction::Remove;
+            }
+            Ok(read) => content.extend_from_slice(&buffer[..read]),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return PostAction::Continue,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => {
+                warn!("Cannot read dropped content: {}", e);
+                return PostAction::Remove;
+            }
+        }
+    }
+}
+
+/// Preferred mime type of dropped content among those offered
+fn drop_mime_type(offered: &[String]) -> Option<&'static str> {
+    DROP_MIME_TYPES
+        .into_iter()
+        .find(|mime_type| offered.iter().any(|offered| offered == mime_type))
+}
+
+/// Node type and label for dropped content
+///
+/// A URI list becomes a media node of its first local file; text becomes
+/// a note labelled with its first line.
+fn dropped_content(mime_type: &str, content: &[u8]) -> Option<(NodeType, String)> {
+    let text = std::str::from_utf8(content).ok()?;
+
+    if mime_type == "text/uri-list" {
+        let path = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .find_map(file_uri_path)?;
+        let label = path.rsplit('/').next().unwrap_or(&path).to_string();
+        return Some((NodeType::Media { path }, label));
+    }
+
+    let text = text.trim_end_matches('\0');
+    let label: String = text
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())?
+        .chars()
+        .take(MAX_NOTE_LABEL_CHARS)
+        .collect();
+    Some((
+        NodeType::Note {
+            text: text.to_string(),
+        },
+        label,
+    ))
+}
+
+/// Local path of a `file://` URI, percent-decoded
+fn file_uri_path(uri: &str) -> Option<String> {
+    let rest = uri.strip_prefix("file://")?;
+    // An empty or `localhost` host, then an absolute path
+    let path = rest.strip_prefix("localhost").unwrap_or(rest);
+    if !path.starts_with('/') {
+        return None;
+    }
+
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    let path = String::from_utf8(decoded).ok()?;
+    (!path.contains('\0')).then_some(path)
+}
+
+/// Non-blocking pipe for reading content from a client
+fn pipe() -> io::Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    // SAFETY: `fds` has room for the two descriptors
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: both descriptors were just created and are owned here only
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{
+        headless::HeadlessBackend,
+        test_client::TestClient,
+        test_input::{move_pointer, press_button},
+    };
+    use crate::input::BTN_LEFT;
+    use wayland_client::protocol::{
+        wl_data_device_manager::{self, WlDataDeviceManager},
+        wl_seat::WlSeat,
+        wl_shm::WlShm,
+    };
+
+    #[test]
+    fn test_drop_mime_type() {
+        let offered = |types: &[&str]| types.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            drop_mime_type(&offered(&["text/plain", "text/uri-list"])),
+            Some("text/uri-list")
+        );
+        assert_eq!(
+            drop_mime_type(&offered(&["UTF8_STRING", "text/plain"])),
+            Some("UTF8_STRING")
+        );
+        assert_eq!(drop_mime_type(&offered(&["image/png"])), None);
+    }
+
+    #[test]
+    fn test_dropped_content() {
+        let media = dropped_content(
+            "text/uri-list",
+            b"# comment\r\nhttps://example.com\r\nfile:///home/me/My%20Photo.png\r\n",
+        );
+        assert!(matches!(
+            media,
+            Some((NodeType::Media { ref path }, ref label))
+                if path == "/home/me/My Photo.png" && label == "My Photo.png"
+        ));
+        assert!(dropped_content("text/uri-list", b"https://example.com\n").is_none());
+
+        let note = dropped_content("text/plain", b"\n  Shopping list \nmilk\n");
+        assert!(matches!(
+            note,
+            Some((NodeType::Note { ref text }, ref label))
+                if text == "\n  Shopping list \nmilk\n" && label == "Shopping list"
+        ));
+        assert!(dropped_content("text/plain", b" \n").is_none());
+        assert!(dropped_content("text/plain", &[0xff, 0xfe]).is_none());
+
+        assert_eq!(
+            file_uri_path("file://localhost/tmp/a"),
+            Some("/tmp/a".into())
+        );
+        assert_eq!(file_uri_path("file://host/tmp/a"), None);
+        assert_eq!(file_uri_path("file:///tmp/%2"), None);
+        assert_eq!(file_uri_path("file:///tmp/%00"), None);
+    }
+
+    #[test]
+    fn test_read_dropped() {
+        use std::io::Write;
+
+        let (read_end, write_end) = pipe().unwrap();
+        let file = File::from(read_end);
+        let mut writer = File::from(write_end);
+        let mut content = Vec::new();
+        let mut received = None;
+
+        // Nothing sent yet: keep waiting
+        assert_eq!(
+            read_dropped(&file, &mut content, |_| unreachable!()),
+            PostAction::Continue
+        );
+
+        writer.write_all(b"hello").unwrap();
+        assert_eq!(
+            read_dropped(&file, &mut content, |_| unreachable!()),
+            PostAction::Continue
+        );
+        drop(writer);
+        assert_eq!(
+            read_dropped(&file, &mut content, |c| received = Some(c.to_vec())),
+            PostAction::Remove
+        );
+        assert_eq!(received.as_deref(), Some(&b"hello"[..]));
+    }
+
+    #[test]
+    fn test_drop_text_on_canvas() {
+        let mut backend = HeadlessBackend::for_tests(640, 480);
+        let mut client = TestClient::connect(&mut backend);
+        let shm: WlShm = client.bind(1);
+        let seat: WlSeat = client.bind(1);
+        let manager: WlDataDeviceManager = client.bind(3);
+        let _pointer = seat.get_pointer(&client.handle(), ());
+        let device = manager.get_data_device(&seat, &client.handle(), ());
+        let buffer = client.shm_buffer(&shm, 100, 80, 0xff00ff00);
+        let (surface, _, _) = client.map_toplevel(&mut backend, &buffer);
+
+        // Press in the window and drag text out of it
+        let state = backend.state_mut();
+        let window = state.windows.values().next().unwrap().window.clone();
+        let location = state.space.element_location(&window).unwrap().to_f64();
+        move_pointer(state, location + Point::from((10.0, 10.0)));
+        press_button(state, BTN_LEFT, true);
+        client.roundtrip(&mut backend).unwrap();
+
+        let source = manager.create_data_source(&client.handle(), ());
+        source.offer("text/plain".into());
+        source.set_actions(
+            wl_data_device_manager::DndAction::Copy | wl_data_device_manager::DndAction::Move,
+        );
+        client.data.source_content = b"Shopping list\nmilk\n".to_vec();
+        let serial = client.data.button_serial.expect("No button event");
+        device.start_drag(Some(&source), &surface, None, serial);
+        client.roundtrip(&mut backend).unwrap();
+        assert!(backend.state().dnd.source.is_some());
+
+        // Released over the background, the canvas takes a copy
+        let point = Point::from((20.0, 30.0));
+        let state = backend.state_mut();
+        move_pointer(state, point);
+        assert!(state.surface_under(point).is_none());
+        press_button(state, BTN_LEFT, false);
+        client.roundtrip(&mut backend).unwrap();
+        client.roundtrip(&mut backend).unwrap();
+
+        assert_eq!(
+            client.data.source_events,
+            [
+                "target",
+                "action",
+                "dnd_drop_performed",
+                "send",
+                "dnd_finished"
+            ]
+        );
+        let state = backend.state();
+        assert!(state.dnd.source.is_none() && state.dnd.dropped.is_none());
+        let note = state
+            .canvas
+            .nodes()
+            .find(|node| matches!(node.node_type, NodeType::Note { .. }))
+            .expect("No note made of the drop");
+        assert_eq!(note.label.as_deref(), Some("Shopping list"));
+        assert_eq!(Point::from((note.x, note.y)), state.global_to_canvas(point));
+
+        // Drawn as a note card
+        backend.render().unwrap();
+        let frame = backend.frame(0).unwrap();
+        let pixel = frame.pixel(100, 150).unwrap();
+        let note_color = [250u8, 237, 158];
+        assert!(
+            pixel
+                .iter()
+                .zip(note_color)
+                .all(|(&c, e)| c.abs_diff(e) <= 1),
+            "{pixel:?}"
+        );
+    }
+}
@@ -0,0 +1,320 @@
+//! Keyboard focus history and cycling
+//!
+//! Every seat keeps its windows in most-recently-used order in a
+//! [`FocusHistory`]. `Alt+Tab` walks the history while the modifiers are
+//! held, without reordering it; once they are released the window that
+//! ended up focused moves to the front. Repeated short `Alt+Tab` presses
+//! therefore toggle between the two most recent windows.
+//!
+//! A window that gets focused by cycling may be anywhere on the infinite
+//! canvas, so its view is panned just enough to bring the node into sight.
+
+use crate::state::LoomState;
+use crate::types::WindowId;
+use smithay::{
+    input::Seat,
+    utils::{Logical, Point, Rectangle, SERIAL_COUNTER},
+};
+use std::cell::{RefCell, RefMut};
+use tracing::debug;
+
+/// Space kept between a revealed node and the edge of its view, in canvas
+/// units
+const REVEAL_MARGIN: f64 = 32.0;
+
+/// Windows of a seat, most recently focused first
+#[derive(Debug, Default)]
+pub struct FocusHistory {
+    /// Windows in most-recently-used order
+    windows: Vec<WindowId>,
+    /// Position of the window focused by an ongoing cycle
+    cycle: Option<usize>,
+}
+
+impl FocusHistory {
+    /// Add a window that has never been focused, as least recently used
+    pub fn add(&mut self, id: WindowId) {
+        if !self.windows.contains(&id) {
+            self.windows.push(id);
+        }
+    }
+
+    /// Record that a window got the focus
+    ///
+    /// While cycling the order is kept, so the cycle doesn't lose its
+    /// place; the cycled window moves to the front when the cycle ends.
+    pub fn focused(&mut self, id: WindowId) {
+        match self.cycle {
+            Some(index) => {
+                if !self.windows.contains(&id) {
+                    self.windows.insert(0, id);
+                    self.cycle = Some(index + 1);
+                }
+            }
+            None => {
+                self.windows.retain(|&w| w != id);
+                self.windows.insert(0, id);
+            }
+        }
+    }
+
+    /// Forget a window
+    pub fn remove(&mut self, id: WindowId) {
+        let Some(position) = self.windows.iter().position(|&w| w == id) else {
+            return;
+        };
+        self.windows.remove(position);
+
+        if let Some(index) = self.cycle {
+            self.cycle = if self.windows.is_empty() {
+                None
+            } else if position < index {
+                Some(index - 1)
+            } else {
+                Some(index.min(self.windows.len() - 1))
+            };
+        }
+    }
+
+    /// Step the cycle to the next (or previous) window
+    ///
+    /// The first step starts at `current`, the focused window; without a
+    /// focused window it starts at the front (or back) of the history.
+    pub fn cycle(&mut self, current: Option<WindowId>, forward: bool) -> Option<WindowId> {
+        let len = self.windows.len();
+        if len == 0 {
+            return None;
+        }
+
+        let start = self
+            .cycle
+            .or_else(|| current.and_then(|id| self.windows.iter().position(|&w| w == id)));
+        let index = match start {
+            Some(index) if forward => (index + 1) % len,
+            Some(index) => (index + len - 1) % len,
+            None if forward => 0,
+            None => len - 1,
+        };
+
+        self.cycle = Some(index);
+        Some(self.windows[index])
+    }
+
+    /// Finish a cycle, moving the cycled window to the front
+    pub fn end_cycle(&mut self) {
+        if let Some(index) = self.cycle.take()
+            && index < self.windows.len()
+        {
+            let id = self.windows.remove(index);
+            self.windows.insert(0, id);
+        }
+    }
+
+    /// Whether a cycle is in progress
+    pub fn is_cycling(&self) -> bool {
+        self.cycle.is_some()
+    }
+
+    /// Most recently focused window
+    pub fn front(&self) -> Option<WindowId> {
+        self.windows.first().copied()
+    }
+
+    /// Windows, most recently focused first
+    pub fn windows(&self) -> &[WindowId] {
+        &self.windows
+    }
+}
+
+/// Focus history of a seat
+pub fn focus_history(seat: &Seat<LoomState>) -> RefMut<'_, FocusHistory> {
+    let data = seat.user_data();
+    data.insert_if_missing(|| RefCell::new(FocusHistory::default()));
+    data.get::<RefCell<FocusHistory>>().unwrap().borrow_mut()
+}
+
+/// Offset to move a view by so that it shows a node
+///
+/// Both rectangles are in canvas coordinates. Nodes already inside the
+/// visible area (with a margin) need no offset; nodes larger than it are
+/// aligned to its top-left corner.
+fn reveal_offset(
+    node: Rectangle<f64, Logical>,
+    visible: Rectangle<f64, Logical>,
+    margin: f64,
+) -> Point<f64, Logical> {
+    let axis = |start: f64, len: f64, visible_start: f64, visible_len: f64| {
+        let (start, end) = (start - margin, start + len + margin);
+        if end - start > visible_len || start < visible_start {
+            start - visible_start
+        } else if end > visible_start + visible_len {
+            end - (visible_start + visible_len)
+        } else {
+            0.0
+        }
+    };
+
+    Point::from((
+        axis(node.loc.x, node.size.w, visible.loc.x, visible.size.w),
+        axis(node.loc.y, node.size.h, visible.loc.y, visible.size.h),
+    ))
+}
+
+impl LoomState {
+    /// Focus the next (or previous) window of the focus history
+    pub fn cycle_focus(&mut self, forward: bool) {
+        let current = self.focused_window();
+        let Some(id) = focus_history(&self.seat).cycle(current, forward) else {
+            return;
+        };
+
+        debug!("Cycling focus to window {:?}", id);
+        self.raise_and_focus(Some(id), SERIAL_COUNTER.next_serial());
+        self.reveal_window(id);
+        self.refresh_pointer_focus();
+    }
+
+    /// Finish a focus cycle once its modifiers are released
+    pub fn end_focus_cycle(&mut self) {
+        focus_history(&self.seat).end_cycle();
+    }
+
+    /// Ask the focused window to close (xdg_toplevel.close)
+    pub fn close_focused(&mut self) {
+        let Some(id) = self.focused_window() else {
+            return;
+        };
+
+        if let Some(toplevel) = self.windows[id].window.toplevel() {
+            debug!("Closing window {:?}", id);
+            toplevel.send_close();
+        }
+    }
+
+    /// Pan a view so that it shows a window's node
+    ///
+    /// The view already showing part of the node is moved; a node that
+    /// isn't visible anywhere is brought into the view under the pointer.
+    /// Fullscreen windows are always visible and never move a view.
+    pub fn reveal_window(&mut self, id: WindowId) {
+        if self.windows.get(id).is_none_or(|m| m.fullscreen.is_some()) {
+            return;
+        }
+        let Some(rect) = self.window_node_rect(id) else {
+            return;
+        };
+
+        let shown = self
+            .views
+            .iter()
+            .any(|view| self.view_canvas_rect(view).overlaps(rect));
+        let view = if shown {
+            self.node_view(rect)
+        } else {
+            self.view_at(self.pointer_location)
+        };
+        let Some(view) = view else {
+            return;
+        };
+
+        let offset = reveal_offset(rect, self.usable_canvas_rect(view), REVEAL_MARGIN);
+        if offset == Point::from((0.0, 0.0)) {
+            return;
+        }
+
+        let id = view.viewport;
+        if let Some(viewport) = self.canvas.viewport_mut(id) {
+            viewport.x += offset.x;
+            viewport.y += offset.y;
+        }
+        self.sync_space_to_canvas();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use slotmap::SlotMap;
+
+    fn ids(n: usize) -> Vec<WindowId> {
+        let mut map = SlotMap::<WindowId, ()>::with_key();
+        (0..n).map(|_| map.insert(())).collect()
+    }
+
+    #[test]
+    fn test_focus_moves_to_front() {
+        let w = ids(3);
+        let mut history = FocusHistory::default();
+        for &id in &w {
+            history.add(id);
+        }
+
+        history.focused(w[2]);
+        assert_eq!(history.windows(), [w[2], w[0], w[1]]);
+    }
+
+    #[test]
+    fn test_cycle_keeps_order_until_finished() {
+        let w = ids(3);
+        let mut history = FocusHistory::default();
+        for &id in w.iter().rev() {
+            history.focused(id);
+        }
+        // Most recent first: w0, w1, w2
+
+        assert_eq!(history.cycle(Some(w[0]), true), Some(w[1]));
+        history.focused(w[1]);
+        assert_eq!(history.cycle(Some(w[1]), true), Some(w[2]));
+        history.focused(w[2]);
+        assert_eq!(history.windows(), [w[0], w[1], w[2]]);
+
+        history.end_cycle();
+        assert!(!history.is_cycling());
+        assert_eq!(history.windows(), [w[2], w[0], w[1]]);
+
+        // A single step toggles back to the previous window
+        assert_eq!(history.cycle(Some(w[2]), true), Some(w[0]));
+        assert_eq!(history.cycle(Some(w[0]), false), Some(w[2]));
+        assert_eq!(history.cycle(Some(w[2]), false), Some(w[1]));
+    }
+
+    #[test]
+    fn test_remove_during_cycle() {
+        let w = ids(3);
+        let mut history = FocusHistory::default();
+        for &id in w.iter().rev() {
+            history.focused(id);
+        }
+
+        assert_eq!(history.cycle(Some(w[0]), true), Some(w[1]));
+        history.remove(w[0]);
+        assert_eq!(history.cycle(None, true), Some(w[2]));
+
+        history.remove(w[1]);
+        history.remove(w[2]);
+        assert!(!history.is_cycling());
+        assert_eq!(history.cycle(None, true), None);
+    }
+
+    #[test]
+    fn test_reveal_offset() {
+        let visible = Rectangle::new((0.0, 0.0).into(), (1000.0, 800.0).into());
+        let node = |x, y, w, h| Rectangle::new((x, y).into(), (w, h).into());
+
+        // Already visible
+        let offset = reveal_offset(node(100.0, 100.0, 200.0, 200.0), visible, 10.0);
+        assert_eq!(offset, Point::from((0.0, 0.0)));
+
+        // Past the bottom-right edge
+        let offset = reveal_offset(node(900.0, 700.0, 200.0, 200.0), visible, 10.0);
+        assert_eq!(offset, Point::from((110.0, 110.0)));
+
+        // Before the top-left edge
+        let offset = reveal_offset(node(-500.0, 100.0, 200.0, 200.0), visible, 10.0);
+        assert_eq!(offset, Point::from((-510.0, 0.0)));
+
+        // Larger than the view: aligned to its corner
+        let offset = reveal_offset(node(100.0, 100.0, 2000.0, 200.0), visible, 10.0);
+        assert_eq!(offset, Point::from((90.0, 0.0)));
+    }
+}
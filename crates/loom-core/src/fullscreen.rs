@@ -0,0 +1,258 @@
+//! Fullscreen windows
+//!
+//! A fullscreen window is taken out of the canvas transform: it is drawn
+//! at the origin of its output at the output's scale, whatever the view's
+//! pan and zoom, fills the output and covers everything else on it. Its
+//! node stays on the canvas where it was, and gets its previous size back
+//! when the window leaves fullscreen.
+//!
+//! Pointer input on a fullscreen output goes to the fullscreen window (see
+//! [`LoomState::surface_under`]).
+
+use crate::state::LoomState;
+use crate::types::{WindowFlags, WindowId};
+use smithay::{
+    output::Output,
+    reexports::{wayland_protocols::xdg::shell::server::xdg_toplevel, wayland_server::Resource},
+    utils::{Logical, Point, Rectangle, Size},
+};
+use tracing::debug;
+
+/// Fullscreen state of a window
+#[derive(Debug, Clone)]
+pub struct FullscreenState {
+    /// Output filled by the window
+    pub output: Output,
+    /// Node size to restore when leaving fullscreen, in canvas units
+    pub restore_size: Size<f64, Logical>,
+}
+
+impl LoomState {
+    /// Toggle fullscreen for the focused window
+    pub fn toggle_fullscreen_focused(&mut self) {
+        let Some(id) = self.focused_window() else {
+            return;
+        };
+
+        if self.windows[id].fullscreen.is_some() {
+            self.unset_fullscreen(id);
+        } else {
+            self.set_fullscreen(id, None);
+        }
+    }
+
+    /// Make a window fill an output
+    ///
+    /// Without an output, the window fills the output showing the center
+    /// of its node. A window already fullscreen on that output leaves
+    /// fullscreen.
+    pub fn set_fullscreen(&mut self, id: WindowId, output: Option<Output>) {
+        let Some(rect) = self.window_node_rect(id) else {
+            return;
+        };
+        let Some(output) = output
+            .filter(|output| self.space.output_geometry(output).is_some())
+            .or_else(|| self.node_output(rect))
+        else {
+            return;
+        };
+        let Some(geometry) = self.space.output_geometry(&output) else {
+            return;
+        };
+
+        if let Some(other) = self.fullscreen_window_on(&output)
+            && other != id
+        {
+            self.unset_fullscreen(other);
+        }
+
+        let managed = &mut self.windows[id];
+        // The node keeps its size while fullscreen, but a window moving to
+        // another output restores the size it had on the canvas
+        let restore_size = managed
+            .fullscreen
+            .as_ref()
+            .map_or(rect.size, |fullscreen| fullscreen.restore_size);
+        managed.fullscreen = Some(FullscreenState {
+            output: output.clone(),
+            restore_size,
+        });
+        managed.flags.insert(WindowFlags::FULLSCREEN);
+
+        if let Some(toplevel) = managed.window.toplevel() {
+            let wl_output = toplevel
+                .wl_surface()
+                .client()
+                .and_then(|client| output.client_outputs(&client).next());
+            toplevel.with_pending_state(|state| {
+                state.states.set(xdg_toplevel::State::Fullscreen);
+                state.size = Some(geometry.size);
+                state.fullscreen_output = wl_output;
+            });
+            if toplevel.is_initial_configure_sent() {
+                toplevel.send_pending_configure();
+            }
+        }
+
+        debug!("Window {:?} fullscreen on {}", id, output.name());
+        let window = managed.window.clone();
+        self.space.raise_element(&window, true);
+        self.sync_space_to_canvas();
+        self.refresh_pointer_focus();
+    }
+
+    /// Return a window from fullscreen to its node on the canvas
+    pub fn unset_fullscreen(&mut self, id: WindowId) {
+        let Some(managed) = self.windows.get_mut(id) else {
+            return;
+        };
+        let Some(fullscreen) = managed.fullscreen.take() else {
+            return;
+        };
+        managed.flags.remove(WindowFlags::FULLSCREEN);
+
+        let restore_size = fullscreen.restore_size;
+        if let Some(toplevel) = managed.window.toplevel() {
+            toplevel.with_pending_state(|state| {
+                state.states.unset(xdg_toplevel::State::Fullscreen);
+                state.size = Some(restore_size.to_i32_round());
+                state.fullscreen_output = None;
+            });
+            if toplevel.is_initial_configure_sent() {
+                toplevel.send_pending_configure();
+            }
+        }
+
+        let node_id = managed.node_id;
+        if let Some(node) = self.canvas.get_node_mut(node_id) {
+            node.width = restore_size.w;
+            node.height = restore_size.h;
+        }
+
+        debug!("Window {:?} left fullscreen", id);
+        self.sync_space_to_canvas();
+        self.refresh_pointer_focus();
+    }
+
+    /// Fullscreen window on an output
+    pub fn fullscreen_window_on(&self, output: &Output) -> Option<WindowId> {
+        self.windows
+            .iter()
+            .find(|(_, managed)| {
+                managed
+                    .fullscreen
+                    .as_ref()
+                    .is_some_and(|fullscreen| &fullscreen.output == output)
+            })
+            .map(|(id, _)| id)
+    }
+
+    /// Global geometry of the output a window fills, if it is fullscreen
+    pub fn fullscreen_geometry(&self, id: WindowId) -> Option<Rectangle<i32, Logical>> {
+        let fullscreen = self.windows.get(id)?.fullscreen.as_ref()?;
+        self.space.output_geometry(&fullscreen.output)
+    }
+
+    /// Keep fullscreen windows filling their outputs after a layout change
+    ///
+    /// Windows whose output is gone leave fullscreen.
+    pub fn update_fullscreen_windows(&mut self) {
+        let fullscreen: Vec<_> = self
+            .windows
+            .iter()
+            .filter(|(_, managed)| managed.fullscreen.is_some())
+            .map(|(id, _)| id)
+            .collect();
+
+        for id in fullscreen {
+            let Some(geometry) = self.fullscreen_geometry(id) else {
+                self.unset_fullscreen(id);
+                continue;
+            };
+
+            if let Some(toplevel) = self.windows[id].window.toplevel() {
+                toplevel.with_pending_state(|state| state.size = Some(geometry.size));
+                if toplevel.is_initial_configure_sent() {
+                    toplevel.send_pending_configure();
+                }
+            }
+        }
+    }
+
+    /// Output showing the center of a node
+    fn node_output(&self, rect: Rectangle<f64, Logical>) -> Option<Output> {
+        let view = self.node_view(rect)?;
+        let center = rect.loc + Point::from((rect.size.w / 2.0, rect.size.h / 2.0));
+        let center = self.canvas_to_view(view, center);
+
+        view.outputs
+            .iter()
+            .find(|(_, geometry)| geometry.to_f64().contains(center))
+            .or_else(|| view.outputs.first())
+            .map(|(output, _)| output.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::{headless::HeadlessBackend, test_client::TestClient};
+    use crate::types::WindowFlags;
+    use smithay::utils::Rectangle;
+    use wayland_client::protocol::wl_shm::WlShm;
+
+    #[test]
+    fn test_fullscreen_and_back() {
+        let mut backend = HeadlessBackend::for_tests(640, 480);
+        let mut client = TestClient::connect(&mut backend);
+        let shm: WlShm = client.bind(1);
+        let small = client.shm_buffer(&shm, 100, 80, 0xff00ff00);
+        let large = client.shm_buffer(&shm, 640, 480, 0xff00ff00);
+        let (surface, xdg_surface, _) = client.map_toplevel(&mut backend, &small);
+
+        let state = backend.state_mut();
+        let (id, window) = state
+            .windows
+            .iter()
+            .map(|(id, managed)| (id, managed.window.clone()))
+            .next()
+            .unwrap();
+        let rect = state.window_node_rect(id).unwrap();
+        let location = state.space.element_location(&window).unwrap();
+
+        // The client is asked to fill the output, and does
+        state.set_fullscreen(id, None);
+        assert!(state.windows[id].flags.contains(WindowFlags::FULLSCREEN));
+        client.roundtrip(&mut backend).unwrap();
+        assert_eq!(client.data.toplevel_configure, Some((640, 480, true)));
+        xdg_surface.ack_configure(client.data.xdg_configure.unwrap());
+        surface.attach(Some(&large.buffer), 0, 0);
+        surface.commit();
+        client.roundtrip(&mut backend).unwrap();
+
+        let state = backend.state_mut();
+        let output = state.space.outputs().next().unwrap().clone();
+        assert_eq!(
+            Some(Rectangle::new(
+                state.space.element_location(&window).unwrap(),
+                window.geometry().size
+            )),
+            state.space.output_geometry(&output)
+        );
+        // The node keeps its place and size on the canvas meanwhile
+        assert_eq!(state.window_node_rect(id), Some(rect));
+
+        // Back to the node's rectangle and space location
+        state.unset_fullscreen(id);
+        assert!(!state.windows[id].flags.contains(WindowFlags::FULLSCREEN));
+        client.roundtrip(&mut backend).unwrap();
+        assert_eq!(client.data.toplevel_configure, Some((100, 80, false)));
+        xdg_surface.ack_configure(client.data.xdg_configure.unwrap());
+        surface.attach(Some(&small.buffer), 0, 0);
+        surface.commit();
+        client.roundtrip(&mut backend).unwrap();
+
+        let state = backend.state();
+        assert_eq!(state.window_node_rect(id), Some(rect));
+        assert_eq!(state.space.element_location(&window), Some(location));
+    }
+}
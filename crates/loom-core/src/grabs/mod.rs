@@ -0,0 +1,124 @@
+//! Compositor pointer grabs
+//!
+//! While a grab is active, Smithay routes all pointer events to it instead
+//! of the focused client. Grabs are used for interactions driven by the
+//! compositor itself: panning the canvas, and moving or resizing nodes
+//! (either on a client's request or with `Logo`+drag).
+//!
+//! Pointer locations are in canvas coordinates (see
+//! [`LoomState::surface_under`]), so drag distances in move and resize
+//! grabs are canvas units at any zoom level.
+
+mod move_grab;
+mod pan_grab;
+mod resize_grab;
+
+pub use move_grab::MoveSurfaceGrab;
+pub use pan_grab::PanGrab;
+pub use resize_grab::{ResizeEdge, ResizeState, ResizeSurfaceGrab};
+
+use crate::state::LoomState;
+use crate::types::{WindowFlags, WindowId};
+use smithay::{
+    input::pointer::{Focus, GrabStartData as PointerGrabStartData},
+    utils::{Point, Serial},
+};
+
+impl LoomState {
+    /// Start moving a window's node with the pointer
+    ///
+    /// Fullscreen windows are pinned to their output and can't be moved.
+    pub fn start_move_grab(
+        &mut self,
+        id: WindowId,
+        start_data: PointerGrabStartData<Self>,
+        serial: Serial,
+    ) {
+        let Some(rect) = self.window_node_rect(id) else {
+            return;
+        };
+        let Some(pointer) = self.seat.get_pointer() else {
+            return;
+        };
+        if self.windows[id].fullscreen.is_some() {
+            return;
+        }
+
+        self.windows[id].flags.insert(WindowFlags::MOVING);
+
+        let grab = MoveSurfaceGrab::new(start_data, id, rect.loc);
+        pointer.set_grab(self, grab, serial, Focus::Clear);
+    }
+
+    /// Start resizing a window's node from the given edges
+    ///
+    /// Fullscreen windows keep the size of their output.
+    pub fn start_resize_grab(
+        &mut self,
+        id: WindowId,
+        edges: ResizeEdge,
+        start_data: PointerGrabStartData<Self>,
+        serial: Serial,
+    ) {
+        let Some(initial_rect) = self.window_node_rect(id) else {
+            return;
+        };
+        let Some(pointer) = self.seat.get_pointer() else {
+            return;
+        };
+        if self.windows[id].fullscreen.is_some() {
+            return;
+        }
+
+        let managed = &mut self.windows[id];
+        managed.flags.insert(WindowFlags::RESIZING);
+        managed.resize_state = ResizeState::Resizing {
+            edges,
+            initial_rect,
+        };
+
+        let grab = ResizeSurfaceGrab::new(start_data, id, edges, initial_rect);
+        pointer.set_grab(self, grab, serial, Focus::Clear);
+    }
+
+    /// Keep the edges opposite to the dragged ones fixed after a commit
+    ///
+    /// Resizing from the top or left changes the window size, and the node
+    /// must move by the difference once the client commits the new size.
+    pub fn resize_commit(&mut self, id: WindowId) {
+        let Some(managed) = self.windows.get_mut(id) else {
+            return;
+        };
+
+        let (edges, initial_rect) = match managed.resize_state {
+            ResizeState::Idle => return,
+            ResizeState::Resizing {
+                edges,
+                initial_rect,
+            } => (edges, initial_rect),
+            ResizeState::WaitingForLastCommit {
+                edges,
+                initial_rect,
+            } => {
+                // The client committed its final size
+                managed.resize_state = ResizeState::Idle;
+                (edges, initial_rect)
+            }
+        };
+
+        if !edges.intersects(ResizeEdge::TOP_LEFT) {
+            return;
+        }
+
+        let size = managed.window.geometry().size.to_f64();
+        let mut location: Point<f64, _> = initial_rect.loc;
+        if edges.intersects(ResizeEdge::LEFT) {
+            location.x += initial_rect.size.w - size.w;
+        }
+        if edges.intersects(ResizeEdge::TOP) {
+            location.y += initial_rect.size.h - size.h;
+        }
+
+        self.move_window_node(id, location.x, location.y);
+    }
+}
@@ -0,0 +1,176 @@
+//! Interactive move of a node
+
+use crate::state::LoomState;
+use crate::types::{WindowFlags, WindowId};
+use smithay::{
+    input::pointer::{
+        AxisFrame, ButtonEvent, GestureHoldBeginEvent, GestureHoldEndEvent, GesturePinchBeginEvent,
+        GesturePinchEndEvent, GesturePinchUpdateEvent, GestureSwipeBeginEvent,
+        GestureSwipeEndEvent, GestureSwipeUpdateEvent, GrabStartData as PointerGrabStartData,
+        MotionEvent, PointerGrab, PointerInnerHandle, RelativeMotionEvent,
+    },
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Logical, Point},
+};
+
+/// Move a node on the canvas while a button is held
+pub struct MoveSurfaceGrab {
+    start_data: PointerGrabStartData<LoomState>,
+    window_id: WindowId,
+    /// Node location when the grab started, in canvas coordinates
+    initial_location: Point<f64, Logical>,
+}
+
+impl MoveSurfaceGrab {
+    /// Start moving a window's node from its current location
+    pub fn new(
+        start_data: PointerGrabStartData<LoomState>,
+        window_id: WindowId,
+        initial_location: Point<f64, Logical>,
+    ) -> Self {
+        Self {
+            start_data,
+            window_id,
+            initial_location,
+        }
+    }
+}
+
+impl PointerGrab<LoomState> for MoveSurfaceGrab {
+    fn motion(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        _focus: Option<(WlSurface, Point<f64, Logical>)>,
+        event: &MotionEvent,
+    ) {
+        // No client has pointer focus while moving
+        handle.motion(data, None, event);
+
+        if !data.windows.contains_key(self.window_id) {
+            handle.unset_grab(self, data, event.serial, event.time, true);
+            return;
+        }
+
+        let location = self.initial_location + (event.location - self.start_data.location);
+        data.move_window_node(self.window_id, location.x, location.y);
+    }
+
+    fn relative_motion(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        _focus: Option<(WlSurface, Point<f64, Logical>)>,
+        event: &RelativeMotionEvent,
+    ) {
+        handle.relative_motion(data, None, event);
+    }
+
+    fn button(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &ButtonEvent,
+    ) {
+        handle.button(data, event);
+
+        if !handle.current_pressed().contains(&self.start_data.button) {
+            handle.unset_grab(self, data, event.serial, event.time, true);
+        }
+    }
+
+    fn axis(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        details: AxisFrame,
+    ) {
+        handle.axis(data, details);
+    }
+
+    fn frame(&mut self, data: &mut LoomState, handle: &mut PointerInnerHandle<'_, LoomState>) {
+        handle.frame(data);
+    }
+
+    fn gesture_swipe_begin(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GestureSwipeBeginEvent,
+    ) {
+        handle.gesture_swipe_begin(data, event);
+    }
+
+    fn gesture_swipe_update(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GestureSwipeUpdateEvent,
+    ) {
+        handle.gesture_swipe_update(data, event);
+    }
+
+    fn gesture_swipe_end(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GestureSwipeEndEvent,
+    ) {
+        handle.gesture_swipe_end(data, event);
+    }
+
+    fn gesture_pinch_begin(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GesturePinchBeginEvent,
+    ) {
+        handle.gesture_pinch_begin(data, event);
+    }
+
+    fn gesture_pinch_update(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GesturePinchUpdateEvent,
+    ) {
+        handle.gesture_pinch_update(data, event);
+    }
+
+    fn gesture_pinch_end(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GesturePinchEndEvent,
+    ) {
+        handle.gesture_pinch_end(data, event);
+    }
+
+    fn gesture_hold_begin(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GestureHoldBeginEvent,
+    ) {
+        handle.gesture_hold_begin(data, event);
+    }
+
+    fn gesture_hold_end(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GestureHoldEndEvent,
+    ) {
+        handle.gesture_hold_end(data, event);
+    }
+
+    fn start_data(&self) -> &PointerGrabStartData<LoomState> {
+        &self.start_data
+    }
+
+    fn unset(&mut self, data: &mut LoomState) {
+        if let Some(managed) = data.windows.get_mut(self.window_id) {
+            managed.flags.remove(WindowFlags::MOVING);
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! Pan the canvas by dragging the background
+
+use crate::state::LoomState;
+use smithay::{
+    input::pointer::{
+        AxisFrame, ButtonEvent, GestureHoldBeginEvent, GestureHoldEndEvent, GesturePinchBeginEvent,
+        GesturePinchEndEvent, GesturePinchUpdateEvent, GestureSwipeBeginEvent,
+        GestureSwipeEndEvent, GestureSwipeUpdateEvent, GrabStartData as PointerGrabStartData,
+        MotionEvent, PointerGrab, PointerInnerHandle, RelativeMotionEvent,
+    },
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Logical, Point},
+};
+
+/// Drag the canvas while a button is held
+///
+/// Tracks the pointer in global (screen) coordinates: the canvas location
+/// under the pointer changes as the view pans, so canvas deltas would
+/// feed back into themselves. The view where the drag started keeps
+/// panning when the pointer crosses to another output.
+pub struct PanGrab {
+    start_data: PointerGrabStartData<LoomState>,
+    /// Pointer location when the grab started, in global coordinates
+    start_location: Point<f64, Logical>,
+    /// Last pointer location, in global coordinates
+    last_location: Point<f64, Logical>,
+}
+
+impl PanGrab {
+    /// Start panning from a global pointer location
+    pub fn new(start_data: PointerGrabStartData<LoomState>, location: Point<f64, Logical>) -> Self {
+        Self {
+            start_data,
+            start_location: location,
+            last_location: location,
+        }
+    }
+}
+
+impl PointerGrab<LoomState> for PanGrab {
+    fn motion(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        _focus: Option<(WlSurface, Point<f64, Logical>)>,
+        event: &MotionEvent,
+    ) {
+        // No client has pointer focus while panning
+        handle.motion(data, None, event);
+
+        let delta = data.pointer_location - self.last_location;
+        self.last_location = data.pointer_location;
+        data.pan_view_at(self.start_location, delta.x, delta.y);
+    }
+
+    fn relative_motion(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        _focus: Option<(WlSurface, Point<f64, Logical>)>,
+        event: &RelativeMotionEvent,
+    ) {
+        handle.relative_motion(data, None, event);
+    }
+
+    fn button(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &ButtonEvent,
+    ) {
+        handle.button(data, event);
+
+        if !handle.current_pressed().contains(&self.start_data.button) {
+            handle.unset_grab(self, data, event.serial, event.time, true);
+        }
+    }
+
+    fn axis(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        details: AxisFrame,
+    ) {
+        handle.axis(data, details);
+    }
+
+    fn frame(&mut self, data: &mut LoomState, handle: &mut PointerInnerHandle<'_, LoomState>) {
+        handle.frame(data);
+    }
+
+    fn gesture_swipe_begin(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GestureSwipeBeginEvent,
+    ) {
+        handle.gesture_swipe_begin(data, event);
+    }
+
+    fn gesture_swipe_update(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GestureSwipeUpdateEvent,
+    ) {
+        handle.gesture_swipe_update(data, event);
+    }
+
+    fn gesture_swipe_end(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GestureSwipeEndEvent,
+    ) {
+        handle.gesture_swipe_end(data, event);
+    }
+
+    fn gesture_pinch_begin(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GesturePinchBeginEvent,
+    ) {
+        handle.gesture_pinch_begin(data, event);
+    }
+
+    fn gesture_pinch_update(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GesturePinchUpdateEvent,
+    ) {
+        handle.gesture_pinch_update(data, event);
+    }
+
+    fn gesture_pinch_end(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GesturePinchEndEvent,
+    ) {
+        handle.gesture_pinch_end(data, event);
+    }
+
+    fn gesture_hold_begin(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GestureHoldBeginEvent,
+    ) {
+        handle.gesture_hold_begin(data, event);
+    }
+
+    fn gesture_hold_end(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GestureHoldEndEvent,
+    ) {
+        handle.gesture_hold_end(data, event);
+    }
+
+    fn start_data(&self) -> &PointerGrabStartData<LoomState> {
+        &self.start_data
+    }
+
+    fn unset(&mut self, _data: &mut LoomState) {}
+}
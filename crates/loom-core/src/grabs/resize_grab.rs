@@ -0,0 +1,416 @@
+//! Interactive resize of a node
+
+use crate::security::{MAX_BUFFER_HEIGHT, MAX_BUFFER_WIDTH};
+use crate::state::LoomState;
+use crate::types::{WindowFlags, WindowId};
+use smithay::{
+    input::pointer::{
+        AxisFrame, ButtonEvent, GestureHoldBeginEvent, GestureHoldEndEvent, GesturePinchBeginEvent,
+        GesturePinchEndEvent, GesturePinchUpdateEvent, GestureSwipeBeginEvent,
+        GestureSwipeEndEvent, GestureSwipeUpdateEvent, GrabStartData as PointerGrabStartData,
+        MotionEvent, PointerGrab, PointerInnerHandle, RelativeMotionEvent,
+    },
+    reexports::{
+        wayland_protocols::xdg::shell::server::xdg_toplevel,
+        wayland_server::protocol::wl_surface::WlSurface,
+    },
+    utils::{Logical, Point, Rectangle, Size},
+    wayland::{compositor::with_states, shell::xdg::SurfaceCachedState},
+};
+
+bitflags::bitflags! {
+    /// Window edges dragged during a resize
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct ResizeEdge: u32 {
+        const TOP          = 0b0001;
+        const BOTTOM       = 0b0010;
+        const LEFT         = 0b0100;
+        const RIGHT        = 0b1000;
+
+        const TOP_LEFT     = Self::TOP.bits() | Self::LEFT.bits();
+        const BOTTOM_LEFT  = Self::BOTTOM.bits() | Self::LEFT.bits();
+
+        const TOP_RIGHT    = Self::TOP.bits() | Self::RIGHT.bits();
+        const BOTTOM_RIGHT = Self::BOTTOM.bits() | Self::RIGHT.bits();
+    }
+}
+
+impl From<xdg_toplevel::ResizeEdge> for ResizeEdge {
+    #[inline]
+    fn from(edge: xdg_toplevel::ResizeEdge) -> Self {
+        // The protocol values use the same bit layout
+        Self::from_bits_truncate(edge as u32)
+    }
+}
+
+impl ResizeEdge {
+    /// Edges to drag for a pointer at `point` over a node
+    ///
+    /// The outer thirds of the node pick the nearest edges; the center
+    /// resizes from the bottom right corner.
+    pub fn nearest(rect: Rectangle<f64, Logical>, point: Point<f64, Logical>) -> Self {
+        let relative = point - rect.loc;
+        let mut edges = Self::empty();
+
+        if relative.x < rect.size.w / 3.0 {
+            edges |= Self::LEFT;
+        } else if relative.x > rect.size.w * 2.0 / 3.0 {
+            edges |= Self::RIGHT;
+        }
+        if relative.y < rect.size.h / 3.0 {
+            edges |= Self::TOP;
+        } else if relative.y > rect.size.h * 2.0 / 3.0 {
+            edges |= Self::BOTTOM;
+        }
+
+        if edges.is_empty() {
+            Self::BOTTOM_RIGHT
+        } else {
+            edges
+        }
+    }
+}
+
+/// Resize progress of a window, used to anchor the node on commit
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ResizeState {
+    /// Not being resized
+    #[default]
+    Idle,
+    /// A resize grab is active
+    Resizing {
+        edges: ResizeEdge,
+        initial_rect: Rectangle<f64, Logical>,
+    },
+    /// The grab ended, waiting for the client to commit its final size
+    WaitingForLastCommit {
+        edges: ResizeEdge,
+        initial_rect: Rectangle<f64, Logical>,
+    },
+}
+
+/// New window size after dragging `edges` by `delta` canvas units
+///
+/// Honors the client's min/max size (0 means unbounded) and the buffer
+/// size limit.
+pub fn resized_size(
+    initial: Size<f64, Logical>,
+    edges: ResizeEdge,
+    delta: Point<f64, Logical>,
+    min_size: Size<i32, Logical>,
+    max_size: Size<i32, Logical>,
+) -> Size<i32, Logical> {
+    let mut width = initial.w;
+    let mut height = initial.h;
+
+    if edges.intersects(ResizeEdge::LEFT) {
+        width -= delta.x;
+    } else if edges.intersects(ResizeEdge::RIGHT) {
+        width += delta.x;
+    }
+    if edges.intersects(ResizeEdge::TOP) {
+        height -= delta.y;
+    } else if edges.intersects(ResizeEdge::BOTTOM) {
+        height += delta.y;
+    }
+
+    let clamp = |value: f64, min: i32, max: i32, limit: u32| {
+        let limit = limit as i32;
+        let min = min.clamp(1, limit);
+        let max = if max > 0 { max.min(limit) } else { limit };
+        (value.round() as i32).clamp(min, max.max(min))
+    };
+
+    Size::from((
+        clamp(width, min_size.w, max_size.w, MAX_BUFFER_WIDTH),
+        clamp(height, min_size.h, max_size.h, MAX_BUFFER_HEIGHT),
+    ))
+}
+
+/// Resize a node while a button is held, configuring the client
+pub struct ResizeSurfaceGrab {
+    start_data: PointerGrabStartData<LoomState>,
+    window_id: WindowId,
+    edges: ResizeEdge,
+    /// Node geometry when the grab started, in canvas coordinates
+    initial_rect: Rectangle<f64, Logical>,
+    /// Last size sent to the client
+    last_size: Size<i32, Logical>,
+}
+
+impl ResizeSurfaceGrab {
+    /// Start resizing a window's node from its current geometry
+    pub fn new(
+        start_data: PointerGrabStartData<LoomState>,
+        window_id: WindowId,
+        edges: ResizeEdge,
+        initial_rect: Rectangle<f64, Logical>,
+    ) -> Self {
+        Self {
+            start_data,
+            window_id,
+            edges,
+            initial_rect,
+            last_size: initial_rect.size.to_i32_round(),
+        }
+    }
+}
+
+impl PointerGrab<LoomState> for ResizeSurfaceGrab {
+    fn motion(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        _focus: Option<(WlSurface, Point<f64, Logical>)>,
+        event: &MotionEvent,
+    ) {
+        // No client has pointer focus while resizing
+        handle.motion(data, None, event);
+
+        let Some(toplevel) = data
+            .windows
+            .get(self.window_id)
+            .and_then(|managed| managed.window.toplevel().cloned())
+        else {
+            handle.unset_grab(self, data, event.serial, event.time, true);
+            return;
+        };
+
+        let (min_size, max_size) = with_states(toplevel.wl_surface(), |states| {
+            let mut guard = states.cached_state.get::<SurfaceCachedState>();
+            let cached = guard.current();
+            (cached.min_size, cached.max_size)
+        });
+
+        let delta = event.location - self.start_data.location;
+        self.last_size = resized_size(
+            self.initial_rect.size,
+            self.edges,
+            delta,
+            min_size,
+            max_size,
+        );
+
+        toplevel.with_pending_state(|state| {
+            state.states.set(xdg_toplevel::State::Resizing);
+            state.size = Some(self.last_size);
+        });
+        toplevel.send_pending_configure();
+    }
+
+    fn relative_motion(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        _focus: Option<(WlSurface, Point<f64, Logical>)>,
+        event: &RelativeMotionEvent,
+    ) {
+        handle.relative_motion(data, None, event);
+    }
+
+    fn button(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &ButtonEvent,
+    ) {
+        handle.button(data, event);
+
+        if !handle.current_pressed().contains(&self.start_data.button) {
+            handle.unset_grab(self, data, event.serial, event.time, true);
+        }
+    }
+
+    fn axis(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        details: AxisFrame,
+    ) {
+        handle.axis(data, details);
+    }
+
+    fn frame(&mut self, data: &mut LoomState, handle: &mut PointerInnerHandle<'_, LoomState>) {
+        handle.frame(data);
+    }
+
+    fn gesture_swipe_begin(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GestureSwipeBeginEvent,
+    ) {
+        handle.gesture_swipe_begin(data, event);
+    }
+
+    fn gesture_swipe_update(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GestureSwipeUpdateEvent,
+    ) {
+        handle.gesture_swipe_update(data, event);
+    }
+
+    fn gesture_swipe_end(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GestureSwipeEndEvent,
+    ) {
+        handle.gesture_swipe_end(data, event);
+    }
+
+    fn gesture_pinch_begin(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GesturePinchBeginEvent,
+    ) {
+        handle.gesture_pinch_begin(data, event);
+    }
+
+    fn gesture_pinch_update(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GesturePinchUpdateEvent,
+    ) {
+        handle.gesture_pinch_update(data, event);
+    }
+
+    fn gesture_pinch_end(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GesturePinchEndEvent,
+    ) {
+        handle.gesture_pinch_end(data, event);
+    }
+
+    fn gesture_hold_begin(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GestureHoldBeginEvent,
+    ) {
+        handle.gesture_hold_begin(data, event);
+    }
+
+    fn gesture_hold_end(
+        &mut self,
+        data: &mut LoomState,
+        handle: &mut PointerInnerHandle<'_, LoomState>,
+        event: &GestureHoldEndEvent,
+    ) {
+        handle.gesture_hold_end(data, event);
+    }
+
+    fn start_data(&self) -> &PointerGrabStartData<LoomState> {
+        &self.start_data
+    }
+
+    fn unset(&mut self, data: &mut LoomState) {
+        let Some(managed) = data.windows.get_mut(self.window_id) else {
+            return;
+        };
+
+        managed.flags.remove(WindowFlags::RESIZING);
+        managed.resize_state = ResizeState::WaitingForLastCommit {
+            edges: self.edges,
+            initial_rect: self.initial_rect,
+        };
+
+        if let Some(toplevel) = managed.window.toplevel() {
+            toplevel.with_pending_state(|state| {
+                state.states.unset(xdg_toplevel::State::Resizing);
+                state.size = Some(self.last_size);
+            });
+            toplevel.send_pending_configure();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect() -> Rectangle<f64, Logical> {
+        Rectangle::new((100.0, 100.0).into(), (300.0, 300.0).into())
+    }
+
+    #[test]
+    fn test_resize_edge_from_protocol() {
+        use xdg_toplevel::ResizeEdge as Xdg;
+
+        assert_eq!(ResizeEdge::from(Xdg::None), ResizeEdge::empty());
+        assert_eq!(ResizeEdge::from(Xdg::Top), ResizeEdge::TOP);
+        assert_eq!(ResizeEdge::from(Xdg::Bottom), ResizeEdge::BOTTOM);
+        assert_eq!(ResizeEdge::from(Xdg::Left), ResizeEdge::LEFT);
+        assert_eq!(ResizeEdge::from(Xdg::Right), ResizeEdge::RIGHT);
+        assert_eq!(ResizeEdge::from(Xdg::TopLeft), ResizeEdge::TOP_LEFT);
+        assert_eq!(ResizeEdge::from(Xdg::TopRight), ResizeEdge::TOP_RIGHT);
+        assert_eq!(ResizeEdge::from(Xdg::BottomLeft), ResizeEdge::BOTTOM_LEFT);
+        assert_eq!(ResizeEdge::from(Xdg::BottomRight), ResizeEdge::BOTTOM_RIGHT);
+    }
+
+    #[test]
+    fn test_nearest_edges() {
+        let rect = rect();
+        let at = |x: f64, y: f64| ResizeEdge::nearest(rect, (x, y).into());
+
+        assert_eq!(at(110.0, 110.0), ResizeEdge::TOP_LEFT);
+        assert_eq!(at(390.0, 390.0), ResizeEdge::BOTTOM_RIGHT);
+        assert_eq!(at(250.0, 110.0), ResizeEdge::TOP);
+        assert_eq!(at(390.0, 250.0), ResizeEdge::RIGHT);
+        assert_eq!(at(250.0, 250.0), ResizeEdge::BOTTOM_RIGHT);
+    }
+
+    #[test]
+    fn test_resized_size() {
+        let size = rect().size;
+        let unbounded = Size::from((0, 0));
+
+        // Dragging the right edge right grows, the left edge right shrinks
+        let grown = resized_size(
+            size,
+            ResizeEdge::RIGHT,
+            (50.0, 80.0).into(),
+            unbounded,
+            unbounded,
+        );
+        assert_eq!(grown, Size::from((350, 300)));
+
+        let shrunk = resized_size(
+            size,
+            ResizeEdge::TOP_LEFT,
+            (50.0, 80.0).into(),
+            unbounded,
+            unbounded,
+        );
+        assert_eq!(shrunk, Size::from((250, 220)));
+    }
+
+    #[test]
+    fn test_resized_size_clamped() {
+        let size = rect().size;
+
+        let clamped = resized_size(
+            size,
+            ResizeEdge::BOTTOM_RIGHT,
+            (-1000.0, 1000.0).into(),
+            Size::from((100, 0)),
+            Size::from((0, 400)),
+        );
+        assert_eq!(clamped, Size::from((100, 400)));
+
+        let minimal = resized_size(
+            size,
+            ResizeEdge::BOTTOM_RIGHT,
+            (-1000.0, -1000.0).into(),
+            Size::from((0, 0)),
+            Size::from((0, 0)),
+        );
+        assert_eq!(minimal, Size::from((1, 1)));
+    }
+}
@@ -10,7 +10,10 @@ use smithay::{
         Resource,
         protocol::{wl_buffer, wl_surface::WlSurface},
     },
-    wayland::compositor::{CompositorClientState, CompositorHandler, CompositorState},
+    wayland::{
+        compositor::{CompositorClientState, CompositorHandler, CompositorState, with_states},
+        shell::xdg::XdgToplevelSurfaceData,
+    },
 };
 use tracing::trace;
 
@@ -28,27 +31,40 @@ impl CompositorHandler for LoomState {
 
     fn commit(&mut self, surface: &WlSurface) {
         trace!("Surface commit: {:?}", surface.id());
+        self.queue_redraw();
 
         // Handle buffer submission
         on_commit_buffer_handler::<Self>(surface);
 
+        // Popups need their initial configure as well
+        self.popup_commit(surface);
+
+        // Panels and wallpapers are arranged on their output
+        self.layer_commit(surface);
+        self.dnd_icon_commit(surface);
+
         // Handle XDG shell commits
-        if let Some(window) = self
-            .space
-            .elements()
-            .find(|w| {
-                w.toplevel()
-                    .map(|t| t.wl_surface() == surface)
-                    .unwrap_or(false)
-            })
-            .cloned()
-        {
+        if let Some(id) = self.window_id_for_surface(surface) {
+            let window = self.windows[id].window.clone();
             window.on_commit();
+
+            // Clients wait for the initial configure before attaching a buffer
+            if let Some(toplevel) = window.toplevel()
+                && !initial_configure_sent(surface)
+            {
+                toplevel.send_configure();
+            }
+
+            // Keep the node's size and label in sync with the client, and
+            // anchor it while resizing from the top or left
+            self.sync_window_node(id);
+            self.resize_commit(id);
         }
     }
 
     fn destroyed(&mut self, _surface: &WlSurface) {
         trace!("Surface destroyed");
+        self.queue_redraw();
     }
 }
 
@@ -58,4 +74,15 @@ impl smithay::wayland::buffer::BufferHandler for LoomState {
     }
 }
 
+/// Check whether the initial configure was sent for a toplevel surface
+fn initial_configure_sent(surface: &WlSurface) -> bool {
+    with_states(surface, |states| {
+        states
+            .data_map
+            .get::<XdgToplevelSurfaceData>()
+            .and_then(|data| data.lock().ok().map(|data| data.initial_configure_sent))
+            .unwrap_or(true)
+    })
+}
+
 delegate_compositor!(LoomState);
@@ -0,0 +1,19 @@
+//! Foreign toplevel list handler
+//!
+//! Handles ext_foreign_toplevel_list_v1, which lists the windows with their
+//! titles and app ids. Its handles name windows to capture (see
+//! [`crate::capture`]); each window's handle is kept with the window.
+
+use crate::state::LoomState;
+use smithay::{
+    delegate_foreign_toplevel_list,
+    wayland::foreign_toplevel_list::{ForeignToplevelListHandler, ForeignToplevelListState},
+};
+
+impl ForeignToplevelListHandler for LoomState {
+    fn foreign_toplevel_list_state(&mut self) -> &mut ForeignToplevelListState {
+        &mut self.foreign_toplevel_list_state
+    }
+}
+
+delegate_foreign_toplevel_list!(LoomState);
@@ -0,0 +1,35 @@
+//! Idle handlers
+//!
+//! Handles ext_idle_notifier_v1, which tells clients like swayidle when
+//! the user is idle, and zwp_idle_inhibit_manager_v1, which lets clients
+//! like video players hold idleness off; the timers and inhibitors are
+//! managed by [`crate::idle`].
+
+use crate::state::LoomState;
+use smithay::{
+    delegate_idle_inhibit, delegate_idle_notify,
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    wayland::{
+        idle_inhibit::IdleInhibitHandler,
+        idle_notify::{IdleNotifierHandler, IdleNotifierState},
+    },
+};
+
+impl IdleNotifierHandler for LoomState {
+    fn idle_notifier_state(&mut self) -> &mut IdleNotifierState<Self> {
+        &mut self.idle_notifier_state
+    }
+}
+
+impl IdleInhibitHandler for LoomState {
+    fn inhibit(&mut self, surface: WlSurface) {
+        self.add_idle_inhibitor(surface);
+    }
+
+    fn uninhibit(&mut self, surface: WlSurface) {
+        self.remove_idle_inhibitor(&surface);
+    }
+}
+
+delegate_idle_notify!(LoomState);
+delegate_idle_inhibit!(LoomState);
@@ -0,0 +1,51 @@
+//! Layer shell handler
+//!
+//! Handles zwlr_layer_shell_v1 for panels, launchers and wallpapers; the
+//! surfaces themselves are managed by [`crate::layers`].
+
+use crate::state::LoomState;
+use smithay::{
+    delegate_layer_shell,
+    output::Output,
+    reexports::wayland_server::protocol::wl_output::WlOutput,
+    wayland::shell::{
+        wlr_layer::{
+            Layer, LayerSurface as WlrLayerSurface, WlrLayerShellHandler, WlrLayerShellState,
+        },
+        xdg::PopupSurface,
+    },
+};
+use tracing::debug;
+
+impl WlrLayerShellHandler for LoomState {
+    fn shell_state(&mut self) -> &mut WlrLayerShellState {
+        &mut self.layer_shell_state
+    }
+
+    fn new_layer_surface(
+        &mut self,
+        surface: WlrLayerSurface,
+        output: Option<WlOutput>,
+        layer: Layer,
+        namespace: String,
+    ) {
+        debug!("New layer surface {:?} on layer {:?}", namespace, layer);
+
+        let output = output.as_ref().and_then(Output::from_resource);
+        self.add_layer_surface(surface, output, namespace);
+    }
+
+    fn new_popup(&mut self, parent: WlrLayerSurface, popup: PopupSurface) {
+        debug!("New layer surface popup");
+
+        self.unconstrain_layer_popup(&popup, parent.wl_surface());
+    }
+
+    fn layer_destroyed(&mut self, surface: WlrLayerSurface) {
+        debug!("Layer surface destroyed");
+
+        self.remove_layer_surface(&surface);
+    }
+}
+
+delegate_layer_shell!(LoomState);
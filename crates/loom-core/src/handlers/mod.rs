@@ -4,7 +4,15 @@
 //! Each handler implements the corresponding delegate trait.
 
 mod compositor;
+mod foreign_toplevel;
+mod idle;
+mod layer_shell;
 mod output;
 mod seat;
+mod selection;
+mod session_lock;
 mod shm;
+mod xdg_decoration;
 mod xdg_shell;
+
+pub(crate) use selection::data_control_filter;
@@ -1,12 +1,21 @@
 //! Seat (input) handler
 //!
-//! Handles wl_seat protocol for keyboard, pointer, and touch input.
+//! Handles wl_seat protocol for keyboard, pointer, and touch input,
+//! touchpad gestures through zwp_pointer_gestures_v1 and drawing tablets
+//! through zwp_tablet_v2. The clipboard and primary selection are offered
+//! to the client with keyboard focus.
 
+use crate::focus::focus_history;
 use crate::state::LoomState;
+use crate::types::WindowFlags;
 use smithay::{
-    delegate_seat,
+    delegate_pointer_gestures, delegate_seat, delegate_tablet_manager,
     input::{Seat, SeatHandler, SeatState, pointer::CursorImageStatus},
     reexports::wayland_server::{Resource, protocol::wl_surface::WlSurface},
+    wayland::{
+        selection::{data_device::set_data_device_focus, primary_selection::set_primary_focus},
+        tablet_manager::TabletSeatHandler,
+    },
 };
 use tracing::debug;
 
@@ -23,9 +32,28 @@ impl SeatHandler for LoomState {
         self.cursor_status = image;
     }
 
-    fn focus_changed(&mut self, _seat: &Seat<Self>, focused: Option<&Self::KeyboardFocus>) {
+    fn focus_changed(&mut self, seat: &Seat<Self>, focused: Option<&Self::KeyboardFocus>) {
         debug!("Focus changed to: {:?}", focused.map(|s| s.id()));
+
+        // The focused client may paste the selections
+        let client = focused.and_then(|surface| self.display_handle.get_client(surface.id()).ok());
+        set_data_device_focus(&self.display_handle, seat, client.clone());
+        set_primary_focus(&self.display_handle, seat, client);
+
+        let focused = focused.and_then(|surface| self.window_id_for_surface(surface));
+        for (id, managed) in self.windows.iter_mut() {
+            managed.flags.set(WindowFlags::FOCUSED, Some(id) == focused);
+        }
+
+        if let Some(id) = focused {
+            focus_history(seat).focused(id);
+        }
     }
 }
 
+// Tool cursors are not drawn, the pen doesn't move the pointer
+impl TabletSeatHandler for LoomState {}
+
 delegate_seat!(LoomState);
+delegate_pointer_gestures!(LoomState);
+delegate_tablet_manager!(LoomState);
@@ -0,0 +1,180 @@
+//! Selection handlers
+//!
+//! Handles the clipboard and drag and drop through wl_data_device_manager,
+//! the primary selection (middle click paste) through
+//! zwp_primary_selection_device_manager_v1, and clipboard managers through
+//! zwlr_data_control_manager_v1, which only clients allowed by the
+//! `[clipboard]` config see. Transfers happen between clients; the
+//! selection follows the keyboard focus (see the seat handler). Drags with
+//! a data source are relayed by the compositor so the canvas can take
+//! drops too, see [`crate::dnd`].
+
+use crate::state::{ClientState, LoomState};
+use loom_config::ClipboardConfig;
+use smithay::{
+    delegate_data_control, delegate_primary_selection,
+    input::Seat,
+    reexports::wayland_server::{
+        Client, DataInit, Dispatch, DisplayHandle, delegate_dispatch, delegate_global_dispatch,
+        protocol::{
+            wl_data_device::{self, WlDataDevice},
+            wl_data_device_manager::{DndAction, WlDataDeviceManager},
+            wl_data_source::WlDataSource,
+            wl_surface::WlSurface,
+        },
+    },
+    wayland::selection::{
+        SelectionHandler,
+        data_device::{
+            ClientDndGrabHandler, DataDeviceHandler, DataDeviceState, DataDeviceUserData,
+            DataSourceUserData, ServerDndGrabHandler,
+        },
+        primary_selection::{PrimarySelectionHandler, PrimarySelectionState},
+        wlr_data_control::{DataControlHandler, DataControlState},
+    },
+};
+use std::os::fd::OwnedFd;
+use std::sync::{Arc, RwLock};
+use tracing::debug;
+
+/// Filter of the data control global: only allowed clients see it
+///
+/// The filter can't reach the compositor state, so it reads the config
+/// shared with it, which a reload updates.
+pub fn data_control_filter(
+    config: Arc<RwLock<ClipboardConfig>>,
+) -> impl Fn(&Client) -> bool + Send + Sync + 'static {
+    move |client| {
+        client
+            .get_data::<ClientState>()
+            .and_then(|data| data.executable.as_deref())
+            .is_some_and(|executable| config.read().is_ok_and(|config| config.allows(executable)))
+    }
+}
+
+impl SelectionHandler for LoomState {
+    type SelectionUserData = ();
+}
+
+impl DataDeviceHandler for LoomState {
+    fn data_device_state(&self) -> &DataDeviceState {
+        &self.data_device_state
+    }
+}
+
+impl ClientDndGrabHandler for LoomState {
+    fn started(
+        &mut self,
+        source: Option<WlDataSource>,
+        icon: Option<WlSurface>,
+        _seat: Seat<Self>,
+    ) {
+        debug!("Drag started");
+        self.start_dnd(source, icon);
+    }
+
+    fn dropped(&mut self, _target: Option<WlSurface>, validated: bool, _seat: Seat<Self>) {
+        debug!("Dropped (accepted: {})", validated);
+        self.end_dnd();
+    }
+}
+
+// Compositor drags relay the drags of client data sources
+impl ServerDndGrabHandler for LoomState {
+    fn accept(&mut self, mime_type: Option<String>, _seat: Seat<Self>) {
+        self.dnd_accepted(mime_type);
+    }
+
+    fn action(&mut self, action: DndAction, _seat: Seat<Self>) {
+        self.dnd_action(action);
+    }
+
+    fn dropped(&mut self, _seat: Seat<Self>) {
+        debug!("Relayed drag dropped");
+        self.dnd_dropped();
+    }
+
+    fn cancelled(&mut self, _seat: Seat<Self>) {
+        self.dnd_cancelled();
+    }
+
+    fn send(&mut self, mime_type: String, fd: OwnedFd, _seat: Seat<Self>) {
+        self.dnd_send(mime_type, fd);
+    }
+
+    fn finished(&mut self, _seat: Seat<Self>) {
+        self.dnd_finished();
+    }
+}
+
+// Pointer drags with a data source are relayed, Smithay handles the rest
+impl Dispatch<WlDataDevice, DataDeviceUserData> for LoomState {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        resource: &WlDataDevice,
+        request: wl_data_device::Request,
+        data: &DataDeviceUserData,
+        dh: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if let wl_data_device::Request::StartDrag {
+            source: Some(source),
+            icon,
+            serial,
+            ..
+        } = &request
+            && state.start_source_drag(resource, source.clone(), icon.clone(), (*serial).into())
+        {
+            return;
+        }
+
+        <DataDeviceState as Dispatch<WlDataDevice, DataDeviceUserData, Self>>::request(
+            state, client, resource, request, data, dh, data_init,
+        );
+    }
+}
+
+impl PrimarySelectionHandler for LoomState {
+    fn primary_selection_state(&self) -> &PrimarySelectionState {
+        &self.primary_selection_state
+    }
+}
+
+impl DataControlHandler for LoomState {
+    fn data_control_state(&self) -> &DataControlState {
+        &self.data_control_state
+    }
+}
+
+delegate_global_dispatch!(LoomState: [WlDataDeviceManager: ()] => DataDeviceState);
+delegate_dispatch!(LoomState: [WlDataDeviceManager: ()] => DataDeviceState);
+delegate_dispatch!(LoomState: [WlDataSource: DataSourceUserData] => DataDeviceState);
+delegate_primary_selection!(LoomState);
+delegate_data_control!(LoomState);
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::{headless::HeadlessBackend, test_client::TestClient};
+    use loom_config::Config;
+
+    const DATA_CONTROL: &str = "zwlr_data_control_manager_v1";
+
+    #[test]
+    fn test_data_control_allowlist() {
+        let mut backend = HeadlessBackend::for_tests(64, 48);
+
+        // Hidden by default
+        let client = TestClient::connect(&mut backend);
+        assert!(client.has_global("wl_data_device_manager"));
+        assert!(!client.has_global(DATA_CONTROL));
+
+        // Test clients run the test binary
+        let mut config = Config::default();
+        let executable = std::env::current_exe().unwrap();
+        config.clipboard.allowed_clients = vec![executable.display().to_string()];
+        backend.state_mut().apply_config(&config);
+        let client = TestClient::connect(&mut backend);
+        assert!(client.has_global(DATA_CONTROL));
+    }
+}
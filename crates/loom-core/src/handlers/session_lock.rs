@@ -0,0 +1,80 @@
+//! Session lock handler
+//!
+//! Handles ext_session_lock_manager_v1 for screen lockers; the lock itself
+//! is managed by [`crate::lock`].
+//!
+//! Smithay unlocks on any `unlock_and_destroy`, even from a lock that was
+//! refused, so lock requests are checked here before reaching it.
+
+use crate::state::LoomState;
+use smithay::{
+    output::Output,
+    reexports::{
+        wayland_protocols::ext::session_lock::v1::server::{
+            ext_session_lock_manager_v1::ExtSessionLockManagerV1,
+            ext_session_lock_surface_v1::ExtSessionLockSurfaceV1,
+            ext_session_lock_v1::{self, ExtSessionLockV1},
+        },
+        wayland_server::{
+            Client, DataInit, Dispatch, DisplayHandle, Resource, delegate_dispatch,
+            delegate_global_dispatch, protocol::wl_output::WlOutput,
+        },
+    },
+    wayland::session_lock::{
+        ExtLockSurfaceUserData, LockSurface, SessionLockHandler, SessionLockManagerGlobalData,
+        SessionLockManagerState, SessionLockState, SessionLocker,
+    },
+};
+use tracing::debug;
+
+impl SessionLockHandler for LoomState {
+    fn lock_state(&mut self) -> &mut SessionLockManagerState {
+        &mut self.session_lock_state
+    }
+
+    fn lock(&mut self, confirmation: SessionLocker) {
+        debug!("Session lock requested");
+
+        self.lock_session(confirmation);
+    }
+
+    fn unlock(&mut self) {
+        self.unlock_session();
+    }
+
+    fn new_surface(&mut self, surface: LockSurface, output: WlOutput) {
+        debug!("New lock surface");
+
+        self.add_lock_surface(surface, Output::from_resource(&output));
+    }
+}
+
+impl Dispatch<ExtSessionLockV1, SessionLockState> for LoomState {
+    fn request(
+        state: &mut Self,
+        client: &Client,
+        lock: &ExtSessionLockV1,
+        request: ext_session_lock_v1::Request,
+        data: &SessionLockState,
+        dhandle: &DisplayHandle,
+        data_init: &mut DataInit<'_, Self>,
+    ) {
+        if matches!(request, ext_session_lock_v1::Request::UnlockAndDestroy)
+            && !state.lock.is_locked_by(lock)
+        {
+            lock.post_error(
+                ext_session_lock_v1::Error::InvalidUnlock,
+                "the session is not locked by this lock",
+            );
+            return;
+        }
+
+        <SessionLockManagerState as Dispatch<ExtSessionLockV1, SessionLockState, Self>>::request(
+            state, client, lock, request, data, dhandle, data_init,
+        );
+    }
+}
+
+delegate_global_dispatch!(LoomState: [ExtSessionLockManagerV1: SessionLockManagerGlobalData] => SessionLockManagerState);
+delegate_dispatch!(LoomState: [ExtSessionLockManagerV1: ()] => SessionLockManagerState);
+delegate_dispatch!(LoomState: [ExtSessionLockSurfaceV1: ExtLockSurfaceUserData] => SessionLockManagerState);
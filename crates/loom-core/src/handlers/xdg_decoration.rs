@@ -0,0 +1,51 @@
+//! XDG decoration handler
+//!
+//! Negotiates who draws window decorations through
+//! zxdg_decoration_manager_v1. Server-side decorations are preferred unless
+//! disabled in the config, but a client asking for a mode gets it; framed
+//! windows are drawn by [`crate::decorations`].
+
+use crate::state::LoomState;
+use smithay::{
+    delegate_xdg_decoration,
+    reexports::wayland_protocols::xdg::decoration::zv1::server::zxdg_toplevel_decoration_v1::Mode,
+    wayland::shell::xdg::{ToplevelSurface, decoration::XdgDecorationHandler},
+};
+use tracing::debug;
+
+impl XdgDecorationHandler for LoomState {
+    fn new_decoration(&mut self, toplevel: ToplevelSurface) {
+        set_decoration_mode(&toplevel, self.preferred_decoration_mode());
+    }
+
+    fn request_mode(&mut self, toplevel: ToplevelSurface, mode: Mode) {
+        debug!("Decoration mode requested: {:?}", mode);
+        set_decoration_mode(&toplevel, mode);
+    }
+
+    fn unset_mode(&mut self, toplevel: ToplevelSurface) {
+        set_decoration_mode(&toplevel, self.preferred_decoration_mode());
+    }
+}
+
+impl LoomState {
+    /// Decoration mode for clients without a preference
+    fn preferred_decoration_mode(&self) -> Mode {
+        if self.general_config.server_side_decorations {
+            Mode::ServerSide
+        } else {
+            Mode::ClientSide
+        }
+    }
+}
+
+/// Set a toplevel's decoration mode, configuring it unless its initial
+/// configure is still to come
+fn set_decoration_mode(toplevel: &ToplevelSurface, mode: Mode) {
+    toplevel.with_pending_state(|state| state.decoration_mode = Some(mode));
+    if toplevel.is_initial_configure_sent() {
+        toplevel.send_pending_configure();
+    }
+}
+
+delegate_xdg_decoration!(LoomState);
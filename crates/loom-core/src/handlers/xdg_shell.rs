@@ -5,17 +5,22 @@
 use crate::state::LoomState;
 use smithay::{
     delegate_xdg_shell,
-    desktop::{PopupKind, Window},
+    desktop::Window,
+    input::{Seat, pointer::GrabStartData as PointerGrabStartData},
+    output::Output,
     reexports::{
         wayland_protocols::xdg::shell::server::xdg_toplevel,
-        wayland_server::protocol::wl_seat::WlSeat,
+        wayland_server::{
+            Resource,
+            protocol::{wl_output::WlOutput, wl_seat::WlSeat},
+        },
     },
     utils::Serial,
     wayland::shell::xdg::{
         PopupSurface, PositionerState, ToplevelSurface, XdgShellHandler, XdgShellState,
     },
 };
-use tracing::{debug, warn};
+use tracing::debug;
 
 impl XdgShellHandler for LoomState {
     fn xdg_shell_state(&mut self) -> &mut XdgShellState {
@@ -27,70 +32,68 @@ impl XdgShellHandler for LoomState {
 
         let window = Window::new_wayland_window(surface);
 
-        // Place the window at the origin for now
-        // TODO: Implement proper window placement on the canvas
-        self.space.map_element(window, (0, 0), false);
+        // Every toplevel becomes a node on the canvas
+        self.add_window_node(window);
     }
 
     fn new_popup(&mut self, surface: PopupSurface, _positioner: PositionerState) {
         debug!("New popup surface created");
 
-        // For now, just track the popup but don't position it
-        // TODO: Implement proper popup positioning
-        let _ = self.space.elements().find(|w| {
-            w.toplevel()
-                .map(|t| {
-                    let popup = PopupKind::Xdg(surface.clone());
-                    t.wl_surface() == popup.wl_surface()
-                })
-                .unwrap_or(false)
-        });
+        // The positioner is already stored in the surface's pending state
+        self.track_popup(surface);
     }
 
-    fn grab(&mut self, _surface: PopupSurface, _seat: WlSeat, _serial: Serial) {
-        // TODO: Implement popup grab
-        warn!("Popup grab requested but not implemented");
+    fn grab(&mut self, surface: PopupSurface, seat: WlSeat, serial: Serial) {
+        debug!("Popup grab requested");
+
+        if let Some(seat) = Seat::<LoomState>::from_resource(&seat) {
+            self.grab_popup(surface, &seat, serial);
+        }
     }
 
     fn reposition_request(
         &mut self,
-        _surface: PopupSurface,
-        _positioner: PositionerState,
-        _token: u32,
+        surface: PopupSurface,
+        positioner: PositionerState,
+        token: u32,
     ) {
-        // TODO: Implement popup reposition
-        warn!("Popup reposition requested but not implemented");
+        debug!("Popup reposition requested (token {})", token);
+        self.reposition_popup(surface, positioner, token);
     }
 
     fn toplevel_destroyed(&mut self, surface: ToplevelSurface) {
         debug!("Toplevel destroyed");
 
-        // Remove the window from the space
-        let window = self
-            .space
-            .elements()
-            .find(|w| w.toplevel().map(|t| t == &surface).unwrap_or(false))
-            .cloned();
-
-        if let Some(window) = window {
-            self.space.unmap_elem(&window);
-        }
+        // Remove the window from the space and its node from the canvas
+        self.remove_window_node(&surface);
     }
 
-    fn move_request(&mut self, _surface: ToplevelSurface, _seat: WlSeat, _serial: Serial) {
-        // TODO: Implement interactive move
-        debug!("Move request (not yet implemented)");
+    fn move_request(&mut self, surface: ToplevelSurface, seat: WlSeat, serial: Serial) {
+        debug!("Move request");
+
+        let Some(id) = self.window_id_for_surface(surface.wl_surface()) else {
+            return;
+        };
+        if let Some(start_data) = check_grab(&seat, surface.wl_surface(), serial) {
+            self.start_move_grab(id, start_data, serial);
+        }
     }
 
     fn resize_request(
         &mut self,
-        _surface: ToplevelSurface,
-        _seat: WlSeat,
-        _serial: Serial,
-        _edges: xdg_toplevel::ResizeEdge,
+        surface: ToplevelSurface,
+        seat: WlSeat,
+        serial: Serial,
+        edges: xdg_toplevel::ResizeEdge,
     ) {
-        // TODO: Implement interactive resize
-        debug!("Resize request (not yet implemented)");
+        debug!("Resize request ({:?})", edges);
+
+        let Some(id) = self.window_id_for_surface(surface.wl_surface()) else {
+            return;
+        };
+        if let Some(start_data) = check_grab(&seat, surface.wl_surface(), serial) {
+            self.start_resize_grab(id, edges.into(), start_data, serial);
+        }
     }
 
     fn maximize_request(&mut self, surface: ToplevelSurface) {
@@ -103,18 +106,31 @@ impl XdgShellHandler for LoomState {
         surface.send_configure();
     }
 
-    fn fullscreen_request(
-        &mut self,
-        surface: ToplevelSurface,
-        _output: Option<smithay::reexports::wayland_server::protocol::wl_output::WlOutput>,
-    ) {
+    fn fullscreen_request(&mut self, surface: ToplevelSurface, output: Option<WlOutput>) {
         debug!("Fullscreen request");
-        surface.send_configure();
+
+        let Some(id) = self.window_id_for_surface(surface.wl_surface()) else {
+            return;
+        };
+        self.set_fullscreen(id, output.as_ref().and_then(Output::from_resource));
+
+        // The window may not fit anywhere, but the request needs an answer
+        if self.windows[id].fullscreen.is_none() && surface.is_initial_configure_sent() {
+            surface.send_configure();
+        }
     }
 
     fn unfullscreen_request(&mut self, surface: ToplevelSurface) {
         debug!("Unfullscreen request");
-        surface.send_configure();
+
+        let Some(id) = self.window_id_for_surface(surface.wl_surface()) else {
+            return;
+        };
+        if self.windows[id].fullscreen.is_some() {
+            self.unset_fullscreen(id);
+        } else if surface.is_initial_configure_sent() {
+            surface.send_configure();
+        }
     }
 
     fn minimize_request(&mut self, _surface: ToplevelSurface) {
@@ -142,4 +158,29 @@ impl XdgShellHandler for LoomState {
 
 use smithay::reexports::wayland_server::protocol::wl_surface::WlSurface;
 
+/// Validate a client's move/resize request against the pointer's click grab
+///
+/// Clients may only start an interactive grab while one of their surfaces
+/// holds the implicit grab of a button press with the given serial.
+fn check_grab(
+    seat: &WlSeat,
+    surface: &WlSurface,
+    serial: Serial,
+) -> Option<PointerGrabStartData<LoomState>> {
+    let seat = Seat::<LoomState>::from_resource(seat)?;
+    let pointer = seat.get_pointer()?;
+
+    if !pointer.has_grab(serial) {
+        return None;
+    }
+
+    let start_data = pointer.grab_start_data()?;
+    let (focus, _) = start_data.focus.as_ref()?;
+    if !focus.id().same_client_as(&surface.id()) {
+        return None;
+    }
+
+    Some(start_data)
+}
+
 delegate_xdg_shell!(LoomState);
@@ -0,0 +1,258 @@
+//! Idle timeouts and inhibitors
+//!
+//! Input restarts the idle timers (see [`LoomState::notify_activity`]):
+//! those of clients watching for idleness through `ext_idle_notify_v1`
+//! (swayidle), and the compositor's own `[idle]` timeouts, which run the
+//! screen locker and turn the outputs off. Outputs come back on with the
+//! next input. The DRM backend powers them down; the nested and headless
+//! backends keep drawing.
+//!
+//! Clients hold idleness off through `zwp_idle_inhibit_manager_v1`, for as
+//! long as the inhibiting surface is visible: a video player on screen
+//! keeps the outputs on, one scrolled out of view, behind a fullscreen
+//! window or behind the lock screen doesn't.
+
+use crate::security::MAX_IDLE_INHIBITORS;
+use crate::state::LoomState;
+use crate::types::WindowId;
+use smithay::{
+    reexports::{
+        calloop::{
+            RegistrationToken,
+            timer::{TimeoutAction, Timer},
+        },
+        wayland_server::{Resource, protocol::wl_surface::WlSurface},
+    },
+    wayland::compositor::get_parent,
+};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Idle timers and inhibitors
+#[derive(Debug)]
+pub struct IdleState {
+    /// Time of the last input, or of the last check while inhibited
+    last_activity: Instant,
+    /// Whether the lock command ran since the last input
+    lock_ran: bool,
+    /// Whether the outputs are turned off
+    outputs_off: bool,
+    /// Surfaces inhibiting idleness while visible, once per inhibitor
+    inhibitors: Vec<WlSurface>,
+    /// Timer acting on the `[idle]` timeouts, while one is ahead
+    timer: Option<RegistrationToken>,
+}
+
+impl Default for IdleState {
+    fn default() -> Self {
+        Self {
+            last_activity: Instant::now(),
+            lock_ran: false,
+            outputs_off: false,
+            inhibitors: Vec::new(),
+            timer: None,
+        }
+    }
+}
+
+impl IdleState {
+    /// Whether the outputs are turned off for idleness
+    pub fn outputs_off(&self) -> bool {
+        self.outputs_off
+    }
+}
+
+impl LoomState {
+    /// Restart the idle timers on user input, turning the outputs back on
+    pub fn notify_activity(&mut self) {
+        self.idle.last_activity = Instant::now();
+        self.idle.lock_ran = false;
+        if self.idle.outputs_off {
+            info!("Input, turning outputs on");
+            self.idle.outputs_off = false;
+        }
+
+        self.idle_notifier_state.notify_activity(&self.seat);
+        if self.idle.timer.is_none() {
+            self.start_idle_timer();
+        }
+    }
+
+    /// (Re)start the timer acting on the `[idle]` timeouts, after the
+    /// config changed
+    pub fn start_idle_timer(&mut self) {
+        if let Some(token) = self.idle.timer.take() {
+            self.loop_handle.remove(token);
+        }
+        let Some(delay) = self.next_idle_timeout() else {
+            return;
+        };
+
+        let timer = Timer::from_duration(delay);
+        let result = self.loop_handle.insert_source(timer, |_, _, state| {
+            state.check_idle();
+            match state.next_idle_timeout() {
+                Some(delay) => TimeoutAction::ToDuration(delay),
+                None => {
+                    state.idle.timer = None;
+                    TimeoutAction::Drop
+                }
+            }
+        });
+        match result {
+            Ok(token) => self.idle.timer = Some(token),
+            Err(e) => warn!("Failed to start idle timer: {}", e),
+        }
+    }
+
+    /// Inhibit idleness while a surface is visible
+    pub fn add_idle_inhibitor(&mut self, surface: WlSurface) {
+        self.idle.inhibitors.retain(Resource::is_alive);
+        if self.idle.inhibitors.len() >= MAX_IDLE_INHIBITORS {
+            warn!(
+                "Ignoring idle inhibitor: max idle inhibitors ({}) reached",
+                MAX_IDLE_INHIBITORS
+            );
+            return;
+        }
+        debug!("Idle inhibitor added");
+        self.idle.inhibitors.push(surface);
+        self.refresh_idle_inhibitors();
+    }
+
+    /// Drop an inhibitor of a surface
+    pub fn remove_idle_inhibitor(&mut self, surface: &WlSurface) {
+        if let Some(index) = self.idle.inhibitors.iter().position(|s| s == surface) {
+            debug!("Idle inhibitor removed");
+            self.idle.inhibitors.swap_remove(index);
+        }
+        self.refresh_idle_inhibitors();
+    }
+
+    /// Whether a visible surface inhibits idleness
+    pub fn idle_inhibited(&self) -> bool {
+        !self.session_locked
+            && self
+                .idle
+                .inhibitors
+                .iter()
+                .any(|surface| surface.is_alive() && self.surface_visible(surface))
+    }
+
+    /// Hold the idle notifications of clients while idleness is inhibited
+    ///
+    /// Called after every dispatch, since panning and zooming change what
+    /// is visible.
+    pub fn refresh_idle_inhibitors(&mut self) {
+        if self.idle.inhibitors.is_empty() && !self.idle_notifier_state.is_inhibited() {
+            return;
+        }
+        self.idle.inhibitors.retain(Resource::is_alive);
+        let inhibited = self.idle_inhibited();
+        self.idle_notifier_state.set_is_inhibited(inhibited);
+    }
+
+    /// Act on the `[idle]` timeouts that passed
+    fn check_idle(&mut self) {
+        if self.idle_inhibited() {
+            self.idle.last_activity = Instant::now();
+            return;
+        }
+        let idle = self.idle.last_activity.elapsed();
+
+        let lock = self.idle_config.lock_timeout();
+        if !self.idle.lock_ran && lock.is_some_and(|timeout| idle >= timeout) {
+            self.idle.lock_ran = true;
+            if let Some(command) = self.idle_config.lock_command.clone()
+                && !self.session_locked
+            {
+                info!("Idle, locking the session");
+                self.spawn_command(&command);
+            }
+        }
+
+        let dpms = self.idle_config.dpms_timeout();
+        if !self.idle.outputs_off && dpms.is_some_and(|timeout| idle >= timeout) {
+            info!("Idle, turning outputs off");
+            self.idle.outputs_off = true;
+        }
+    }
+
+    /// Time until the next `[idle]` timeout, `None` if all passed or none
+    /// is configured
+    fn next_idle_timeout(&self) -> Option<Duration> {
+        let idle = self.idle.last_activity.elapsed();
+        let lock = self
+            .idle_config
+            .lock_timeout()
+            .filter(|_| !self.idle.lock_ran);
+        let dpms = self
+            .idle_config
+            .dpms_timeout()
+            .filter(|_| !self.idle.outputs_off);
+
+        [lock, dpms]
+            .into_iter()
+            .flatten()
+            .map(|timeout| timeout.saturating_sub(idle))
+            .min()
+    }
+
+    /// Whether a surface is shown on an output: a window on screen and not
+    /// behind a fullscreen window, or a layer surface
+    fn surface_visible(&self, surface: &WlSurface) -> bool {
+        let mut root = surface.clone();
+        while let Some(parent) = get_parent(&root) {
+            root = parent;
+        }
+
+        match self.window_id_for_surface(&root) {
+            Some(id) => self.window_visible(id),
+            None => self.layer_for_surface(&root).is_some(),
+        }
+    }
+
+    /// Whether a window is on screen, not behind a fullscreen window
+    fn window_visible(&self, id: WindowId) -> bool {
+        let Some(rect) = self.window_node_rect(id) else {
+            return false;
+        };
+
+        self.views.iter().any(|view| {
+            view.outputs.iter().any(
+                |(output, geometry)| match self.fullscreen_window_on(output) {
+                    Some(fullscreen) => fullscreen == id,
+                    None => self.global_rect_to_canvas(view, *geometry).overlaps(rect),
+                },
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::headless::HeadlessBackend;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_idle_timeouts() {
+        let mut backend = HeadlessBackend::for_tests(64, 48);
+        let state = backend.state_mut();
+        assert_eq!(state.next_idle_timeout(), None);
+
+        state.idle_config.dpms_timeout_secs = 60;
+        state.idle.last_activity = Instant::now() - Duration::from_secs(30);
+        let next = state.next_idle_timeout().unwrap();
+        assert!(next <= Duration::from_secs(30) && next > Duration::from_secs(29));
+
+        state.idle.last_activity = Instant::now() - Duration::from_secs(61);
+        state.check_idle();
+        assert!(state.idle.outputs_off());
+        assert_eq!(state.next_idle_timeout(), None);
+
+        state.notify_activity();
+        assert!(!state.idle.outputs_off());
+        assert!(state.next_idle_timeout().unwrap() > Duration::from_secs(59));
+        assert!(!state.idle_inhibited());
+    }
+}
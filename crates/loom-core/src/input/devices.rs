@@ -0,0 +1,234 @@
+//! Keyboard layout and input device settings
+//!
+//! The keymap and key repeat of the seat's keyboard come from the
+//! `[input]` config section. With the DRM backend, libinput devices get
+//! the options of the device sections matching them when they are added,
+//! and again when the config is reloaded; options no section sets are
+//! reset to libinput's defaults.
+
+use crate::state::LoomState;
+use loom_config::InputConfig;
+use smithay::input::keyboard::XkbConfig;
+use tracing::warn;
+
+#[cfg(feature = "backend-drm")]
+use loom_config::input::{AccelProfile, DeviceType, ScrollMethod};
+#[cfg(feature = "backend-drm")]
+use smithay::reexports::input::{self as libinput, DeviceCapability};
+#[cfg(feature = "backend-drm")]
+use tracing::debug;
+
+/// XKB settings of the configured keymap
+pub(crate) fn xkb_config(config: &InputConfig) -> XkbConfig<'_> {
+    XkbConfig {
+        rules: &config.xkb_rules,
+        model: &config.xkb_model,
+        layout: &config.xkb_layout,
+        variant: &config.xkb_variant,
+        options: config.xkb_options.clone(),
+    }
+}
+
+impl LoomState {
+    /// Apply the configured keymap and key repeat to the keyboard
+    ///
+    /// An invalid keymap is logged and the current one kept.
+    pub fn apply_keyboard_config(&mut self) {
+        let Some(keyboard) = self.seat.get_keyboard() else {
+            return;
+        };
+
+        let config = self.input_config.clone();
+        if let Err(e) = keyboard.set_xkb_config(self, xkb_config(&config)) {
+            warn!(
+                "Invalid keymap (layout {:?}, variant {:?}): {:?}",
+                config.xkb_layout, config.xkb_variant, e
+            );
+        }
+
+        let (delay, rate) = config.repeat_info();
+        keyboard.change_repeat_info(rate, delay);
+    }
+}
+
+#[cfg(feature = "backend-drm")]
+impl LoomState {
+    /// Configure a new libinput device and keep it for config reloads
+    pub fn add_libinput_device(&mut self, mut device: libinput::Device) {
+        configure_device(&self.input_config, &mut device);
+        self.libinput_devices.push(device);
+    }
+
+    /// Forget a removed libinput device
+    pub fn remove_libinput_device(&mut self, device: &libinput::Device) {
+        self.libinput_devices.retain(|d| d != device);
+    }
+
+    /// Apply the device settings to all libinput devices again
+    pub fn configure_libinput_devices(&mut self) {
+        for device in &mut self.libinput_devices {
+            configure_device(&self.input_config, device);
+        }
+    }
+}
+
+/// Type of a libinput device, from its capabilities
+///
+/// Pointers that can tap are touchpads.
+#[cfg(feature = "backend-drm")]
+fn device_type(device: &libinput::Device) -> DeviceType {
+    if device.has_capability(DeviceCapability::TabletTool)
+        || device.has_capability(DeviceCapability::TabletPad)
+    {
+        DeviceType::Tablet
+    } else if device.has_capability(DeviceCapability::Touch) {
+        DeviceType::Touch
+    } else if device.has_capability(DeviceCapability::Pointer) {
+        if device.config_tap_finger_count() > 0 {
+            DeviceType::Touchpad
+        } else {
+            DeviceType::Pointer
+        }
+    } else {
+        DeviceType::Keyboard
+    }
+}
+
+/// Apply the settings matching a device
+///
+/// Options no section sets go back to the device's defaults, in case a
+/// reload dropped them. Settings the device doesn't support are logged and
+/// skipped; defaults it doesn't support are skipped silently.
+#[cfg(feature = "backend-drm")]
+fn configure_device(config: &InputConfig, device: &mut libinput::Device) {
+    let name = device.name().to_string();
+    let device_type = device_type(device);
+    let settings = config.device_settings(&name, device_type);
+    debug!("Configuring {:?} {}: {:?}", device_type, name, settings);
+
+    let report = |option: &str, configured: bool, result: libinput::DeviceConfigResult| {
+        if configured && let Err(e) = result {
+            warn!("Cannot set {} of {}: {:?}", option, name, e);
+        }
+    };
+
+    report(
+        "tap_to_click",
+        settings.tap_to_click.is_some(),
+        device.config_tap_set_enabled(
+            settings
+                .tap_to_click
+                .unwrap_or(device.config_tap_default_enabled()),
+        ),
+    );
+    report(
+        "natural_scroll",
+        settings.natural_scroll.is_some(),
+        device.config_scroll_set_natural_scroll_enabled(
+            settings
+                .natural_scroll
+                .unwrap_or(device.config_scroll_default_natural_scroll_enabled()),
+        ),
+    );
+    let profile = settings
+        .accel_profile
+        .map(|profile| match profile {
+            AccelProfile::Flat => libinput::AccelProfile::Flat,
+            AccelProfile::Adaptive => libinput::AccelProfile::Adaptive,
+        })
+        .or(device.config_accel_default_profile());
+    if let Some(profile) = profile {
+        report(
+            "accel_profile",
+            settings.accel_profile.is_some(),
+            device.config_accel_set_profile(profile),
+        );
+    }
+    report(
+        "accel_speed",
+        settings.accel_speed.is_some(),
+        device.config_accel_set_speed(
+            settings
+                .accel_speed
+                .unwrap_or(device.config_accel_default_speed()),
+        ),
+    );
+    report(
+        "left_handed",
+        settings.left_handed.is_some(),
+        device.config_left_handed_set(
+            settings
+                .left_handed
+                .unwrap_or(device.config_left_handed_default()),
+        ),
+    );
+    report(
+        "disable_while_typing",
+        settings.disable_while_typing.is_some(),
+        device.config_dwt_set_enabled(
+            settings
+                .disable_while_typing
+                .unwrap_or(device.config_dwt_default_enabled()),
+        ),
+    );
+    let method = settings
+        .scroll_method
+        .map(|method| match method {
+            ScrollMethod::None => libinput::ScrollMethod::NoScroll,
+            ScrollMethod::TwoFinger => libinput::ScrollMethod::TwoFinger,
+            ScrollMethod::Edge => libinput::ScrollMethod::Edge,
+            ScrollMethod::OnButtonDown => libinput::ScrollMethod::OnButtonDown,
+        })
+        .or(device.config_scroll_default_method());
+    if let Some(method) = method {
+        report(
+            "scroll_method",
+            settings.scroll_method.is_some(),
+            device.config_scroll_set_method(method),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::headless::HeadlessBackend;
+    use loom_config::Config;
+    use loom_config::input::{DeviceConfig, DeviceType};
+
+    #[test]
+    fn test_apply_input_config() {
+        let mut backend = HeadlessBackend::for_tests(64, 48);
+        let state = backend.state_mut();
+        let keyboard = state.seat.get_keyboard().unwrap();
+        let layouts = |state: &mut crate::state::LoomState| {
+            keyboard.with_xkb_state(state, |context| {
+                context.xkb().lock().unwrap().layouts().count()
+            })
+        };
+
+        let mut config = Config::default();
+        config.input.xkb_layout = "us,de".to_string();
+        config.input.devices.push(DeviceConfig {
+            device_type: Some(DeviceType::Touchpad),
+            natural_scroll: Some(true),
+            ..DeviceConfig::default()
+        });
+        state.apply_config(&config);
+        assert_eq!(layouts(state), 2);
+        let settings = state
+            .input_config
+            .device_settings("Touchpad", DeviceType::Touchpad);
+        assert_eq!(settings.natural_scroll, Some(true));
+
+        // An invalid layout keeps the current keymap, and options dropped
+        // by a reload are unset, so devices go back to their defaults
+        config.input.xkb_layout = "no-such-layout".to_string();
+        config.input.devices.clear();
+        state.apply_config(&config);
+        assert_eq!(layouts(state), 2);
+        let settings = state
+            .input_config
+            .device_settings("Touchpad", DeviceType::Touchpad);
+        assert_eq!(settings.natural_scroll, None);
+    }
+}
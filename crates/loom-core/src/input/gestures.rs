@@ -0,0 +1,392 @@
+//! Touchpad gestures
+//!
+//! Gestures bound in the config navigate the canvas: by default a
+//! three-finger swipe pans, a pinch zooms around the cursor, a four-finger
+//! swipe cycles the focus and resting fingers stop a gliding canvas. The
+//! binding is chosen when a gesture begins, preferring one for its exact
+//! finger count; gestures no binding matches go to the client under the
+//! pointer through `zwp_pointer_gestures_v1`, begin to end. A hold bound to
+//! stopping inertia is only taken while the canvas glides, so clients still
+//! see resting fingers otherwise.
+
+use crate::security::MAX_GESTURE_BINDINGS;
+use crate::state::LoomState;
+use loom_config::{GestureAction, GestureBinding, GestureKind};
+use smithay::{
+    backend::input::{
+        Event, GestureBeginEvent, GestureEndEvent, GesturePinchUpdateEvent as _,
+        GestureSwipeUpdateEvent as _, InputBackend,
+    },
+    input::pointer::{
+        GestureHoldBeginEvent, GestureHoldEndEvent, GesturePinchBeginEvent, GesturePinchEndEvent,
+        GesturePinchUpdateEvent, GestureSwipeBeginEvent, GestureSwipeEndEvent,
+        GestureSwipeUpdateEvent,
+    },
+    utils::SERIAL_COUNTER,
+};
+use tracing::{debug, warn};
+
+/// Horizontal swipe distance that moves the focus by one window
+const CYCLE_SWIPE_STEP: f64 = 150.0;
+
+/// Gesture bindings and the gesture in progress
+#[derive(Debug, Default)]
+pub struct GestureState {
+    bindings: Vec<GestureBinding>,
+    /// Gesture in progress, unless it goes to a client
+    active: Option<ActiveGesture>,
+}
+
+/// Gesture handled by the compositor
+#[derive(Debug, Clone, Copy)]
+struct ActiveGesture {
+    action: GestureAction,
+    /// Pinch scale at the last update, relative to the start
+    scale: f64,
+    /// Horizontal distance swiped since the focus last moved
+    swipe: f64,
+}
+
+impl GestureState {
+    /// Gesture state with the configured bindings
+    ///
+    /// Bindings beyond the limit are logged and dropped.
+    pub fn from_config(bindings: &[GestureBinding]) -> Self {
+        if bindings.len() > MAX_GESTURE_BINDINGS {
+            warn!(
+                "Ignoring gesture bindings beyond the first {}",
+                MAX_GESTURE_BINDINGS
+            );
+        }
+
+        Self {
+            bindings: bindings
+                .iter()
+                .take(MAX_GESTURE_BINDINGS)
+                .cloned()
+                .collect(),
+            active: None,
+        }
+    }
+
+    /// Action bound to a gesture
+    ///
+    /// A binding for the exact finger count wins over one for any count.
+    pub fn action_for(&self, gesture: GestureKind, fingers: u32) -> Option<GestureAction> {
+        let matching = || self.bindings.iter().filter(|b| b.gesture == gesture);
+        matching()
+            .find(|b| b.fingers == Some(fingers))
+            .or_else(|| matching().find(|b| b.fingers.is_none()))
+            .map(|b| b.action)
+    }
+
+    /// Start handling a gesture if it's bound, returning whether it is
+    fn begin(&mut self, gesture: GestureKind, fingers: u32) -> bool {
+        self.active = self
+            .action_for(gesture, fingers)
+            .map(|action| ActiveGesture {
+                action,
+                scale: 1.0,
+                swipe: 0.0,
+            });
+        self.active.is_some()
+    }
+}
+
+impl LoomState {
+    /// Start a bound gesture
+    ///
+    /// Gestures go to the locker while the session is locked, and holds
+    /// bound to stopping inertia go to clients unless the canvas glides.
+    fn begin_gesture(&mut self, gesture: GestureKind, fingers: u32) -> bool {
+        if self.session_locked {
+            self.gestures.active = None;
+            return false;
+        }
+        if !self.gestures.begin(gesture, fingers) {
+            return false;
+        }
+        if self
+            .gestures
+            .active
+            .is_some_and(|active| active.action == GestureAction::StopInertia)
+            && !self.navigation.kinetic.is_active()
+        {
+            self.gestures.active = None;
+            return false;
+        }
+
+        debug!("{:?} gesture with {} fingers bound", gesture, fingers);
+        // Fingers on the touchpad catch a gliding canvas
+        self.stop_kinetic_pan();
+        true
+    }
+
+    /// Apply a bound gesture's movement
+    ///
+    /// `scale` is the pinch scale relative to the start, 1 for swipes.
+    fn update_gesture(&mut self, time_msec: u32, dx: f64, dy: f64, scale: f64) {
+        let Some(mut active) = self.gestures.active else {
+            return;
+        };
+
+        match active.action {
+            GestureAction::Pan => {
+                let sensitivity = self.canvas_config.pan_sensitivity;
+                let (dx, dy) = (dx * sensitivity, dy * sensitivity);
+                self.navigation.kinetic.push(time_msec, dx, dy);
+                self.pan_view(dx, dy);
+                self.refresh_pointer_focus();
+            }
+            GestureAction::Zoom => {
+                let factor = scale / active.scale;
+                active.scale = scale;
+                if factor.is_finite() && factor > 0.0 {
+                    self.zoom_view_at(factor, self.pointer_location);
+                    self.refresh_pointer_focus();
+                }
+            }
+            GestureAction::CycleFocus => {
+                active.swipe += dx;
+                let steps = (active.swipe / CYCLE_SWIPE_STEP).trunc();
+                active.swipe -= steps * CYCLE_SWIPE_STEP;
+                for _ in 0..steps.abs() as u32 {
+                    self.cycle_focus(steps < 0.0);
+                }
+            }
+            GestureAction::StopInertia => {}
+        }
+        self.gestures.active = Some(active);
+    }
+
+    /// Finish a bound gesture
+    fn end_gesture(&mut self, time_msec: u32, cancelled: bool) {
+        let Some(active) = self.gestures.active.take() else {
+            return;
+        };
+
+        match active.action {
+            GestureAction::Pan if !cancelled => self.start_kinetic_pan(time_msec),
+            GestureAction::Pan => self.navigation.kinetic.stop(),
+            GestureAction::CycleFocus => self.end_focus_cycle(),
+            GestureAction::Zoom | GestureAction::StopInertia => {}
+        }
+    }
+
+    /// Whether a gesture in progress is handled by the compositor
+    fn gesture_bound(&self) -> bool {
+        self.gestures.active.is_some()
+    }
+}
+
+pub(super) fn process_swipe_begin<B: InputBackend>(
+    state: &mut LoomState,
+    event: B::GestureSwipeBeginEvent,
+) {
+    if state.begin_gesture(GestureKind::Swipe, event.fingers()) {
+        return;
+    }
+
+    let pointer = state.seat.get_pointer().unwrap();
+    pointer.gesture_swipe_begin(
+        state,
+        &GestureSwipeBeginEvent {
+            serial: SERIAL_COUNTER.next_serial(),
+            time: event.time_msec(),
+            fingers: event.fingers(),
+        },
+    );
+}
+
+pub(super) fn process_swipe_update<B: InputBackend>(
+    state: &mut LoomState,
+    event: B::GestureSwipeUpdateEvent,
+) {
+    if state.gesture_bound() {
+        state.update_gesture(event.time_msec(), event.delta_x(), event.delta_y(), 1.0);
+        return;
+    }
+
+    let pointer = state.seat.get_pointer().unwrap();
+    pointer.gesture_swipe_update(
+        state,
+        &GestureSwipeUpdateEvent {
+            time: event.time_msec(),
+            delta: event.delta(),
+        },
+    );
+}
+
+pub(super) fn process_swipe_end<B: InputBackend>(
+    state: &mut LoomState,
+    event: B::GestureSwipeEndEvent,
+) {
+    if state.gesture_bound() {
+        state.end_gesture(event.time_msec(), event.cancelled());
+        return;
+    }
+
+    let pointer = state.seat.get_pointer().unwrap();
+    pointer.gesture_swipe_end(
+        state,
+        &GestureSwipeEndEvent {
+            serial: SERIAL_COUNTER.next_serial(),
+            time: event.time_msec(),
+            cancelled: event.cancelled(),
+        },
+    );
+}
+
+pub(super) fn process_pinch_begin<B: InputBackend>(
+    state: &mut LoomState,
+    event: B::GesturePinchBeginEvent,
+) {
+    if state.begin_gesture(GestureKind::Pinch, event.fingers()) {
+        return;
+    }
+
+    let pointer = state.seat.get_pointer().unwrap();
+    pointer.gesture_pinch_begin(
+        state,
+        &GesturePinchBeginEvent {
+            serial: SERIAL_COUNTER.next_serial(),
+            time: event.time_msec(),
+            fingers: event.fingers(),
+        },
+    );
+}
+
+pub(super) fn process_pinch_update<B: InputBackend>(
+    state: &mut LoomState,
+    event: B::GesturePinchUpdateEvent,
+) {
+    if state.gesture_bound() {
+        state.update_gesture(
+            event.time_msec(),
+            event.delta_x(),
+            event.delta_y(),
+            event.scale(),
+        );
+        return;
+    }
+
+    let pointer = state.seat.get_pointer().unwrap();
+    pointer.gesture_pinch_update(
+        state,
+        &GesturePinchUpdateEvent {
+            time: event.time_msec(),
+            delta: event.delta(),
+            scale: event.scale(),
+            rotation: event.rotation(),
+        },
+    );
+}
+
+pub(super) fn process_pinch_end<B: InputBackend>(
+    state: &mut LoomState,
+    event: B::GesturePinchEndEvent,
+) {
+    if state.gesture_bound() {
+        state.end_gesture(event.time_msec(), event.cancelled());
+        return;
+    }
+
+    let pointer = state.seat.get_pointer().unwrap();
+    pointer.gesture_pinch_end(
+        state,
+        &GesturePinchEndEvent {
+            serial: SERIAL_COUNTER.next_serial(),
+            time: event.time_msec(),
+            cancelled: event.cancelled(),
+        },
+    );
+}
+
+pub(super) fn process_hold_begin<B: InputBackend>(
+    state: &mut LoomState,
+    event: B::GestureHoldBeginEvent,
+) {
+    if state.begin_gesture(GestureKind::Hold, event.fingers()) {
+        return;
+    }
+
+    let pointer = state.seat.get_pointer().unwrap();
+    pointer.gesture_hold_begin(
+        state,
+        &GestureHoldBeginEvent {
+            serial: SERIAL_COUNTER.next_serial(),
+            time: event.time_msec(),
+            fingers: event.fingers(),
+        },
+    );
+}
+
+pub(super) fn process_hold_end<B: InputBackend>(
+    state: &mut LoomState,
+    event: B::GestureHoldEndEvent,
+) {
+    if state.gesture_bound() {
+        state.end_gesture(event.time_msec(), event.cancelled());
+        return;
+    }
+
+    let pointer = state.seat.get_pointer().unwrap();
+    pointer.gesture_hold_end(
+        state,
+        &GestureHoldEndEvent {
+            serial: SERIAL_COUNTER.next_serial(),
+            time: event.time_msec(),
+            cancelled: event.cancelled(),
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::headless::HeadlessBackend;
+
+    #[test]
+    fn test_gesture_action_for() {
+        let gestures = GestureState::from_config(&[
+            GestureBinding::new(GestureKind::Swipe, None, GestureAction::Pan),
+            GestureBinding::new(GestureKind::Swipe, Some(4), GestureAction::CycleFocus),
+            GestureBinding::new(GestureKind::Hold, Some(2), GestureAction::StopInertia),
+        ]);
+
+        // The exact finger count wins, wherever it is listed
+        assert_eq!(
+            gestures.action_for(GestureKind::Swipe, 4),
+            Some(GestureAction::CycleFocus)
+        );
+        assert_eq!(
+            gestures.action_for(GestureKind::Swipe, 3),
+            Some(GestureAction::Pan)
+        );
+        assert_eq!(gestures.action_for(GestureKind::Hold, 1), None);
+        assert_eq!(gestures.action_for(GestureKind::Pinch, 2), None);
+    }
+
+    #[test]
+    fn test_hold_catches_glide_only() {
+        let mut backend = HeadlessBackend::for_tests(64, 48);
+        let state = backend.state_mut();
+
+        // Nothing glides: the hold goes to the client under the pointer
+        assert!(!state.begin_gesture(GestureKind::Hold, 2));
+        assert!(!state.gesture_bound());
+
+        // A flick sets the canvas gliding, and resting fingers catch it
+        for time in (0..50).step_by(10) {
+            state.navigation.kinetic.push(time, 20.0, 0.0);
+        }
+        state.start_kinetic_pan(40);
+        assert!(state.navigation.kinetic.is_active());
+        assert!(state.begin_gesture(GestureKind::Hold, 2));
+        assert!(state.gesture_bound());
+        assert!(!state.navigation.kinetic.is_active());
+
+        state.end_gesture(60, false);
+        assert!(!state.gesture_bound());
+    }
+}
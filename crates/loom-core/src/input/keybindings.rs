@@ -11,12 +11,73 @@
 //! - `Alt+Tab`: Focus next window
 //! - `Alt+Shift+Tab`: Focus previous window
 //! - `Logo+F`: Toggle fullscreen
+//! - `Ctrl+Alt+F1..F12`: Switch to another VT (DRM backend only)
+//! - `Logo+Ctrl+Left/Right/Up/Down`: Focus the neighbouring output
+//! - `Logo+Ctrl+Shift+Left/Right/Up/Down`: Move the focused window to the
+//!   neighbouring output
+//!
+//! # Configured keybindings
+//!
+//! Bindings from the config file (see [`loom_config::Keybinding`]) are
+//! parsed with [`parse_key_combination`] and [`KeyAction::from_config`]
+//! and take precedence over defaults with the same keys. Key combinations
+//! are modifier names and an XKB keysym name joined by `+`, e.g.
+//! `Super+Shift+Return`:
+//!
+//! - `Super`, `Logo`, `Mod4` or `Win`
+//! - `Ctrl` or `Control`
+//! - `Alt` or `Mod1`
+//! - `Shift`
+//!
+//! Keysym names are matched case-insensitively if there is no exact match,
+//! so `Super+Plus` binds the `plus` keysym. Letters bind the key regardless
+//! of Shift: `Super+Shift+Q` and `Super+Shift+q` are the same binding.
+//!
+//! # Matching
+//!
+//! A binding matches when its keysym was pressed and its modifiers are
+//! held; extra modifiers are allowed. When several bindings match, the one
+//! requiring the most modifiers wins, so `Alt+Shift+Tab` is never shadowed
+//! by `Alt+Tab`.
+//!
+//! # Chords
+//!
+//! The keys of a binding may be a chord of combinations separated by
+//! commas, e.g. `Super+K, Super+C`, each pressed within the chord timeout
+//! of the previous one. Keys continuing a chord are not forwarded to
+//! clients; a key that doesn't continue it ends the chord and is matched
+//! on its own. Pressing modifier keys never advances or ends a chord.
+//!
+//! # Modes
+//!
+//! Bindings may belong to a named mode, entered by an
+//! [`KeyAction::EnterMode`] binding. While a mode is active only its
+//! bindings are, and `Escape` returns to the default mode unless the mode
+//! binds it to something else.
+//!
+//! # Release bindings
+//!
+//! Release bindings fire when their key is released with no other key
+//! pressed since, so tapping `Super_L` can open something while
+//! `Super+Return` still works. Their key events still reach the client.
+//!
+//! # Locked sessions
+//!
+//! While the session is locked, only the bindings of the default mode
+//! allowed when locked are active, such as the VT switches.
 
-use smallvec::SmallVec;
-use smithay::input::keyboard::{ModifiersState, keysyms};
+use crate::security::{MAX_BINDING_MODES, MAX_KEYBINDINGS};
+use loom_config::keybindings::{self as config, security};
+use loom_config::{ConfigError, Keybinding, KeybindingAction};
+use smallvec::{SmallVec, smallvec};
+use smithay::input::keyboard::{
+    ModifiersState,
+    xkb::{self, keysyms},
+};
+use std::time::{Duration, Instant};
 
 /// Actions that can be triggered by keybindings
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum KeyAction {
     /// Do nothing (used for suppressed key releases)
     None,
@@ -30,35 +91,275 @@ pub enum KeyAction {
     FocusPrev,
     /// Toggle fullscreen for focused window
     ToggleFullscreen,
+    /// Switch to the given virtual terminal
+    SwitchVt(i32),
+    /// Focus the output in a direction
+    FocusOutput(Direction),
+    /// Move the focused window to the view of the output in a direction
+    MoveToOutput(Direction),
+    /// Open the configured terminal
+    Terminal,
+    /// Open the configured launcher
+    Launcher,
+    /// Pan the view under the pointer, moving it in a direction
+    Pan(Direction),
+    /// Zoom the view under the pointer
+    Zoom(ZoomDirection),
+    /// Reset the view under the pointer to the canvas origin
+    ResetView,
+    /// Open the AI prompt
+    AiPrompt,
+    /// Turn pen annotation of the canvas on or off
+    ToggleAnnotation,
+    /// Launch an application by its desktop file id (validated)
+    LaunchApp(String),
+    /// Run a script from the scripts directory (validated name)
+    RunScript(String),
+    /// Switch to a binding mode
+    EnterMode(String),
+    /// Return to the default binding mode
+    ExitMode,
+    /// Reload the config file
+    ReloadConfig,
 }
 
-/// A single keybinding pattern
-#[derive(Debug, Clone)]
-pub struct KeyPattern {
-    /// Required modifiers
-    pub modifiers: ModifiersState,
-    /// The keysym to match
-    pub keysym: u32,
-    /// Action to execute
-    pub action: KeyAction,
+impl KeyAction {
+    /// Convert a configured action
+    ///
+    /// App ids and script names are validated here, so invalid ones are
+    /// rejected when the config is loaded rather than when pressed. Mode
+    /// names are checked when the bindings are added.
+    pub fn from_config(action: &KeybindingAction) -> loom_config::Result<Self> {
+        Ok(match action {
+            KeybindingAction::Terminal => Self::Terminal,
+            KeybindingAction::Launcher => Self::Launcher,
+            KeybindingAction::Close => Self::CloseFocused,
+            KeybindingAction::Fullscreen => Self::ToggleFullscreen,
+            KeybindingAction::Pan { direction } => Self::Pan(direction.into()),
+            KeybindingAction::Zoom { direction } => Self::Zoom(direction.into()),
+            KeybindingAction::ResetView => Self::ResetView,
+            KeybindingAction::AiPrompt => Self::AiPrompt,
+            KeybindingAction::ToggleAnnotation => Self::ToggleAnnotation,
+            KeybindingAction::LaunchApp { app_id } => {
+                security::validate_app_id(app_id)?;
+                Self::LaunchApp(app_id.clone())
+            }
+            KeybindingAction::RunScript { script_name } => {
+                security::validate_script_name(script_name)?;
+                Self::RunScript(script_name.clone())
+            }
+            KeybindingAction::EnterMode { mode } => Self::EnterMode(mode.clone()),
+            KeybindingAction::ExitMode => Self::ExitMode,
+            KeybindingAction::ReloadConfig => Self::ReloadConfig,
+            KeybindingAction::Quit => Self::Quit,
+        })
+    }
 }
 
-impl KeyPattern {
-    /// Create a new key pattern
-    pub fn new(keysym: u32, modifiers: ModifiersState, action: KeyAction) -> Self {
-        Self {
-            modifiers,
-            keysym,
-            action,
+/// Direction to a neighbouring output, or to pan in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl From<&config::Direction> for Direction {
+    fn from(direction: &config::Direction) -> Self {
+        match direction {
+            config::Direction::Left => Self::Left,
+            config::Direction::Right => Self::Right,
+            config::Direction::Up => Self::Up,
+            config::Direction::Down => Self::Down,
+        }
+    }
+}
+
+/// Zoom direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZoomDirection {
+    In,
+    Out,
+}
+
+impl From<&config::ZoomDirection> for ZoomDirection {
+    fn from(direction: &config::ZoomDirection) -> Self {
+        match direction {
+            config::ZoomDirection::In => Self::In,
+            config::ZoomDirection::Out => Self::Out,
+        }
+    }
+}
+
+/// Parse a key combination like `Super+Shift+Return`
+///
+/// Returns the keysym and the required modifiers. Errors name the
+/// offending combination.
+pub fn parse_key_combination(combination: &str) -> loom_config::Result<(u32, ModifiersState)> {
+    let invalid =
+        |reason: String| ConfigError::InvalidKeybinding(format!("\"{combination}\": {reason}"));
+
+    let mut parts: Vec<&str> = combination.split('+').map(str::trim).collect();
+    // A trailing "+" binds the plus key itself, e.g. "Super++"
+    if combination.ends_with("++") {
+        parts.truncate(parts.len() - 2);
+        parts.push("plus");
+    }
+
+    let Some((key, modifier_names)) = parts.split_last() else {
+        return Err(invalid("empty key combination".to_string()));
+    };
+
+    let mut modifiers = ModifiersState::default();
+    for name in modifier_names {
+        let modifier = match name.to_ascii_lowercase().as_str() {
+            "super" | "logo" | "mod4" | "win" => &mut modifiers.logo,
+            "ctrl" | "control" => &mut modifiers.ctrl,
+            "alt" | "mod1" => &mut modifiers.alt,
+            "shift" => &mut modifiers.shift,
+            "" => return Err(invalid("empty modifier".to_string())),
+            _ => return Err(invalid(format!("unknown modifier \"{name}\""))),
+        };
+        if *modifier {
+            return Err(invalid(format!("duplicate modifier \"{name}\"")));
         }
+        *modifier = true;
+    }
+
+    if key.is_empty() {
+        return Err(invalid("missing key".to_string()));
     }
 
-    /// Check if this pattern matches the given keysym and modifiers
+    Ok((
+        keysym_from_name(key).ok_or_else(|| invalid(format!("unknown key \"{key}\"")))?,
+        modifiers,
+    ))
+}
+
+/// Parse the keys of a binding: a key combination or a chord of them
+/// separated by commas, like `Super+K, Super+C`
+pub fn parse_key_sequence(keys: &str) -> loom_config::Result<SmallVec<[KeyCombo; 2]>> {
+    let combos = keys
+        .split(',')
+        .map(|combination| {
+            let (keysym, modifiers) = parse_key_combination(combination.trim())?;
+            Ok(KeyCombo { keysym, modifiers })
+        })
+        .collect::<loom_config::Result<SmallVec<[KeyCombo; 2]>>>()?;
+
+    if combos.len() > MAX_CHORD_LENGTH {
+        return Err(ConfigError::InvalidKeybinding(format!(
+            "\"{keys}\": chords are limited to {MAX_CHORD_LENGTH} keys"
+        )));
+    }
+    Ok(combos)
+}
+
+/// Check a binding mode name
+///
+/// Names are short identifiers (letters, digits, `-` and `_`), so they can
+/// be shown in a status bar as they are.
+fn validate_mode_name(name: &str) -> loom_config::Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_MODE_NAME_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(ConfigError::InvalidKeybinding(format!(
+            "invalid mode name \"{name}\""
+        )))
+    }
+}
+
+/// Whether a keysym is a modifier key
+///
+/// Modifier presses are part of the combination that follows them, so
+/// they never advance or end a chord.
+#[inline]
+fn is_modifier_key(keysym: u32) -> bool {
+    (keysyms::KEY_Shift_L..=keysyms::KEY_Hyper_R).contains(&keysym)
+        || keysym == keysyms::KEY_ISO_Level3_Shift
+        || keysym == keysyms::KEY_ISO_Level5_Shift
+}
+
+/// Look up an XKB keysym by name
+///
+/// Single letters are lowercased, since bindings match the unshifted
+/// keysym of a key as well (see [`Keybindings::process_key`]).
+fn keysym_from_name(name: &str) -> Option<u32> {
+    let name = match name.len() {
+        1 => name.to_ascii_lowercase(),
+        _ => name.to_string(),
+    };
+
+    [xkb::KEYSYM_NO_FLAGS, xkb::KEYSYM_CASE_INSENSITIVE]
+        .into_iter()
+        .map(|flags| xkb::keysym_from_name(&name, flags).raw())
+        .find(|&keysym| keysym != keysyms::KEY_NoSymbol)
+}
+
+/// Maximum number of key combinations in a chord
+const MAX_CHORD_LENGTH: usize = 4;
+
+/// Maximum length of a binding mode name
+const MAX_MODE_NAME_LENGTH: usize = 64;
+
+/// Name of the mode active when no other is
+pub const DEFAULT_MODE: &str = "default";
+
+/// A key combination: a keysym and the modifiers held with it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyCombo {
+    /// The keysym to match
+    pub keysym: u32,
+    /// Required modifiers
+    pub modifiers: ModifiersState,
+}
+
+impl KeyCombo {
+    /// Check if this combination matches the given keysym and modifiers
     #[inline]
     pub fn matches(&self, keysym: u32, modifiers: ModifiersState) -> bool {
         self.keysym == keysym && self.modifiers_match(modifiers)
     }
 
+    /// Check if a key press matches, by its modified or its raw keysym
+    #[inline]
+    fn matches_press(&self, press: &KeyPress) -> bool {
+        self.matches(press.keysym, press.modifiers)
+            || press
+                .raw_keysym
+                .is_some_and(|raw| self.matches(raw, press.modifiers))
+    }
+
+    /// Check if another combination uses the same keys
+    #[inline]
+    pub fn same_keys(&self, other: &KeyCombo) -> bool {
+        self.keysym == other.keysym
+            && self.modifiers.ctrl == other.modifiers.ctrl
+            && self.modifiers.alt == other.modifiers.alt
+            && self.modifiers.shift == other.modifiers.shift
+            && self.modifiers.logo == other.modifiers.logo
+    }
+
+    /// Number of required modifiers
+    #[inline]
+    fn modifier_count(&self) -> usize {
+        [
+            self.modifiers.ctrl,
+            self.modifiers.alt,
+            self.modifiers.shift,
+            self.modifiers.logo,
+        ]
+        .into_iter()
+        .filter(|&held| held)
+        .count()
+    }
+
     /// Check if the modifiers match (allowing extra modifiers)
     #[inline]
     fn modifiers_match(&self, modifiers: ModifiersState) -> bool {
@@ -71,14 +372,139 @@ impl KeyPattern {
     }
 }
 
+bitflags::bitflags! {
+    /// Options of a keybinding
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct BindingFlags: u8 {
+        /// Fire when the key is released, if it was tapped alone
+        const RELEASE       = 0b0000_0001;
+        /// Keep working while the session is locked
+        const LOCKED        = 0b0000_0010;
+        /// Repeat while the key is held
+        const REPEAT        = 0b0000_0100;
+    }
+}
+
+/// A single keybinding pattern
+#[derive(Debug, Clone)]
+pub struct KeyPattern {
+    /// Key combinations to press in order (more than one for a chord)
+    pub keys: SmallVec<[KeyCombo; 2]>,
+    /// Action to execute
+    pub action: KeyAction,
+    /// Binding options
+    pub flags: BindingFlags,
+    /// Index of the binding mode the pattern belongs to (0 is the default
+    /// mode)
+    pub mode: usize,
+}
+
+impl KeyPattern {
+    /// Create a new key pattern for a single combination in the default
+    /// mode
+    pub fn new(keysym: u32, modifiers: ModifiersState, action: KeyAction) -> Self {
+        Self {
+            keys: smallvec![KeyCombo { keysym, modifiers }],
+            action,
+            flags: BindingFlags::empty(),
+            mode: 0,
+        }
+    }
+
+    /// Set the options of the pattern
+    pub fn with_flags(mut self, flags: BindingFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Check if this pattern is a single combination matching the given
+    /// keysym and modifiers
+    #[inline]
+    pub fn matches(&self, keysym: u32, modifiers: ModifiersState) -> bool {
+        self.keys.len() == 1 && self.keys[0].matches(keysym, modifiers)
+    }
+
+    /// Check if another pattern is triggered by the same keys in the same
+    /// mode
+    #[inline]
+    pub fn same_keys(&self, other: &KeyPattern) -> bool {
+        self.mode == other.mode
+            && self.flags.contains(BindingFlags::RELEASE)
+                == other.flags.contains(BindingFlags::RELEASE)
+            && self.keys.len() == other.keys.len()
+            && self
+                .keys
+                .iter()
+                .zip(&other.keys)
+                .all(|(a, b)| a.same_keys(b))
+    }
+
+    /// Whether the pattern is active in a mode, and while locked
+    #[inline]
+    fn active(&self, mode: usize, locked: bool) -> bool {
+        self.mode == mode && (!locked || self.flags.contains(BindingFlags::LOCKED))
+    }
+
+    /// Number of modifiers required by the last combination
+    #[inline]
+    fn modifier_count(&self) -> usize {
+        self.keys.last().map_or(0, KeyCombo::modifier_count)
+    }
+}
+
+/// A key event, as seen by the keybindings
+#[derive(Debug, Clone, Copy)]
+pub struct KeyPress {
+    /// Hardware keycode of the key
+    pub keycode: u32,
+    /// Keysym with the current modifiers applied
+    pub keysym: u32,
+    /// Raw (unshifted, latin if possible) keysym
+    pub raw_keysym: Option<u32>,
+    /// Modifiers held
+    pub modifiers: ModifiersState,
+}
+
+/// Result of matching a key press
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyMatch {
+    /// No binding matches; the key goes to the client
+    None,
+    /// The key continues a chord; more keys are needed
+    Pending,
+    /// A binding matched
+    Action {
+        /// Action to execute
+        action: KeyAction,
+        /// Whether the action repeats while the key is held
+        repeat: bool,
+    },
+}
+
+/// Number of VTs reachable with `Ctrl+Alt+F1..F12`
+const VT_COUNT: u32 = 12;
+
 /// Keybindings manager
 ///
-/// Stores and processes keybindings for the compositor.
+/// Stores and processes keybindings for the compositor, and tracks the
+/// active mode and any chord in progress.
 /// Uses SmallVec to avoid heap allocation for typical binding counts.
 #[derive(Debug)]
 pub struct Keybindings {
     /// List of keybindings
-    bindings: SmallVec<[KeyPattern; 16]>,
+    bindings: SmallVec<[KeyPattern; 32]>,
+    /// Names of the binding modes, the default mode first
+    modes: SmallVec<[String; 4]>,
+    /// Index of the active mode
+    active_mode: usize,
+    /// Keys of the chord in progress
+    chord: SmallVec<[KeyPress; 4]>,
+    /// Time by which the chord must continue
+    chord_deadline: Option<Instant>,
+    /// Time allowed between the keys of a chord
+    chord_timeout: Duration,
+    /// Keycode of the last key pressed, if no binding used it
+    tap: Option<u32>,
 }
 
 impl Default for Keybindings {
@@ -123,6 +549,20 @@ impl Keybindings {
             KeyAction::CloseFocused,
         ));
 
+        // Alt+Shift+Tab: Focus previous
+        // With Shift held, most keymaps turn Tab into ISO_Left_Tab
+        for keysym in [keysyms::KEY_ISO_Left_Tab, keysyms::KEY_Tab] {
+            bindings.push(KeyPattern::new(
+                keysym,
+                ModifiersState {
+                    alt: true,
+                    shift: true,
+                    ..Default::default()
+                },
+                KeyAction::FocusPrev,
+            ));
+        }
+
         // Alt+Tab: Focus next
         bindings.push(KeyPattern::new(
             keysyms::KEY_Tab,
@@ -133,17 +573,6 @@ impl Keybindings {
             KeyAction::FocusNext,
         ));
 
-        // Alt+Shift+Tab: Focus previous
-        bindings.push(KeyPattern::new(
-            keysyms::KEY_Tab,
-            ModifiersState {
-                alt: true,
-                shift: true,
-                ..Default::default()
-            },
-            KeyAction::FocusPrev,
-        ));
-
         // Logo+F: Toggle fullscreen
         bindings.push(KeyPattern::new(
             keysyms::KEY_f,
@@ -154,37 +583,385 @@ impl Keybindings {
             KeyAction::ToggleFullscreen,
         ));
 
-        Self { bindings }
+        // Logo+Ctrl+Shift+Arrows: Move to output
+        let arrows = [
+            (keysyms::KEY_Left, Direction::Left),
+            (keysyms::KEY_Right, Direction::Right),
+            (keysyms::KEY_Up, Direction::Up),
+            (keysyms::KEY_Down, Direction::Down),
+        ];
+        for (keysym, direction) in arrows {
+            bindings.push(KeyPattern::new(
+                keysym,
+                ModifiersState {
+                    logo: true,
+                    ctrl: true,
+                    shift: true,
+                    ..Default::default()
+                },
+                KeyAction::MoveToOutput(direction),
+            ));
+        }
+
+        // Logo+Ctrl+Arrows: Focus output
+        for (keysym, direction) in arrows {
+            bindings.push(KeyPattern::new(
+                keysym,
+                ModifiersState {
+                    logo: true,
+                    ctrl: true,
+                    ..Default::default()
+                },
+                KeyAction::FocusOutput(direction),
+            ));
+        }
+
+        // Ctrl+Alt+F1..F12: Switch VT
+        // The keymap turns these into the XF86Switch_VT_* keysyms. They
+        // stay available on a locked session, so a stuck lock screen can
+        // be left for a console.
+        for vt in 1..=VT_COUNT {
+            bindings.push(
+                KeyPattern::new(
+                    keysyms::KEY_XF86Switch_VT_1 + vt - 1,
+                    ModifiersState {
+                        ctrl: true,
+                        alt: true,
+                        ..Default::default()
+                    },
+                    KeyAction::SwitchVt(vt as i32),
+                )
+                .with_flags(BindingFlags::LOCKED),
+            );
+        }
+
+        Self {
+            bindings,
+            modes: SmallVec::from_iter([DEFAULT_MODE.to_string()]),
+            active_mode: 0,
+            chord: SmallVec::new(),
+            chord_deadline: None,
+            chord_timeout: Duration::from_millis(1000),
+            tap: None,
+        }
+    }
+
+    /// Create a keybindings manager with the defaults and configured
+    /// bindings
+    ///
+    /// Configured bindings replace defaults with the same keys. Invalid
+    /// bindings are skipped and returned as errors, as are bindings past
+    /// [`MAX_KEYBINDINGS`]. `Escape` leaves every mode that doesn't bind
+    /// it.
+    pub fn from_config(bindings: &[Keybinding]) -> (Self, Vec<ConfigError>) {
+        let mut keybindings = Self::new();
+        let mut errors = Vec::new();
+
+        for binding in bindings {
+            if let Err(e) = keybindings.add_config(binding) {
+                errors.push(e);
+            }
+        }
+
+        for mode in 1..keybindings.modes.len() {
+            let escape = KeyPattern {
+                mode,
+                ..KeyPattern::new(
+                    keysyms::KEY_Escape,
+                    ModifiersState::default(),
+                    KeyAction::ExitMode,
+                )
+            };
+            if !keybindings.bindings.iter().any(|b| b.same_keys(&escape))
+                && let Err(e) = keybindings.insert(escape)
+            {
+                errors.push(e);
+            }
+        }
+
+        (keybindings, errors)
+    }
+
+    /// Add a configured binding
+    fn add_config(&mut self, binding: &Keybinding) -> loom_config::Result<()> {
+        let keys = parse_key_sequence(&binding.key)?;
+        let action = KeyAction::from_config(&binding.action)?;
+
+        let mut flags = BindingFlags::empty();
+        flags.set(BindingFlags::RELEASE, binding.on_release);
+        flags.set(BindingFlags::LOCKED, binding.allow_when_locked);
+        flags.set(BindingFlags::REPEAT, binding.repeat);
+        if binding.on_release && keys.len() > 1 {
+            return Err(ConfigError::InvalidKeybinding(format!(
+                "\"{}\": release bindings can't be chords",
+                binding.key
+            )));
+        }
+
+        // Modes exist once something binds keys in them or enters them
+        let mode = match &binding.mode {
+            Some(name) => self.mode_index(name)?,
+            None => 0,
+        };
+        if let KeyAction::EnterMode(name) = &action {
+            self.mode_index(name)?;
+        }
+
+        self.insert(KeyPattern {
+            keys,
+            action,
+            flags,
+            mode,
+        })
+    }
+
+    /// Index of a binding mode, registering it if it is new
+    fn mode_index(&mut self, name: &str) -> loom_config::Result<usize> {
+        validate_mode_name(name)?;
+        if let Some(index) = self.modes.iter().position(|mode| mode == name) {
+            return Ok(index);
+        }
+
+        if self.modes.len() >= MAX_BINDING_MODES {
+            return Err(ConfigError::SecurityViolation(format!(
+                "Too many binding modes (max {MAX_BINDING_MODES})"
+            )));
+        }
+        self.modes.push(name.to_string());
+        Ok(self.modes.len() - 1)
+    }
+
+    /// Set the time allowed between the keys of a chord
+    pub fn set_chord_timeout(&mut self, timeout: Duration) {
+        self.chord_timeout = timeout;
     }
 
     /// Process a key press and return an action if a keybinding matches
     ///
-    /// Returns `Some(action)` if a keybinding was matched, `None` otherwise.
+    /// Only single-combination bindings of the active mode are considered,
+    /// and no chord state is kept. Returns `Some(action)` if a keybinding
+    /// was matched, `None` otherwise.
     #[inline]
     pub fn process(&self, keysym: u32, modifiers: ModifiersState) -> Option<KeyAction> {
-        // More specific bindings (more modifiers) should be checked first
-        // Since we check in order and more specific patterns match more strictly,
-        // we need to put Alt+Shift+Tab before Alt+Tab
+        self.process_key(keysym, None, modifiers)
+    }
+
+    /// Process a key press given both its modified and its raw keysym
+    ///
+    /// The raw (unshifted, latin if possible) keysym lets `Super+Shift+Q`
+    /// match although Shift turns `q` into `Q`, and bindings work on
+    /// non-latin layouts. The binding requiring the most modifiers wins.
+    pub fn process_key(
+        &self,
+        keysym: u32,
+        raw_keysym: Option<u32>,
+        modifiers: ModifiersState,
+    ) -> Option<KeyAction> {
+        let press = KeyPress {
+            keycode: 0,
+            keysym,
+            raw_keysym,
+            modifiers,
+        };
+
+        self.best_match(&press, self.active_mode, false, &[])
+            .map(|binding| binding.action.clone())
+    }
+
+    /// Handle a key press, advancing or ending any chord in progress
+    ///
+    /// `locked` restricts matching to the bindings allowed on a locked
+    /// session; `now` is checked against the chord timeout.
+    pub fn press(&mut self, press: KeyPress, locked: bool, now: Instant) -> KeyMatch {
+        if is_modifier_key(press.keysym) {
+            self.tap = Some(press.keycode);
+            return KeyMatch::None;
+        }
+
+        if self.chord_deadline.is_some_and(|deadline| now >= deadline) {
+            self.reset_chord();
+        }
+
+        let mut result = self.match_chord(press, locked, now);
+        if result == KeyMatch::None && !self.chord.is_empty() {
+            // The key doesn't continue the chord: try it on its own
+            self.reset_chord();
+            result = self.match_chord(press, locked, now);
+        }
+
+        self.tap = (result == KeyMatch::None).then_some(press.keycode);
+        result
+    }
+
+    /// Handle a key release
+    ///
+    /// Returns the action of a release binding if the key was the last one
+    /// pressed and no binding used its press.
+    pub fn release(&mut self, press: KeyPress, locked: bool) -> Option<KeyAction> {
+        if self.tap.take() != Some(press.keycode) {
+            return None;
+        }
+
+        let mode = if locked { 0 } else { self.active_mode };
+        self.bindings
+            .iter()
+            .filter(|binding| {
+                binding.flags.contains(BindingFlags::RELEASE)
+                    && binding.active(mode, locked)
+                    && binding.keys[0].matches_press(&press)
+            })
+            .max_by_key(|binding| binding.modifier_count())
+            .map(|binding| binding.action.clone())
+    }
+
+    /// Forget the tapped key, after the pointer was used with it held
+    pub fn cancel_tap(&mut self) {
+        self.tap = None;
+    }
+
+    /// Match a press as the next key of the chord in progress (or the
+    /// first key of a new one)
+    fn match_chord(&mut self, press: KeyPress, locked: bool, now: Instant) -> KeyMatch {
+        let mode = if locked { 0 } else { self.active_mode };
+
+        if let Some(binding) = self.best_match(&press, mode, locked, &self.chord) {
+            let action = binding.action.clone();
+            let repeat = binding.flags.contains(BindingFlags::REPEAT);
+            self.reset_chord();
+            return KeyMatch::Action { action, repeat };
+        }
+
+        let step = self.chord.len();
+        let continues = self.bindings.iter().any(|binding| {
+            !binding.flags.contains(BindingFlags::RELEASE)
+                && binding.active(mode, locked)
+                && binding.keys.len() > step + 1
+                && chord_matches(&binding.keys[..=step], &self.chord, &press)
+        });
+        if !continues {
+            return KeyMatch::None;
+        }
+
+        self.chord.push(press);
+        self.chord_deadline = Some(now + self.chord_timeout);
+        KeyMatch::Pending
+    }
+
+    /// Press binding completed by a key after the given chord prefix
+    ///
+    /// The binding requiring the most modifiers on its last key wins.
+    fn best_match(
+        &self,
+        press: &KeyPress,
+        mode: usize,
+        locked: bool,
+        chord: &[KeyPress],
+    ) -> Option<&KeyPattern> {
+        let mut best: Option<&KeyPattern> = None;
+
         for binding in &self.bindings {
-            if binding.matches(keysym, modifiers) {
-                return Some(binding.action);
+            let matches = !binding.flags.contains(BindingFlags::RELEASE)
+                && binding.active(mode, locked)
+                && binding.keys.len() == chord.len() + 1
+                && chord_matches(&binding.keys, chord, press);
+            if matches && best.is_none_or(|best| binding.modifier_count() > best.modifier_count()) {
+                best = Some(binding);
             }
         }
-        None
+
+        best
     }
 
-    /// Add a custom keybinding
-    pub fn add(&mut self, keysym: u32, modifiers: ModifiersState, action: KeyAction) {
-        self.bindings
-            .push(KeyPattern::new(keysym, modifiers, action));
+    /// Forget the chord in progress
+    fn reset_chord(&mut self) {
+        self.chord.clear();
+        self.chord_deadline = None;
+    }
+
+    /// Whether a chord is in progress
+    pub fn chord_pending(&self) -> bool {
+        !self.chord.is_empty()
+    }
+
+    /// Switch to a binding mode
+    ///
+    /// Returns `false` for a mode no binding uses.
+    pub fn enter_mode(&mut self, name: &str) -> bool {
+        let Some(index) = self.modes.iter().position(|mode| mode == name) else {
+            return false;
+        };
+        self.active_mode = index;
+        self.reset_chord();
+        true
+    }
+
+    /// Return to the default mode
+    pub fn exit_mode(&mut self) {
+        self.active_mode = 0;
+        self.reset_chord();
+    }
+
+    /// Name of the active binding mode
+    pub fn active_mode(&self) -> &str {
+        &self.modes[self.active_mode]
+    }
+
+    /// Add a custom keybinding, replacing any binding with the same keys
+    ///
+    /// Fails once [`MAX_KEYBINDINGS`] bindings exist.
+    pub fn add(
+        &mut self,
+        keysym: u32,
+        modifiers: ModifiersState,
+        action: KeyAction,
+    ) -> loom_config::Result<()> {
+        self.insert(KeyPattern::new(keysym, modifiers, action))
+    }
+
+    /// Add a key pattern, replacing any pattern with the same keys in the
+    /// same mode
+    ///
+    /// Fails once [`MAX_KEYBINDINGS`] bindings exist.
+    pub fn insert(&mut self, pattern: KeyPattern) -> loom_config::Result<()> {
+        self.bindings.retain(|binding| !binding.same_keys(&pattern));
+
+        if self.bindings.len() >= MAX_KEYBINDINGS {
+            return Err(ConfigError::SecurityViolation(format!(
+                "Too many keybindings (max {MAX_KEYBINDINGS})"
+            )));
+        }
+
+        self.bindings.push(pattern);
+        Ok(())
+    }
+
+    /// Number of keybindings
+    pub fn len(&self) -> usize {
+        self.bindings.len()
+    }
+
+    /// Whether there are no keybindings
+    pub fn is_empty(&self) -> bool {
+        self.bindings.is_empty()
     }
 
     /// Clear all keybindings
     pub fn clear(&mut self) {
         self.bindings.clear();
+        self.reset_chord();
     }
 }
 
+/// Whether keys (the chord pressed so far, then a new press) match the
+/// first combinations of a binding
+fn chord_matches(keys: &[KeyCombo], chord: &[KeyPress], press: &KeyPress) -> bool {
+    keys.len() == chord.len() + 1
+        && keys
+            .iter()
+            .zip(chord.iter().chain([press]))
+            .all(|(combo, press)| combo.matches_press(press))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -226,6 +1003,359 @@ mod tests {
         assert_eq!(action, Some(KeyAction::FocusNext));
     }
 
+    #[test]
+    fn test_alt_shift_tab_focuses_prev() {
+        let keybindings = Keybindings::new();
+        let modifiers = ModifiersState {
+            alt: true,
+            shift: true,
+            ..Default::default()
+        };
+
+        let action = keybindings.process(keysyms::KEY_ISO_Left_Tab, modifiers);
+        assert_eq!(action, Some(KeyAction::FocusPrev));
+
+        let action = keybindings.process(keysyms::KEY_Tab, modifiers);
+        assert_eq!(action, Some(KeyAction::FocusPrev));
+    }
+
+    #[test]
+    fn test_ctrl_alt_function_keys_switch_vt() {
+        let keybindings = Keybindings::new();
+        let modifiers = ModifiersState {
+            ctrl: true,
+            alt: true,
+            ..Default::default()
+        };
+
+        let action = keybindings.process(keysyms::KEY_XF86Switch_VT_1, modifiers);
+        assert_eq!(action, Some(KeyAction::SwitchVt(1)));
+
+        let action = keybindings.process(keysyms::KEY_XF86Switch_VT_12, modifiers);
+        assert_eq!(action, Some(KeyAction::SwitchVt(12)));
+    }
+
+    #[test]
+    fn test_output_bindings() {
+        let keybindings = Keybindings::new();
+        let focus = ModifiersState {
+            logo: true,
+            ctrl: true,
+            ..Default::default()
+        };
+        let mov = ModifiersState {
+            shift: true,
+            ..focus
+        };
+
+        let action = keybindings.process(keysyms::KEY_Right, focus);
+        assert_eq!(action, Some(KeyAction::FocusOutput(Direction::Right)));
+
+        let action = keybindings.process(keysyms::KEY_Left, mov);
+        assert_eq!(action, Some(KeyAction::MoveToOutput(Direction::Left)));
+    }
+
+    #[test]
+    fn test_parse_key_combination() {
+        let (keysym, modifiers) = parse_key_combination("Super+Shift+Q").unwrap();
+        assert_eq!(keysym, keysyms::KEY_q);
+        assert!(modifiers.logo && modifiers.shift && !modifiers.ctrl && !modifiers.alt);
+
+        let (keysym, modifiers) = parse_key_combination("ctrl + alt + Return").unwrap();
+        assert_eq!(keysym, keysyms::KEY_Return);
+        assert!(modifiers.ctrl && modifiers.alt);
+
+        assert_eq!(
+            parse_key_combination("Super+Plus").unwrap().0,
+            keysyms::KEY_plus
+        );
+        assert_eq!(
+            parse_key_combination("Super++").unwrap().0,
+            keysyms::KEY_plus
+        );
+        assert_eq!(parse_key_combination("F5").unwrap().0, keysyms::KEY_F5);
+    }
+
+    #[test]
+    fn test_parse_errors_name_the_combination() {
+        for (combination, reason) in [
+            ("Hyper+Q", "unknown modifier \"Hyper\""),
+            ("Super+NoSuchKey", "unknown key \"NoSuchKey\""),
+            ("Super+Super+Q", "duplicate modifier"),
+            ("Super+", "missing key"),
+            ("", "missing key"),
+        ] {
+            let error = parse_key_combination(combination).unwrap_err().to_string();
+            assert!(
+                error.contains(&format!("\"{combination}\"")) && error.contains(reason),
+                "{error}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_config_bindings_override_defaults() {
+        let bindings = [
+            Keybinding::new("Super+Q", KeybindingAction::Close),
+            Keybinding::new("Super+Shift+Q", KeybindingAction::Quit),
+            Keybinding::new(
+                "Super+A",
+                KeybindingAction::LaunchApp {
+                    app_id: "../evil".to_string(),
+                },
+            ),
+            Keybinding::new("Super+Nope", KeybindingAction::Terminal),
+        ];
+        let (keybindings, errors) = Keybindings::from_config(&bindings);
+        assert_eq!(errors.len(), 2);
+
+        let logo = ModifiersState {
+            logo: true,
+            ..Default::default()
+        };
+        let logo_shift = ModifiersState {
+            shift: true,
+            ..logo
+        };
+
+        let action = keybindings.process(keysyms::KEY_q, logo);
+        assert_eq!(action, Some(KeyAction::CloseFocused));
+
+        // Shift turns q into Q; the raw keysym still matches
+        let action = keybindings.process_key(keysyms::KEY_Q, Some(keysyms::KEY_q), logo_shift);
+        assert_eq!(action, Some(KeyAction::Quit));
+
+        assert_eq!(keybindings.process(keysyms::KEY_a, logo), None);
+    }
+
+    #[test]
+    fn test_default_config_bindings_load() {
+        let (keybindings, errors) = Keybindings::from_config(&Keybinding::defaults());
+        assert!(errors.is_empty(), "{errors:?}");
+
+        let logo = ModifiersState {
+            logo: true,
+            ..Default::default()
+        };
+        let action = keybindings.process(keysyms::KEY_Return, logo);
+        assert_eq!(action, Some(KeyAction::Terminal));
+    }
+
+    #[test]
+    fn test_max_keybindings() {
+        let mut keybindings = Keybindings::new();
+        let mut result = Ok(());
+        for keysym in 0..MAX_KEYBINDINGS as u32 {
+            result = keybindings.add(
+                0x1000_0000 + keysym,
+                ModifiersState::default(),
+                KeyAction::Quit,
+            );
+            if result.is_err() {
+                break;
+            }
+        }
+
+        assert!(result.is_err());
+        assert_eq!(keybindings.len(), MAX_KEYBINDINGS);
+    }
+
+    fn press(keycode: u32, keysym: u32, modifiers: ModifiersState) -> KeyPress {
+        KeyPress {
+            keycode,
+            keysym,
+            raw_keysym: None,
+            modifiers,
+        }
+    }
+
+    #[test]
+    fn test_chords() {
+        let bindings = [
+            Keybinding::new("Super+K, Super+C", KeybindingAction::Close),
+            Keybinding::new("Super+K, T", KeybindingAction::Terminal),
+        ];
+        let (mut keybindings, errors) = Keybindings::from_config(&bindings);
+        assert!(errors.is_empty(), "{errors:?}");
+
+        let logo = ModifiersState {
+            logo: true,
+            ..Default::default()
+        };
+        let now = Instant::now();
+        let k = press(45, keysyms::KEY_k, logo);
+
+        assert_eq!(keybindings.press(k, false, now), KeyMatch::Pending);
+        // Releasing and pressing modifiers doesn't end the chord
+        let super_l = press(133, keysyms::KEY_Super_L, ModifiersState::default());
+        assert_eq!(keybindings.press(super_l, false, now), KeyMatch::None);
+        assert_eq!(
+            keybindings.press(press(54, keysyms::KEY_c, logo), false, now),
+            KeyMatch::Action {
+                action: KeyAction::CloseFocused,
+                repeat: false
+            }
+        );
+        assert!(!keybindings.chord_pending());
+
+        // A key that doesn't continue the chord is matched on its own
+        assert_eq!(keybindings.press(k, false, now), KeyMatch::Pending);
+        assert_eq!(
+            keybindings.press(press(41, keysyms::KEY_f, logo), false, now),
+            KeyMatch::Action {
+                action: KeyAction::ToggleFullscreen,
+                repeat: false
+            }
+        );
+
+        // Too late for the second key
+        assert_eq!(keybindings.press(k, false, now), KeyMatch::Pending);
+        let later = now + Duration::from_secs(2);
+        let t = press(28, keysyms::KEY_t, ModifiersState::default());
+        assert_eq!(keybindings.press(t, false, later), KeyMatch::None);
+    }
+
+    #[test]
+    fn test_modes() {
+        let bindings = [
+            Keybinding::new(
+                "Super+R",
+                KeybindingAction::EnterMode {
+                    mode: "resize".to_string(),
+                },
+            ),
+            Keybinding::new(
+                "Left",
+                KeybindingAction::Pan {
+                    direction: config::Direction::Left,
+                },
+            )
+            .in_mode("resize"),
+        ];
+        let (mut keybindings, errors) = Keybindings::from_config(&bindings);
+        assert!(errors.is_empty(), "{errors:?}");
+
+        let none = ModifiersState::default();
+        assert_eq!(keybindings.process(keysyms::KEY_Left, none), None);
+
+        assert!(keybindings.enter_mode("resize"));
+        assert_eq!(keybindings.active_mode(), "resize");
+        assert_eq!(
+            keybindings.process(keysyms::KEY_Left, none),
+            Some(KeyAction::Pan(Direction::Left))
+        );
+        // Default bindings are inactive, Escape leaves the mode
+        let logo = ModifiersState {
+            logo: true,
+            ..Default::default()
+        };
+        assert_eq!(keybindings.process(keysyms::KEY_f, logo), None);
+        assert_eq!(
+            keybindings.process(keysyms::KEY_Escape, none),
+            Some(KeyAction::ExitMode)
+        );
+
+        keybindings.exit_mode();
+        assert_eq!(keybindings.active_mode(), DEFAULT_MODE);
+        assert!(!keybindings.enter_mode("missing"));
+    }
+
+    #[test]
+    fn test_invalid_mode_and_release_chord() {
+        let bindings = [
+            Keybinding::new("Super+R", KeybindingAction::Terminal).in_mode("bad mode"),
+            Keybinding {
+                on_release: true,
+                ..Keybinding::new("Super+K, C", KeybindingAction::Close)
+            },
+            Keybinding::new("A, B, C, D, E", KeybindingAction::Close),
+        ];
+        let (_, errors) = Keybindings::from_config(&bindings);
+        assert_eq!(errors.len(), 3, "{errors:?}");
+    }
+
+    #[test]
+    fn test_release_binding_fires_on_tap() {
+        let bindings = [Keybinding {
+            on_release: true,
+            ..Keybinding::new("Super_L", KeybindingAction::AiPrompt)
+        }];
+        let (mut keybindings, errors) = Keybindings::from_config(&bindings);
+        assert!(errors.is_empty(), "{errors:?}");
+
+        let now = Instant::now();
+        let none = ModifiersState::default();
+        let logo = ModifiersState {
+            logo: true,
+            ..Default::default()
+        };
+        let super_l = press(133, keysyms::KEY_Super_L, none);
+
+        assert_eq!(keybindings.press(super_l, false, now), KeyMatch::None);
+        assert_eq!(
+            keybindings.release(super_l, false),
+            Some(KeyAction::AiPrompt)
+        );
+
+        // Used as a modifier: no tap
+        keybindings.press(super_l, false, now);
+        keybindings.press(press(36, keysyms::KEY_Return, logo), false, now);
+        assert_eq!(keybindings.release(super_l, false), None);
+    }
+
+    #[test]
+    fn test_locked_session_and_repeat() {
+        let bindings = [
+            Keybinding {
+                repeat: true,
+                ..Keybinding::new(
+                    "Super+Right",
+                    KeybindingAction::Pan {
+                        direction: config::Direction::Right,
+                    },
+                )
+            },
+            Keybinding {
+                allow_when_locked: true,
+                ..Keybinding::new("XF86AudioMute", KeybindingAction::Terminal)
+            },
+        ];
+        let (mut keybindings, errors) = Keybindings::from_config(&bindings);
+        assert!(errors.is_empty(), "{errors:?}");
+
+        let now = Instant::now();
+        let logo = ModifiersState {
+            logo: true,
+            ..Default::default()
+        };
+        let right = press(114, keysyms::KEY_Right, logo);
+        assert_eq!(
+            keybindings.press(right, false, now),
+            KeyMatch::Action {
+                action: KeyAction::Pan(Direction::Right),
+                repeat: true
+            }
+        );
+        assert_eq!(keybindings.press(right, true, now), KeyMatch::None);
+
+        let mute = press(121, keysyms::KEY_XF86AudioMute, ModifiersState::default());
+        assert!(matches!(
+            keybindings.press(mute, true, now),
+            KeyMatch::Action { .. }
+        ));
+
+        let ctrl_alt = ModifiersState {
+            ctrl: true,
+            alt: true,
+            ..Default::default()
+        };
+        let vt = press(67, keysyms::KEY_XF86Switch_VT_1, ctrl_alt);
+        assert!(matches!(
+            keybindings.press(vt, true, now),
+            KeyMatch::Action { .. }
+        ));
+    }
+
     #[test]
     fn test_no_match_returns_none() {
         let keybindings = Keybindings::new();
@@ -0,0 +1,189 @@
+//! Keyboard events and keybinding execution
+//!
+//! Every key event first goes through the [`Keybindings`](super::Keybindings).
+//! Keys that trigger a binding or continue a chord are not forwarded, and
+//! neither are their releases, so clients never see half a key press.
+//! Release bindings fire on the release of a tapped key, which is forwarded
+//! as usual since the client saw the press.
+//!
+//! Bindings with repeat enabled are repeated by the compositor while their
+//! key is held, at the keyboard's repeat delay and rate; any other key
+//! press or the key's release stops the repeat.
+
+use super::keybindings::{KeyMatch, KeyPress};
+use super::{KeyAction, execute_action};
+use crate::state::LoomState;
+use crate::types::SmallVec4;
+use smithay::{
+    backend::input::{Event, InputBackend, KeyState, KeyboardKeyEvent},
+    input::keyboard::Keycode,
+    reexports::calloop::{
+        RegistrationToken,
+        timer::{TimeoutAction, Timer},
+    },
+    utils::SERIAL_COUNTER,
+};
+use std::time::{Duration, Instant};
+use tracing::{debug, trace, warn};
+
+/// Keyboard state kept by the compositor
+#[derive(Debug, Default)]
+pub struct KeyboardState {
+    /// Keys whose press was intercepted, so their release is too
+    intercepted: SmallVec4<Keycode>,
+    /// Key repeating a keybinding, with the timer driving the repeat
+    repeat: Option<(Keycode, RegistrationToken)>,
+}
+
+/// What to do with a key event after matching the keybindings
+#[derive(Debug, Default)]
+struct KeyOutcome {
+    /// Whether the event goes to the client
+    forward: bool,
+    /// Action to execute
+    action: Option<KeyAction>,
+    /// Whether the action repeats while the key is held
+    repeat: bool,
+}
+
+/// Process a keyboard event
+///
+/// First checks for compositor keybindings, then forwards to the focused
+/// surface if not intercepted.
+pub(super) fn process_keyboard_event<B: InputBackend>(
+    state: &mut LoomState,
+    event: B::KeyboardKeyEvent,
+) {
+    let serial = SERIAL_COUNTER.next_serial();
+    let time = event.time_msec();
+    let keycode = event.key_code();
+    let key_state = event.state();
+
+    // Get keyboard from seat
+    let keyboard = state.seat.get_keyboard().unwrap();
+
+    // Process through XKB and check for compositor keybindings
+    let (outcome, mods_changed) =
+        keyboard.input_intercept(state, keycode, key_state, |state, modifiers, handle| {
+            let press = KeyPress {
+                keycode: keycode.raw(),
+                keysym: handle.modified_sym().raw(),
+                raw_keysym: handle.raw_latin_sym_or_raw_current_sym().map(|k| k.raw()),
+                modifiers: *modifiers,
+            };
+
+            match key_state {
+                KeyState::Pressed => key_pressed(state, keycode, press),
+                KeyState::Released => key_released(state, keycode, press),
+            }
+        });
+
+    if outcome.forward {
+        keyboard.input_forward(state, keycode, key_state, serial, time, mods_changed);
+    }
+
+    // Execute the action if one was matched
+    if let Some(action) = outcome.action {
+        if outcome.repeat {
+            state.start_key_repeat(keycode, action.clone());
+        }
+        execute_action(state, action);
+    }
+}
+
+/// Match a key press against the keybindings
+fn key_pressed(state: &mut LoomState, keycode: Keycode, press: KeyPress) -> KeyOutcome {
+    state.stop_key_repeat();
+
+    let locked = state.session_locked;
+    match state.keybindings.press(press, locked, Instant::now()) {
+        KeyMatch::Action { action, repeat } => {
+            debug!("Keybinding matched: {:?}", action);
+            state.keyboard_state.intercepted.push(keycode);
+            KeyOutcome {
+                forward: false,
+                action: Some(action),
+                repeat,
+            }
+        }
+        KeyMatch::Pending => {
+            trace!("Keybinding chord continues");
+            state.keyboard_state.intercepted.push(keycode);
+            KeyOutcome::default()
+        }
+        // Forward to client
+        KeyMatch::None => KeyOutcome {
+            forward: true,
+            ..Default::default()
+        },
+    }
+}
+
+/// Handle a key release: end cycles and repeats, fire release bindings
+fn key_released(state: &mut LoomState, keycode: Keycode, press: KeyPress) -> KeyOutcome {
+    // Releasing the modifiers ends an Alt+Tab cycle
+    let modifiers = press.modifiers;
+    if !(modifiers.alt || modifiers.ctrl || modifiers.logo) {
+        state.end_focus_cycle();
+    }
+
+    if state
+        .keyboard_state
+        .repeat
+        .as_ref()
+        .is_some_and(|(key, _)| *key == keycode)
+    {
+        state.stop_key_repeat();
+    }
+
+    let action = state.keybindings.release(press, state.session_locked);
+    if let Some(action) = &action {
+        debug!("Release keybinding matched: {:?}", action);
+    }
+
+    let intercepted = &mut state.keyboard_state.intercepted;
+    let forward = match intercepted.iter().position(|key| *key == keycode) {
+        Some(index) => {
+            intercepted.swap_remove(index);
+            false
+        }
+        None => true,
+    };
+
+    KeyOutcome {
+        forward,
+        action,
+        repeat: false,
+    }
+}
+
+impl LoomState {
+    /// Repeat a keybinding's action while its key is held
+    fn start_key_repeat(&mut self, keycode: Keycode, action: KeyAction) {
+        self.stop_key_repeat();
+
+        let (delay, rate) = self.input_config.repeat_info();
+        if rate == 0 {
+            return;
+        }
+        let delay = Duration::from_millis(delay as u64);
+        let interval = Duration::from_millis(1000 / rate as u64);
+        let timer = Timer::from_duration(delay);
+        let result = self.loop_handle.insert_source(timer, move |_, _, state| {
+            execute_action(state, action.clone());
+            TimeoutAction::ToDuration(interval)
+        });
+
+        match result {
+            Ok(token) => self.keyboard_state.repeat = Some((keycode, token)),
+            Err(e) => warn!("Failed to start key repeat: {}", e),
+        }
+    }
+
+    /// Stop repeating a keybinding
+    pub fn stop_key_repeat(&mut self) {
+        if let Some((_, token)) = self.keyboard_state.repeat.take() {
+            self.loop_handle.remove(token);
+        }
+    }
+}
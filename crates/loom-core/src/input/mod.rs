@@ -6,41 +6,153 @@
 //!
 //! # Keybindings
 //!
-//! Compositor shortcuts are processed before forwarding to clients:
-//! - `Logo+Q` or `Ctrl+Alt+Backspace`: Quit compositor
-//! - `Logo+Return`: Launch terminal (future)
-//! - `Logo+Left/Right`: Move focus (future)
+//! Compositor shortcuts are processed before forwarding to clients. The
+//! configured bindings (by default `Super+Return` for the terminal,
+//! `Super+Q` to close, `Super+Shift+R` to reload the config,
+//! `Super+Shift+Q` to quit, ...) come on top of the built-in ones:
+//! - `Ctrl+Alt+Backspace`: Quit compositor
+//! - `Logo+W`: Close the focused window
+//! - `Alt+Tab` / `Alt+Shift+Tab`: Cycle the focus through the windows, most
+//!   recently used first, panning the view to each
+//! - `Logo+F`: Toggle fullscreen
+//! - `Ctrl+Alt+F1..F12`: Switch VT (DRM backend)
+//! - `Logo+Ctrl+Arrows`: Focus the neighbouring output
+//! - `Logo+Ctrl+Shift+Arrows`: Move the focused node to the neighbouring
+//!   output's view
+//!
+//! Configured bindings can also be chords (`Super+K, Super+C`), belong to a
+//! mode entered by another binding, fire on the release of a tapped key, or
+//! repeat while held (see [`Keybindings`]).
+//!
+//! # Canvas navigation
+//!
+//! Scrolling with `Logo` held, or over the canvas background, navigates
+//! the canvas instead of scrolling a client. With several outputs, the
+//! view of the output under the pointer moves:
+//! - Mouse wheel: zoom around the cursor
+//! - Touchpad (finger) scroll: pan, gliding on after the fingers lift
+//! - `Logo`+drag or middle-drag on the background: pan
+//!
+//! `Logo`+drag on a node moves it and `Logo`+right-drag resizes it from
+//! the nearest edges, using the same grabs as client move/resize requests.
+//! Dragging the title strip of a node frame moves it too, and dragging its
+//! border resizes it from that edge (see [`crate::decorations`]).
+//!
+//! # Touchpad gestures
+//!
+//! Configured gestures pan, zoom or cycle the focus; others are sent to
+//! clients (see [`GestureState`]).
+//!
+//! # Touch
+//!
+//! Touches on windows go to their clients; touches on the canvas background
+//! pan, and pinch to zoom, the view (see [`TouchState`]).
+//!
+//! # Switches
+//!
+//! Closing the lid and entering tablet mode act as configured (see
+//! [`Switches`]).
+//!
+//! # Tablets
+//!
+//! Pens over windows go to their clients. In annotation mode (`Super+I` by
+//! default), pen strokes on the canvas background draw ink nodes (see
+//! [`TabletState`]).
 //!
 //! # Security
 //!
 //! - Input events are only forwarded to the focused surface
 //! - No raw keycodes are exposed to clients (XKB keysyms only)
 
+mod devices;
+mod gestures;
 mod keybindings;
+mod keyboard;
+mod switches;
+mod tablet;
+mod touch;
+
+pub use gestures::GestureState;
+pub use keybindings::{
+    BindingFlags, DEFAULT_MODE, Direction, KeyAction, KeyCombo, KeyMatch, KeyPattern, KeyPress,
+    Keybindings, ZoomDirection, parse_key_combination, parse_key_sequence,
+};
+pub use keyboard::KeyboardState;
+pub use switches::Switches;
+pub use tablet::TabletState;
+pub use touch::{TouchGesture, TouchState};
 
-pub use keybindings::{KeyAction, KeyPattern, Keybindings};
+pub(crate) use devices::xkb_config;
 
+use gestures::{
+    process_hold_begin, process_hold_end, process_pinch_begin, process_pinch_end,
+    process_pinch_update, process_swipe_begin, process_swipe_end, process_swipe_update,
+};
+use keyboard::process_keyboard_event;
+use switches::process_switch_toggle;
+use tablet::{
+    add_tablet_device, process_tool_axis, process_tool_button, process_tool_proximity,
+    process_tool_tip, remove_tablet_device,
+};
+use touch::{
+    process_touch_cancel, process_touch_down, process_touch_frame, process_touch_motion,
+    process_touch_up,
+};
+
+use crate::decorations::FrameHit;
+use crate::grabs::{PanGrab, ResizeEdge};
+use crate::layers::LOWER_LAYERS;
+use crate::navigation::wheel_steps;
 use crate::state::LoomState;
+use crate::types::WindowId;
 use smithay::{
     backend::input::{
         AbsolutePositionEvent, Axis, AxisSource, ButtonState, Device, Event, InputBackend,
-        InputEvent, KeyState, KeyboardKeyEvent, PointerAxisEvent, PointerButtonEvent,
-        PointerMotionEvent,
+        InputEvent, PointerAxisEvent, PointerButtonEvent, PointerMotionEvent,
     },
-    desktop::WindowSurfaceType,
-    input::{
-        keyboard::FilterResult,
-        pointer::{AxisFrame, ButtonEvent, MotionEvent, RelativeMotionEvent},
+    input::pointer::{
+        AxisFrame, ButtonEvent, Focus, GrabStartData as PointerGrabStartData, MotionEvent,
+        RelativeMotionEvent,
     },
-    utils::{Logical, Point, SERIAL_COUNTER},
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Logical, Point, Rectangle, SERIAL_COUNTER, Serial},
 };
-use tracing::{debug, trace, warn};
+use tracing::{debug, info, trace};
+
+/// Left mouse button (linux/input-event-codes.h)
+pub const BTN_LEFT: u32 = 0x110;
+
+/// Right mouse button
+pub const BTN_RIGHT: u32 = 0x111;
+
+/// Middle mouse button
+pub const BTN_MIDDLE: u32 = 0x112;
 
 /// Process all input events from a backend
 ///
 /// This is the main entry point for input handling. It dispatches events
-/// to the appropriate handlers based on event type.
+/// to the appropriate handlers based on event type. Any input but devices
+/// coming and going restarts the idle timers (see [`crate::idle`]), and
+/// pointer, touch and tablet input redraws the outputs, as it moves the
+/// cursor or the view.
 pub fn process_input_event<B: InputBackend>(state: &mut LoomState, event: InputEvent<B>) {
+    if !matches!(
+        event,
+        InputEvent::DeviceAdded { .. } | InputEvent::DeviceRemoved { .. } | InputEvent::Special(_)
+    ) {
+        state.notify_activity();
+    }
+    if !matches!(
+        event,
+        InputEvent::Keyboard { .. }
+            | InputEvent::DeviceAdded { .. }
+            | InputEvent::DeviceRemoved { .. }
+            | InputEvent::SwitchToggle { .. }
+            | InputEvent::Special(_)
+    ) {
+        state.queue_redraw();
+    }
+
     match event {
         InputEvent::Keyboard { event } => {
             process_keyboard_event::<B>(state, event);
@@ -59,39 +171,65 @@ pub fn process_input_event<B: InputBackend>(state: &mut LoomState, event: InputE
         }
         InputEvent::DeviceAdded { device } => {
             debug!("Input device added: {}", device.name());
+            add_tablet_device::<B>(state, &device);
         }
         InputEvent::DeviceRemoved { device } => {
             debug!("Input device removed: {}", device.name());
+            remove_tablet_device::<B>(state, &device);
+        }
+        InputEvent::TouchDown { event } => {
+            process_touch_down::<B>(state, event);
+        }
+        InputEvent::TouchMotion { event } => {
+            process_touch_motion::<B>(state, event);
+        }
+        InputEvent::TouchUp { event } => {
+            process_touch_up::<B>(state, event);
+        }
+        InputEvent::TouchFrame { .. } => {
+            process_touch_frame(state);
+        }
+        InputEvent::TouchCancel { .. } => {
+            process_touch_cancel(state);
+        }
+        InputEvent::GestureSwipeBegin { event } => {
+            process_swipe_begin::<B>(state, event);
+        }
+        InputEvent::GestureSwipeUpdate { event } => {
+            process_swipe_update::<B>(state, event);
+        }
+        InputEvent::GestureSwipeEnd { event } => {
+            process_swipe_end::<B>(state, event);
+        }
+        InputEvent::GesturePinchBegin { event } => {
+            process_pinch_begin::<B>(state, event);
+        }
+        InputEvent::GesturePinchUpdate { event } => {
+            process_pinch_update::<B>(state, event);
+        }
+        InputEvent::GesturePinchEnd { event } => {
+            process_pinch_end::<B>(state, event);
+        }
+        InputEvent::GestureHoldBegin { event } => {
+            process_hold_begin::<B>(state, event);
         }
-        // Touch events - forward to seat
-        InputEvent::TouchDown { .. }
-        | InputEvent::TouchUp { .. }
-        | InputEvent::TouchMotion { .. }
-        | InputEvent::TouchCancel { .. }
-        | InputEvent::TouchFrame { .. } => {
-            trace!("Touch event (not yet handled)");
-        }
-        // Gesture events
-        InputEvent::GestureSwipeBegin { .. }
-        | InputEvent::GestureSwipeUpdate { .. }
-        | InputEvent::GestureSwipeEnd { .. }
-        | InputEvent::GesturePinchBegin { .. }
-        | InputEvent::GesturePinchUpdate { .. }
-        | InputEvent::GesturePinchEnd { .. }
-        | InputEvent::GestureHoldBegin { .. }
-        | InputEvent::GestureHoldEnd { .. } => {
-            trace!("Gesture event (not yet handled)");
-        }
-        // Tablet events
-        InputEvent::TabletToolAxis { .. }
-        | InputEvent::TabletToolProximity { .. }
-        | InputEvent::TabletToolTip { .. }
-        | InputEvent::TabletToolButton { .. } => {
-            trace!("Tablet event (not yet handled)");
-        }
-        // Switch events (lid, tablet mode)
-        InputEvent::SwitchToggle { .. } => {
-            trace!("Switch event (not yet handled)");
+        InputEvent::GestureHoldEnd { event } => {
+            process_hold_end::<B>(state, event);
+        }
+        InputEvent::TabletToolAxis { event } => {
+            process_tool_axis::<B>(state, event);
+        }
+        InputEvent::TabletToolProximity { event } => {
+            process_tool_proximity::<B>(state, event);
+        }
+        InputEvent::TabletToolTip { event } => {
+            process_tool_tip::<B>(state, event);
+        }
+        InputEvent::TabletToolButton { event } => {
+            process_tool_button::<B>(state, event);
+        }
+        InputEvent::SwitchToggle { event } => {
+            process_switch_toggle::<B>(state, event);
         }
         // Backend-specific events
         InputEvent::Special(_) => {
@@ -100,65 +238,26 @@ pub fn process_input_event<B: InputBackend>(state: &mut LoomState, event: InputE
     }
 }
 
-/// Process a keyboard event
-///
-/// First checks for compositor keybindings, then forwards to the focused
-/// surface if not intercepted.
-fn process_keyboard_event<B: InputBackend>(state: &mut LoomState, event: B::KeyboardKeyEvent) {
-    let serial = SERIAL_COUNTER.next_serial();
-    let time = event.time_msec();
-    let keycode = event.key_code();
-    let key_state = event.state();
-
-    // Get keyboard from seat
-    let keyboard = state.seat.get_keyboard().unwrap();
-
-    // Process through XKB and check for compositor keybindings
-    let action = keyboard.input(
-        state,
-        keycode,
-        key_state,
-        serial,
-        time,
-        |state, modifiers, handle| {
-            // Only check keybindings on press
-            if key_state == KeyState::Pressed {
-                let keysym = handle.modified_sym();
-                if let Some(action) = state.keybindings.process(keysym.raw(), *modifiers) {
-                    debug!("Keybinding matched: {:?}", action);
-                    return FilterResult::Intercept(Some(action));
-                }
-            }
-            // Forward to client
-            FilterResult::Forward
-        },
-    );
-
-    // Execute the action if one was intercepted
-    if let Some(action) = action.flatten() {
-        execute_action(state, action);
-    }
-}
-
 /// Process relative pointer motion
 fn process_pointer_motion<B: InputBackend>(state: &mut LoomState, event: B::PointerMotionEvent) {
     let serial = SERIAL_COUNTER.next_serial();
     let delta = event.delta();
 
-    // Update pointer position
+    // Update pointer position, crossing between outputs
     state.pointer_location += delta;
-    clamp_pointer_to_output(state);
+    state.clamp_pointer_to_outputs();
 
     // Find surface under pointer
     let under = surface_under_pointer(state);
 
-    // Send motion event to seat
+    // Send motion event to seat (in canvas coordinates, see `surface_under`)
+    let location = state.global_to_canvas(state.pointer_location);
     let pointer = state.seat.get_pointer().unwrap();
     pointer.motion(
         state,
         under.clone(),
         &MotionEvent {
-            location: state.pointer_location,
+            location,
             serial,
             time: event.time_msec(),
         },
@@ -185,31 +284,20 @@ fn process_pointer_motion_absolute<B: InputBackend>(
 ) {
     let serial = SERIAL_COUNTER.next_serial();
 
-    // Get output size for coordinate transformation
-    let output_size = state
-        .space
-        .outputs()
-        .next()
-        .map(|o| {
-            o.current_mode()
-                .map(|m| m.size)
-                .unwrap_or((1920, 1080).into())
-        })
-        .unwrap_or((1920, 1080).into());
-
-    // Transform to output coordinates
-    state.pointer_location = event.position_transformed(output_size.to_logical(1));
+    let geometry = absolute_geometry(state);
+    state.pointer_location = geometry.loc.to_f64() + event.position_transformed(geometry.size);
 
     // Find surface under pointer
     let under = surface_under_pointer(state);
 
-    // Send motion event
+    // Send motion event (in canvas coordinates, see `surface_under`)
+    let location = state.global_to_canvas(state.pointer_location);
     let pointer = state.seat.get_pointer().unwrap();
     pointer.motion(
         state,
         under,
         &MotionEvent {
-            location: state.pointer_location,
+            location,
             serial,
             time: event.time_msec(),
         },
@@ -218,36 +306,69 @@ fn process_pointer_motion_absolute<B: InputBackend>(
     pointer.frame(state);
 }
 
+/// Area absolute devices (touchscreens, nested windows) map to, the
+/// first output
+fn absolute_geometry(state: &LoomState) -> Rectangle<i32, Logical> {
+    state
+        .output_geometries()
+        .first()
+        .map(|(_, geometry)| *geometry)
+        .unwrap_or_else(|| Rectangle::from_size((1920, 1080).into()))
+}
+
 /// Process pointer button press/release
 fn process_pointer_button<B: InputBackend>(state: &mut LoomState, event: B::PointerButtonEvent) {
     let serial = SERIAL_COUNTER.next_serial();
     let button = event.button_code();
     let button_state = event.state();
+    let pointer = state.seat.get_pointer().unwrap();
 
-    // On click, update keyboard focus to window under pointer
+    // A click with a key held (like Logo+drag) isn't a tap of that key
     if button_state == ButtonState::Pressed {
-        if let Some((window, _)) = state
-            .space
-            .element_under(state.pointer_location)
-            .map(|(w, p)| (w.clone(), p))
-        {
-            // Raise window to top
-            state.space.raise_element(&window, true);
-
-            // Set keyboard focus
-            let keyboard = state.seat.get_keyboard().unwrap();
-            if let Some(toplevel) = window.toplevel() {
-                keyboard.set_focus(state, Some(toplevel.wl_surface().clone()), serial);
-            }
+        state.keybindings.cancel_tap();
+    }
+
+    // On click, update keyboard focus to window under pointer
+    if button_state == ButtonState::Pressed && !pointer.is_grabbed() {
+        // A click catches a gliding canvas
+        state.stop_kinetic_pan();
+
+        if state.session_locked {
+            // Only the locker gets clicks
+            state.focus_lock_surface_under(state.pointer_location, serial);
+        } else if let Some((id, hit)) = state.frame_under(state.pointer_location) {
+            state.focus_window(Some(id), serial);
+            start_frame_grab(state, id, hit, button, serial);
+        } else if state.focus_layer_under(state.pointer_location, serial) {
+            // Panels in front of the canvas get the click
         } else {
-            // Clicked on background - clear focus
-            let keyboard = state.seat.get_keyboard().unwrap();
-            keyboard.set_focus(state, None, serial);
+            // Raise and focus the window under the pointer, or clear the
+            // focus when clicking the background
+            let under = state.window_under(state.pointer_location);
+            state.focus_window(under, serial);
+
+            // Desktop surfaces below the canvas may take the focus
+            if under.is_none()
+                && let Some(layer) = state.layer_under(state.pointer_location, &LOWER_LAYERS)
+            {
+                state.focus_layer(&layer, serial);
+            }
+
+            start_compositor_grab(state, under, button, serial);
         }
     }
 
+    // Releasing the button that started a drag drops it; drops on the
+    // canvas must be taken before the drag sees the release
+    if button_state == ButtonState::Released
+        && pointer
+            .grab_start_data()
+            .is_some_and(|start| start.button == button)
+    {
+        state.drop_on_canvas(state.pointer_location);
+    }
+
     // Send button event
-    let pointer = state.seat.get_pointer().unwrap();
     pointer.button(
         state,
         &ButtonEvent {
@@ -261,10 +382,75 @@ fn process_pointer_button<B: InputBackend>(state: &mut LoomState, event: B::Poin
     pointer.frame(state);
 }
 
+/// Start a pan, move or resize grab for a compositor drag binding
+///
+/// The grab is set before the press is forwarded, so the client under the
+/// pointer never sees the button.
+fn start_compositor_grab(
+    state: &mut LoomState,
+    under: Option<WindowId>,
+    button: u32,
+    serial: Serial,
+) {
+    let logo = logo_pressed(state);
+    let start_data = PointerGrabStartData {
+        focus: None,
+        button,
+        location: state.global_to_canvas(state.pointer_location),
+    };
+
+    match under {
+        None if button == BTN_MIDDLE || (logo && button == BTN_LEFT) => {
+            let pointer = state.seat.get_pointer().unwrap();
+            let grab = PanGrab::new(start_data, state.pointer_location);
+            pointer.set_grab(state, grab, serial, Focus::Clear);
+        }
+        Some(id) if logo && button == BTN_LEFT => {
+            state.start_move_grab(id, start_data, serial);
+        }
+        Some(id) if logo && button == BTN_RIGHT => {
+            let Some(rect) = state.window_node_rect(id) else {
+                return;
+            };
+            let edges = ResizeEdge::nearest(rect, start_data.location);
+            state.start_resize_grab(id, edges, start_data, serial);
+        }
+        _ => {}
+    }
+}
+
+/// Start a move or resize grab for a left press on a node frame
+fn start_frame_grab(
+    state: &mut LoomState,
+    id: WindowId,
+    hit: FrameHit,
+    button: u32,
+    serial: Serial,
+) {
+    if button != BTN_LEFT {
+        return;
+    }
+
+    let start_data = PointerGrabStartData {
+        focus: None,
+        button,
+        location: state.global_to_canvas(state.pointer_location),
+    };
+    match hit {
+        FrameHit::Title => state.start_move_grab(id, start_data, serial),
+        FrameHit::Edge(edges) => state.start_resize_grab(id, edges, start_data, serial),
+    }
+}
+
 /// Process pointer axis (scroll) event
 fn process_pointer_axis<B: InputBackend>(state: &mut LoomState, event: B::PointerAxisEvent) {
     let source = event.source();
 
+    if scroll_targets_canvas::<B>(state, &event) {
+        process_canvas_scroll::<B>(state, &event);
+        return;
+    }
+
     let mut frame = AxisFrame::new(event.time_msec()).source(source);
 
     // Handle horizontal axis
@@ -298,6 +484,94 @@ fn process_pointer_axis<B: InputBackend>(state: &mut LoomState, event: B::Pointe
     pointer.frame(state);
 }
 
+/// Whether a scroll event navigates the canvas instead of a client
+///
+/// Finger scroll sequences keep the target chosen by their first event.
+fn scroll_targets_canvas<B: InputBackend>(
+    state: &mut LoomState,
+    event: &B::PointerAxisEvent,
+) -> bool {
+    let pointer = state.seat.get_pointer().unwrap();
+    if pointer.is_grabbed() || state.session_locked {
+        return false;
+    }
+
+    let decide = |state: &LoomState| {
+        logo_pressed(state)
+            || (state.window_under(state.pointer_location).is_none()
+                && !state.layer_covers(state.pointer_location))
+    };
+
+    if event.source() != AxisSource::Finger {
+        return decide(state);
+    }
+
+    if let Some(canvas) = state.navigation.finger_scroll {
+        if is_finger_stop::<B>(event) {
+            state.navigation.finger_scroll = None;
+        }
+        return canvas;
+    }
+
+    let canvas = decide(state);
+    if !is_finger_stop::<B>(event) {
+        state.navigation.finger_scroll = Some(canvas);
+    }
+    canvas
+}
+
+/// Zoom or pan the canvas from a scroll event
+fn process_canvas_scroll<B: InputBackend>(state: &mut LoomState, event: &B::PointerAxisEvent) {
+    let horizontal = event.amount(Axis::Horizontal).unwrap_or(0.0);
+    let vertical = event.amount(Axis::Vertical).unwrap_or(0.0);
+
+    match event.source() {
+        AxisSource::Wheel => {
+            state.stop_kinetic_pan();
+
+            let steps = wheel_steps(vertical, event.amount_v120(Axis::Vertical));
+            let factor = state.wheel_zoom_factor(steps);
+            state.zoom_view_at(factor, state.pointer_location);
+        }
+        source => {
+            // Positive scroll values move the content up/left
+            let sensitivity = state.canvas_config.pan_sensitivity;
+            let (dx, dy) = (-horizontal * sensitivity, -vertical * sensitivity);
+
+            if source == AxisSource::Finger {
+                if is_finger_stop::<B>(event) {
+                    state.start_kinetic_pan(event.time_msec());
+                    return;
+                }
+                state.stop_kinetic_pan();
+                state.navigation.kinetic.push(event.time_msec(), dx, dy);
+            }
+
+            state.pan_view(dx, dy);
+        }
+    }
+
+    state.refresh_pointer_focus();
+}
+
+/// Whether a finger scroll event signals the fingers lifting
+fn is_finger_stop<B: InputBackend>(event: &B::PointerAxisEvent) -> bool {
+    let horizontal = event.amount(Axis::Horizontal);
+    let vertical = event.amount(Axis::Vertical);
+
+    (horizontal.is_some() || vertical.is_some())
+        && horizontal.is_none_or(|v| v == 0.0)
+        && vertical.is_none_or(|v| v == 0.0)
+}
+
+/// Whether the `Logo` (Super) modifier is held
+fn logo_pressed(state: &LoomState) -> bool {
+    state
+        .seat
+        .get_keyboard()
+        .is_some_and(|keyboard| keyboard.modifier_state().logo)
+}
+
 /// Execute a compositor action
 fn execute_action(state: &mut LoomState, action: KeyAction) {
     match action {
@@ -307,64 +581,94 @@ fn execute_action(state: &mut LoomState, action: KeyAction) {
         }
         KeyAction::CloseFocused => {
             debug!("Close focused window");
-            // TODO: Send close request to focused window
-            warn!("CloseFocused not yet implemented");
+            state.close_focused();
         }
         KeyAction::FocusNext => {
             debug!("Focus next window");
-            // TODO: Cycle focus to next window
-            warn!("FocusNext not yet implemented");
+            state.cycle_focus(true);
         }
         KeyAction::FocusPrev => {
             debug!("Focus previous window");
-            // TODO: Cycle focus to previous window
-            warn!("FocusPrev not yet implemented");
+            state.cycle_focus(false);
         }
         KeyAction::ToggleFullscreen => {
             debug!("Toggle fullscreen");
-            // TODO: Toggle fullscreen for focused window
-            warn!("ToggleFullscreen not yet implemented");
+            state.toggle_fullscreen_focused();
+        }
+        KeyAction::FocusOutput(direction) => {
+            debug!("Focus output {:?}", direction);
+            state.focus_output(direction);
+        }
+        KeyAction::MoveToOutput(direction) => {
+            debug!("Move focused window to output {:?}", direction);
+            state.move_focused_to_output(direction);
+        }
+        KeyAction::Terminal => {
+            debug!("Open terminal");
+            state.spawn_terminal();
+        }
+        KeyAction::Launcher => {
+            debug!("Open launcher");
+            state.spawn_launcher();
+        }
+        KeyAction::Pan(direction) => {
+            debug!("Pan {:?}", direction);
+            state.pan_view_step(direction);
+        }
+        KeyAction::Zoom(direction) => {
+            debug!("Zoom {:?}", direction);
+            state.zoom_view_step(direction);
+        }
+        KeyAction::ResetView => {
+            debug!("Reset view");
+            state.reset_view();
+        }
+        KeyAction::AiPrompt => {
+            debug!("Open AI prompt");
+            state.open_ai_prompt();
+        }
+        KeyAction::ToggleAnnotation => {
+            debug!("Toggle annotation");
+            state.toggle_annotation();
+        }
+        KeyAction::LaunchApp(app_id) => {
+            debug!("Launch app {}", app_id);
+            state.launch_app(&app_id);
+        }
+        KeyAction::RunScript(script_name) => {
+            debug!("Run script {}", script_name);
+            state.run_script(&script_name);
+        }
+        KeyAction::EnterMode(mode) => {
+            if state.keybindings.enter_mode(&mode) {
+                info!("Entered keybinding mode {}", mode);
+            }
+        }
+        KeyAction::ExitMode => {
+            debug!(
+                "Leaving keybinding mode {}",
+                state.keybindings.active_mode()
+            );
+            state.keybindings.exit_mode();
+        }
+        KeyAction::ReloadConfig => {
+            debug!("Reload config");
+            state.reload_config();
+        }
+        KeyAction::SwitchVt(vt) => {
+            // Only the DRM backend owns a session; others ignore the request
+            debug!("Switch to VT {}", vt);
+            state.vt_switch = Some(vt);
         }
         KeyAction::None => {}
     }
 }
 
-/// Clamp pointer position to valid output bounds
-fn clamp_pointer_to_output(state: &mut LoomState) {
-    // Get the bounding box of all outputs
-    let (min_x, min_y, max_x, max_y) = state.space.outputs().fold(
-        (0.0_f64, 0.0_f64, 0.0_f64, 0.0_f64),
-        |(min_x, min_y, max_x, max_y), output| {
-            let geometry = state.space.output_geometry(output).unwrap();
-            (
-                min_x.min(geometry.loc.x as f64),
-                min_y.min(geometry.loc.y as f64),
-                max_x.max((geometry.loc.x + geometry.size.w) as f64),
-                max_y.max((geometry.loc.y + geometry.size.h) as f64),
-            )
-        },
-    );
-
-    state.pointer_location.x = state.pointer_location.x.clamp(min_x, max_x - 1.0);
-    state.pointer_location.y = state.pointer_location.y.clamp(min_y, max_y - 1.0);
-}
-
 /// Find the surface under the pointer
-fn surface_under_pointer(
-    state: &LoomState,
-) -> Option<(
-    smithay::reexports::wayland_server::protocol::wl_surface::WlSurface,
-    Point<f64, Logical>,
-)> {
-    state
-        .space
-        .element_under(state.pointer_location)
-        .and_then(|(window, location)| {
-            window
-                .surface_under(
-                    state.pointer_location - location.to_f64(),
-                    WindowSurfaceType::ALL,
-                )
-                .map(|(surface, surface_loc)| (surface, (surface_loc + location).to_f64()))
-        })
+///
+/// The returned origin is in canvas coordinates, matching the locations
+/// sent to the seat, so clients receive correct surface-local coordinates
+/// at any zoom level.
+fn surface_under_pointer(state: &LoomState) -> Option<(WlSurface, Point<f64, Logical>)> {
+    state.surface_under(state.pointer_location)
 }
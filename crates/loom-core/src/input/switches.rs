@@ -0,0 +1,81 @@
+//! Lid and tablet-mode switches
+//!
+//! Closing the lid does what `[switches] lid_close` says: nothing, run a
+//! command (a screen locker, `systemctl suspend`), or turn the internal
+//! display off while another output is connected. The DRM backend owns
+//! the outputs, so it applies the last one, checking
+//! [`LoomState::lid_disables_internal_outputs`] after every dispatch; the
+//! internal display's nodes move to the remaining outputs like those of an
+//! unplugged monitor (see [`crate::outputs`]).
+//!
+//! In tablet mode touch input is made for fingers rather than a pointer:
+//! touches landing just outside a window still go to it, and two fingers
+//! pan and pinch-zoom the canvas even on windows (see [`TouchState`]).
+//!
+//! [`TouchState`]: super::TouchState
+
+use crate::state::LoomState;
+use loom_config::LidAction;
+use smithay::backend::input::{InputBackend, Switch, SwitchState, SwitchToggleEvent};
+use tracing::{debug, info, warn};
+
+/// State of the lid and tablet-mode switches
+#[derive(Debug, Default)]
+pub struct Switches {
+    /// Whether the lid is closed
+    lid_closed: bool,
+    /// Whether the device is in tablet mode
+    tablet_mode: bool,
+}
+
+impl LoomState {
+    /// Whether internal displays should be off, the lid being closed
+    ///
+    /// Backends only turn them off while another output is connected.
+    pub fn lid_disables_internal_outputs(&self) -> bool {
+        self.switches.lid_closed && self.switch_config.lid_close == LidAction::DisableOutput
+    }
+
+    /// Whether touch input is in tablet mode
+    pub fn tablet_mode(&self) -> bool {
+        self.switches.tablet_mode && self.switch_config.tablet_mode.enabled
+    }
+
+    /// Handle the lid opening or closing
+    fn lid_changed(&mut self, closed: bool) {
+        if self.switches.lid_closed == closed {
+            return;
+        }
+        self.switches.lid_closed = closed;
+        info!("Lid {}", if closed { "closed" } else { "opened" });
+
+        if closed && self.switch_config.lid_close == LidAction::Command {
+            match self.switch_config.lid_command.clone() {
+                Some(command) => self.spawn_command(&command),
+                None => warn!("No lid_command configured"),
+            }
+        }
+    }
+
+    /// Handle entering or leaving tablet mode
+    fn tablet_mode_changed(&mut self, on: bool) {
+        if self.switches.tablet_mode == on {
+            return;
+        }
+        self.switches.tablet_mode = on;
+        info!("Tablet mode {}", if on { "on" } else { "off" });
+    }
+}
+
+/// Process a switch toggling
+pub(super) fn process_switch_toggle<B: InputBackend>(
+    state: &mut LoomState,
+    event: B::SwitchToggleEvent,
+) {
+    let on = event.state() == SwitchState::On;
+    match event.switch() {
+        Some(Switch::Lid) => state.lid_changed(on),
+        Some(Switch::TabletMode) => state.tablet_mode_changed(on),
+        _ => debug!("Unknown switch toggled"),
+    }
+}
@@ -0,0 +1,398 @@
+//! Drawing tablets
+//!
+//! Tablets and their tools are announced to clients through
+//! `zwp_tablet_v2`, and pen events over a window go to its client, a tip
+//! touching down focusing it like a click.
+//!
+//! In annotation mode (toggled by a keybinding), a pen touching down on the
+//! canvas background draws instead: the stroke follows the pen, with its
+//! pressure and tilt, until the tip lifts, then becomes part of a
+//! [`NodeType::Ink`](loom_canvas::NodeType::Ink) node. A stroke starting on
+//! or near an existing ink node joins it, so a drawing or handwritten note
+//! stays one node. Strokes are kept in canvas coordinates, so the view can
+//! be zoomed while drawing.
+//!
+//! Tablets map to the first output, like other absolute devices. The pen
+//! doesn't move the pointer.
+
+use super::absolute_geometry;
+use crate::state::LoomState;
+use loom_canvas::{InkPoint, InkStroke, NodeId};
+use loom_config::theme::parse_color;
+use smithay::{
+    backend::input::{
+        Device, DeviceCapability, Event, InputBackend, ProximityState, TabletToolButtonEvent,
+        TabletToolEvent, TabletToolProximityEvent, TabletToolTipEvent, TabletToolTipState,
+    },
+    utils::{Logical, Point, SERIAL_COUNTER},
+    wayland::tablet_manager::{
+        TabletDescriptor, TabletHandle, TabletSeatHandle, TabletSeatTrait, TabletToolHandle,
+    },
+};
+use tracing::{debug, info, warn};
+
+/// Width of ink strokes at full pressure, in canvas units
+const INK_WIDTH: f64 = 3.0;
+
+/// Distance from an ink node within which a new stroke joins it, in canvas
+/// units
+const INK_JOIN_MARGIN: f64 = 24.0;
+
+/// Annotation mode and the stroke being drawn
+#[derive(Debug, Default)]
+pub struct TabletState {
+    /// Whether pen strokes on the background draw ink
+    annotating: bool,
+    /// Stroke being drawn, in canvas coordinates
+    stroke: Option<InkStroke>,
+    /// Ink node the stroke being drawn joins
+    join: Option<NodeId>,
+}
+
+impl TabletState {
+    /// Whether pen strokes on the background draw ink
+    pub fn annotating(&self) -> bool {
+        self.annotating
+    }
+
+    /// Stroke being drawn, in canvas coordinates
+    pub fn stroke(&self) -> Option<&InkStroke> {
+        self.stroke.as_ref()
+    }
+}
+
+impl LoomState {
+    /// Turn annotation mode on or off
+    ///
+    /// A stroke being drawn is finished when the pen lifts either way.
+    pub fn toggle_annotation(&mut self) {
+        self.tablet_state.annotating = !self.tablet_state.annotating;
+        info!("Annotation mode: {}", self.tablet_state.annotating);
+    }
+
+    /// Start a stroke at a global point if the pen draws there: in
+    /// annotation mode, on the canvas background
+    ///
+    /// Returns whether the stroke started.
+    fn begin_stroke(
+        &mut self,
+        location: Point<f64, Logical>,
+        pressure: f64,
+        tilt: (f64, f64),
+    ) -> bool {
+        if !self.tablet_state.annotating
+            || self.session_locked
+            || self.window_under(location).is_some()
+            || self.layer_covers(location)
+        {
+            return false;
+        }
+
+        let point = self.global_to_canvas(location);
+        let mut stroke = InkStroke::new(self.ink_color(), INK_WIDTH);
+        stroke.push(InkPoint::new(point.x, point.y, pressure, tilt));
+
+        self.tablet_state.join = self.canvas.ink_node_at(point.x, point.y, INK_JOIN_MARGIN);
+        self.tablet_state.stroke = Some(stroke);
+        true
+    }
+
+    /// Add a pen sample at a global point to the stroke being drawn
+    ///
+    /// Returns whether a stroke is being drawn.
+    fn extend_stroke(
+        &mut self,
+        location: Point<f64, Logical>,
+        pressure: f64,
+        tilt: (f64, f64),
+    ) -> bool {
+        let point = self.global_to_canvas(location);
+        let Some(stroke) = &mut self.tablet_state.stroke else {
+            return false;
+        };
+        stroke.push(InkPoint::new(point.x, point.y, pressure, tilt));
+        true
+    }
+
+    /// Add the stroke being drawn to the canvas
+    fn finish_stroke(&mut self) {
+        let Some(stroke) = self.tablet_state.stroke.take() else {
+            return;
+        };
+
+        let result = match self.tablet_state.join.take() {
+            // The node may have been removed, or be full, while drawing
+            Some(id) => self
+                .canvas
+                .add_ink_stroke(stroke.clone(), Some(id))
+                .or_else(|_| self.canvas.add_ink_stroke(stroke, None)),
+            None => self.canvas.add_ink_stroke(stroke, None),
+        };
+        match result {
+            Ok(id) => debug!("Ink stroke added to node {}", id),
+            Err(e) => warn!("Dropping ink stroke: {}", e),
+        }
+    }
+
+    /// Color of new ink, the theme's text color
+    fn ink_color(&self) -> [f32; 4] {
+        parse_color(&self.theme.text).unwrap_or([1.0; 4])
+    }
+}
+
+/// Announce a new tablet to clients
+pub(super) fn add_tablet_device<B: InputBackend>(state: &mut LoomState, device: &B::Device) {
+    if !device.has_capability(DeviceCapability::TabletTool) {
+        return;
+    }
+
+    debug!("Tablet added: {}", device.name());
+    state
+        .seat
+        .tablet_seat()
+        .add_tablet::<LoomState>(&state.display_handle, &TabletDescriptor::from(device));
+}
+
+/// Remove a tablet, and its tools once no tablet remains
+pub(super) fn remove_tablet_device<B: InputBackend>(state: &mut LoomState, device: &B::Device) {
+    if !device.has_capability(DeviceCapability::TabletTool) {
+        return;
+    }
+
+    let tablet_seat = state.seat.tablet_seat();
+    tablet_seat.remove_tablet(&TabletDescriptor::from(device));
+    if tablet_seat.count_tablets() == 0 {
+        tablet_seat.clear_tools();
+    }
+}
+
+/// Process the pen moving or its axes changing
+pub(super) fn process_tool_axis<B: InputBackend>(
+    state: &mut LoomState,
+    event: B::TabletToolAxisEvent,
+) {
+    let location = tool_location::<B, _>(state, &event);
+    if state.extend_stroke(location, event.pressure(), event.tilt()) {
+        return;
+    }
+
+    let (tool, tablet) = tool_and_tablet::<B, _>(state, &event);
+    if event.pressure_has_changed() {
+        tool.pressure(event.pressure());
+    }
+    if event.distance_has_changed() {
+        tool.distance(event.distance());
+    }
+    if event.tilt_has_changed() {
+        tool.tilt(event.tilt());
+    }
+    if event.rotation_has_changed() {
+        tool.rotation(event.rotation());
+    }
+    if event.slider_has_changed() {
+        tool.slider_position(event.slider_position());
+    }
+    if event.wheel_has_changed() {
+        tool.wheel(event.wheel_delta(), event.wheel_delta_discrete());
+    }
+
+    tool.motion(
+        state.global_to_canvas(location),
+        state.surface_under(location),
+        &tablet,
+        SERIAL_COUNTER.next_serial(),
+        event.time_msec(),
+    );
+}
+
+/// Process the pen entering or leaving the tablet's range
+pub(super) fn process_tool_proximity<B: InputBackend>(
+    state: &mut LoomState,
+    event: B::TabletToolProximityEvent,
+) {
+    let (tool, tablet) = tool_and_tablet::<B, _>(state, &event);
+
+    match event.state() {
+        ProximityState::In => {
+            let location = tool_location::<B, _>(state, &event);
+            if let Some(focus) = state.surface_under(location) {
+                tool.proximity_in(
+                    state.global_to_canvas(location),
+                    focus,
+                    &tablet,
+                    SERIAL_COUNTER.next_serial(),
+                    event.time_msec(),
+                );
+            }
+        }
+        ProximityState::Out => {
+            // The tip lifts before the pen leaves, but don't lose the stroke
+            // if it didn't
+            state.finish_stroke();
+            tool.proximity_out(event.time_msec());
+        }
+    }
+}
+
+/// Process the pen tip touching or leaving the tablet
+pub(super) fn process_tool_tip<B: InputBackend>(
+    state: &mut LoomState,
+    event: B::TabletToolTipEvent,
+) {
+    let serial = SERIAL_COUNTER.next_serial();
+    let (tool, _) = tool_and_tablet::<B, _>(state, &event);
+
+    match event.tip_state() {
+        TabletToolTipState::Down => {
+            let location = tool_location::<B, _>(state, &event);
+            if state.begin_stroke(location, event.pressure(), event.tilt()) {
+                return;
+            }
+
+            if state.session_locked {
+                state.focus_lock_surface_under(location, serial);
+            } else if !state.focus_layer_under(location, serial) {
+                let under = state.window_under(location);
+                state.focus_window(under, serial);
+            }
+            tool.tip_down(serial, event.time_msec());
+        }
+        TabletToolTipState::Up => {
+            if state.tablet_state.stroke.is_some() {
+                state.finish_stroke();
+                return;
+            }
+            tool.tip_up(event.time_msec());
+        }
+    }
+}
+
+/// Process a button on the pen
+pub(super) fn process_tool_button<B: InputBackend>(
+    state: &mut LoomState,
+    event: B::TabletToolButtonEvent,
+) {
+    let (tool, _) = tool_and_tablet::<B, _>(state, &event);
+    tool.button(
+        event.button(),
+        event.button_state(),
+        SERIAL_COUNTER.next_serial(),
+        event.time_msec(),
+    );
+}
+
+/// Global location of the pen
+fn tool_location<B: InputBackend, E: TabletToolEvent<B>>(
+    state: &LoomState,
+    event: &E,
+) -> Point<f64, Logical> {
+    let geometry = absolute_geometry(state);
+    geometry.loc.to_f64() + event.position_transformed(geometry.size)
+}
+
+/// Handles of an event's tool and tablet
+///
+/// Both are added if unknown, since backends may not report the tablet
+/// being added before its tools are used.
+fn tool_and_tablet<B: InputBackend, E: TabletToolEvent<B> + Event<B>>(
+    state: &mut LoomState,
+    event: &E,
+) -> (TabletToolHandle, TabletHandle) {
+    let tablet_seat: TabletSeatHandle = state.seat.tablet_seat();
+    let tablet = tablet_seat.add_tablet::<LoomState>(
+        &state.display_handle,
+        &TabletDescriptor::from(&event.device()),
+    );
+
+    let descriptor = event.tool();
+    let tool = match tablet_seat.get_tool(&descriptor) {
+        Some(tool) => tool,
+        None => {
+            let display_handle = state.display_handle.clone();
+            tablet_seat.add_tool::<LoomState>(state, &display_handle, &descriptor)
+        }
+    };
+
+    (tool, tablet)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::headless::HeadlessBackend;
+    use crate::state::LoomState;
+    use loom_canvas::NodeType;
+
+    /// Draw a stroke between global points, returning whether it started
+    fn draw(state: &mut LoomState, from: (f64, f64), to: (f64, f64)) -> bool {
+        if !state.begin_stroke(from.into(), 1.0, (0.0, 0.0)) {
+            return false;
+        }
+        assert!(state.extend_stroke(to.into(), 0.5, (0.0, 0.0)));
+        state.finish_stroke();
+        true
+    }
+
+    fn ink_nodes(state: &LoomState) -> Vec<(u64, usize)> {
+        let mut nodes: Vec<_> = state
+            .canvas
+            .nodes()
+            .filter_map(|node| match &node.node_type {
+                NodeType::Ink { strokes } => Some((node.id, strokes.len())),
+                _ => None,
+            })
+            .collect();
+        nodes.sort();
+        nodes
+    }
+
+    #[test]
+    fn test_strokes_on_background() {
+        let mut backend = HeadlessBackend::for_tests(640, 480);
+        let state = backend.state_mut();
+
+        // Outside annotation mode the pen goes to clients
+        assert!(!draw(state, (100.0, 100.0), (150.0, 100.0)));
+        assert!(!state.extend_stroke((150.0, 100.0).into(), 1.0, (0.0, 0.0)));
+
+        state.toggle_annotation();
+        assert!(draw(state, (100.0, 100.0), (150.0, 100.0)));
+        assert!(state.tablet_state.stroke().is_none());
+        let nodes = ink_nodes(state);
+        assert_eq!(nodes.len(), 1);
+        assert_eq!(nodes[0].1, 1);
+
+        // A stroke starting near the node joins it, one far away doesn't
+        assert!(draw(state, (155.0, 110.0), (200.0, 110.0)));
+        assert_eq!(ink_nodes(state), vec![(nodes[0].0, 2)]);
+        assert!(draw(state, (500.0, 400.0), (550.0, 400.0)));
+        assert_eq!(ink_nodes(state).len(), 2);
+
+        // No ink while locked
+        state.session_locked = true;
+        assert!(!draw(state, (300.0, 300.0), (350.0, 300.0)));
+    }
+
+    #[test]
+    fn test_finish_stroke_without_joined_node() {
+        let mut backend = HeadlessBackend::for_tests(640, 480);
+        let state = backend.state_mut();
+        state.toggle_annotation();
+        assert!(draw(state, (100.0, 100.0), (150.0, 100.0)));
+        let (id, _) = ink_nodes(state)[0];
+
+        // The node is removed while a stroke joining it is drawn
+        assert!(state.begin_stroke((155.0, 100.0).into(), 1.0, (0.0, 0.0)));
+        assert!(state.extend_stroke((200.0, 100.0).into(), 1.0, (0.0, 0.0)));
+        state.canvas.remove_node(id);
+        state.finish_stroke();
+
+        let nodes = ink_nodes(state);
+        assert_eq!(nodes.len(), 1);
+        assert_ne!(nodes[0].0, id);
+        assert_eq!(nodes[0].1, 1);
+
+        // Finishing without a stroke does nothing
+        state.finish_stroke();
+        assert_eq!(ink_nodes(state), nodes);
+    }
+}
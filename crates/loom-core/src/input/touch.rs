@@ -0,0 +1,382 @@
+//! Touchscreen events
+//!
+//! A touch landing on a window focuses it and goes to its client, like a
+//! click; the whole touch sequence stays with the client. Touches landing
+//! on the canvas background navigate instead: one finger pans the view,
+//! two fingers pan with their midpoint and zoom as they spread or pinch.
+//! While fingers are on the background, further fingers join the gesture
+//! wherever they land.
+//!
+//! In tablet mode (see [`super::Switches`]), touches landing within a
+//! margin around a window go to it as if on its edge, and a second finger
+//! landing while one is on a window takes the whole touch sequence over
+//! for navigation, cancelling it for the client.
+//!
+//! Touchscreens map to the first output, like other absolute devices.
+//! Touch locations are sent to the seat in canvas coordinates, matching
+//! the surface origins of [`LoomState::surface_under`].
+
+use super::absolute_geometry;
+use crate::state::LoomState;
+use crate::types::{SmallVec4, WindowId};
+use smithay::{
+    backend::input::{AbsolutePositionEvent, Event, InputBackend, TouchEvent as _, TouchSlot},
+    input::touch::{DownEvent, MotionEvent, UpEvent},
+    utils::{Logical, Point, Rectangle, SERIAL_COUNTER, Serial},
+};
+
+/// Smallest finger distance used for pinch zooming, in pixels
+///
+/// Below it the zoom factor would jump wildly from tiny movements.
+const MIN_PINCH_DISTANCE: f64 = 10.0;
+
+/// Distance inside a window's edge that touches near it are moved to, in
+/// canvas units
+const SNAP_INSET: f64 = 1.0;
+
+/// Touch points navigating the canvas
+#[derive(Debug, Default)]
+pub struct TouchState {
+    /// Fingers on the canvas background, with their global locations
+    canvas: SmallVec4<(TouchSlot, Point<f64, Logical>)>,
+    /// Fingers sent to clients, with their global locations
+    client: SmallVec4<(TouchSlot, Point<f64, Logical>)>,
+}
+
+impl TouchState {
+    /// Whether a finger navigates the canvas
+    fn is_canvas(&self, slot: TouchSlot) -> bool {
+        self.canvas.iter().any(|(s, _)| *s == slot)
+    }
+}
+
+/// Change of the view from moving fingers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TouchGesture {
+    /// Global point around which to zoom, the fingers' new midpoint
+    pub center: Point<f64, Logical>,
+    /// Movement of the fingers' midpoint, in screen pixels
+    pub delta: Point<f64, Logical>,
+    /// Zoom factor (above 1 zooms in)
+    pub factor: f64,
+}
+
+impl TouchGesture {
+    /// Gesture between two sets of finger locations
+    ///
+    /// One finger pans; with two or more, the first two also zoom.
+    pub fn between(before: &[Point<f64, Logical>], after: &[Point<f64, Logical>]) -> Option<Self> {
+        match (before, after) {
+            ([a0, b0, ..], [a1, b1, ..]) => {
+                let center = midpoint(*a1, *b1);
+                let distance = |a: Point<f64, Logical>, b: Point<f64, Logical>| {
+                    (a.x - b.x).hypot(a.y - b.y).max(MIN_PINCH_DISTANCE)
+                };
+                Some(Self {
+                    center,
+                    delta: center - midpoint(*a0, *b0),
+                    factor: distance(*a1, *b1) / distance(*a0, *b0),
+                })
+            }
+            ([a0, ..], [a1, ..]) => Some(Self {
+                center: *a1,
+                delta: *a1 - *a0,
+                factor: 1.0,
+            }),
+            _ => None,
+        }
+    }
+}
+
+fn midpoint(a: Point<f64, Logical>, b: Point<f64, Logical>) -> Point<f64, Logical> {
+    Point::from(((a.x + b.x) / 2.0, (a.y + b.y) / 2.0))
+}
+
+/// Point moved just inside a rectangle, if it is within a margin of it
+fn snap_into(
+    rect: Rectangle<f64, Logical>,
+    point: Point<f64, Logical>,
+    margin: f64,
+) -> Option<Point<f64, Logical>> {
+    if rect.size.w <= 0.0 || rect.size.h <= 0.0 {
+        return None;
+    }
+
+    // Rectangles don't contain their right and bottom edges
+    let inset = (rect.size.w.min(rect.size.h) / 2.0).min(SNAP_INSET);
+    let x = point
+        .x
+        .clamp(rect.loc.x + inset, rect.loc.x + rect.size.w - inset);
+    let y = point
+        .y
+        .clamp(rect.loc.y + inset, rect.loc.y + rect.size.h - inset);
+    ((point.x - x).hypot(point.y - y) <= margin).then(|| Point::from((x, y)))
+}
+
+impl LoomState {
+    /// Topmost window within the tablet-mode touch margin of a global
+    /// point, with the point moved onto the window
+    fn window_near(&self, point: Point<f64, Logical>) -> Option<(WindowId, Point<f64, Logical>)> {
+        let view = self.view_at(point)?;
+        let zoom = self.canvas.viewport(view.viewport)?.zoom;
+        let margin = self.switch_config.tablet_mode.touch_margin() / zoom;
+        let canvas_point = self.view_to_canvas(view, point);
+
+        self.space.elements().rev().find_map(|window| {
+            let id = self.window_id(window)?;
+            let snapped = snap_into(self.window_node_rect(id)?, canvas_point, margin)?;
+            Some((id, self.canvas_to_view(view, snapped)))
+        })
+    }
+}
+
+/// Process a finger touching down
+pub(super) fn process_touch_down<B: InputBackend>(state: &mut LoomState, event: B::TouchDownEvent) {
+    let serial = SERIAL_COUNTER.next_serial();
+    let slot = event.slot();
+    let geometry = absolute_geometry(state);
+    let location = geometry.loc.to_f64() + event.position_transformed(geometry.size);
+
+    // A touch with a key held isn't a tap of that key
+    state.keybindings.cancel_tap();
+
+    let touch = state.seat.get_touch().unwrap();
+    let joins_gesture = !state.touch_state.canvas.is_empty();
+
+    // Only the locker gets touches while the session is locked
+    if state.session_locked {
+        state.focus_lock_surface_under(location, serial);
+        client_touch_down(state, slot, location, serial, event.time_msec());
+        return;
+    }
+
+    if state.tablet_mode()
+        && state.switch_config.tablet_mode.multi_touch_navigation
+        && !state.touch_state.client.is_empty()
+        && !touch.is_grabbed()
+        && state
+            .output_at(location)
+            .and_then(|output| state.fullscreen_window_on(&output))
+            .is_none()
+    {
+        // A second finger: navigate with all of them instead
+        touch.cancel(state);
+        let client = std::mem::take(&mut state.touch_state.client);
+        state.touch_state.canvas.extend(client);
+        state.touch_state.canvas.push((slot, location));
+        state.stop_kinetic_pan();
+        return;
+    }
+
+    let covered = state.layer_covers(location);
+    let (under, location) = match state.window_under(location) {
+        None if state.tablet_mode() && !joins_gesture && !covered => state
+            .window_near(location)
+            .map_or((None, location), |(id, snapped)| (Some(id), snapped)),
+        under => (under, location),
+    };
+
+    if joins_gesture || (under.is_none() && !covered && !touch.is_grabbed()) {
+        if !joins_gesture {
+            // Touching the background catches a gliding canvas and clears
+            // the focus, like a click
+            state.stop_kinetic_pan();
+            state.focus_window(None, serial);
+        }
+        state.touch_state.canvas.push((slot, location));
+        return;
+    }
+
+    if !touch.is_grabbed() && !state.focus_layer_under(location, serial) {
+        state.focus_window(under, serial);
+    }
+    client_touch_down(state, slot, location, serial, event.time_msec());
+}
+
+/// Send a touch down to the client of the surface under it
+fn client_touch_down(
+    state: &mut LoomState,
+    slot: TouchSlot,
+    location: Point<f64, Logical>,
+    serial: Serial,
+    time: u32,
+) {
+    state.touch_state.client.push((slot, location));
+    let touch = state.seat.get_touch().unwrap();
+    let focus = state.surface_under(location);
+    touch.down(
+        state,
+        focus,
+        &DownEvent {
+            slot,
+            location: state.global_to_canvas(location),
+            serial,
+            time,
+        },
+    );
+}
+
+/// Process a finger moving
+pub(super) fn process_touch_motion<B: InputBackend>(
+    state: &mut LoomState,
+    event: B::TouchMotionEvent,
+) {
+    let slot = event.slot();
+    let geometry = absolute_geometry(state);
+    let location = geometry.loc.to_f64() + event.position_transformed(geometry.size);
+
+    if state.touch_state.is_canvas(slot) {
+        move_canvas_touch(state, slot, location, event.time_msec());
+        return;
+    }
+
+    for (s, point) in state.touch_state.client.iter_mut() {
+        if *s == slot {
+            *point = location;
+        }
+    }
+
+    let touch = state.seat.get_touch().unwrap();
+    let focus = state.surface_under(location);
+    touch.motion(
+        state,
+        focus,
+        &MotionEvent {
+            slot,
+            location: state.global_to_canvas(location),
+            time: event.time_msec(),
+        },
+    );
+}
+
+/// Process a finger lifting
+pub(super) fn process_touch_up<B: InputBackend>(state: &mut LoomState, event: B::TouchUpEvent) {
+    let slot = event.slot();
+
+    let canvas = &mut state.touch_state.canvas;
+    if let Some(index) = canvas.iter().position(|(s, _)| *s == slot) {
+        canvas.remove(index);
+        if canvas.is_empty() {
+            state.start_kinetic_pan(event.time_msec());
+            state.refresh_pointer_focus();
+        } else {
+            // The remaining fingers continue from where they are
+            state.navigation.kinetic.stop();
+        }
+        return;
+    }
+
+    state.touch_state.client.retain(|(s, _)| *s != slot);
+    let touch = state.seat.get_touch().unwrap();
+    touch.up(
+        state,
+        &UpEvent {
+            slot,
+            serial: SERIAL_COUNTER.next_serial(),
+            time: event.time_msec(),
+        },
+    );
+}
+
+/// Process the end of a set of touch events
+pub(super) fn process_touch_frame(state: &mut LoomState) {
+    let touch = state.seat.get_touch().unwrap();
+    touch.frame(state);
+}
+
+/// Process the touch sequence being cancelled
+pub(super) fn process_touch_cancel(state: &mut LoomState) {
+    state.touch_state.canvas.clear();
+    state.touch_state.client.clear();
+    state.navigation.kinetic.stop();
+
+    let touch = state.seat.get_touch().unwrap();
+    touch.cancel(state);
+}
+
+/// Pan and zoom the view from a finger on the background moving
+fn move_canvas_touch(
+    state: &mut LoomState,
+    slot: TouchSlot,
+    location: Point<f64, Logical>,
+    time_msec: u32,
+) {
+    let canvas = &mut state.touch_state.canvas;
+    let before: SmallVec4<_> = canvas.iter().map(|(_, point)| *point).collect();
+    for (s, point) in canvas.iter_mut() {
+        if *s == slot {
+            *point = location;
+        }
+    }
+    let after: SmallVec4<_> = canvas.iter().map(|(_, point)| *point).collect();
+
+    let Some(gesture) = TouchGesture::between(&before, &after) else {
+        return;
+    };
+
+    if after.len() == 1 {
+        state
+            .navigation
+            .kinetic
+            .push(time_msec, gesture.delta.x, gesture.delta.y);
+    }
+
+    state.pan_view_at(gesture.center, gesture.delta.x, gesture.delta.y);
+    if gesture.factor != 1.0 {
+        state.zoom_view_at(gesture.factor, gesture.center);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f64, y: f64) -> Point<f64, Logical> {
+        Point::from((x, y))
+    }
+
+    #[test]
+    fn test_touch_gesture() {
+        // One finger pans
+        let pan = TouchGesture::between(&[point(10.0, 10.0)], &[point(15.0, 8.0)]).unwrap();
+        assert_eq!(pan.delta, point(5.0, -2.0));
+        assert_eq!(pan.factor, 1.0);
+
+        // Two fingers spreading apart zoom in around their midpoint
+        let before = [point(100.0, 100.0), point(200.0, 100.0)];
+        let after = [point(50.0, 110.0), point(250.0, 110.0)];
+        let pinch = TouchGesture::between(&before, &after).unwrap();
+        assert_eq!(pinch.center, point(150.0, 110.0));
+        assert_eq!(pinch.delta, point(0.0, 10.0));
+        assert_eq!(pinch.factor, 2.0);
+
+        // Fingers on the same spot don't divide by zero
+        let close = [point(100.0, 100.0), point(100.0, 100.0)];
+        let pinch = TouchGesture::between(&close, &after).unwrap();
+        assert!(pinch.factor.is_finite());
+
+        assert_eq!(TouchGesture::between(&[], &[]), None);
+    }
+
+    #[test]
+    fn test_snap_into() {
+        let rect = Rectangle::new((100.0, 100.0).into(), (200.0, 100.0).into());
+
+        // Inside stays put
+        assert_eq!(
+            snap_into(rect, point(150.0, 150.0), 10.0),
+            Some(point(150.0, 150.0))
+        );
+        // Left of the window: onto its left edge, at the same height
+        assert_eq!(
+            snap_into(rect, point(92.0, 150.0), 10.0),
+            Some(point(101.0, 150.0))
+        );
+        // Past the bottom-right corner, within the margin
+        assert_eq!(
+            snap_into(rect, point(304.0, 203.0), 10.0),
+            Some(point(299.0, 199.0))
+        );
+        assert_eq!(snap_into(rect, point(320.0, 150.0), 10.0), None);
+    }
+}
@@ -0,0 +1,507 @@
+//! Launching programs from keybindings
+//!
+//! Commands are split into arguments like the `Exec` lines of desktop
+//! entries (double quotes group an argument) and run directly, never
+//! through a shell, with `WAYLAND_DISPLAY` pointing at the compositor's
+//! socket. Applications are launched through their `.desktop` file (see
+//! [`crate::desktop`]), and scripts must live in the scripts directory of
+//! the config (see [`loom_config::keybindings::security`]).
+//!
+//! Programs are detached from the compositor: they run as a grandchild in
+//! a session of their own, so they survive a compositor restart and never
+//! linger as its zombies. The grandchild's PID is remembered together with
+//! the canvas point the launch was requested at, and the first toplevel of
+//! that process (or of one it started) gets its node placed there.
+
+use crate::desktop::{DesktopEntry, desktop_file_path, parse_desktop_entry, read_desktop_file};
+use crate::state::LoomState;
+use loom_config::keybindings::security;
+use smithay::{
+    desktop::Window,
+    reexports::wayland_server::Resource,
+    utils::{Logical, Point},
+};
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Time a launched program has to open its first window to get placed
+const LAUNCH_PLACEMENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Maximum number of launches waiting for their first window
+const MAX_PENDING_LAUNCHES: usize = 32;
+
+/// Process generations searched for a launched ancestor of a client
+///
+/// Covers wrapper scripts and programs that re-exec or fork a helper
+/// before connecting.
+const MAX_ANCESTOR_DEPTH: usize = 8;
+
+/// A launched program waiting for its first window
+#[derive(Debug, Clone)]
+pub struct PendingLaunch {
+    /// Process id of the program
+    pub pid: u32,
+    /// Canvas point the launch was requested at
+    pub location: Point<f64, Logical>,
+    /// When the program was started
+    pub started: Instant,
+}
+
+impl LoomState {
+    /// Open the configured terminal
+    pub fn spawn_terminal(&mut self) {
+        let terminal = self.general_config.terminal.clone();
+        self.spawn_command(&terminal);
+    }
+
+    /// Open the configured launcher
+    ///
+    /// The launcher may be the desktop file id of an installed app rather
+    /// than a command line.
+    pub fn spawn_launcher(&mut self) {
+        let Some(launcher) = self.general_config.launcher.clone() else {
+            info!("No launcher configured");
+            return;
+        };
+
+        match self.apps.get(launcher.trim()) {
+            Some(app) => {
+                let id = app.id.clone();
+                self.launch_app(&id);
+            }
+            None => self.spawn_command(&launcher),
+        }
+    }
+
+    /// Open the configured AI prompt, unless AI features are disabled
+    pub fn open_ai_prompt(&mut self) {
+        if !self.ai_config.enabled {
+            info!("AI features are disabled");
+            return;
+        }
+        match self.ai_config.prompt_command.clone() {
+            Some(command) => self.spawn_command(&command),
+            None => info!("No AI prompt configured"),
+        }
+    }
+
+    /// Launch the installed app best matching free text, like the app of
+    /// an `Intent::Launch`, placing its window at a canvas point
+    ///
+    /// Returns the id of the launched app.
+    pub fn launch_query(&mut self, query: &str, location: Point<f64, Logical>) -> Option<String> {
+        let Some(app_id) = self.resolve_app(query) else {
+            info!("No installed app matches {:?}", query);
+            return None;
+        };
+
+        self.launch_app_at(&app_id, location);
+        Some(app_id)
+    }
+
+    /// Launch an application by its desktop file id, placing its window
+    /// under the pointer
+    pub fn launch_app(&mut self, app_id: &str) {
+        let location = self.global_to_canvas(self.pointer_location);
+        self.launch_app_at(app_id, location);
+    }
+
+    /// Launch an application by its desktop file id, placing its window at
+    /// a canvas point
+    pub fn launch_app_at(&mut self, app_id: &str, location: Point<f64, Logical>) {
+        if let Err(e) = security::validate_app_id(app_id) {
+            warn!("Not launching {:?}: {}", app_id, e);
+            return;
+        }
+
+        // Apps installed since the last scan are looked up directly
+        let found = match self.apps.get(app_id) {
+            Some(app) => Some((app.path.clone(), app.entry.clone())),
+            None => desktop_file_path(app_id).and_then(|path| {
+                let entry = parse_desktop_entry(&read_desktop_file(&path)?, &[])?;
+                Some((path, entry))
+            }),
+        };
+        let Some((path, entry)) = found else {
+            warn!("No launchable desktop entry for {}", app_id);
+            return;
+        };
+        let Some(argv) = exec_argv(&entry, &path) else {
+            warn!("Invalid Exec line in {:?}", path);
+            return;
+        };
+
+        self.spawn(&argv, entry.working_dir.as_deref(), location);
+    }
+
+    /// Run a script from the scripts directory
+    pub fn run_script(&mut self, script_name: &str) {
+        match security::get_script_path(script_name) {
+            Ok(path) => {
+                let location = self.global_to_canvas(self.pointer_location);
+                self.spawn(&[path.to_string_lossy().into_owned()], None, location);
+            }
+            Err(e) => warn!("Not running script {:?}: {}", script_name, e),
+        }
+    }
+
+    /// Run a command line, quoted like a desktop entry's `Exec` line
+    pub(crate) fn spawn_command(&mut self, command: &str) {
+        let Some(argv) = split_command(command) else {
+            warn!("Invalid or empty command {:?}, nothing to run", command);
+            return;
+        };
+        let location = self.global_to_canvas(self.pointer_location);
+        self.spawn(&argv, None, location);
+    }
+
+    /// Start a program connected to the compositor, detached from it
+    fn spawn(
+        &mut self,
+        argv: &[String],
+        working_dir: Option<&Path>,
+        location: Point<f64, Logical>,
+    ) {
+        let Some((program, args)) = argv.split_first() else {
+            return;
+        };
+
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        if let Some(socket_name) = &self.socket_name {
+            command.env("WAYLAND_DISPLAY", socket_name);
+        }
+        if let Some(dir) = working_dir.filter(|dir| dir.is_absolute() && dir.is_dir()) {
+            command.current_dir(dir);
+        }
+
+        match spawn_detached(command) {
+            Ok(pid) => {
+                debug!("Spawned {} (pid {})", program, pid);
+                self.record_launch(pid, location);
+            }
+            Err(e) => warn!("Failed to run {}: {}", program, e),
+        }
+    }
+
+    /// Remember where a launched program's first window goes
+    fn record_launch(&mut self, pid: u32, location: Point<f64, Logical>) {
+        let now = Instant::now();
+        self.pending_launches
+            .retain(|launch| now.duration_since(launch.started) < LAUNCH_PLACEMENT_TIMEOUT);
+        if self.pending_launches.len() >= MAX_PENDING_LAUNCHES {
+            self.pending_launches.remove(0);
+        }
+
+        self.pending_launches.push(PendingLaunch {
+            pid,
+            location,
+            started: now,
+        });
+    }
+
+    /// Canvas point requested for a new window by the launch that started
+    /// its client
+    ///
+    /// The launch is used up, so later windows of the program are placed
+    /// as usual.
+    pub fn take_launch_location(&mut self, window: &Window) -> Option<Point<f64, Logical>> {
+        let now = Instant::now();
+        self.pending_launches
+            .retain(|launch| now.duration_since(launch.started) < LAUNCH_PLACEMENT_TIMEOUT);
+        if self.pending_launches.is_empty() {
+            return None;
+        }
+
+        let client = window.toplevel()?.wl_surface().client()?;
+        let pid = u32::try_from(client.get_credentials(&self.display_handle).ok()?.pid).ok()?;
+
+        let index = std::iter::successors(Some(pid), |&pid| parent_pid(pid))
+            .take(MAX_ANCESTOR_DEPTH)
+            .find_map(|pid| {
+                self.pending_launches
+                    .iter()
+                    .position(|launch| launch.pid == pid)
+            })?;
+        Some(self.pending_launches.remove(index).location)
+    }
+}
+
+/// Run a command as a grandchild in a session of its own
+///
+/// The child forks again right away and exits, sending the grandchild's
+/// PID through a pipe; the grandchild calls `setsid` and execs the
+/// program. Waiting for the child reaps it, and the grandchild belongs to
+/// init (or the nearest subreaper) from then on. Exec failures in the
+/// grandchild are still reported, since it inherits the error pipe of
+/// [`Command::spawn`].
+fn spawn_detached(mut command: Command) -> io::Result<u32> {
+    let mut fds = [0; 2];
+    // SAFETY: `fds` has room for the two descriptors
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: both descriptors were just created and are owned here only
+    let (read_end, write_end) =
+        unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) };
+    let pid_fd = write_end.as_raw_fd();
+
+    // SAFETY: only async-signal-safe functions are called after the fork
+    unsafe {
+        command.pre_exec(move || {
+            match libc::fork() {
+                -1 => return Err(io::Error::last_os_error()),
+                0 => {}
+                pid => {
+                    let bytes = pid.to_ne_bytes();
+                    libc::write(pid_fd, bytes.as_ptr().cast(), bytes.len());
+                    libc::_exit(0);
+                }
+            }
+
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+
+    let mut child = command.spawn()?;
+    drop(write_end);
+    child.wait()?;
+
+    let mut bytes = [0; size_of::<libc::pid_t>()];
+    File::from(read_end).read_exact(&mut bytes)?;
+    u32::try_from(libc::pid_t::from_ne_bytes(bytes))
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid child pid"))
+}
+
+/// Parent of a process, from `/proc`
+fn parent_pid(pid: u32) -> Option<u32> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    stat_parent_pid(&stat).filter(|&ppid| ppid > 1)
+}
+
+/// Parent PID field of a `/proc/<pid>/stat` line
+///
+/// The command name before it is in parentheses and may contain spaces
+/// and parentheses itself, so fields are counted from the last `)`.
+fn stat_parent_pid(stat: &str) -> Option<u32> {
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Arguments of a command line, as it is split
+#[derive(Debug, Default)]
+struct Argv {
+    args: Vec<String>,
+    arg: String,
+    /// Whether an argument was started (quotes can make an empty one)
+    in_arg: bool,
+}
+
+impl Argv {
+    /// Append to the current argument
+    fn push(&mut self, text: &str) {
+        self.in_arg = true;
+        self.arg.push_str(text);
+    }
+
+    /// Finish the current argument, if one was started
+    fn end_arg(&mut self) {
+        if self.in_arg {
+            self.args.push(std::mem::take(&mut self.arg));
+            self.in_arg = false;
+        }
+    }
+}
+
+/// Expansion of a field code into the arguments, `None` if it is invalid
+type FieldCode<'a> = &'a dyn Fn(char, &mut Argv) -> Option<()>;
+
+/// Split a command line into arguments
+///
+/// Follows the quoting rules of the Desktop Entry Specification: arguments
+/// are separated by spaces, and double-quoted arguments may contain spaces
+/// and backslash-escaped `"`, `` ` ``, `$` and `\`. With `field_code`, a
+/// `%` outside quotes starts a field code it expands. Invalid or empty
+/// command lines yield `None`.
+fn split_args(line: &str, field_code: Option<FieldCode>) -> Option<Vec<String>> {
+    let mut argv = Argv::default();
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' | '\n' => argv.end_arg(),
+            '"' => {
+                argv.in_arg = true;
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => argv.arg.push(chars.next()?),
+                        c => argv.arg.push(c),
+                    }
+                }
+            }
+            '%' if let Some(field_code) = field_code => field_code(chars.next()?, &mut argv)?,
+            c => {
+                argv.in_arg = true;
+                argv.arg.push(c);
+            }
+        }
+    }
+    argv.end_arg();
+
+    (!argv.args.is_empty()).then_some(argv.args)
+}
+
+/// Arguments of a configured command line, quoted like an `Exec` line
+/// without field codes
+fn split_command(command: &str) -> Option<Vec<String>> {
+    split_args(command, None)
+}
+
+/// Arguments of a desktop entry's `Exec` command line
+///
+/// Quoting follows [`split_args`]. Field codes are expanded outside quotes:
+/// `%i` to `--icon <Icon>`, `%c` to the name, `%k` to the file's path and
+/// `%%` to `%`. File and URL codes (`%f`, `%U`, ...) expand to nothing,
+/// since nothing is opened with the application, as do the deprecated
+/// ones. Invalid command lines yield `None`.
+fn exec_argv(entry: &DesktopEntry, file: &Path) -> Option<Vec<String>> {
+    let field_code = |code: char, argv: &mut Argv| {
+        match code {
+            '%' => argv.push("%"),
+            'c' => argv.push(entry.name.as_deref().unwrap_or_default()),
+            'k' => argv.push(&file.to_string_lossy()),
+            'i' => {
+                if let Some(icon) = &entry.icon {
+                    argv.end_arg();
+                    argv.args.extend(["--icon".to_string(), icon.clone()]);
+                }
+            }
+            'f' | 'F' | 'u' | 'U' | 'd' | 'D' | 'n' | 'N' | 'v' | 'm' => {}
+            _ => return None,
+        }
+        Some(())
+    };
+    split_args(&entry.exec, Some(&field_code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::headless::HeadlessBackend;
+    use crate::desktop::unescape_value;
+
+    fn argv(exec: &str) -> Option<Vec<String>> {
+        let entry = DesktopEntry {
+            name: Some("Files".to_string()),
+            icon: Some("folder".to_string()),
+            exec: unescape_value(exec),
+            ..Default::default()
+        };
+        exec_argv(&entry, Path::new("/usr/share/applications/files.desktop"))
+    }
+
+    #[test]
+    fn test_exec_field_codes() {
+        assert_eq!(
+            argv("files %i --title=%c %F").unwrap(),
+            ["files", "--icon", "folder", "--title=Files"]
+        );
+        assert_eq!(
+            argv("launch %k 100%%").unwrap(),
+            ["launch", "/usr/share/applications/files.desktop", "100%"]
+        );
+        assert_eq!(argv("files %x"), None);
+        assert_eq!(argv("%U"), None);
+    }
+
+    #[test]
+    fn test_exec_quoting() {
+        assert_eq!(
+            argv(r#""/opt/My App/bin/app" --flag "" "say \"hi\"""#).unwrap(),
+            ["/opt/My App/bin/app", "--flag", "", "say \"hi\""]
+        );
+        // The string escape of the backslash applies before quoting
+        assert_eq!(argv(r#"echo "a\\\\b" \s"#).unwrap(), ["echo", r"a\b"]);
+        // Field codes inside quotes are left alone
+        assert_eq!(argv(r#"app "%u""#).unwrap(), ["app", "%u"]);
+        // Unterminated quotes
+        assert_eq!(argv(r#"app "oops"#), None);
+    }
+
+    #[test]
+    fn test_split_command() {
+        assert_eq!(
+            split_command(r#"swaylock -f --image "/home/me/My Pictures/lock.png""#).unwrap(),
+            ["swaylock", "-f", "--image", "/home/me/My Pictures/lock.png"]
+        );
+        // No field codes in configured commands
+        assert_eq!(
+            split_command("notify-send 100% %u").unwrap(),
+            ["notify-send", "100%", "%u"]
+        );
+        assert_eq!(split_command("  "), None);
+        assert_eq!(split_command(r#"app "oops"#), None);
+    }
+
+    #[test]
+    fn test_stat_parent_pid() {
+        let stat = "1234 (my (weird) prog) S 42 1234 1234 0 -1 4194560";
+        assert_eq!(stat_parent_pid(stat), Some(42));
+        assert_eq!(stat_parent_pid("garbage"), None);
+
+        let own = std::fs::read_to_string("/proc/self/stat").unwrap();
+        assert_eq!(
+            stat_parent_pid(&own),
+            Some(std::os::unix::process::parent_id())
+        );
+    }
+
+    #[test]
+    fn test_spawn_detached() {
+        let pid = spawn_detached(Command::new("true")).unwrap();
+        assert!(pid > 1);
+        assert_ne!(pid, std::process::id());
+
+        assert!(spawn_detached(Command::new("/nonexistent/program")).is_err());
+    }
+
+    #[test]
+    fn test_open_ai_prompt() {
+        let dir = std::env::temp_dir().join(format!("loom-ai-prompt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let disabled = dir.join("disabled");
+        let opened = dir.join("opened");
+
+        let mut backend = HeadlessBackend::for_tests(64, 48);
+        let state = backend.state_mut();
+        state.ai_config.enabled = false;
+        state.ai_config.prompt_command = Some(format!("touch {}", disabled.display()));
+        state.open_ai_prompt();
+
+        state.ai_config.enabled = true;
+        state.ai_config.prompt_command = Some(format!("touch {}", opened.display()));
+        state.open_ai_prompt();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !opened.exists() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(opened.exists());
+        assert!(!disabled.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
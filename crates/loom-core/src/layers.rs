@@ -0,0 +1,516 @@
+//! Layer shell surfaces (panels, launchers, wallpapers)
+//!
+//! Clients like waybar, fuzzel or swaybg put surfaces on one of the four
+//! layers of an output through `zwlr_layer_shell_v1`. Layer surfaces live
+//! in screen space: they are arranged per output by Smithay's
+//! [`LayerMap`](smithay::desktop::LayerMap) and drawn at the output's
+//! scale, whatever the view's pan and zoom. The background and bottom
+//! layers are drawn below the canvas, the top and overlay layers above it;
+//! a fullscreen window covers all but the overlay layer.
+//!
+//! Exclusive zones (a panel's strip) shrink the usable area of their
+//! output: new nodes are placed, revealed and moved between outputs within
+//! it (see [`LoomState::usable_canvas_rect`]), while the canvas itself is
+//! still drawn below the panels.
+//!
+//! Keyboard interactivity follows the protocol: surfaces with `none` never
+//! get the keyboard focus, `on_demand` ones get it when clicked, and an
+//! `exclusive` surface on the top or overlay layer (a launcher, a locker)
+//! takes it while mapped, windows getting it back when it goes away.
+//!
+//! Layers are arranged again whenever the outputs change (see
+//! [`LoomState::update_views`]).
+
+use crate::focus::focus_history;
+use crate::security::MAX_LAYER_SURFACES;
+use crate::state::LoomState;
+use crate::views::OutputView;
+use smithay::{
+    desktop::{
+        LayerSurface, PopupKind, WindowSurfaceType, get_popup_toplevel_coords, layer_map_for_output,
+    },
+    output::Output,
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Logical, Point, Rectangle, SERIAL_COUNTER, Serial},
+    wayland::{
+        compositor::with_states,
+        shell::{
+            wlr_layer::{
+                KeyboardInteractivity, Layer, LayerSurface as WlrLayerSurface, LayerSurfaceData,
+            },
+            xdg::PopupSurface,
+        },
+    },
+};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Layers drawn above the canvas, front first
+pub const UPPER_LAYERS: [Layer; 2] = [Layer::Overlay, Layer::Top];
+
+/// Layers drawn below the canvas, front first
+pub const LOWER_LAYERS: [Layer; 2] = [Layer::Bottom, Layer::Background];
+
+impl LoomState {
+    /// Place a new layer surface on an output
+    ///
+    /// Without an output requested, the surface goes to the output under
+    /// the pointer. Surfaces beyond the limit, or without any output, are
+    /// closed.
+    pub fn add_layer_surface(
+        &mut self,
+        surface: WlrLayerSurface,
+        output: Option<Output>,
+        namespace: String,
+    ) {
+        let output = output
+            .filter(|output| self.space.output_geometry(output).is_some())
+            .or_else(|| self.output_at(self.pointer_location))
+            .or_else(|| self.space.outputs().next().cloned());
+        let Some(output) = output else {
+            debug!("No output for layer surface {:?}", namespace);
+            surface.send_close();
+            return;
+        };
+
+        if self.layer_surface_count() >= MAX_LAYER_SURFACES {
+            warn!(
+                "Closing layer surface {:?}: max layer surfaces ({}) reached",
+                namespace, MAX_LAYER_SURFACES
+            );
+            surface.send_close();
+            return;
+        }
+
+        debug!("Layer surface {:?} on output {}", namespace, output.name());
+        let layer = LayerSurface::new(surface, namespace);
+        if let Err(e) = layer_map_for_output(&output).map_layer(&layer) {
+            warn!("Failed to map layer surface: {}", e);
+        }
+    }
+
+    /// Stop showing a destroyed layer surface
+    ///
+    /// If it had the keyboard focus, the focus goes back to the most
+    /// recently focused window.
+    pub fn remove_layer_surface(&mut self, surface: &WlrLayerSurface) {
+        let had_focus = self
+            .seat
+            .get_keyboard()
+            .and_then(|keyboard| keyboard.current_focus())
+            .is_some_and(|focus| &focus == surface.wl_surface());
+
+        for output in self.space.outputs() {
+            let mut map = layer_map_for_output(output);
+            let layer = map
+                .layers()
+                .find(|layer| layer.layer_surface() == surface)
+                .cloned();
+            if let Some(layer) = layer {
+                map.unmap_layer(&layer);
+            }
+        }
+
+        if had_focus {
+            self.refocus_window();
+        }
+        self.refresh_layer_focus();
+    }
+
+    /// Handle a commit on a layer surface
+    ///
+    /// Arranges the surface's output, and sends the initial configure,
+    /// which clients wait for before attaching a buffer.
+    pub fn layer_commit(&mut self, surface: &WlSurface) {
+        let Some(output) = self.layer_output(surface) else {
+            return;
+        };
+
+        {
+            let mut map = layer_map_for_output(&output);
+            map.arrange();
+
+            let initial_configure_sent = with_states(surface, |states| {
+                states
+                    .data_map
+                    .get::<LayerSurfaceData>()
+                    .and_then(|data| data.lock().ok().map(|data| data.initial_configure_sent))
+                    .unwrap_or(true)
+            });
+            if !initial_configure_sent
+                && let Some(layer) = map.layer_for_surface(surface, WindowSurfaceType::TOPLEVEL)
+            {
+                layer.layer_surface().send_configure();
+            }
+        }
+
+        self.refresh_layer_focus();
+    }
+
+    /// Arrange the layer surfaces of every output, after outputs changed
+    pub fn arrange_layers(&self) {
+        for output in self.space.outputs() {
+            layer_map_for_output(output).arrange();
+        }
+    }
+
+    /// Close the layer surfaces of an output that is going away
+    ///
+    /// Clients usually create them again on the remaining outputs.
+    pub fn close_layer_surfaces(&mut self, output: &Output) {
+        let layers: Vec<LayerSurface> = layer_map_for_output(output).layers().cloned().collect();
+        for layer in layers {
+            layer.layer_surface().send_close();
+            self.remove_layer_surface(layer.layer_surface());
+        }
+    }
+
+    /// Send frame callbacks to the layer surfaces of an output, after it
+    /// was drawn
+    pub fn send_layer_frames<T: Into<Duration> + Copy>(&self, output: &Output, time: T) {
+        for layer in layer_map_for_output(output).layers() {
+            layer.send_frame(output, time, Some(Duration::ZERO), |_, _| {
+                Some(output.clone())
+            });
+        }
+    }
+
+    /// Drop layer surfaces whose client went away without destroying them
+    pub fn cleanup_layers(&self) {
+        for output in self.space.outputs() {
+            layer_map_for_output(output).cleanup();
+        }
+    }
+
+    /// Output showing a layer surface
+    pub fn layer_output(&self, surface: &WlSurface) -> Option<Output> {
+        self.space
+            .outputs()
+            .find(|output| {
+                layer_map_for_output(output)
+                    .layer_for_surface(surface, WindowSurfaceType::TOPLEVEL)
+                    .is_some()
+            })
+            .cloned()
+    }
+
+    /// Layer surface of a surface, or of one of its popups
+    pub fn layer_for_surface(&self, surface: &WlSurface) -> Option<LayerSurface> {
+        self.space.outputs().find_map(|output| {
+            layer_map_for_output(output)
+                .layer_for_surface(surface, WindowSurfaceType::ALL)
+                .cloned()
+        })
+    }
+
+    /// Topmost layer surface on one of `layers` at a global point
+    pub fn layer_under(
+        &self,
+        point: Point<f64, Logical>,
+        layers: &[Layer],
+    ) -> Option<LayerSurface> {
+        let output = self.output_at(point)?;
+        let output_loc = self.space.output_geometry(&output)?.loc;
+        let map = layer_map_for_output(&output);
+        let local = point - output_loc.to_f64();
+
+        layers.iter().find_map(|&layer| {
+            map.layers_on(layer).rev().find_map(|surface| {
+                let origin = map.layer_geometry(surface)?.loc.to_f64();
+                surface
+                    .surface_under(local - origin, WindowSurfaceType::ALL)
+                    .map(|_| surface.clone())
+            })
+        })
+    }
+
+    /// Topmost surface of a layer surface on one of `layers` at a global
+    /// point, with its origin in canvas coordinates
+    ///
+    /// Like fullscreen windows, layer surfaces aren't zoomed, so the origin
+    /// is chosen such that the seat's `location - origin` is the offset
+    /// from the surface (see [`LoomState::surface_under`]).
+    pub fn layer_surface_under(
+        &self,
+        point: Point<f64, Logical>,
+        layers: &[Layer],
+    ) -> Option<(WlSurface, Point<f64, Logical>)> {
+        let output = self.output_at(point)?;
+        let output_loc = self.space.output_geometry(&output)?.loc.to_f64();
+        let map = layer_map_for_output(&output);
+
+        layers.iter().find_map(|&layer| {
+            map.layers_on(layer).rev().find_map(|surface| {
+                let origin = output_loc + map.layer_geometry(surface)?.loc.to_f64();
+                surface
+                    .surface_under(point - origin, WindowSurfaceType::ALL)
+                    .map(|(wl_surface, location)| {
+                        let local = point - (origin + location.to_f64());
+                        (wl_surface, self.global_to_canvas(point) - local)
+                    })
+            })
+        })
+    }
+
+    /// Usable area of an output, outside the exclusive zones of its layer
+    /// surfaces, in global coordinates
+    pub fn usable_area(&self, output: &Output) -> Option<Rectangle<i32, Logical>> {
+        let geometry = self.space.output_geometry(output)?;
+        let mut zone = layer_map_for_output(output).non_exclusive_zone();
+        zone.loc += geometry.loc;
+        Some(zone)
+    }
+
+    /// Usable area of a view's outputs, in global coordinates
+    pub fn usable_view_area(&self, view: &OutputView) -> Rectangle<i32, Logical> {
+        view.outputs
+            .iter()
+            .filter_map(|(output, _)| self.usable_area(output))
+            .filter(|area| !area.is_empty())
+            .reduce(|acc, area| acc.merge(area))
+            .unwrap_or(view.geometry)
+    }
+
+    /// Usable part of the canvas shown by a view, outside the exclusive
+    /// zones of its outputs' layer surfaces, in canvas coordinates
+    pub fn usable_canvas_rect(&self, view: &OutputView) -> Rectangle<f64, Logical> {
+        self.global_rect_to_canvas(view, self.usable_view_area(view))
+    }
+
+    /// Layers in front of the windows at a global point: the top and
+    /// overlay layers, or only the overlay layer over a fullscreen window
+    pub fn covering_layers(&self, point: Point<f64, Logical>) -> &'static [Layer] {
+        let fullscreen = self
+            .output_at(point)
+            .and_then(|output| self.fullscreen_window_on(&output));
+        if fullscreen.is_some() {
+            &UPPER_LAYERS[..1]
+        } else {
+            &UPPER_LAYERS
+        }
+    }
+
+    /// Whether a layer surface in front of the windows takes input at a
+    /// global point
+    pub fn layer_covers(&self, point: Point<f64, Logical>) -> bool {
+        self.layer_under(point, self.covering_layers(point))
+            .is_some()
+    }
+
+    /// Focus the layer surface in front of the windows at a global point,
+    /// if any, returning whether there was one
+    ///
+    /// Input there goes to the layer surface rather than to the canvas.
+    pub fn focus_layer_under(&mut self, point: Point<f64, Logical>, serial: Serial) -> bool {
+        let Some(layer) = self.layer_under(point, self.covering_layers(point)) else {
+            return false;
+        };
+        self.focus_layer(&layer, serial);
+        true
+    }
+
+    /// Offset from the center of a view to the center of its usable area,
+    /// in canvas units
+    pub fn usable_center_offset(&self, view: &OutputView) -> Point<f64, Logical> {
+        let center = |rect: Rectangle<f64, Logical>| rect.loc + rect.size.to_point().downscale(2.0);
+        center(self.usable_canvas_rect(view)) - center(self.view_canvas_rect(view))
+    }
+
+    /// Give the keyboard focus to a clicked layer surface, if it accepts it
+    pub fn focus_layer(&mut self, layer: &LayerSurface, serial: Serial) {
+        if !layer.can_receive_keyboard_focus()
+            || self.session_locked
+            || self.exclusive_layer().is_some()
+        {
+            return;
+        }
+        if let Some(keyboard) = self.seat.get_keyboard() {
+            keyboard.set_focus(self, Some(layer.wl_surface().clone()), serial);
+        }
+    }
+
+    /// Topmost layer surface taking the keyboard exclusively
+    pub fn exclusive_layer(&self) -> Option<LayerSurface> {
+        UPPER_LAYERS.iter().find_map(|&layer| {
+            self.space.outputs().find_map(|output| {
+                layer_map_for_output(output)
+                    .layers_on(layer)
+                    .rev()
+                    .find(|surface| {
+                        surface.cached_state().keyboard_interactivity
+                            == KeyboardInteractivity::Exclusive
+                    })
+                    .cloned()
+            })
+        })
+    }
+
+    /// Keep the keyboard focus in line with layer surface interactivity
+    ///
+    /// An exclusive layer surface takes the focus; a focused layer surface
+    /// that no longer accepts it gives it back to the windows. Lock surfaces
+    /// keep the focus while the session is locked.
+    pub fn refresh_layer_focus(&mut self) {
+        if self.session_locked {
+            return;
+        }
+        let Some(keyboard) = self.seat.get_keyboard() else {
+            return;
+        };
+        let focus = keyboard.current_focus();
+
+        if let Some(layer) = self.exclusive_layer() {
+            // Its own popups may have the focus
+            let focused = focus
+                .as_ref()
+                .and_then(|surface| self.layer_for_surface(surface))
+                .is_some_and(|focused| focused == layer);
+            if !focused {
+                let serial = SERIAL_COUNTER.next_serial();
+                keyboard.set_focus(self, Some(layer.wl_surface().clone()), serial);
+            }
+            return;
+        }
+
+        let refused = focus
+            .and_then(|surface| self.layer_for_surface(&surface))
+            .is_some_and(|layer| !layer.can_receive_keyboard_focus());
+        if refused {
+            self.refocus_window();
+        }
+    }
+
+    /// Constrain a layer surface popup's pending geometry to its output
+    pub fn unconstrain_layer_popup(&self, popup: &PopupSurface, root: &WlSurface) {
+        let Some(output) = self.layer_output(root) else {
+            return;
+        };
+        let Some(output_geometry) = self.space.output_geometry(&output) else {
+            return;
+        };
+
+        let layer_loc = {
+            let map = layer_map_for_output(&output);
+            let Some(layer) = map.layer_for_surface(root, WindowSurfaceType::TOPLEVEL) else {
+                return;
+            };
+            let Some(geometry) = map.layer_geometry(layer) else {
+                return;
+            };
+            geometry.loc
+        };
+
+        // Relative to the popup's parent
+        let mut target = Rectangle::from_size(output_geometry.size);
+        target.loc -= layer_loc;
+        target.loc -= get_popup_toplevel_coords(&PopupKind::Xdg(popup.clone()));
+
+        popup.with_pending_state(|state| {
+            state.geometry = state.positioner.get_unconstrained_geometry(target);
+        });
+    }
+
+    /// Give the keyboard focus back to the most recently focused window
+    pub(crate) fn refocus_window(&mut self) {
+        let front = focus_history(&self.seat).front();
+        self.raise_and_focus(front, SERIAL_COUNTER.next_serial());
+    }
+
+    /// Number of layer surfaces on all outputs
+    fn layer_surface_count(&self) -> usize {
+        self.space
+            .outputs()
+            .map(|output| layer_map_for_output(output).layers().count())
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::{headless::HeadlessBackend, test_client::TestClient};
+    use smithay::reexports::wayland_server::Resource;
+    use wayland_client::{Proxy, protocol::wl_compositor::WlCompositor};
+    use wayland_protocols_wlr::layer_shell::v1::client::{
+        zwlr_layer_shell_v1::{Layer, ZwlrLayerShellV1},
+        zwlr_layer_surface_v1::{Anchor, KeyboardInteractivity},
+    };
+
+    #[test]
+    fn test_usable_area_without_panels() {
+        let mut backend = HeadlessBackend::for_tests(64, 48);
+        let state = backend.state_mut();
+
+        let view = &state.views[0];
+        assert_eq!(state.usable_view_area(view), view.geometry);
+        assert_eq!(state.usable_canvas_rect(view), state.view_canvas_rect(view));
+        assert_eq!(state.usable_center_offset(view), (0.0, 0.0).into());
+        assert!(!state.layer_covers((32.0, 24.0).into()));
+        assert!(state.exclusive_layer().is_none());
+    }
+
+    #[test]
+    fn test_exclusive_panel() {
+        let mut backend = HeadlessBackend::for_tests(640, 480);
+        let view_geometry = backend.state().views[0].geometry;
+        let canvas_rect = {
+            let state = backend.state();
+            state.usable_canvas_rect(&state.views[0])
+        };
+
+        // A panel along the top edge, taking the keyboard
+        let mut client = TestClient::connect(&mut backend);
+        let compositor: WlCompositor = client.bind(4);
+        let layer_shell: ZwlrLayerShellV1 = client.bind(4);
+        let surface = compositor.create_surface(&client.handle(), ());
+        let panel = layer_shell.get_layer_surface(
+            &surface,
+            None,
+            Layer::Top,
+            "panel".into(),
+            &client.handle(),
+            (),
+        );
+        panel.set_anchor(Anchor::Top | Anchor::Left | Anchor::Right);
+        panel.set_size(0, 10);
+        panel.set_exclusive_zone(10);
+        panel.set_keyboard_interactivity(KeyboardInteractivity::Exclusive);
+        surface.commit();
+        client.roundtrip(&mut backend).unwrap();
+
+        let (serial, width, height) = client.data.layer_configure.unwrap();
+        assert_eq!((width, height), (640, 10));
+        panel.ack_configure(serial);
+        surface.commit();
+        client.roundtrip(&mut backend).unwrap();
+
+        let state = backend.state();
+        let view = &state.views[0];
+        let mut usable = view_geometry;
+        usable.loc.y += 10;
+        usable.size.h -= 10;
+        assert_eq!(state.usable_view_area(view), usable);
+        let canvas = state.usable_canvas_rect(view);
+        assert_eq!(canvas.loc.y, canvas_rect.loc.y + 10.0);
+        assert_eq!(canvas.size.h, canvas_rect.size.h - 10.0);
+
+        let layer = state.exclusive_layer().unwrap();
+        assert_eq!(
+            layer.wl_surface().id().protocol_id(),
+            surface.id().protocol_id()
+        );
+        let focus = state.seat.get_keyboard().unwrap().current_focus();
+        assert_eq!(focus.as_ref(), Some(layer.wl_surface()));
+
+        // Everything is back once it goes away
+        panel.destroy();
+        surface.destroy();
+        client.roundtrip(&mut backend).unwrap();
+        let state = backend.state();
+        let view = &state.views[0];
+        assert_eq!(state.usable_view_area(view), view_geometry);
+        assert_eq!(state.usable_canvas_rect(view), canvas_rect);
+        assert!(state.exclusive_layer().is_none());
+        assert!(state.seat.get_keyboard().unwrap().current_focus().is_none());
+        assert!(!client.data.layer_closed);
+    }
+}
@@ -10,6 +10,9 @@
 //!
 //! This crate follows security-by-default principles:
 //! - Resource limits prevent DoS attacks (see [`security`] module)
+//! - Screen capture is limited to allowlisted clients (see [`capture`] module)
+//! - A locked session shows and sends input to the locker only (see [`lock`]
+//!   module)
 //! - No unsafe code without explicit safety documentation
 //! - Input validation at all system boundaries
 //!
@@ -21,13 +24,30 @@
 //! - Frame timing and stutter detection (see [`perf`] module)
 
 pub mod backend;
+pub mod capture;
 pub mod compositor;
+pub mod decorations;
+pub mod desktop;
+pub mod dnd;
+pub mod focus;
+pub mod fullscreen;
+pub mod grabs;
 mod handlers;
+pub mod idle;
 pub mod input;
+pub mod launcher;
+pub mod layers;
+pub mod lock;
+pub mod navigation;
+pub mod nodes;
+pub mod outputs;
 pub mod perf;
+pub mod popups;
+pub mod render;
 pub mod security;
 pub mod state;
 pub mod types;
+pub mod views;
 
 pub use compositor::Compositor;
 pub use perf::FrameTimer;
@@ -50,7 +70,7 @@ pub enum CoreError {
     #[error("Input error: {0}")]
     Input(String),
 
-    #[error("No backend available - compile with 'drm' or 'winit' feature")]
+    #[error("No backend available - compile with 'drm', 'winit' or 'headless' feature")]
     NoBackendAvailable,
 
     #[error("Event loop error: {0}")]
@@ -0,0 +1,370 @@
+//! Session lock
+//!
+//! Screen lockers like swaylock lock the session through
+//! `ext_session_lock_v1`. From the moment a lock is requested, outputs only
+//! show the locker's surface for them, or nothing until it has one (see
+//! [`crate::render`]), and only lock surfaces get the keyboard, pointer and
+//! touch focus; compositor bindings are limited to those allowed when
+//! locked (see [`crate::input::Keybindings`]). The locker is told the
+//! session is locked once every output was drawn that way.
+//!
+//! Only the locker unlocks the session. If it goes away while locked
+//! (crashes, is killed), the session stays locked with blank outputs
+//! until another locker takes over the lock, typically run by a binding
+//! allowed when locked. Other lock requests are refused.
+
+use crate::state::LoomState;
+use smithay::{
+    desktop::{
+        WindowSurfaceType,
+        utils::{send_frames_surface_tree, under_from_surface_tree},
+    },
+    input::pointer::CursorImageStatus,
+    output::{Output, WeakOutput},
+    reexports::{
+        wayland_protocols::ext::session_lock::v1::server::ext_session_lock_v1::ExtSessionLockV1,
+        wayland_server::{Resource, protocol::wl_surface::WlSurface},
+    },
+    utils::{Logical, Point, SERIAL_COUNTER, Serial},
+    wayland::session_lock::{LockSurface, SessionLocker},
+};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Lock of the session and the locker's surfaces
+#[derive(Debug, Default)]
+pub struct LockState {
+    /// Lock of the current locker
+    lock: Option<ExtSessionLockV1>,
+    /// Confirmation sent once every output was drawn locked
+    pending: Option<SessionLocker>,
+    /// Outputs drawn locked while the confirmation is pending
+    drawn: Vec<WeakOutput>,
+    /// Lock surfaces of the current locker, with their output
+    surfaces: Vec<(WeakOutput, LockSurface)>,
+}
+
+impl LockState {
+    /// Whether a session is locked by a lock, and was told so
+    pub fn is_locked_by(&self, lock: &ExtSessionLockV1) -> bool {
+        self.lock.as_ref() == Some(lock) && self.pending.is_none()
+    }
+
+    /// Lock surface shown on an output
+    pub fn surface_for(&self, output: &Output) -> Option<&WlSurface> {
+        self.surfaces
+            .iter()
+            .find(|(surface_output, surface)| surface_output == output && surface.alive())
+            .map(|(_, surface)| surface.wl_surface())
+    }
+}
+
+impl LoomState {
+    /// Lock the session for a locker
+    ///
+    /// Refused while another locker holds the lock.
+    pub fn lock_session(&mut self, locker: SessionLocker) {
+        let lock = locker.ext_session_lock().clone();
+        if self
+            .lock
+            .lock
+            .as_ref()
+            .is_some_and(|current| current.is_alive())
+        {
+            warn!("Refusing session lock: the session is already locked");
+            return;
+        }
+
+        info!("Session locked");
+        let relock = self.session_locked;
+        self.session_locked = true;
+        self.lock = LockState {
+            lock: Some(lock),
+            pending: Some(locker),
+            ..Default::default()
+        };
+        if !relock {
+            self.clear_focus_for_lock();
+        }
+        self.queue_redraw();
+
+        // Nothing to wait for without outputs showing anything
+        if self.space.outputs().next().is_none() || self.idle.outputs_off() {
+            self.confirm_lock();
+        }
+    }
+
+    /// Unlock the session, at the locker's request
+    pub fn unlock_session(&mut self) {
+        info!("Session unlocked");
+        self.session_locked = false;
+        self.lock = LockState::default();
+        self.queue_redraw();
+
+        self.refocus_window();
+        self.refresh_layer_focus();
+        self.refresh_pointer_focus();
+    }
+
+    /// Show a surface of the locker on an output
+    ///
+    /// Surfaces of other clients, from refused locks, are ignored.
+    pub fn add_lock_surface(&mut self, surface: LockSurface, output: Option<Output>) {
+        let locker = self.lock.lock.as_ref().and_then(Resource::client);
+        if locker.is_none() || surface.wl_surface().client() != locker {
+            debug!("Ignoring lock surface of a refused lock");
+            return;
+        }
+        let Some(output) = output else {
+            return;
+        };
+
+        // Smithay sends the initial configure
+        self.size_lock_surface(&surface, &output);
+        output.enter(surface.wl_surface());
+        self.lock
+            .surfaces
+            .retain(|(surface_output, _)| surface_output != &output);
+        self.lock
+            .surfaces
+            .push((output.downgrade(), surface.clone()));
+
+        // The first lock surface takes the keyboard, then the one clicked
+        let focused = self
+            .seat
+            .get_keyboard()
+            .and_then(|keyboard| keyboard.current_focus())
+            .is_some_and(|focus| self.is_lock_surface(&focus));
+        if !focused || self.output_at(self.pointer_location).as_ref() == Some(&output) {
+            self.focus_lock_surface(surface.wl_surface().clone(), SERIAL_COUNTER.next_serial());
+        }
+        self.refresh_pointer_focus();
+    }
+
+    /// Size the lock surfaces to their outputs again, after outputs changed
+    pub fn configure_lock_surfaces(&mut self) {
+        self.lock
+            .surfaces
+            .retain(|(output, surface)| output.upgrade().is_some() && surface.alive());
+        for (output, surface) in &self.lock.surfaces {
+            if let Some(output) = output.upgrade()
+                && self.size_lock_surface(surface, &output)
+            {
+                surface.send_configure();
+            }
+        }
+    }
+
+    /// Topmost surface of the lock surface at a global point, with its
+    /// origin in canvas coordinates (see [`LoomState::layer_surface_under`])
+    pub fn lock_surface_under(
+        &self,
+        point: Point<f64, Logical>,
+    ) -> Option<(WlSurface, Point<f64, Logical>)> {
+        let output = self.output_at(point)?;
+        let origin = self.space.output_geometry(&output)?.loc.to_f64();
+        let surface = self.lock.surface_for(&output)?;
+
+        under_from_surface_tree(surface, point - origin, (0, 0), WindowSurfaceType::ALL).map(
+            |(wl_surface, location)| {
+                let local = point - (origin + location.to_f64());
+                (wl_surface, self.global_to_canvas(point) - local)
+            },
+        )
+    }
+
+    /// Give the keyboard to the lock surface of the output at a global
+    /// point, if any
+    pub fn focus_lock_surface_under(&mut self, point: Point<f64, Logical>, serial: Serial) {
+        let surface = self
+            .output_at(point)
+            .and_then(|output| self.lock.surface_for(&output).cloned());
+        if let Some(surface) = surface {
+            self.focus_lock_surface(surface, serial);
+        }
+    }
+
+    /// Send frame callbacks to the lock surface of an output, after it was
+    /// drawn
+    ///
+    /// Tells a waiting locker the session is locked once every output was
+    /// drawn locked.
+    pub fn send_lock_frames<T: Into<Duration> + Copy>(&mut self, output: &Output, time: T) {
+        if !self.session_locked {
+            return;
+        }
+
+        if let Some(surface) = self.lock.surface_for(output) {
+            send_frames_surface_tree(surface, output, time, Some(Duration::ZERO), |_, _| {
+                Some(output.clone())
+            });
+        }
+
+        if self.lock.pending.is_some() {
+            if !self.lock.drawn.iter().any(|drawn| drawn == output) {
+                self.lock.drawn.push(output.downgrade());
+            }
+            let all_drawn = self
+                .space
+                .outputs()
+                .all(|output| self.lock.drawn.iter().any(|drawn| drawn == output));
+            if all_drawn {
+                self.confirm_lock();
+            }
+        }
+    }
+
+    /// Whether a surface belongs to a lock surface
+    fn is_lock_surface(&self, surface: &WlSurface) -> bool {
+        self.lock
+            .surfaces
+            .iter()
+            .any(|(_, lock_surface)| lock_surface.wl_surface() == surface)
+    }
+
+    /// Tell the locker the session is locked
+    fn confirm_lock(&mut self) {
+        if let Some(locker) = self.lock.pending.take() {
+            debug!("Session lock confirmed");
+            locker.lock();
+        }
+        self.lock.drawn.clear();
+    }
+
+    /// Take the focus and grabs away from the windows and layer surfaces
+    fn clear_focus_for_lock(&mut self) {
+        let serial = SERIAL_COUNTER.next_serial();
+        if let Some(keyboard) = self.seat.get_keyboard() {
+            keyboard.unset_grab(self);
+            keyboard.set_focus(self, None, serial);
+        }
+        if let Some(pointer) = self.seat.get_pointer() {
+            let time = Duration::from(self.clock.now()).as_millis() as u32;
+            pointer.unset_grab(self, serial, time);
+        }
+        self.stop_key_repeat();
+        self.cursor_status = CursorImageStatus::default_named();
+        self.refresh_pointer_focus();
+    }
+
+    /// Give the keyboard to a lock surface
+    fn focus_lock_surface(&mut self, surface: WlSurface, serial: Serial) {
+        if let Some(keyboard) = self.seat.get_keyboard() {
+            keyboard.set_focus(self, Some(surface), serial);
+        }
+    }
+
+    /// Size a lock surface to its output, returning whether its size
+    /// changed
+    fn size_lock_surface(&self, surface: &LockSurface, output: &Output) -> bool {
+        let Some(geometry) = self.space.output_geometry(output) else {
+            return false;
+        };
+        let size = Some((geometry.size.w as u32, geometry.size.h as u32).into());
+        surface.with_pending_state(|state| {
+            let changed = state.size != size;
+            state.size = size;
+            changed
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::{headless::HeadlessBackend, test_client::TestClient};
+    use crate::render;
+    use smithay::backend::renderer::pixman::PixmanRenderer;
+    use wayland_client::protocol::{
+        wl_compositor::WlCompositor, wl_output::WlOutput, wl_shm::WlShm,
+    };
+    use wayland_protocols::ext::session_lock::v1::client::{
+        ext_session_lock_manager_v1::ExtSessionLockManagerV1, ext_session_lock_v1,
+    };
+
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+    const RED: [u8; 4] = [255, 0, 0, 255];
+
+    /// Whether a rendered output shows a color anywhere
+    fn shows(backend: &mut HeadlessBackend, color: [u8; 4]) -> bool {
+        backend.render().unwrap();
+        let frame = backend.frame(0).unwrap();
+        frame.data.chunks_exact(4).any(|pixel| pixel == color)
+    }
+
+    #[test]
+    fn test_locked_output_elements() {
+        let mut backend = HeadlessBackend::for_tests(320, 240);
+        let output = backend.outputs().next().unwrap().clone();
+        let mut renderer = PixmanRenderer::new().unwrap();
+
+        let mut app = TestClient::connect(&mut backend);
+        let shm: WlShm = app.bind(1);
+        let window = app.shm_buffer(&shm, 100, 80, 0xff00ff00);
+        let _toplevel = app.map_toplevel(&mut backend, &window);
+        assert!(shows(&mut backend, GREEN));
+
+        // Nothing is drawn until the locker has a surface, which is then
+        // all there is
+        let mut locker = TestClient::connect(&mut backend);
+        let manager: ExtSessionLockManagerV1 = locker.bind(1);
+        let lock = manager.lock(&locker.handle(), ());
+        locker.roundtrip(&mut backend).unwrap();
+        assert!(backend.state().session_locked);
+        assert!(render::output_elements(&mut renderer, &output, backend.state()).is_empty());
+        assert!(!shows(&mut backend, GREEN));
+        locker.roundtrip(&mut backend).unwrap();
+        assert!(locker.data.locked);
+
+        let compositor: WlCompositor = locker.bind(4);
+        let wl_output: WlOutput = locker.bind(4);
+        let shm: WlShm = locker.bind(1);
+        let surface = compositor.create_surface(&locker.handle(), ());
+        let lock_surface = lock.get_lock_surface(&surface, &wl_output, &locker.handle(), ());
+        locker.roundtrip(&mut backend).unwrap();
+        let (serial, width, height) = locker.data.lock_configure.unwrap();
+        assert_eq!((width, height), (320, 240));
+        let buffer = locker.shm_buffer(&shm, width, height, 0xffff0000);
+        lock_surface.ack_configure(serial);
+        surface.attach(Some(&buffer.buffer), 0, 0);
+        surface.commit();
+        locker.roundtrip(&mut backend).unwrap();
+
+        let elements = render::output_elements(&mut renderer, &output, backend.state());
+        assert_eq!(elements.len(), 1);
+        assert!(shows(&mut backend, RED));
+        assert!(!shows(&mut backend, GREEN));
+
+        lock.unlock_and_destroy();
+        locker.roundtrip(&mut backend).unwrap();
+        assert!(!backend.state().session_locked);
+        assert!(shows(&mut backend, GREEN));
+        assert!(!shows(&mut backend, RED));
+    }
+
+    #[test]
+    fn test_refused_lock_cannot_unlock() {
+        let mut backend = HeadlessBackend::for_tests(320, 240);
+        let mut locker = TestClient::connect(&mut backend);
+        let manager: ExtSessionLockManagerV1 = locker.bind(1);
+        let _lock = manager.lock(&locker.handle(), ());
+        locker.roundtrip(&mut backend).unwrap();
+        backend.render().unwrap();
+        locker.roundtrip(&mut backend).unwrap();
+        assert!(locker.data.locked);
+
+        // A second lock is refused, and can't unlock the first
+        let mut intruder = TestClient::connect(&mut backend);
+        let manager: ExtSessionLockManagerV1 = intruder.bind(1);
+        let lock = manager.lock(&intruder.handle(), ());
+        intruder.roundtrip(&mut backend).unwrap();
+        assert!(intruder.data.lock_finished);
+        assert!(!intruder.data.locked);
+
+        lock.unlock_and_destroy();
+        assert!(intruder.roundtrip(&mut backend).is_err());
+        let error = intruder.protocol_error().unwrap();
+        assert_eq!(error.code, ext_session_lock_v1::Error::InvalidUnlock as u32);
+        assert!(backend.state().session_locked);
+        assert!(!locker.data.lock_finished);
+    }
+}
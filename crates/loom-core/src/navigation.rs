@@ -0,0 +1,224 @@
+//! Canvas navigation (pan and zoom)
+//!
+//! The view is changed by moving the [`Viewport`](loom_canvas::Viewport)
+//! of the output under the pointer; nodes never move. After every change
+//! the space is re-synced from the canvas and the pointer focus is
+//! refreshed, since a different surface may now be under the cursor.
+//!
+//! Touchpad scrolls glide on after the fingers lift (see
+//! [`KineticPan`]), driven by a timer on the event loop.
+
+use crate::input::{Direction, ZoomDirection};
+use crate::state::LoomState;
+use loom_canvas::KineticPan;
+use smithay::{
+    input::pointer::MotionEvent,
+    reexports::calloop::{
+        RegistrationToken,
+        timer::{TimeoutAction, Timer},
+    },
+    utils::{Logical, Point, SERIAL_COUNTER},
+};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// Interval between kinetic pan steps
+const KINETIC_TICK: Duration = Duration::from_millis(8);
+
+/// Scroll amount of one wheel notch, for wheels without v120 data
+const WHEEL_STEP: f64 = 15.0;
+
+/// Distance panned by a pan keybinding, in screen pixels
+const KEY_PAN_STEP: f64 = 100.0;
+
+/// Navigation state
+#[derive(Debug, Default)]
+pub struct NavigationState {
+    /// Inertial panning after touchpad scrolls
+    pub kinetic: KineticPan,
+    /// Timer driving the kinetic pan, while gliding
+    kinetic_timer: Option<RegistrationToken>,
+    /// Target of the current finger scroll sequence (`true` for the canvas)
+    ///
+    /// Latched on the first event so the sequence doesn't switch to a
+    /// client when a window slides under the cursor.
+    pub finger_scroll: Option<bool>,
+}
+
+/// Wheel steps from a scroll amount and optional v120 value
+pub fn wheel_steps(amount: f64, v120: Option<f64>) -> f64 {
+    v120.map_or(amount / WHEEL_STEP, |v120| v120 / 120.0)
+}
+
+impl LoomState {
+    /// Pan the view under the pointer so canvas content moves by
+    /// `(dx, dy)` screen pixels
+    pub fn pan_view(&mut self, dx: f64, dy: f64) {
+        self.pan_view_at(self.pointer_location, dx, dy);
+    }
+
+    /// Pan the view under a global point
+    pub fn pan_view_at(&mut self, point: Point<f64, Logical>, dx: f64, dy: f64) {
+        if dx == 0.0 && dy == 0.0 {
+            return;
+        }
+        let Some(id) = self.view_at(point).map(|view| view.viewport) else {
+            return;
+        };
+
+        // Moving the viewport right moves the content left
+        if let Some(viewport) = self.canvas.viewport_mut(id) {
+            viewport.pan(-dx, -dy);
+        }
+        self.sync_space_to_canvas();
+    }
+
+    /// Zoom the view under a global point by `factor`, keeping the point
+    /// fixed on screen
+    pub fn zoom_view_at(&mut self, factor: f64, point: Point<f64, Logical>) {
+        let Some(id) = self.view_at(point).map(|view| view.viewport) else {
+            return;
+        };
+
+        let anchor = self.global_to_canvas(point);
+        if let Some(viewport) = self.canvas.viewport_mut(id) {
+            viewport.zoom_at(factor, anchor.x, anchor.y);
+        }
+        self.sync_space_to_canvas();
+    }
+
+    /// Pan the view under the pointer one step in a direction
+    ///
+    /// The view moves in the direction, so the content moves the other way.
+    pub fn pan_view_step(&mut self, direction: Direction) {
+        let (dx, dy) = match direction {
+            Direction::Left => (KEY_PAN_STEP, 0.0),
+            Direction::Right => (-KEY_PAN_STEP, 0.0),
+            Direction::Up => (0.0, KEY_PAN_STEP),
+            Direction::Down => (0.0, -KEY_PAN_STEP),
+        };
+
+        self.stop_kinetic_pan();
+        self.pan_view(dx, dy);
+        self.refresh_pointer_focus();
+    }
+
+    /// Zoom the view under the pointer one step around the center of its
+    /// usable area
+    pub fn zoom_view_step(&mut self, direction: ZoomDirection) {
+        let Some(center) = self
+            .view_at(self.pointer_location)
+            .map(|view| self.usable_view_area(view).to_f64())
+            .map(|geometry| {
+                geometry.loc + Point::from((geometry.size.w / 2.0, geometry.size.h / 2.0))
+            })
+        else {
+            return;
+        };
+
+        let steps = match direction {
+            ZoomDirection::In => -1.0,
+            ZoomDirection::Out => 1.0,
+        };
+        let factor = self.wheel_zoom_factor(steps);
+        self.zoom_view_at(factor, center);
+        self.refresh_pointer_focus();
+    }
+
+    /// Center the view under the pointer on the canvas origin, at the
+    /// initial zoom level
+    pub fn reset_view(&mut self) {
+        let Some(id) = self
+            .view_at(self.pointer_location)
+            .map(|view| view.viewport)
+        else {
+            return;
+        };
+
+        self.stop_kinetic_pan();
+        let initial_zoom = self.canvas_config.initial_zoom;
+        if let Some(viewport) = self.canvas.viewport_mut(id) {
+            viewport.reset();
+            viewport.zoom_at(initial_zoom, 0.0, 0.0);
+        }
+        self.sync_space_to_canvas();
+        self.refresh_pointer_focus();
+    }
+
+    /// Zoom factor for a number of wheel steps (negative zooms in)
+    pub fn wheel_zoom_factor(&self, steps: f64) -> f64 {
+        let sensitivity = self.canvas_config.zoom_sensitivity.clamp(0.0, 1.0);
+        (1.0 + sensitivity).powf(-steps)
+    }
+
+    /// Start gliding after the fingers lifted, if they were moving
+    pub fn start_kinetic_pan(&mut self, time_msec: u32) {
+        if !self.canvas_config.kinetic_panning || !self.navigation.kinetic.release(time_msec) {
+            return;
+        }
+        if self.navigation.kinetic_timer.is_some() {
+            return;
+        }
+
+        let mut last_tick = Instant::now();
+        let timer = Timer::from_duration(KINETIC_TICK);
+        let result = self.loop_handle.insert_source(timer, move |_, _, state| {
+            let now = Instant::now();
+            let dt = now.duration_since(last_tick).as_secs_f64();
+            last_tick = now;
+
+            match state.navigation.kinetic.tick(dt) {
+                Some((dx, dy)) => {
+                    state.pan_view(dx, dy);
+                    state.refresh_pointer_focus();
+                    TimeoutAction::ToDuration(KINETIC_TICK)
+                }
+                None => {
+                    state.navigation.kinetic_timer = None;
+                    TimeoutAction::Drop
+                }
+            }
+        });
+
+        match result {
+            Ok(token) => self.navigation.kinetic_timer = Some(token),
+            Err(e) => {
+                warn!("Failed to start kinetic pan: {}", e);
+                self.navigation.kinetic.stop();
+            }
+        }
+    }
+
+    /// Stop any kinetic pan in progress
+    pub fn stop_kinetic_pan(&mut self) {
+        self.navigation.kinetic.stop();
+        if let Some(token) = self.navigation.kinetic_timer.take() {
+            self.loop_handle.remove(token);
+        }
+    }
+
+    /// Re-send the pointer position after the view changed
+    ///
+    /// The pointer didn't move on screen, but its canvas location did, and
+    /// a different surface may be under it now.
+    pub fn refresh_pointer_focus(&mut self) {
+        let Some(pointer) = self.seat.get_pointer() else {
+            return;
+        };
+
+        let under = self.surface_under(self.pointer_location);
+        let location = self.global_to_canvas(self.pointer_location);
+        let time = Duration::from(self.clock.now()).as_millis() as u32;
+
+        pointer.motion(
+            self,
+            under,
+            &MotionEvent {
+                location,
+                serial: SERIAL_COUNTER.next_serial(),
+                time,
+            },
+        );
+        pointer.frame(self);
+    }
+}
@@ -0,0 +1,524 @@
+//! Bridge between Smithay windows and canvas nodes
+//!
+//! Every toplevel becomes a [`Node`] on the [`loom_canvas::Canvas`]. The
+//! node owns the canvas geometry; the window's location in the Smithay
+//! [`Space`](smithay::desktop::Space) is derived from it through the
+//! [`Viewport`](loom_canvas::Viewport) transform and is never the source of
+//! truth.
+//!
+//! Windows are stored in a slotmap keyed by [`WindowId`]. The key doubles
+//! as the stable surface id of [`NodeType::Surface`], so a node can always
+//! be resolved back to its window in O(1).
+
+use crate::focus::focus_history;
+use crate::fullscreen::FullscreenState;
+use crate::grabs::ResizeState;
+use crate::layers::LOWER_LAYERS;
+use crate::security;
+use crate::state::LoomState;
+use crate::types::{WindowFlags, WindowId};
+use loom_canvas::{Node, NodeId, NodeType};
+use slotmap::{Key, KeyData};
+use smithay::{
+    desktop::{Window, WindowSurfaceType, space::SpaceElement},
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Logical, Point, Rectangle, SERIAL_COUNTER, Serial},
+    wayland::{
+        compositor::with_states,
+        foreign_toplevel_list::ForeignToplevelHandle,
+        shell::xdg::{ToplevelSurface, XdgToplevelSurfaceData},
+    },
+};
+use tracing::{debug, warn};
+
+/// Offset between successively placed nodes, in canvas units
+const CASCADE_STEP: f64 = 32.0;
+
+/// Number of cascade steps before wrapping back to the center
+const CASCADE_STEPS: usize = 8;
+
+/// A toplevel window managed as a canvas node
+#[derive(Debug)]
+pub struct ManagedWindow {
+    /// The Smithay window
+    pub window: Window,
+    /// The canvas node showing this window
+    pub node_id: NodeId,
+    /// Window state flags
+    pub flags: WindowFlags,
+    /// Interactive resize progress
+    pub resize_state: ResizeState,
+    /// Output filled by the window while fullscreen
+    pub fullscreen: Option<FullscreenState>,
+    /// The window in the foreign toplevel list, naming it for capture
+    pub foreign_toplevel: ForeignToplevelHandle,
+}
+
+/// Window id stored in the window's user data
+struct WindowIdData(WindowId);
+
+/// Stable surface id stored in [`NodeType::Surface`] for a window
+#[inline]
+pub fn surface_id(id: WindowId) -> u64 {
+    id.data().as_ffi()
+}
+
+/// Recover the window id from a [`NodeType::Surface`] surface id
+#[inline]
+pub fn window_id_from_surface_id(surface_id: u64) -> WindowId {
+    KeyData::from_ffi(surface_id).into()
+}
+
+/// Title and app id of a window's toplevel, if within the security limits
+fn toplevel_title_and_app_id(window: &Window) -> (Option<String>, Option<String>) {
+    let Some(toplevel) = window.toplevel() else {
+        return (None, None);
+    };
+    with_states(toplevel.wl_surface(), |states| {
+        states
+            .data_map
+            .get::<XdgToplevelSurfaceData>()
+            .and_then(|data| {
+                let data = data.lock().ok()?;
+                let title = data.title.clone().filter(|t| security::is_valid_title(t));
+                let app_id = data.app_id.clone().filter(|a| security::is_valid_app_id(a));
+                Some((title, app_id))
+            })
+            .unwrap_or_default()
+    })
+}
+
+impl LoomState {
+    /// Start managing a new toplevel window as a canvas node
+    ///
+    /// The node is placed around the center of the view under the pointer,
+    /// cascading so that consecutive windows don't fully overlap.
+    pub fn add_window_node(&mut self, window: Window) -> Option<WindowId> {
+        let node_id = match self.canvas.allocate_node_id() {
+            Ok(id) => id,
+            Err(e) => {
+                warn!("Cannot create node for window: {}", e);
+                return None;
+            }
+        };
+
+        // Where it was launched, or around the center of the view under
+        // the pointer, clear of panels
+        let (x, y) = match self.take_launch_location(&window) {
+            Some(location) => (location.x, location.y),
+            None => {
+                let cascade = (self.windows.len() % CASCADE_STEPS) as f64 * CASCADE_STEP;
+                let (x, y) = self
+                    .viewport_at(self.pointer_location)
+                    .map_or((0.0, 0.0), |viewport| (viewport.x, viewport.y));
+                let shift = self
+                    .view_at(self.pointer_location)
+                    .map_or(Point::from((0.0, 0.0)), |view| {
+                        self.usable_center_offset(view)
+                    });
+                (x + shift.x + cascade, y + shift.y + cascade)
+            }
+        };
+
+        let (title, app_id) = toplevel_title_and_app_id(&window);
+        let foreign_toplevel = self
+            .foreign_toplevel_list_state
+            .new_toplevel::<Self>(title.unwrap_or_default(), app_id.unwrap_or_default());
+        let id = self.windows.insert(ManagedWindow {
+            window: window.clone(),
+            node_id,
+            flags: WindowFlags::VISIBLE,
+            resize_state: ResizeState::Idle,
+            fullscreen: None,
+            foreign_toplevel: foreign_toplevel.clone(),
+        });
+        foreign_toplevel
+            .user_data()
+            .insert_if_missing_threadsafe(|| id);
+
+        let node = Node::new(
+            node_id,
+            NodeType::Surface {
+                surface_id: surface_id(id),
+            },
+            x,
+            y,
+        );
+
+        if let Err(e) = self.canvas.add_node(node) {
+            warn!("Cannot add node for window: {}", e);
+            self.windows.remove(id);
+            self.foreign_toplevel_list_state
+                .remove_toplevel(&foreign_toplevel);
+            return None;
+        }
+
+        window.user_data().insert_if_missing(|| WindowIdData(id));
+        focus_history(&self.seat).add(id);
+        let location = self.node_space_location(node_id);
+        self.space.map_element(window, location, true);
+
+        debug!("Window {:?} mapped as node {}", id, node_id);
+        Some(id)
+    }
+
+    /// Stop managing a toplevel window and remove its node
+    ///
+    /// If the window had the keyboard focus, it passes to the most
+    /// recently focused remaining window.
+    pub fn remove_window_node(&mut self, toplevel: &ToplevelSurface) {
+        let Some(id) = self.window_id_for_surface(toplevel.wl_surface()) else {
+            return;
+        };
+        let was_focused = self.focused_window() == Some(id);
+
+        if let Some(managed) = self.windows.remove(id) {
+            self.space.unmap_elem(&managed.window);
+            self.canvas.remove_node(managed.node_id);
+            self.foreign_toplevel_list_state
+                .remove_toplevel(&managed.foreign_toplevel);
+            debug!("Window {:?} (node {}) removed", id, managed.node_id);
+        }
+
+        let next = {
+            let mut history = focus_history(&self.seat);
+            history.remove(id);
+            history.front()
+        };
+        if was_focused {
+            self.raise_and_focus(next, SERIAL_COUNTER.next_serial());
+        }
+    }
+
+    /// Get the id of a managed window
+    pub fn window_id(&self, window: &Window) -> Option<WindowId> {
+        window
+            .user_data()
+            .get::<WindowIdData>()
+            .map(|data| data.0)
+            .filter(|id| self.windows.contains_key(*id))
+    }
+
+    /// Find the managed window whose toplevel has the given root surface
+    pub fn window_id_for_surface(&self, surface: &WlSurface) -> Option<WindowId> {
+        self.windows
+            .iter()
+            .find(|(_, managed)| {
+                managed
+                    .window
+                    .toplevel()
+                    .is_some_and(|t| t.wl_surface() == surface)
+            })
+            .map(|(id, _)| id)
+    }
+
+    /// Find the window shown by a canvas node
+    pub fn window_id_for_node(&self, node_id: NodeId) -> Option<WindowId> {
+        match self.canvas.get_node(node_id)?.node_type {
+            NodeType::Surface { surface_id } => {
+                let id = window_id_from_surface_id(surface_id);
+                self.windows.contains_key(id).then_some(id)
+            }
+            _ => None,
+        }
+    }
+
+    /// The window with keyboard focus
+    pub fn focused_window(&self) -> Option<WindowId> {
+        let surface = self.seat.get_keyboard()?.current_focus()?;
+        self.window_id_for_surface(&surface)
+    }
+
+    /// Raise a window and give it keyboard focus, or clear the focus
+    ///
+    /// Ends any focus cycle in progress.
+    pub fn focus_window(&mut self, id: Option<WindowId>, serial: Serial) {
+        self.end_focus_cycle();
+        self.raise_and_focus(id, serial);
+    }
+
+    /// Raise a window and give it keyboard focus, keeping the focus history
+    /// order while cycling
+    pub(crate) fn raise_and_focus(&mut self, id: Option<WindowId>, serial: Serial) {
+        let Some(keyboard) = self.seat.get_keyboard() else {
+            return;
+        };
+
+        // Lock surfaces, or an exclusive layer surface (a launcher), keep
+        // the keyboard until they go away
+        let exclusive = self.session_locked || self.exclusive_layer().is_some();
+        // Stacking and focus decorations change
+        self.queue_redraw();
+
+        let Some(window) = id
+            .and_then(|id| self.windows.get(id))
+            .map(|m| m.window.clone())
+        else {
+            if !exclusive {
+                keyboard.set_focus(self, None, serial);
+            }
+            return;
+        };
+
+        self.space.raise_element(&window, true);
+        if exclusive {
+            return;
+        }
+        if let Some(toplevel) = window.toplevel() {
+            keyboard.set_focus(self, Some(toplevel.wl_surface().clone()), serial);
+        }
+    }
+
+    /// Canvas geometry of a window's node
+    pub fn window_node_rect(&self, id: WindowId) -> Option<Rectangle<f64, Logical>> {
+        let node = self.canvas.get_node(self.windows.get(id)?.node_id)?;
+        Some(Rectangle::new(
+            (node.x, node.y).into(),
+            (node.width, node.height).into(),
+        ))
+    }
+
+    /// Move a window's node on the canvas and update its space location
+    pub fn move_window_node(&mut self, id: WindowId, x: f64, y: f64) {
+        let Some(managed) = self.windows.get(id) else {
+            return;
+        };
+
+        if let Err(e) = self.canvas.move_node(managed.node_id, x, y) {
+            warn!("Cannot move node {}: {}", managed.node_id, e);
+            return;
+        }
+
+        self.sync_window_location(id);
+    }
+
+    /// Update a window's node after a commit (size and label), and its
+    /// entry in the foreign toplevel list
+    pub fn sync_window_node(&mut self, id: WindowId) {
+        let Some(managed) = self.windows.get(id) else {
+            return;
+        };
+
+        let geometry = managed.window.geometry();
+        let (title, app_id) = toplevel_title_and_app_id(&managed.window);
+
+        let handle = &managed.foreign_toplevel;
+        let mut changed = false;
+        if let Some(title) = title.as_deref().filter(|t| *t != handle.title()) {
+            handle.send_title(title);
+            changed = true;
+        }
+        if let Some(app_id) = app_id.filter(|a| *a != handle.app_id()) {
+            handle.send_app_id(&app_id);
+            changed = true;
+        }
+        if changed {
+            handle.send_done();
+        }
+
+        let fullscreen = managed.fullscreen.is_some();
+        let Some(node) = self.canvas.get_node_mut(managed.node_id) else {
+            return;
+        };
+
+        // Fullscreen windows take the output's size, not the node's
+        if !fullscreen && geometry.size.w > 0 && geometry.size.h > 0 {
+            node.width = geometry.size.w as f64;
+            node.height = geometry.size.h as f64;
+        }
+
+        if let Some(title) = title {
+            node.label = Some(title);
+        }
+    }
+
+    /// Re-derive every window's space location from its node
+    ///
+    /// Must be called whenever a viewport changes (pan, zoom, resize).
+    pub fn sync_space_to_canvas(&mut self) {
+        let ids: Vec<_> = self.windows.keys().collect();
+        for id in ids {
+            self.sync_window_location(id);
+        }
+        self.queue_redraw();
+    }
+
+    /// Space location of a node, derived through the transform of the
+    /// view that shows it best
+    pub fn node_space_location(&self, node_id: NodeId) -> Point<i32, Logical> {
+        let Some(node) = self.canvas.get_node(node_id) else {
+            return Point::from((0, 0));
+        };
+
+        let rect = Rectangle::new((node.x, node.y).into(), (node.width, node.height).into());
+        let location = Point::from((node.x, node.y));
+        match self.node_view(rect) {
+            Some(view) => self.canvas_to_view(view, location).to_i32_round(),
+            None => location.to_i32_round(),
+        }
+    }
+
+    /// Canvas location of a window's surface tree origin
+    ///
+    /// The node position is the top-left corner of the window geometry, so
+    /// client-side decorations (shadows) start before it.
+    pub fn window_render_origin(&self, window: &Window) -> Option<Point<f64, Logical>> {
+        let id = self.window_id(window)?;
+        let node = self.canvas.get_node(self.windows[id].node_id)?;
+        Some(Point::from((node.x, node.y)) - window.geometry().loc.to_f64())
+    }
+
+    /// Find the topmost window with input at a global point
+    ///
+    /// A fullscreen window takes all input on its output. Panels and other
+    /// layer surfaces drawn in front hide the windows behind them.
+    pub fn window_under(&self, point: Point<f64, Logical>) -> Option<WindowId> {
+        if self.session_locked || self.layer_covers(point) {
+            return None;
+        }
+        if let Some((id, _)) = self.fullscreen_under(point) {
+            return Some(id);
+        }
+
+        let canvas_point = self.global_to_canvas(point);
+
+        self.space.elements().rev().find_map(|window| {
+            let origin = self.window_render_origin(window)?;
+            window
+                .is_in_input_region(&(canvas_point - origin))
+                .then(|| self.window_id(window))
+                .flatten()
+        })
+    }
+
+    /// Find the topmost surface with input at a global point
+    ///
+    /// Returns the surface and its origin in canvas coordinates. Pointer
+    /// locations are sent to the seat in canvas coordinates as well, so
+    /// Smithay's `location - origin` yields correct surface-local
+    /// coordinates at any zoom level. Node frames hide the surfaces behind
+    /// them. Layer surfaces come before or after the windows following
+    /// their layer.
+    ///
+    /// Fullscreen windows aren't zoomed, so their surface origin is chosen
+    /// such that `location - origin` is the unscaled offset from the
+    /// surface instead. While the session is locked, only lock surfaces
+    /// are found (see [`crate::lock`]).
+    pub fn surface_under(
+        &self,
+        point: Point<f64, Logical>,
+    ) -> Option<(WlSurface, Point<f64, Logical>)> {
+        if self.session_locked {
+            return self.lock_surface_under(point);
+        }
+
+        let canvas_point = self.global_to_canvas(point);
+
+        if let Some(under) = self.layer_surface_under(point, self.covering_layers(point)) {
+            return Some(under);
+        }
+
+        if let Some((id, origin)) = self.fullscreen_under(point) {
+            return self.windows[id]
+                .window
+                .surface_under(point - origin, WindowSurfaceType::ALL)
+                .map(|(surface, location)| {
+                    let local = point - (origin + location.to_f64());
+                    (surface, canvas_point - local)
+                });
+        }
+
+        for window in self.space.elements().rev() {
+            if let Some(origin) = self.window_render_origin(window)
+                && let Some((surface, location)) =
+                    window.surface_under(canvas_point - origin, WindowSurfaceType::ALL)
+            {
+                return Some((surface, origin + location.to_f64()));
+            }
+
+            // Frames hide the windows behind them
+            if self.frame_covers(window, canvas_point) {
+                return None;
+            }
+        }
+        self.layer_surface_under(point, &LOWER_LAYERS)
+    }
+
+    /// Fullscreen window on the output under a global point, with the
+    /// global location of its surface tree origin
+    fn fullscreen_under(
+        &self,
+        point: Point<f64, Logical>,
+    ) -> Option<(WindowId, Point<f64, Logical>)> {
+        let output = self.output_at(point)?;
+        let id = self.fullscreen_window_on(&output)?;
+        let geometry = self.space.output_geometry(&output)?;
+        let window = &self.windows[id].window;
+        Some((id, (geometry.loc - window.geometry().loc).to_f64()))
+    }
+
+    /// Update the space location of a single window from its node, or
+    /// from its output while fullscreen
+    fn sync_window_location(&mut self, id: WindowId) {
+        let Some(managed) = self.windows.get(id) else {
+            return;
+        };
+
+        let location = match self.fullscreen_geometry(id) {
+            Some(geometry) => geometry.loc,
+            None => self.node_space_location(managed.node_id),
+        };
+        if self.space.element_location(&managed.window) != Some(location) {
+            self.space
+                .map_element(managed.window.clone(), location, false);
+            self.queue_redraw();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::{headless::HeadlessBackend, test_client::TestClient};
+    use smithay::{reexports::wayland_server::Resource, utils::Point};
+    use wayland_client::{Proxy, protocol::wl_shm::WlShm};
+
+    #[test]
+    fn test_surface_under_zoomed_and_panned() {
+        let mut backend = HeadlessBackend::for_tests(640, 480);
+        let mut client = TestClient::connect(&mut backend);
+        let shm: WlShm = client.bind(1);
+        let buffer = client.shm_buffer(&shm, 100, 80, 0xff00ff00);
+        let (surface, _, _) = client.map_toplevel(&mut backend, &buffer);
+
+        let state = backend.state_mut();
+        let window = state.windows.values().next().unwrap().window.clone();
+        let before = state.space.element_location(&window).unwrap();
+        state.zoom_view_at(2.0, before.to_f64());
+        state.pan_view_at(before.to_f64(), 15.0, 10.0);
+
+        // Twice as large, and moved along with the view
+        let location = state.space.element_location(&window).unwrap();
+        assert_eq!(location, before + Point::from((15, 10)));
+        let location = location.to_f64();
+
+        for (offset, local) in [
+            ((20.0, 40.0), (10.0, 20.0)),
+            ((190.0, 150.0), (95.0, 75.0)),
+            ((1.0, 159.0), (0.5, 79.5)),
+        ] {
+            let point = location + Point::from(offset);
+            let (under, origin) = state.surface_under(point).unwrap();
+            assert_eq!(under.id().protocol_id(), surface.id().protocol_id());
+            assert_eq!(state.global_to_canvas(point) - origin, Point::from(local));
+        }
+
+        // Just outside the zoomed window
+        for offset in [(-4.0, -4.0), (210.0, 10.0), (10.0, 170.0)] {
+            assert!(
+                state
+                    .surface_under(location + Point::from(offset))
+                    .is_none()
+            );
+        }
+    }
+}
@@ -0,0 +1,483 @@
+//! Output layout and hotplug
+//!
+//! Outputs are placed at their configured position, or else laid out left
+//! to right, and each shows the canvas through its view (see
+//! [`crate::views`]). Backends place new outputs at
+//! [`LoomState::next_output_position`] and hand removed ones to
+//! [`LoomState::remove_output`], which:
+//!
+//! - closes the gap in the layout, announcing the new positions through
+//!   wl_output/xdg_output
+//! - keeps the remaining outputs showing the same part of the canvas
+//! - moves nodes that were only visible on the removed output into view,
+//!   at the same relative position
+//!
+//! Internal displays are taken out of the layout the same way while the
+//! lid is closed, if so configured (see [`crate::input::Switches`]).
+//!
+//! The pointer moves freely between outputs but never leaves the layout,
+//! and keybindings move the focus or the focused node to the view of a
+//! neighbouring output.
+
+use crate::input::Direction;
+use crate::state::LoomState;
+use crate::types::WindowId;
+use smithay::{
+    output::Output,
+    utils::{Logical, Point, Rectangle, SERIAL_COUNTER},
+};
+use tracing::debug;
+
+/// Map a node's position from one canvas area to another
+///
+/// The node's center keeps its relative position, and the node is kept
+/// inside `to` as far as its size allows (oversized nodes are aligned to
+/// its corner).
+fn map_node_location(
+    node: Rectangle<f64, Logical>,
+    from: Rectangle<f64, Logical>,
+    to: Rectangle<f64, Logical>,
+) -> Point<f64, Logical> {
+    let center = node.loc + Point::from((node.size.w / 2.0, node.size.h / 2.0));
+    let relative_x = (center.x - from.loc.x) / from.size.w;
+    let relative_y = (center.y - from.loc.y) / from.size.h;
+    let x = to.loc.x + relative_x * to.size.w - node.size.w / 2.0;
+    let y = to.loc.y + relative_y * to.size.h - node.size.h / 2.0;
+
+    let max_x = (to.loc.x + to.size.w - node.size.w).max(to.loc.x);
+    let max_y = (to.loc.y + to.size.h - node.size.h).max(to.loc.y);
+
+    Point::from((x.clamp(to.loc.x, max_x), y.clamp(to.loc.y, max_y)))
+}
+
+/// New location for a node that was shown on a removed output
+///
+/// All rectangles are in canvas coordinates. Returns `None` if the node
+/// doesn't need to move: its center wasn't on the removed output, or it is
+/// still partly visible. Otherwise the node's center keeps its relative
+/// position, mapped from the removed area onto the visible one, and the
+/// node is kept inside the visible area as far as its size allows.
+pub fn relocated_node_location(
+    node: Rectangle<f64, Logical>,
+    removed: Rectangle<f64, Logical>,
+    visible: Rectangle<f64, Logical>,
+) -> Option<Point<f64, Logical>> {
+    let center = node.loc + Point::from((node.size.w / 2.0, node.size.h / 2.0));
+    if !removed.contains(center) || node.overlaps(visible) {
+        return None;
+    }
+
+    Some(map_node_location(node, removed, visible))
+}
+
+/// Keep a global point inside the output layout
+///
+/// Points between or beside outputs of different sizes are moved to the
+/// nearest point of the nearest output, so the pointer slides along the
+/// layout's edges instead of getting lost in the gaps.
+pub fn clamp_to_layout(
+    outputs: &[Rectangle<i32, Logical>],
+    point: Point<f64, Logical>,
+) -> Point<f64, Logical> {
+    outputs
+        .iter()
+        .map(|geometry| {
+            let geometry = geometry.to_f64();
+            let max_x = geometry.loc.x + (geometry.size.w - 1.0).max(0.0);
+            let max_y = geometry.loc.y + (geometry.size.h - 1.0).max(0.0);
+            Point::from((
+                point.x.clamp(geometry.loc.x, max_x),
+                point.y.clamp(geometry.loc.y, max_y),
+            ))
+        })
+        .min_by(|a: &Point<f64, Logical>, b: &Point<f64, Logical>| {
+            let da = (a.x - point.x).hypot(a.y - point.y);
+            let db = (b.x - point.x).hypot(b.y - point.y);
+            da.total_cmp(&db)
+        })
+        .unwrap_or(point)
+}
+
+/// Connector types of built-in laptop displays
+const INTERNAL_CONNECTORS: [&str; 3] = ["eDP-", "LVDS-", "DSI-"];
+
+/// Whether an output is a built-in display, from its connector name
+pub fn is_internal_output(name: &str) -> bool {
+    INTERNAL_CONNECTORS
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
+/// Index of the output next to `from` in a direction
+///
+/// Only outputs entirely on that side are candidates; the one whose
+/// center is closest wins.
+pub fn output_in_direction(
+    from: Rectangle<i32, Logical>,
+    outputs: &[Rectangle<i32, Logical>],
+    direction: Direction,
+) -> Option<usize> {
+    let center = |rect: &Rectangle<i32, Logical>| {
+        rect.to_f64().loc + Point::from((rect.size.w as f64 / 2.0, rect.size.h as f64 / 2.0))
+    };
+    let from_center = center(&from);
+
+    outputs
+        .iter()
+        .enumerate()
+        .filter(|(_, rect)| match direction {
+            Direction::Left => rect.loc.x + rect.size.w <= from.loc.x,
+            Direction::Right => rect.loc.x >= from.loc.x + from.size.w,
+            Direction::Up => rect.loc.y + rect.size.h <= from.loc.y,
+            Direction::Down => rect.loc.y >= from.loc.y + from.size.h,
+        })
+        .min_by(|(_, a), (_, b)| {
+            let (a, b) = (center(a), center(b));
+            let da = (a.x - from_center.x).hypot(a.y - from_center.y);
+            let db = (b.x - from_center.x).hypot(b.y - from_center.y);
+            da.total_cmp(&db)
+        })
+        .map(|(index, _)| index)
+}
+
+impl LoomState {
+    /// Layout position for a new output
+    ///
+    /// The configured position if there is one, otherwise right of the
+    /// existing outputs.
+    pub fn next_output_position(&self, name: &str) -> Point<i32, Logical> {
+        if let Some(position) = self.configured_output_position(name) {
+            return position;
+        }
+
+        let x = self
+            .space
+            .outputs()
+            .filter_map(|output| self.space.output_geometry(output))
+            .map(|geometry| geometry.loc.x + geometry.size.w)
+            .max()
+            .unwrap_or(0);
+
+        Point::from((x, 0))
+    }
+
+    /// Remove an output from the layout
+    ///
+    /// The backend is responsible for the output's global.
+    pub fn remove_output(&mut self, output: &Output) {
+        let Some(geometry) = self.space.output_geometry(output) else {
+            return;
+        };
+        let removed = self
+            .view_for_output(output)
+            .map(|view| self.global_rect_to_canvas(view, geometry));
+        self.close_layer_surfaces(output);
+        self.space.unmap_output(output);
+
+        if self.space.outputs().next().is_none() {
+            debug!("Last output removed");
+            return;
+        }
+
+        // Views keep their canvas region while the layout closes the gap
+        self.arrange_outputs();
+        self.update_views();
+
+        if let Some(removed) = removed {
+            self.relocate_nodes(removed);
+        }
+        self.keep_pointer_on_outputs();
+    }
+
+    /// Global geometries of all outputs
+    pub fn output_geometries(&self) -> Vec<(Output, Rectangle<i32, Logical>)> {
+        self.space
+            .outputs()
+            .filter_map(|output| Some((output.clone(), self.space.output_geometry(output)?)))
+            .collect()
+    }
+
+    /// Output under a global point
+    pub fn output_at(&self, point: Point<f64, Logical>) -> Option<Output> {
+        self.output_geometries()
+            .into_iter()
+            .find(|(_, geometry)| geometry.to_f64().contains(point))
+            .map(|(output, _)| output)
+    }
+
+    /// Keep the pointer inside the output layout
+    pub fn clamp_pointer_to_outputs(&mut self) {
+        let outputs: Vec<_> = self
+            .output_geometries()
+            .into_iter()
+            .map(|(_, geometry)| geometry)
+            .collect();
+        self.pointer_location = clamp_to_layout(&outputs, self.pointer_location);
+    }
+
+    /// Move the pointer and keyboard focus to the neighbouring output
+    ///
+    /// The pointer is warped to the output's center and the topmost node
+    /// under it, if any, gets the keyboard focus.
+    pub fn focus_output(&mut self, direction: Direction) {
+        let Some((_, target)) = self.neighbouring_output(direction) else {
+            return;
+        };
+
+        self.pointer_location = target.to_f64().loc
+            + Point::from((target.size.w as f64 / 2.0, target.size.h as f64 / 2.0));
+
+        let under = self.window_under(self.pointer_location);
+        self.focus_window(under, SERIAL_COUNTER.next_serial());
+        self.refresh_pointer_focus();
+    }
+
+    /// Move the focused node into the view of the neighbouring output
+    ///
+    /// The node keeps its relative position in the view, and the pointer
+    /// follows it so it stays under the pointer's output.
+    pub fn move_focused_to_output(&mut self, direction: Direction) {
+        let Some(id) = self.focused_window() else {
+            return;
+        };
+        let Some(rect) = self.window_node_rect(id) else {
+            return;
+        };
+        let Some((target_output, target)) = self.neighbouring_output(direction) else {
+            return;
+        };
+
+        let Some(from) = self
+            .node_view(rect)
+            .map(|view| self.usable_canvas_rect(view))
+        else {
+            return;
+        };
+        let usable = self.usable_area(&target_output).unwrap_or(target);
+        let Some(to) = self
+            .view_for_output(&target_output)
+            .map(|view| self.global_rect_to_canvas(view, usable))
+        else {
+            return;
+        };
+
+        let location = map_node_location(rect, from, to);
+        debug!("Moving window {:?} to output {}", id, target_output.name());
+        self.move_window_node(id, location.x, location.y);
+
+        self.pointer_location = target.to_f64().loc
+            + Point::from((target.size.w as f64 / 2.0, target.size.h as f64 / 2.0));
+        self.refresh_pointer_focus();
+    }
+
+    /// Output next to the pointer's output in a direction
+    fn neighbouring_output(
+        &self,
+        direction: Direction,
+    ) -> Option<(Output, Rectangle<i32, Logical>)> {
+        let outputs = self.output_geometries();
+        let from = outputs
+            .iter()
+            .find(|(_, geometry)| geometry.to_f64().contains(self.pointer_location))?
+            .1;
+
+        let rects: Vec<_> = outputs.iter().map(|(_, geometry)| *geometry).collect();
+        let index = output_in_direction(from, &rects, direction)?;
+        outputs.into_iter().nth(index)
+    }
+
+    /// Configured layout position of an output
+    fn configured_output_position(&self, name: &str) -> Option<Point<i32, Logical>> {
+        self.output_config
+            .iter()
+            .find(|config| config.name == name)
+            .map(|config| Point::from(config.position))
+    }
+
+    /// Lay the outputs out without gaps, keeping their order
+    ///
+    /// Outputs with a configured position stay there; the others follow
+    /// left to right, after the configured ones.
+    fn arrange_outputs(&mut self) {
+        let mut outputs = self.output_geometries();
+        outputs.sort_by_key(|(_, geometry)| (geometry.loc.x, geometry.loc.y));
+
+        let mut x = outputs
+            .iter()
+            .filter(|(output, _)| self.configured_output_position(&output.name()).is_some())
+            .map(|(_, geometry)| geometry.loc.x + geometry.size.w)
+            .max()
+            .unwrap_or(0);
+
+        for (output, geometry) in outputs {
+            if self.configured_output_position(&output.name()).is_some() {
+                continue;
+            }
+
+            let position = Point::from((x, 0));
+            if geometry.loc != position {
+                output.change_current_state(None, None, None, Some(position));
+                self.space.map_output(&output, position);
+            }
+            x += geometry.size.w;
+        }
+    }
+
+    /// Move nodes that were only visible in `removed` into view
+    ///
+    /// Nodes still visible in any view stay; the others go to the view of
+    /// the first remaining output.
+    fn relocate_nodes(&mut self, removed: Rectangle<f64, Logical>) {
+        let visible: Vec<_> = self
+            .views
+            .iter()
+            .map(|view| self.view_canvas_rect(view))
+            .collect();
+        let Some(target) = self.views.first().map(|view| self.usable_canvas_rect(view)) else {
+            return;
+        };
+
+        let moves: Vec<(WindowId, Point<f64, Logical>)> = self
+            .windows
+            .keys()
+            .filter_map(|id| {
+                let rect = self.window_node_rect(id)?;
+                if visible.iter().any(|visible| rect.overlaps(*visible)) {
+                    return None;
+                }
+                relocated_node_location(rect, removed, target).map(|location| (id, location))
+            })
+            .collect();
+
+        for (id, location) in moves {
+            debug!("Relocating window {:?} from removed output", id);
+            self.move_window_node(id, location.x, location.y);
+        }
+    }
+
+    /// Bring the pointer back onto an output if it was on a removed one
+    fn keep_pointer_on_outputs(&mut self) {
+        if self.output_at(self.pointer_location).is_some() {
+            return;
+        }
+
+        if let Some((_, geometry)) = self.output_geometries().first() {
+            self.pointer_location = geometry.to_f64().loc
+                + Point::from((geometry.size.w as f64 / 2.0, geometry.size.h as f64 / 2.0));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: f64, y: f64, w: f64, h: f64) -> Rectangle<f64, Logical> {
+        Rectangle::new((x, y).into(), (w, h).into())
+    }
+
+    #[test]
+    fn test_node_on_removed_output_moves_into_view() {
+        // Two 1000x800 outputs side by side, the right one unplugged
+        let removed = rect(1000.0, 0.0, 1000.0, 800.0);
+        let visible = rect(0.0, 0.0, 1000.0, 800.0);
+
+        let node = rect(1700.0, 300.0, 200.0, 200.0);
+        let location = relocated_node_location(node, removed, visible);
+        assert_eq!(location, Some((700.0, 300.0).into()));
+    }
+
+    #[test]
+    fn test_visible_node_stays() {
+        let removed = rect(1000.0, 0.0, 1000.0, 800.0);
+        let visible = rect(0.0, 0.0, 1000.0, 800.0);
+
+        // Center on the removed output but still partly visible
+        let node = rect(900.0, 100.0, 400.0, 300.0);
+        assert_eq!(relocated_node_location(node, removed, visible), None);
+
+        // Somewhere else on the canvas entirely
+        let node = rect(-3000.0, 100.0, 400.0, 300.0);
+        assert_eq!(relocated_node_location(node, removed, visible), None);
+    }
+
+    #[test]
+    fn test_relocated_node_kept_inside() {
+        let removed = rect(1000.0, 0.0, 2000.0, 800.0);
+        let visible = rect(0.0, 0.0, 1000.0, 800.0);
+
+        // Near the far edge of a wider output
+        let node = rect(2800.0, 650.0, 180.0, 140.0);
+        let location = relocated_node_location(node, removed, visible).unwrap();
+        assert_eq!(location, (820.0, 650.0).into());
+
+        // Larger than the visible area: aligned to its corner
+        let node = rect(1100.0, 0.0, 1800.0, 800.0);
+        let location = relocated_node_location(node, removed, visible).unwrap();
+        assert_eq!(location, (0.0, 0.0).into());
+    }
+    fn output(x: i32, y: i32, w: i32, h: i32) -> Rectangle<i32, Logical> {
+        Rectangle::new((x, y).into(), (w, h).into())
+    }
+
+    #[test]
+    fn test_pointer_clamped_to_layout() {
+        // A tall output left of a short one, both top-aligned
+        let outputs = [output(0, 0, 1000, 1200), output(1000, 0, 800, 600)];
+
+        // Inside an output: unchanged
+        let point = Point::from((1500.0, 300.0));
+        assert_eq!(clamp_to_layout(&outputs, point), point);
+
+        // Below the short output: onto its bottom edge, not into the gap
+        let point = Point::from((1500.0, 900.0));
+        assert_eq!(clamp_to_layout(&outputs, point), (1500.0, 599.0).into());
+
+        // Past the right edge
+        let point = Point::from((2500.0, 100.0));
+        assert_eq!(clamp_to_layout(&outputs, point), (1799.0, 100.0).into());
+
+        // Crossing to the left output works as usual
+        let point = Point::from((990.0, 1100.0));
+        assert_eq!(clamp_to_layout(&outputs, point), point);
+    }
+
+    #[test]
+    fn test_output_in_direction() {
+        // Two outputs side by side and one below the left one
+        let outputs = [
+            output(0, 0, 1000, 800),
+            output(1000, 0, 1000, 800),
+            output(0, 800, 1000, 800),
+        ];
+
+        assert_eq!(
+            output_in_direction(outputs[0], &outputs, Direction::Right),
+            Some(1)
+        );
+        assert_eq!(
+            output_in_direction(outputs[1], &outputs, Direction::Left),
+            Some(0)
+        );
+        assert_eq!(
+            output_in_direction(outputs[0], &outputs, Direction::Down),
+            Some(2)
+        );
+        assert_eq!(
+            output_in_direction(outputs[2], &outputs, Direction::Up),
+            Some(0)
+        );
+        assert_eq!(
+            output_in_direction(outputs[0], &outputs, Direction::Left),
+            None
+        );
+    }
+
+    #[test]
+    fn test_internal_outputs() {
+        assert!(is_internal_output("eDP-1"));
+        assert!(is_internal_output("LVDS-1"));
+        assert!(!is_internal_output("DP-1"));
+        assert!(!is_internal_output("HDMI-A-1"));
+    }
+}
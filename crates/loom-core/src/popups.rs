@@ -0,0 +1,388 @@
+//! XDG popups (menus, tooltips)
+//!
+//! Popups are tracked by Smithay's [`PopupManager`] and drawn as part of
+//! their toplevel's surface tree, so they follow the node through the
+//! viewport transform. They are placed inside the part of the canvas
+//! shown by their node's view using the positioner's constraint
+//! adjustments (flip, slide, resize). Popups of layer surfaces (panel
+//! menus) stay on screen and are placed inside their output instead.
+//!
+//! Explicit popup grabs route keyboard and pointer input to the topmost
+//! popup of the chain; clicking outside the chain dismisses it.
+
+use crate::state::LoomState;
+use smithay::{
+    desktop::{
+        PopupKeyboardGrab, PopupKind, PopupPointerGrab, PopupUngrabStrategy,
+        find_popup_root_surface, get_popup_toplevel_coords,
+    },
+    input::{Seat, pointer::Focus},
+    reexports::wayland_server::protocol::wl_surface::WlSurface,
+    utils::{Logical, Point, Rectangle, Serial},
+    wayland::shell::xdg::{PopupSurface, PositionerState},
+};
+use tracing::{debug, warn};
+
+/// Constraint rectangle for a popup, relative to its parent's geometry
+///
+/// `visible` and `node_location` are in canvas coordinates and
+/// `parent_offset` is the offset of the popup's parent chain from the
+/// toplevel's window geometry. Canvas units are surface-local pixels, so
+/// the result is valid at any zoom level.
+pub fn popup_constraint_target(
+    visible: Rectangle<f64, Logical>,
+    node_location: Point<f64, Logical>,
+    parent_offset: Point<i32, Logical>,
+) -> Rectangle<i32, Logical> {
+    let mut target = Rectangle::new(visible.loc - node_location, visible.size).to_i32_round();
+    target.loc -= parent_offset;
+    target
+}
+
+impl LoomState {
+    /// Start tracking a new popup, placed inside the visible area
+    pub fn track_popup(&mut self, popup: PopupSurface) {
+        self.unconstrain_popup(&popup);
+
+        if let Err(e) = self.popups.track_popup(PopupKind::Xdg(popup)) {
+            warn!("Failed to track popup: {}", e);
+        }
+    }
+
+    /// Apply a new positioner on the client's request
+    ///
+    /// The token is echoed back in `xdg_popup.repositioned`, followed by a
+    /// configure with the new geometry, so the client can match the two.
+    pub fn reposition_popup(
+        &mut self,
+        popup: PopupSurface,
+        positioner: PositionerState,
+        token: u32,
+    ) {
+        popup.with_pending_state(|state| {
+            state.geometry = positioner.get_geometry();
+            state.positioner = positioner;
+        });
+
+        self.unconstrain_popup(&popup);
+        popup.send_repositioned(token);
+    }
+
+    /// Constrain a popup's pending geometry to the visible canvas area,
+    /// outside the panels' exclusive zones
+    pub fn unconstrain_popup(&self, popup: &PopupSurface) {
+        let kind = PopupKind::Xdg(popup.clone());
+        let Ok(root) = find_popup_root_surface(&kind) else {
+            return;
+        };
+        let Some(id) = self.window_id_for_surface(&root) else {
+            self.unconstrain_layer_popup(popup, &root);
+            return;
+        };
+        let Some(rect) = self.window_node_rect(id) else {
+            return;
+        };
+
+        // Fullscreen windows fill their output instead of showing on the
+        // canvas
+        let (visible, location) = match self.fullscreen_geometry(id) {
+            Some(geometry) => (
+                Rectangle::from_size(geometry.size.to_f64()),
+                Point::from((0.0, 0.0)),
+            ),
+            None => {
+                let Some(visible) = self
+                    .node_view(rect)
+                    .map(|view| self.usable_canvas_rect(view))
+                else {
+                    return;
+                };
+                (visible, rect.loc)
+            }
+        };
+
+        let target = popup_constraint_target(visible, location, get_popup_toplevel_coords(&kind));
+
+        popup.with_pending_state(|state| {
+            state.geometry = state.positioner.get_unconstrained_geometry(target);
+        });
+    }
+
+    /// Start an explicit popup grab (xdg_popup.grab)
+    ///
+    /// Only granted in response to an input event the client received,
+    /// only on top of the same client's popup chain, and never while the
+    /// session is locked.
+    pub fn grab_popup(&mut self, popup: PopupSurface, seat: &Seat<Self>, serial: Serial) {
+        if self.session_locked {
+            return;
+        }
+        let kind = PopupKind::Xdg(popup);
+        let Some(root) = find_popup_root_surface(&kind).ok().filter(|root| {
+            self.window_id_for_surface(root).is_some() || self.layer_output(root).is_some()
+        }) else {
+            return;
+        };
+
+        let mut grab = match self.popups.grab_popup::<Self>(root, kind, seat, serial) {
+            Ok(grab) => grab,
+            Err(e) => {
+                debug!("Popup grab denied: {:?}", e);
+                return;
+            }
+        };
+
+        // Grabs of other clients or unrelated input keep their device
+        let keyboard = seat.get_keyboard();
+        let pointer = seat.get_pointer();
+        let previous = grab.previous_serial();
+        if keyboard.as_ref().is_some_and(|keyboard| {
+            keyboard.is_grabbed()
+                && !(keyboard.has_grab(serial) || keyboard.has_grab(previous.unwrap_or(serial)))
+        }) || pointer.as_ref().is_some_and(|pointer| {
+            pointer.is_grabbed()
+                && !(pointer.has_grab(serial)
+                    || pointer.has_grab(previous.unwrap_or_else(|| grab.serial())))
+        }) {
+            grab.ungrab(PopupUngrabStrategy::All);
+            return;
+        }
+
+        // The pointer grab goes first: replacing the parent popup's grab
+        // hands the keyboard back to the root, which the new keyboard grab
+        // then takes over
+        if let Some(pointer) = pointer {
+            pointer.set_grab(self, PopupPointerGrab::new(&grab), serial, Focus::Keep);
+        }
+        if let Some(keyboard) = keyboard {
+            keyboard.set_focus(self, grab.current_grab(), serial);
+            keyboard.set_grab(self, PopupKeyboardGrab::new(&grab), serial);
+        }
+    }
+
+    /// Handle a commit on a popup surface
+    ///
+    /// Sends the initial configure, which clients wait for before
+    /// attaching a buffer.
+    pub fn popup_commit(&mut self, surface: &WlSurface) {
+        self.popups.commit(surface);
+
+        if let Some(PopupKind::Xdg(popup)) = self.popups.find_popup(surface)
+            && !popup.is_initial_configure_sent()
+            && let Err(e) = popup.send_configure()
+        {
+            warn!("Failed to send initial popup configure: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{
+        headless::HeadlessBackend,
+        test_client::TestClient,
+        test_input::{move_pointer, press_button},
+    };
+    use crate::input::BTN_LEFT;
+    use smithay::reexports::{
+        wayland_protocols::xdg::shell::server::xdg_positioner::{
+            Anchor, ConstraintAdjustment, Gravity,
+        },
+        wayland_server::Resource,
+    };
+    use wayland_client::{
+        Proxy,
+        protocol::{wl_seat::WlSeat, wl_shm::WlShm},
+    };
+
+    fn positioner(
+        anchor_rect: Rectangle<i32, Logical>,
+        anchor: Anchor,
+        gravity: Gravity,
+        size: (i32, i32),
+        adjustment: ConstraintAdjustment,
+    ) -> PositionerState {
+        PositionerState {
+            rect_size: size.into(),
+            anchor_rect,
+            anchor_edges: anchor,
+            gravity,
+            constraint_adjustment: adjustment,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_constraint_target() {
+        let visible = Rectangle::new((-100.0, -50.0).into(), (1000.0, 800.0).into());
+        let target = popup_constraint_target(visible, (200.0, 100.0).into(), (10, 20).into());
+
+        assert_eq!(
+            target,
+            Rectangle::new((-310, -170).into(), (1000, 800).into())
+        );
+    }
+
+    #[test]
+    fn test_nested_popup_chain() {
+        // Visible canvas area and a node close to its right edge
+        let visible = Rectangle::new((0.0, 0.0).into(), (1000.0, 800.0).into());
+        let node = Point::from((700.0, 100.0));
+
+        // Menu opened from a menu bar item, slides left to fit
+        let menu = positioner(
+            Rectangle::new((200, 0).into(), (50, 20).into()),
+            Anchor::BottomLeft,
+            Gravity::BottomRight,
+            (150, 200),
+            ConstraintAdjustment::SlideX,
+        );
+        let target = popup_constraint_target(visible, node, (0, 0).into());
+        let menu_geometry = menu.get_unconstrained_geometry(target);
+        assert_eq!(
+            menu_geometry,
+            Rectangle::new((150, 20).into(), (150, 200).into())
+        );
+
+        // Submenu to the right of the menu flips to its left side
+        let submenu = positioner(
+            Rectangle::new((0, 40).into(), (150, 20).into()),
+            Anchor::TopRight,
+            Gravity::BottomRight,
+            (150, 100),
+            ConstraintAdjustment::FlipX,
+        );
+        let offset = menu_geometry.loc;
+        let target = popup_constraint_target(visible, node, offset);
+        let submenu_geometry = submenu.get_unconstrained_geometry(target);
+        assert_eq!(
+            submenu_geometry,
+            Rectangle::new((-150, 40).into(), (150, 100).into())
+        );
+
+        // A tall third level fits horizontally and is shrunk vertically
+        let nested = positioner(
+            Rectangle::new((0, 80).into(), (150, 20).into()),
+            Anchor::TopRight,
+            Gravity::BottomRight,
+            (150, 700),
+            ConstraintAdjustment::FlipX | ConstraintAdjustment::ResizeY,
+        );
+        let offset = offset + submenu_geometry.loc;
+        let target = popup_constraint_target(visible, node, offset);
+        let nested_geometry = nested.get_unconstrained_geometry(target);
+        assert_eq!(
+            nested_geometry,
+            Rectangle::new((150, 80).into(), (150, 560).into())
+        );
+
+        // Every level ends up inside the visible area
+        let absolute = node.to_i32_round() + offset + nested_geometry.loc;
+        let visible = visible.to_i32_round();
+        assert!(visible.contains_rect(Rectangle::new(absolute, nested_geometry.size)));
+    }
+
+    #[test]
+    fn test_unconstrained_popup_untouched() {
+        let visible = Rectangle::new((0.0, 0.0).into(), (1000.0, 800.0).into());
+        let tooltip = positioner(
+            Rectangle::new((10, 10).into(), (20, 20).into()),
+            Anchor::Bottom,
+            Gravity::Bottom,
+            (100, 30),
+            ConstraintAdjustment::all(),
+        );
+
+        let target = popup_constraint_target(visible, (100.0, 100.0).into(), (0, 0).into());
+        assert_eq!(
+            tooltip.get_unconstrained_geometry(target),
+            tooltip.get_geometry()
+        );
+    }
+
+    /// Click into the first window, for a serial to grab popups with
+    fn click_into_window(backend: &mut HeadlessBackend, client: &mut TestClient) -> u32 {
+        let state = backend.state_mut();
+        let window = state.windows.values().next().unwrap().window.clone();
+        let location = state.space.element_location(&window).unwrap().to_f64();
+        move_pointer(state, location + Point::from((10.0, 10.0)));
+        press_button(state, BTN_LEFT, true);
+        press_button(state, BTN_LEFT, false);
+        client.roundtrip(backend).unwrap();
+        client.data.button_serial.expect("No button event")
+    }
+
+    /// Keyboard focus of the compositor's seat, as a protocol object id
+    fn keyboard_focus(backend: &HeadlessBackend) -> Option<u32> {
+        let keyboard = backend.state().seat.get_keyboard().unwrap();
+        keyboard
+            .current_focus()
+            .map(|focus| Resource::id(&focus).protocol_id())
+    }
+
+    #[test]
+    fn test_popup_grab_chain_dismissed_outside() {
+        let mut backend = HeadlessBackend::for_tests(640, 480);
+        let mut client = TestClient::connect(&mut backend);
+        let shm: WlShm = client.bind(1);
+        let seat: WlSeat = client.bind(1);
+        let _pointer = seat.get_pointer(&client.handle(), ());
+        let buffer = client.shm_buffer(&shm, 100, 80, 0xff00ff00);
+        let menu_buffer = client.shm_buffer(&shm, 40, 30, 0xff0000ff);
+        let (surface, xdg_surface, _) = client.map_toplevel(&mut backend, &buffer);
+        let serial = click_into_window(&mut backend, &mut client);
+        assert_eq!(keyboard_focus(&backend), Some(surface.id().protocol_id()));
+
+        // A menu and its submenu each take the grab on top of the chain
+        let positioner = client.positioner((40, 30), (10, 10, 1, 1));
+        let grab = Some((&seat, serial));
+        let (menu, menu_xdg, menu_popup) =
+            client.map_popup(&mut backend, &xdg_surface, &positioner, &menu_buffer, grab);
+        assert_eq!(keyboard_focus(&backend), Some(menu.id().protocol_id()));
+        let (submenu, _, submenu_popup) =
+            client.map_popup(&mut backend, &menu_xdg, &positioner, &menu_buffer, grab);
+        assert_eq!(keyboard_focus(&backend), Some(submenu.id().protocol_id()));
+        assert!(backend.state().seat.get_pointer().unwrap().is_grabbed());
+        assert!(client.data.popups_done.is_empty());
+        assert!(client.protocol_error().is_none());
+
+        // A click on the background dismisses the whole chain and gives
+        // the focus back to the window
+        let state = backend.state_mut();
+        move_pointer(state, Point::from((1.0, 1.0)));
+        assert!(state.surface_under(state.pointer_location).is_none());
+        press_button(state, BTN_LEFT, true);
+        press_button(state, BTN_LEFT, false);
+        client.roundtrip(&mut backend).unwrap();
+        assert!(client.data.popups_done.contains(&menu_popup));
+        assert!(client.data.popups_done.contains(&submenu_popup));
+        assert!(!backend.state().seat.get_pointer().unwrap().is_grabbed());
+        assert_eq!(keyboard_focus(&backend), Some(surface.id().protocol_id()));
+    }
+
+    #[test]
+    fn test_popup_repositioned() {
+        let mut backend = HeadlessBackend::for_tests(640, 480);
+        let mut client = TestClient::connect(&mut backend);
+        let shm: WlShm = client.bind(1);
+        let buffer = client.shm_buffer(&shm, 100, 80, 0xff00ff00);
+        let menu_buffer = client.shm_buffer(&shm, 40, 30, 0xff0000ff);
+        let (_, xdg_surface, _) = client.map_toplevel(&mut backend, &buffer);
+
+        let positioner = client.positioner((40, 30), (10, 10, 1, 1));
+        let (_, _, popup) =
+            client.map_popup(&mut backend, &xdg_surface, &positioner, &menu_buffer, None);
+        assert_eq!(client.data.popup_configure, Some((10, 11, 40, 30)));
+        assert_eq!(client.data.repositioned, None);
+
+        // The token comes back along with the new geometry
+        let moved = client.positioner((50, 20), (30, 40, 1, 1));
+        let configure = client.data.xdg_configure;
+        popup.reposition(&moved, 7);
+        client.roundtrip(&mut backend).unwrap();
+        assert_eq!(client.data.repositioned, Some(7));
+        assert_eq!(client.data.popup_configure, Some((30, 41, 50, 20)));
+        assert_ne!(client.data.xdg_configure, configure);
+    }
+}
@@ -0,0 +1,1359 @@
+//! Canvas rendering shared by all backends
+//!
+//! Windows are not rendered through `Space::render_elements_for_output`,
+//! since the space has no notion of zoom. Instead every node's surface tree
+//! is drawn at its canvas position transformed by the viewport, with the
+//! surface scale multiplied by [`Viewport::zoom`](loom_canvas::Viewport)
+//! of the output's view.
+//!
+//! A fullscreen window is drawn alone, at the output's origin and without
+//! the view's zoom (see [`crate::fullscreen`]), below the overlay layer.
+//!
+//! The drag and drop icon is drawn in front of everything, at the pointer
+//! and zoomed like the canvas of the pointer's view (see [`crate::dnd`]).
+//!
+//! While the session is locked, an output only shows the locker's surface
+//! for it, or nothing until there is one (see [`crate::lock`]).
+//!
+//! Layer surfaces (see [`crate::layers`]) are drawn in screen space at the
+//! output's scale: the top and overlay layers in front of the canvas, the
+//! bottom and background layers behind it.
+//!
+//! Ink nodes are drawn below the windows from textures rasterized at a
+//! density step of the zoom, kept per node and output until the node's
+//! strokes change or the zoom crosses a step (see [`InkBuffers`]). The
+//! stroke being drawn, and ink nodes too large for a texture at the zoom,
+//! are drawn as solid color discs along each stroke instead, spaced by the
+//! zoom so strokes stay smooth. The discs reuse element ids from frame to
+//! frame (see [`InkElementIds`]), so unchanged ink isn't damaged.
+//!
+//! Notes and media nodes have no content drawn yet, only placeholder cards
+//! below the windows: a body in the node's rectangle, with a strip in the
+//! frame color along its top that shows the node's label like a title
+//! (see [`CardBuffers`]).
+//!
+//! Node frames (see [`crate::decorations`]) are drawn behind their window
+//! and in front of the windows further back: solid rectangles for the
+//! straight parts, and small antialiased textures for the rounded corners
+//! and the title. Textures are kept per window and output until the
+//! frame's color or title changes, or the zoom changes their size (see
+//! [`FrameBuffers`]). Corners are rasterized at the output's scale rounded
+//! up; titles at size steps, scaled down to the zoom, so zooming only
+//! rasterizes them again when it crosses a step.
+//!
+//! # Performance
+//!
+//! - Windows and frames entirely outside the output are culled before
+//!   producing elements
+//! - The element vector is sized up front from the window count
+//! - Ink nodes outside the output are culled, and so are discs; discs are
+//!   at least a physical pixel apart, and at most [`MAX_INK_ELEMENTS`] are
+//!   drawn per output
+
+use crate::decorations::{TITLE_PADDING, TitleBitmap, render_title};
+use crate::layers::{LOWER_LAYERS, UPPER_LAYERS};
+use crate::state::LoomState;
+use crate::types::WindowId;
+use crate::views::OutputView;
+use fontdue::Font;
+use loom_canvas::{InkStroke, NodeId, NodeType};
+use smithay::{
+    backend::{
+        allocator::Fourcc,
+        renderer::{
+            ImportAll, ImportMem, Renderer,
+            element::{
+                AsRenderElements, Id, Kind,
+                memory::{MemoryRenderBuffer, MemoryRenderBufferRenderElement},
+                render_elements,
+                solid::SolidColorRenderElement,
+                surface::{WaylandSurfaceRenderElement, render_elements_from_surface_tree},
+            },
+            utils::CommitCounter,
+        },
+    },
+    desktop::layer_map_for_output,
+    output::Output,
+    utils::{Logical, Physical, Point, Rectangle, Scale, Transform},
+    wayland::shell::wlr_layer::Layer,
+};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+/// Share of a disc's radius covered by both rectangles drawing it
+const DISC_INNER: f64 = 0.6;
+
+/// Number of solid rectangles drawing a frame
+const FRAME_RECTS: usize = 5;
+
+/// Smallest title drawn, in physical pixels
+const MIN_TITLE_PX: f64 = 6.0;
+
+/// Title size steps per doubling of the zoom
+const TITLE_STEPS: f64 = 4.0;
+
+/// Most solid elements drawing ink discs per output; further discs are
+/// left out
+pub const MAX_INK_ELEMENTS: usize = 20_000;
+
+/// Largest ink texture, in pixels along either side; larger ink nodes are
+/// drawn as discs
+const MAX_INK_TEXTURE_SIZE: f64 = 2048.0;
+
+/// Body color of note cards
+const NOTE_COLOR: [f32; 4] = [0.98, 0.93, 0.62, 1.0];
+
+/// Body color of media cards
+const MEDIA_COLOR: [f32; 4] = [0.28, 0.29, 0.33, 1.0];
+
+// Render elements produced by the compositor for an output
+render_elements! {
+    pub LoomRenderElement<R> where R: ImportAll + ImportMem;
+    Surface=WaylandSurfaceRenderElement<R>,
+    Solid=SolidColorRenderElement,
+    Memory=MemoryRenderBufferRenderElement<R>,
+}
+
+/// Element ids of ink discs, reused in order every frame
+///
+/// Damage is tracked by element id, so with fresh ids every frame all ink
+/// would be redrawn; reusing them only damages the discs that moved. There
+/// are at most [`MAX_INK_ELEMENTS`].
+#[derive(Debug, Default)]
+pub struct InkElementIds(RefCell<Vec<Id>>);
+
+impl InkElementIds {
+    /// Id of the element at an index of the frame's ink elements
+    fn get(&self, index: usize) -> Id {
+        let mut ids = self.0.borrow_mut();
+        if ids.len() <= index {
+            ids.resize_with(index + 1, Id::new);
+        }
+        ids[index].clone()
+    }
+}
+
+/// Textures of ink nodes, kept per node and output across frames
+///
+/// Textures of nodes an output stopped showing are dropped after its next
+/// frame.
+#[derive(Debug, Default)]
+pub struct InkBuffers(RefCell<HashMap<(NodeId, String), InkBuffer>>);
+
+/// Texture of an ink node on an output
+#[derive(Debug)]
+struct InkBuffer {
+    key: InkKey,
+    buffer: MemoryRenderBuffer,
+    /// Size of the texture in pixels
+    size: (usize, usize),
+    /// Whether the output's current frame draws it
+    used: bool,
+}
+
+/// What an ink texture was rasterized from
+#[derive(Debug, PartialEq)]
+struct InkKey {
+    strokes: usize,
+    points: usize,
+    /// Bits of the node's size
+    size: (u64, u64),
+    /// Bits of the pixels per canvas unit
+    density: u64,
+}
+
+/// Textures and element ids of node frames, kept per window and output
+/// across frames
+///
+/// Like ink, frames reuse their element ids so that only what changed is
+/// damaged. Every window has its own corner textures, since a texture's
+/// element id can only appear once in a frame, and its own textures on
+/// every output, so outputs with different zoom or scale don't replace
+/// each other's.
+#[derive(Debug, Default)]
+pub struct FrameBuffers(RefCell<HashMap<(WindowId, String), WindowFrame>>);
+
+/// Frame textures and element ids of a window on an output
+#[derive(Debug)]
+struct WindowFrame {
+    ids: [Id; FRAME_RECTS],
+    /// Corner textures, top left, top right, bottom left and bottom right
+    corners: Option<(CornerKey, [MemoryRenderBuffer; 4])>,
+    title: Option<TitleCache>,
+}
+
+/// Corner radii in logical pixels, buffer scale and color bits
+type CornerKey = (i32, i32, i32, [u32; 4]);
+
+/// What a title texture was rasterized from
+#[derive(Debug, PartialEq)]
+struct TitleKey {
+    text: String,
+    /// Bits of the size step, in pixels
+    px: u64,
+    color: [u32; 4],
+}
+
+/// A rasterized title
+#[derive(Debug)]
+struct TitleCache {
+    key: TitleKey,
+    /// Widest the title could be, in pixels
+    max_width: usize,
+    /// Texture, `None` if the title doesn't fit
+    texture: Option<TitleTexture>,
+}
+
+/// Texture of a title
+#[derive(Debug)]
+struct TitleTexture {
+    buffer: MemoryRenderBuffer,
+    /// Size in pixels
+    size: (usize, usize),
+    /// Whether the title was cut to fit
+    cut: bool,
+}
+
+/// Element ids and label textures of the cards drawn for notes and media,
+/// kept per node and output across frames
+///
+/// Cards of nodes an output stopped showing are dropped after its next
+/// frame.
+#[derive(Debug, Default)]
+pub struct CardBuffers(RefCell<HashMap<(NodeId, String), Card>>);
+
+/// Element ids and label texture of a card on an output
+#[derive(Debug)]
+struct Card {
+    /// Ids of the body and the strip
+    ids: [Id; 2],
+    label: Option<TitleCache>,
+    /// Whether the output's current frame draws it
+    used: bool,
+}
+
+impl TitleCache {
+    /// Whether the title can be drawn from this texture at a width
+    ///
+    /// A cut title is rasterized again when there is more room, unless
+    /// it's only a few pixels, which rarely fit another character; this
+    /// keeps rounding while zooming from rasterizing titles every frame.
+    fn fits(&self, key: &TitleKey, max_width: usize) -> bool {
+        let slack = (f64::from_bits(key.px) / 4.0) as usize;
+        let room = max_width <= self.max_width + slack;
+        self.key == *key
+            && match &self.texture {
+                Some(texture) => texture.size.0 <= max_width && (!texture.cut || room),
+                None => room,
+            }
+    }
+}
+
+impl FrameBuffers {
+    /// Forget the frames of windows and outputs that are gone
+    fn retain(&self, state: &LoomState) {
+        self.0.borrow_mut().retain(|(id, output_name), _| {
+            state.windows.contains_key(*id)
+                && state
+                    .space
+                    .outputs()
+                    .any(|output| output.name() == *output_name)
+        });
+    }
+}
+
+impl Default for WindowFrame {
+    fn default() -> Self {
+        Self {
+            ids: std::array::from_fn(|_| Id::new()),
+            corners: None,
+            title: None,
+        }
+    }
+}
+
+/// Build the render elements for an output, front to back
+pub fn output_elements<R>(
+    renderer: &mut R,
+    output: &Output,
+    state: &LoomState,
+) -> Vec<LoomRenderElement<R>>
+where
+    R: Renderer + ImportAll + ImportMem,
+    R::TextureId: Send + Clone + 'static,
+{
+    let Some(output_geometry) = state.space.output_geometry(output) else {
+        return Vec::new();
+    };
+    let output_scale = output.current_scale().fractional_scale();
+    if state.session_locked {
+        return lock_elements(renderer, state, output, output_scale);
+    }
+
+    let Some(view) = state.view_for_output(output) else {
+        return Vec::new();
+    };
+    let Some(viewport) = state.canvas.viewport(view.viewport) else {
+        return Vec::new();
+    };
+
+    let mut elements = dnd_icon_elements(renderer, state, output_geometry, output_scale);
+
+    if let Some(id) = state.fullscreen_window_on(output) {
+        let window = &state.windows[id].window;
+        let location = Point::<i32, Logical>::from((0, 0)) - window.geometry().loc;
+        elements.extend(layer_elements(
+            renderer,
+            output,
+            &[Layer::Overlay],
+            output_scale,
+        ));
+        elements.extend(window.render_elements(
+            renderer,
+            location.to_physical_precise_round(output_scale),
+            Scale::from(output_scale),
+            1.0,
+        ));
+        return elements;
+    }
+
+    let zoom = viewport.zoom;
+    let scale = Scale::from(output_scale * zoom);
+    let visible = Rectangle::from_size(output_geometry.size.to_f64());
+
+    elements.reserve(state.windows.len());
+    elements.extend(layer_elements(
+        renderer,
+        output,
+        &UPPER_LAYERS,
+        output_scale,
+    ));
+
+    // Space elements are ordered bottom to top, render elements front to back
+    for window in state.space.elements().rev() {
+        let Some(origin) = state.window_render_origin(window) else {
+            continue;
+        };
+
+        // Surface tree origin relative to the output, in logical pixels
+        let location = state.canvas_to_view(view, origin) - output_geometry.loc.to_f64();
+
+        let bbox = window.bbox_with_popups().to_f64();
+        let scaled_bbox =
+            Rectangle::new(location + bbox.loc.upscale(zoom), bbox.size.upscale(zoom));
+        if scaled_bbox.overlaps(visible) {
+            elements.extend(window.render_elements::<LoomRenderElement<R>>(
+                renderer,
+                location.to_physical(output_scale).to_i32_round(),
+                scale,
+                1.0,
+            ));
+        }
+
+        if let Some(id) = state.window_id(window)
+            && state.has_frame(id)
+        {
+            let frame = frame_elements(
+                renderer,
+                state,
+                id,
+                output,
+                view,
+                output_geometry,
+                output_scale,
+            );
+            elements.extend(frame);
+        }
+    }
+    state.frame_buffers.retain(state);
+
+    elements.extend(ink_elements(
+        renderer,
+        state,
+        output,
+        view,
+        output_geometry,
+        output_scale,
+        zoom,
+    ));
+    elements.extend(card_elements(
+        renderer,
+        state,
+        output,
+        view,
+        output_geometry,
+        output_scale,
+        zoom,
+    ));
+
+    elements.extend(layer_elements(
+        renderer,
+        output,
+        &LOWER_LAYERS,
+        output_scale,
+    ));
+
+    elements
+}
+
+/// Build the elements of an output's lock surface, if any
+fn lock_elements<R>(
+    renderer: &mut R,
+    state: &LoomState,
+    output: &Output,
+    output_scale: f64,
+) -> Vec<LoomRenderElement<R>>
+where
+    R: Renderer + ImportAll + ImportMem,
+    R::TextureId: Send + Clone + 'static,
+{
+    let Some(surface) = state.lock.surface_for(output) else {
+        return Vec::new();
+    };
+    render_elements_from_surface_tree(
+        renderer,
+        surface,
+        Point::<i32, Physical>::from((0, 0)),
+        Scale::from(output_scale),
+        1.0,
+        Kind::Unspecified,
+    )
+}
+
+/// Build the elements of the drag and drop icon, if any
+///
+/// The icon's offset from the pointer is in surface pixels, so it is
+/// zoomed like the canvas under the pointer.
+fn dnd_icon_elements<R>(
+    renderer: &mut R,
+    state: &LoomState,
+    output_geometry: Rectangle<i32, Logical>,
+    output_scale: f64,
+) -> Vec<LoomRenderElement<R>>
+where
+    R: Renderer + ImportAll + ImportMem,
+    R::TextureId: Send + Clone + 'static,
+{
+    let Some(icon) = &state.dnd.icon else {
+        return Vec::new();
+    };
+    let Some(view) = state.view_at(state.pointer_location) else {
+        return Vec::new();
+    };
+    let zoom = state
+        .canvas
+        .viewport(view.viewport)
+        .map_or(1.0, |viewport| viewport.zoom);
+
+    let location =
+        state.pointer_location - output_geometry.loc.to_f64() + icon.offset.to_f64().upscale(zoom);
+    render_elements_from_surface_tree(
+        renderer,
+        &icon.surface,
+        location.to_physical(output_scale).to_i32_round(),
+        Scale::from(output_scale * zoom),
+        1.0,
+        Kind::Unspecified,
+    )
+}
+
+/// Build the elements of an output's layer surfaces on some layers, front
+/// to back
+///
+/// Layer surfaces are placed relative to the output, unaffected by the
+/// view's pan and zoom.
+fn layer_elements<R>(
+    renderer: &mut R,
+    output: &Output,
+    layers: &[Layer],
+    output_scale: f64,
+) -> Vec<LoomRenderElement<R>>
+where
+    R: Renderer + ImportAll + ImportMem,
+    R::TextureId: Send + Clone + 'static,
+{
+    let map = layer_map_for_output(output);
+    let mut elements = Vec::new();
+    for &layer in layers {
+        for surface in map.layers_on(layer).rev() {
+            let Some(geometry) = map.layer_geometry(surface) else {
+                continue;
+            };
+            elements.extend(surface.render_elements::<LoomRenderElement<R>>(
+                renderer,
+                geometry.loc.to_physical_precise_round(output_scale),
+                Scale::from(output_scale),
+                1.0,
+            ));
+        }
+    }
+    elements
+}
+
+/// Build the elements drawing a window's frame, front to back
+///
+/// The frame is laid out in whole logical pixels relative to the output,
+/// so its parts meet without gaps at any zoom.
+fn frame_elements<R>(
+    renderer: &mut R,
+    state: &LoomState,
+    id: WindowId,
+    output: &Output,
+    view: &OutputView,
+    output_geometry: Rectangle<i32, Logical>,
+    output_scale: f64,
+) -> Vec<LoomRenderElement<R>>
+where
+    R: Renderer + ImportAll + ImportMem,
+    R::TextureId: Send + Clone + 'static,
+{
+    let mut elements = Vec::new();
+    let Some(content) = state.window_node_rect(id) else {
+        return elements;
+    };
+    let zoom = state
+        .canvas
+        .viewport(view.viewport)
+        .map_or(1.0, |viewport| viewport.zoom);
+    let style = &state.decorations.style;
+
+    let to_local = |point: Point<f64, Logical>| {
+        let local = state.canvas_to_view(view, point) - output_geometry.loc.to_f64();
+        local.to_i32_round::<i32>()
+    };
+    let top_left = to_local(content.loc);
+    let bottom_right = to_local(content.loc + content.size.to_point());
+
+    let border = if style.border > 0.0 {
+        (style.border * zoom).round().max(1.0) as i32
+    } else {
+        0
+    };
+    let title_height = (style.title_height * zoom).round().max(1.0) as i32;
+    let frame = Rectangle::<i32, Logical>::from_extremities(
+        top_left - Point::from((border, title_height)),
+        bottom_right + Point::from((border, border)),
+    );
+    if !frame.overlaps(Rectangle::from_size(output_geometry.size)) {
+        return elements;
+    }
+
+    let (x, y, w, h) = (frame.loc.x, frame.loc.y, frame.size.w, frame.size.h);
+    let top_radius = ((style.radius * zoom).round() as i32)
+        .min(title_height)
+        .min(w / 2);
+    let bottom_radius = ((style.bottom_radius() * zoom).round() as i32)
+        .min(h - title_height)
+        .min(w / 2);
+
+    let color = state.frame_color(id);
+    let premultiplied = premultiplied_color(color);
+    let buffer_scale = output_scale.ceil().max(1.0) as i32;
+
+    let mut frames = state.frame_buffers.0.borrow_mut();
+    let buffers = frames.entry((id, output.name())).or_default();
+
+    // Title, in front of the strip
+    let padding = top_radius.max((TITLE_PADDING * zoom).round() as i32);
+    if let Some(font) = state.decorations.font()
+        && let Some(text) = state.frame_title(id)
+    {
+        let strip = Rectangle::new(frame.loc, (w, title_height).into());
+        elements.extend(title_element(
+            renderer,
+            &mut buffers.title,
+            font,
+            text,
+            style.font_size * zoom,
+            style.text_color,
+            strip,
+            padding,
+            output_scale,
+        ));
+    }
+
+    // Rounded corners
+    let key = (top_radius, bottom_radius, buffer_scale, color_bits(color));
+    if buffers
+        .corners
+        .as_ref()
+        .is_none_or(|(cached, _)| *cached != key)
+    {
+        let corner = |index: usize| {
+            let radius = if index < 2 { top_radius } else { bottom_radius };
+            corner_buffer(index, radius, buffer_scale, premultiplied)
+        };
+        buffers.corners = Some((key, std::array::from_fn(corner)));
+    }
+    if let Some((_, corners)) = &buffers.corners {
+        let locations = [
+            (x, y, top_radius),
+            (x + w - top_radius, y, top_radius),
+            (x, y + h - bottom_radius, bottom_radius),
+            (x + w - bottom_radius, y + h - bottom_radius, bottom_radius),
+        ];
+        for (buffer, (corner_x, corner_y, radius)) in corners.iter().zip(locations) {
+            if radius <= 0 {
+                continue;
+            }
+            let location = Point::<i32, Logical>::from((corner_x, corner_y));
+            elements.extend(
+                MemoryRenderBufferRenderElement::from_buffer(
+                    renderer,
+                    location.to_f64().to_physical(output_scale),
+                    buffer,
+                    None,
+                    None,
+                    None,
+                    Kind::Unspecified,
+                )
+                .ok()
+                .map(LoomRenderElement::from),
+            );
+        }
+    }
+
+    // Straight parts: between the top corners, the rest of the title strip,
+    // the sides down to the bottom corners and between the bottom corners
+    let side_height = h - title_height - bottom_radius;
+    let rects = [
+        (x + top_radius, y, w - 2 * top_radius, top_radius),
+        (x, y + top_radius, w, title_height - top_radius),
+        (x, y + title_height, border, side_height),
+        (x + w - border, y + title_height, border, side_height),
+        (
+            x + bottom_radius,
+            y + h - border,
+            w - 2 * bottom_radius,
+            border,
+        ),
+    ];
+    for (id, (rect_x, rect_y, rect_w, rect_h)) in buffers.ids.iter().zip(rects) {
+        if rect_w <= 0 || rect_h <= 0 {
+            continue;
+        }
+        let to_physical =
+            |point: Point<i32, Logical>| point.to_f64().to_physical(output_scale).to_i32_round();
+        let geometry = Rectangle::from_extremities(
+            to_physical((rect_x, rect_y).into()),
+            to_physical((rect_x + rect_w, rect_y + rect_h).into()),
+        );
+        elements.push(LoomRenderElement::from(SolidColorRenderElement::new(
+            id.clone(),
+            geometry,
+            CommitCounter::default(),
+            premultiplied,
+            Kind::Unspecified,
+        )));
+    }
+
+    elements
+}
+
+/// Build the element drawing a title in a strip, left aligned after some
+/// padding and centered vertically
+///
+/// The title is rasterized at the size step at or above its size in
+/// pixels and scaled down, and kept in `cache` while it fits.
+#[allow(clippy::too_many_arguments)]
+fn title_element<R>(
+    renderer: &mut R,
+    cache: &mut Option<TitleCache>,
+    font: &Font,
+    text: &str,
+    size: f64,
+    color: [f32; 4],
+    strip: Rectangle<i32, Logical>,
+    padding: i32,
+    output_scale: f64,
+) -> Option<LoomRenderElement<R>>
+where
+    R: Renderer + ImportAll + ImportMem,
+    R::TextureId: Send + Clone + 'static,
+{
+    let px = size * output_scale;
+    if px < MIN_TITLE_PX {
+        return None;
+    }
+
+    let step = title_px(px);
+    let shrink = px / step;
+    let key = TitleKey {
+        text: text.to_string(),
+        px: step.to_bits(),
+        color: color_bits(color),
+    };
+    let max_width = ((strip.size.w - 2 * padding).max(0) as f64 * output_scale / shrink) as usize;
+    if cache
+        .as_ref()
+        .is_none_or(|title| !title.fits(&key, max_width))
+    {
+        let texture = render_title(font, text, step as f32, color, max_width).map(title_texture);
+        *cache = Some(TitleCache {
+            key,
+            max_width,
+            texture,
+        });
+    }
+
+    let texture = cache.as_ref()?.texture.as_ref()?;
+    let (width, height) = (texture.size.0 as f64, texture.size.1 as f64);
+    let size = Point::<f64, Logical>::from((width, height)).upscale(shrink / output_scale);
+    let location = Point::<f64, Logical>::from((
+        (strip.loc.x + padding) as f64,
+        strip.loc.y as f64 + (strip.size.h as f64 - size.y) / 2.0,
+    ));
+    MemoryRenderBufferRenderElement::from_buffer(
+        renderer,
+        location
+            .to_physical(output_scale)
+            .to_i32_round::<i32>()
+            .to_f64(),
+        &texture.buffer,
+        None,
+        Some(Rectangle::from_size((width, height).into())),
+        Some(size.to_i32_round::<i32>().to_size()),
+        Kind::Unspecified,
+    )
+    .ok()
+    .map(LoomRenderElement::from)
+}
+
+/// Texture of a rounded corner, antialiased, with premultiplied alpha
+///
+/// Corners are indexed top left, top right, bottom left and bottom right.
+fn corner_buffer(
+    index: usize,
+    radius: i32,
+    buffer_scale: i32,
+    color: [f32; 4],
+) -> MemoryRenderBuffer {
+    let size = (radius.max(0) * buffer_scale) as usize;
+    let r = size as f64;
+    // Center of the circle, in the corner's pixels
+    let center_x = if matches!(index, 0 | 2) { r } else { 0.0 };
+    let center_y = if index < 2 { r } else { 0.0 };
+
+    let mut pixels = Vec::with_capacity(size * size * 4);
+    for row in 0..size {
+        for column in 0..size {
+            let distance = (column as f64 + 0.5 - center_x).hypot(row as f64 + 0.5 - center_y);
+            let coverage = (r - distance + 0.5).clamp(0.0, 1.0) as f32;
+            let [red, green, blue, alpha] = color.map(|c| (c * coverage * 255.0).round() as u8);
+            pixels.extend([blue, green, red, alpha]);
+        }
+    }
+
+    MemoryRenderBuffer::from_slice(
+        &pixels,
+        Fourcc::Argb8888,
+        (size as i32, size as i32),
+        buffer_scale,
+        Transform::Normal,
+        None,
+    )
+}
+
+/// Texture of a title
+fn title_texture(bitmap: TitleBitmap) -> TitleTexture {
+    let buffer = MemoryRenderBuffer::from_slice(
+        &bitmap.pixels,
+        Fourcc::Argb8888,
+        (bitmap.width as i32, bitmap.height as i32),
+        1,
+        Transform::Normal,
+        None,
+    );
+    TitleTexture {
+        buffer,
+        size: (bitmap.width, bitmap.height),
+        cut: bitmap.cut,
+    }
+}
+
+/// Size step a title of a size in pixels is rasterized at
+///
+/// Steps are [`TITLE_STEPS`] per doubling, so titles are at most about
+/// 1.19 times as large as drawn.
+fn title_px(px: f64) -> f64 {
+    2f64.powf((px.log2() * TITLE_STEPS).ceil() / TITLE_STEPS)
+}
+
+/// Color with premultiplied alpha, as renderers expect
+fn premultiplied_color([r, g, b, a]: [f32; 4]) -> [f32; 4] {
+    [r * a, g * a, b * a, a]
+}
+
+/// Bits of a color, to compare cached textures
+fn color_bits(color: [f32; 4]) -> [u32; 4] {
+    color.map(f32::to_bits)
+}
+
+/// Build the elements drawing ink in a view, the stroke being drawn first
+fn ink_elements<R>(
+    renderer: &mut R,
+    state: &LoomState,
+    output: &Output,
+    view: &OutputView,
+    output_geometry: Rectangle<i32, Logical>,
+    output_scale: f64,
+    zoom: f64,
+) -> Vec<LoomRenderElement<R>>
+where
+    R: Renderer + ImportAll + ImportMem,
+    R::TextureId: Send + Clone + 'static,
+{
+    // Size of a canvas unit in physical pixels
+    let scale = output_scale * zoom;
+    let visible = state.view_canvas_rect(view);
+    let to_local = |point: Point<f64, Logical>| {
+        state.canvas_to_view(view, point) - output_geometry.loc.to_f64()
+    };
+    let mut discs = InkDiscs {
+        ids: &state.ink_ids,
+        clip: Rectangle::from_size(output_geometry.size.to_f64().to_physical(output_scale)),
+        scale,
+        elements: Vec::new(),
+    };
+
+    if let Some(stroke) = state.tablet_state.stroke() {
+        discs.push(stroke, |point| to_local(point).to_physical(output_scale));
+    }
+
+    let mut textures = Vec::new();
+    let mut buffers = state.ink_buffers.0.borrow_mut();
+    for node in state.canvas.nodes() {
+        let NodeType::Ink { strokes } = &node.node_type else {
+            continue;
+        };
+        let rect = Rectangle::new((node.x, node.y).into(), (node.width, node.height).into());
+        if !rect.overlaps(visible) {
+            continue;
+        }
+
+        let density = ink_density(scale);
+        let size = (
+            (node.width * density).ceil().max(1.0),
+            (node.height * density).ceil().max(1.0),
+        );
+        if size.0 > MAX_INK_TEXTURE_SIZE || size.1 > MAX_INK_TEXTURE_SIZE {
+            for stroke in strokes {
+                let to_physical = |point: Point<f64, Logical>| {
+                    to_local(point + Point::from((node.x, node.y))).to_physical(output_scale)
+                };
+                discs.push(stroke, to_physical);
+            }
+            continue;
+        }
+
+        let key = InkKey {
+            strokes: strokes.len(),
+            points: strokes.iter().map(|stroke| stroke.points.len()).sum(),
+            size: (node.width.to_bits(), node.height.to_bits()),
+            density: density.to_bits(),
+        };
+        let ink = match buffers.entry((node.id, output.name())) {
+            Entry::Occupied(entry) if entry.get().key == key => entry.into_mut(),
+            entry => {
+                let size = (size.0 as usize, size.1 as usize);
+                let ink = InkBuffer {
+                    key,
+                    buffer: ink_buffer(strokes, size, density),
+                    size,
+                    used: false,
+                };
+                entry.insert_entry(ink).into_mut()
+            }
+        };
+        ink.used = true;
+        textures.extend(ink_texture_element(
+            renderer,
+            ink,
+            density,
+            to_local(Point::from((node.x, node.y))),
+            output_scale,
+            zoom,
+        ));
+    }
+
+    // Forget the textures this output no longer shows, and those of outputs
+    // that are gone
+    let name = output.name();
+    buffers.retain(|(_, output_name), ink| {
+        if *output_name == name {
+            std::mem::take(&mut ink.used)
+        } else {
+            state
+                .space
+                .outputs()
+                .any(|output| output.name() == *output_name)
+        }
+    });
+
+    discs
+        .elements
+        .into_iter()
+        .map(LoomRenderElement::from)
+        .chain(textures)
+        .collect()
+}
+
+/// Build the elements drawing the cards of notes and media in a view, front
+/// to back
+///
+/// Cards are laid out in whole logical pixels relative to the output, like
+/// frames.
+fn card_elements<R>(
+    renderer: &mut R,
+    state: &LoomState,
+    output: &Output,
+    view: &OutputView,
+    output_geometry: Rectangle<i32, Logical>,
+    output_scale: f64,
+    zoom: f64,
+) -> Vec<LoomRenderElement<R>>
+where
+    R: Renderer + ImportAll + ImportMem,
+    R::TextureId: Send + Clone + 'static,
+{
+    let style = &state.decorations.style;
+    let visible = state.view_canvas_rect(view);
+    let to_local = |point: Point<f64, Logical>| {
+        let local = state.canvas_to_view(view, point) - output_geometry.loc.to_f64();
+        local.to_i32_round::<i32>()
+    };
+    let to_physical =
+        |point: Point<i32, Logical>| point.to_f64().to_physical(output_scale).to_i32_round();
+
+    let mut elements = Vec::new();
+    let mut cards = state.card_buffers.0.borrow_mut();
+    // Later nodes in front
+    let mut nodes: Vec<_> = state
+        .canvas
+        .nodes()
+        .filter_map(|node| match node.node_type {
+            NodeType::Note { .. } => Some((node, NOTE_COLOR)),
+            NodeType::Media { .. } => Some((node, MEDIA_COLOR)),
+            _ => None,
+        })
+        .collect();
+    nodes.sort_unstable_by_key(|(node, _)| std::cmp::Reverse(node.id));
+
+    for (node, body_color) in nodes {
+        let rect = Rectangle::new((node.x, node.y).into(), (node.width, node.height).into());
+        if !rect.overlaps(visible) {
+            continue;
+        }
+
+        let card = cards
+            .entry((node.id, output.name()))
+            .or_insert_with(|| Card {
+                ids: std::array::from_fn(|_| Id::new()),
+                label: None,
+                used: false,
+            });
+        card.used = true;
+
+        let top_left = to_local(rect.loc);
+        let bottom_right = to_local(rect.loc + rect.size.to_point());
+        let height = bottom_right.y - top_left.y;
+        let strip_height = ((style.title_height * zoom).round() as i32).min(height);
+        let strip = Rectangle::new(top_left, (bottom_right.x - top_left.x, strip_height).into());
+
+        if let Some(label) = node.label.as_deref().filter(|label| !label.is_empty())
+            && let Some(font) = state.decorations.font()
+        {
+            let padding = (TITLE_PADDING * zoom).round() as i32;
+            elements.extend(title_element(
+                renderer,
+                &mut card.label,
+                font,
+                label,
+                style.font_size * zoom,
+                style.text_color,
+                strip,
+                padding,
+                output_scale,
+            ));
+        }
+
+        let parts = [
+            (strip.loc, strip.loc + strip.size.to_point(), style.color),
+            (
+                (top_left.x, top_left.y + strip_height).into(),
+                bottom_right,
+                body_color,
+            ),
+        ];
+        for (id, (from, to, color)) in card.ids.iter().zip(parts) {
+            let geometry = Rectangle::from_extremities(to_physical(from), to_physical(to));
+            if geometry.is_empty() {
+                continue;
+            }
+            elements.push(LoomRenderElement::from(SolidColorRenderElement::new(
+                id.clone(),
+                geometry,
+                CommitCounter::default(),
+                premultiplied_color(color),
+                Kind::Unspecified,
+            )));
+        }
+    }
+
+    // Forget the cards this output no longer shows, and those of outputs
+    // that are gone
+    let name = output.name();
+    cards.retain(|(_, output_name), card| {
+        if *output_name == name {
+            std::mem::take(&mut card.used)
+        } else {
+            state
+                .space
+                .outputs()
+                .any(|output| output.name() == *output_name)
+        }
+    });
+
+    elements
+}
+
+/// Solid elements drawing ink as discs, within the output
+struct InkDiscs<'a> {
+    ids: &'a InkElementIds,
+    /// The output, in physical pixels relative to it
+    clip: Rectangle<f64, Physical>,
+    /// Size of a canvas unit in physical pixels
+    scale: f64,
+    elements: Vec<SolidColorRenderElement>,
+}
+
+impl InkDiscs<'_> {
+    /// Add the discs of a stroke, given the physical position of its
+    /// points
+    ///
+    /// Discs outside the output, and those past [`MAX_INK_ELEMENTS`], are
+    /// left out.
+    fn push(
+        &mut self,
+        stroke: &InkStroke,
+        to_physical: impl Fn(Point<f64, Logical>) -> Point<f64, Physical>,
+    ) {
+        let color = premultiplied(stroke);
+        let spacing = (stroke.width / 4.0).max(1.0 / self.scale);
+
+        for (x, y, radius) in stroke.dabs(spacing) {
+            if self.elements.len() >= MAX_INK_ELEMENTS {
+                return;
+            }
+            let center = to_physical(Point::from((x, y)));
+            let radius = (radius * self.scale).max(0.5);
+            let bounds = Rectangle::new(
+                center - Point::from((radius, radius)),
+                (2.0 * radius, 2.0 * radius).into(),
+            );
+            if !bounds.overlaps(self.clip) {
+                continue;
+            }
+
+            // A disc as two overlapping rectangles, or one if it is tiny
+            let shapes: &[(f64, f64)] = if radius < 2.0 {
+                &[(1.0, 1.0)]
+            } else {
+                &[(1.0, DISC_INNER), (DISC_INNER, 1.0)]
+            };
+            for (w, h) in shapes {
+                let (half_w, half_h) = (radius * w, radius * h);
+                let loc = Point::<f64, Physical>::from((center.x - half_w, center.y - half_h));
+                let geometry = Rectangle::new(
+                    loc.to_i32_round(),
+                    ((2.0 * half_w).ceil() as i32, (2.0 * half_h).ceil() as i32).into(),
+                );
+                let id = self.ids.get(self.elements.len());
+                self.elements.push(SolidColorRenderElement::new(
+                    id,
+                    geometry,
+                    CommitCounter::default(),
+                    color,
+                    Kind::Unspecified,
+                ));
+            }
+        }
+    }
+}
+
+/// Build the element drawing an ink node's texture, given the node's
+/// origin relative to the output
+fn ink_texture_element<R>(
+    renderer: &mut R,
+    ink: &InkBuffer,
+    density: f64,
+    origin: Point<f64, Logical>,
+    output_scale: f64,
+    zoom: f64,
+) -> Option<LoomRenderElement<R>>
+where
+    R: Renderer + ImportAll + ImportMem,
+    R::TextureId: Send + Clone + 'static,
+{
+    // The texture extends a little past the node, to whole pixels
+    let (width, height) = (ink.size.0 as f64, ink.size.1 as f64);
+    let size = Point::<f64, Logical>::from((width, height)).upscale(zoom / density);
+
+    MemoryRenderBufferRenderElement::from_buffer(
+        renderer,
+        origin.to_physical(output_scale),
+        &ink.buffer,
+        None,
+        Some(Rectangle::from_size((width, height).into())),
+        Some(size.to_i32_round::<i32>().to_size()),
+        Kind::Unspecified,
+    )
+    .ok()
+    .map(LoomRenderElement::from)
+}
+
+/// Density step ink is rasterized at, in pixels per canvas unit, for the
+/// density it is drawn at
+///
+/// Steps are half powers of two, so textures are at most about 1.4 times
+/// as sharp as needed, and zooming only rasterizes them again when it
+/// crosses a step.
+fn ink_density(density: f64) -> f64 {
+    2f64.powf((density.log2() * 2.0).ceil() / 2.0)
+}
+
+/// Texture of ink strokes, `size` pixels across at `density` pixels per
+/// canvas unit
+fn ink_buffer(strokes: &[InkStroke], size: (usize, usize), density: f64) -> MemoryRenderBuffer {
+    MemoryRenderBuffer::from_slice(
+        &ink_pixels(strokes, size, density),
+        Fourcc::Argb8888,
+        (size.0 as i32, size.1 as i32),
+        1,
+        Transform::Normal,
+        None,
+    )
+}
+
+/// Pixels of ink strokes, antialiased, with premultiplied alpha
+///
+/// Each stroke covers a pixel by its most covering disc, so strokes don't
+/// darken where their discs overlap.
+fn ink_pixels(strokes: &[InkStroke], size: (usize, usize), density: f64) -> Vec<u8> {
+    let (width, height) = size;
+    let mut pixels = vec![0u8; width * height * 4];
+    let mut coverage = Vec::new();
+    let clamp = |value: f64, max: usize| value.clamp(0.0, max as f64) as usize;
+
+    for stroke in strokes {
+        let Some((x0, y0, x1, y1)) = stroke.bounds() else {
+            continue;
+        };
+        let (left, right) = (
+            clamp((x0 * density).floor() - 1.0, width),
+            clamp((x1 * density).ceil() + 1.0, width),
+        );
+        let (top, bottom) = (
+            clamp((y0 * density).floor() - 1.0, height),
+            clamp((y1 * density).ceil() + 1.0, height),
+        );
+        let stride = right - left;
+        if stride == 0 || bottom == top {
+            continue;
+        }
+        coverage.clear();
+        coverage.resize(stride * (bottom - top), 0.0f32);
+
+        let spacing = (stroke.width / 4.0).max(1.0 / density);
+        for (x, y, radius) in stroke.dabs(spacing) {
+            let (x, y) = (x * density, y * density);
+            let radius = (radius * density).max(0.5);
+            let rows = clamp(y - radius - 1.0, bottom).max(top)..clamp(y + radius + 1.0, bottom);
+            let columns = clamp(x - radius - 1.0, right).max(left)..clamp(x + radius + 1.0, right);
+            for row in rows {
+                for column in columns.clone() {
+                    let distance = (column as f64 + 0.5 - x).hypot(row as f64 + 0.5 - y);
+                    let covered = (radius - distance + 0.5).clamp(0.0, 1.0) as f32;
+                    let cell = &mut coverage[(row - top) * stride + column - left];
+                    *cell = cell.max(covered);
+                }
+            }
+        }
+
+        // Blend the stroke over the ink below it
+        let [red, green, blue, alpha] = premultiplied(stroke);
+        for row in top..bottom {
+            for column in left..right {
+                let covered = coverage[(row - top) * stride + column - left];
+                if covered <= 0.0 {
+                    continue;
+                }
+                let keep = 1.0 - alpha * covered;
+                let start = (row * width + column) * 4;
+                let pixel = &mut pixels[start..start + 4];
+                for (channel, value) in pixel.iter_mut().zip([blue, green, red, alpha]) {
+                    let blended = value * covered * 255.0 + *channel as f32 * keep;
+                    *channel = blended.round().clamp(0.0, 255.0) as u8;
+                }
+            }
+        }
+    }
+
+    pixels
+}
+
+/// Color of a stroke with premultiplied alpha
+fn premultiplied(stroke: &InkStroke) -> [f32; 4] {
+    premultiplied_color(stroke.color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::headless::HeadlessBackend;
+    use loom_canvas::InkPoint;
+
+    fn stroke(color: [f32; 4], width: f64, points: &[(f64, f64)]) -> InkStroke {
+        let mut stroke = InkStroke::new(color, width);
+        for &(x, y) in points {
+            stroke.push(InkPoint::new(x, y, 1.0, (0.0, 0.0)));
+        }
+        stroke
+    }
+
+    #[test]
+    fn test_ink_density_steps() {
+        assert_eq!(ink_density(1.0), 1.0);
+        assert_eq!(ink_density(2.0), 2.0);
+        for density in [0.01, 0.3, 1.2, 1.5, 3.0, 7.9] {
+            let step = ink_density(density);
+            assert!(step >= density && step < density * 1.42);
+        }
+        // Zooming within a step keeps the texture
+        assert_eq!(ink_density(1.1), ink_density(1.4));
+    }
+
+    #[test]
+    fn test_title_cache_fits() {
+        assert_eq!(title_px(16.0), 16.0);
+        assert!((title_px(17.0) - 16.0 * 2f64.powf(0.25)).abs() < 1e-9);
+        // Zooming within a step keeps the texture
+        assert_eq!(title_px(16.5), title_px(19.0));
+
+        let key = |text: &str| TitleKey {
+            text: text.to_string(),
+            px: 16f64.to_bits(),
+            color: color_bits([1.0; 4]),
+        };
+        let texture = |width, cut| {
+            Some(TitleTexture {
+                buffer: MemoryRenderBuffer::new(
+                    Fourcc::Argb8888,
+                    (width as i32, 10),
+                    1,
+                    Transform::Normal,
+                    None,
+                ),
+                size: (width, 10),
+                cut,
+            })
+        };
+
+        let whole = TitleCache {
+            key: key("Files"),
+            max_width: 100,
+            texture: texture(40, false),
+        };
+        assert!(whole.fits(&key("Files"), 40));
+        assert!(whole.fits(&key("Files"), 500));
+        assert!(!whole.fits(&key("Files"), 39));
+        assert!(!whole.fits(&key("Terminal"), 100));
+
+        // A cut title takes a little more room, not more
+        let cut = TitleCache {
+            key: key("A long title"),
+            max_width: 100,
+            texture: texture(98, true),
+        };
+        assert!(cut.fits(&key("A long title"), 104));
+        assert!(!cut.fits(&key("A long title"), 120));
+
+        let none = TitleCache {
+            key: key("Files"),
+            max_width: 2,
+            texture: None,
+        };
+        assert!(none.fits(&key("Files"), 1));
+        assert!(!none.fits(&key("Files"), 50));
+    }
+
+    #[test]
+    fn test_ink_pixels() {
+        let pixel = |pixels: &[u8], x: usize, y: usize| {
+            let start = (y * 20 + x) * 4;
+            <[u8; 4]>::try_from(&pixels[start..start + 4]).unwrap()
+        };
+
+        let red = stroke([1.0, 0.0, 0.0, 1.0], 4.0, &[(2.0, 5.0), (18.0, 5.0)]);
+        let pixels = ink_pixels(&[red], (20, 10), 1.0);
+        assert_eq!(pixel(&pixels, 10, 5), [0, 0, 255, 255]);
+        assert_eq!(pixel(&pixels, 10, 0), [0, 0, 0, 0]);
+
+        // Overlapping discs of a translucent stroke don't add up, strokes do
+        let translucent = stroke([0.0, 0.0, 1.0, 0.5], 4.0, &[(2.0, 5.0), (18.0, 5.0)]);
+        let pixels = ink_pixels(std::slice::from_ref(&translucent), (20, 10), 1.0);
+        assert_eq!(pixel(&pixels, 10, 5), [128, 0, 0, 128]);
+        let pixels = ink_pixels(&[translucent.clone(), translucent], (20, 10), 1.0);
+        assert_eq!(pixel(&pixels, 10, 5), [192, 0, 0, 192]);
+    }
+
+    #[test]
+    fn test_ink_discs_culled_and_capped() {
+        let ids = InkElementIds::default();
+        let mut discs = InkDiscs {
+            ids: &ids,
+            clip: Rectangle::from_size((100.0, 100.0).into()),
+            scale: 1.0,
+            elements: Vec::new(),
+        };
+        let to_physical = |point: Point<f64, Logical>| Point::from((point.x, point.y));
+
+        let outside = stroke([1.0; 4], 4.0, &[(200.0, 50.0), (300.0, 50.0)]);
+        discs.push(&outside, to_physical);
+        assert!(discs.elements.is_empty());
+
+        let inside = stroke([1.0; 4], 4.0, &[(10.0, 50.0), (90.0, 50.0)]);
+        discs.push(&inside, to_physical);
+        assert!(!discs.elements.is_empty());
+
+        let long = stroke([1.0; 4], 0.5, &[(0.0, 5_000.0), (20_000.0, 5_000.0)]);
+        discs.push(&long, |point| to_physical(point.upscale(0.004)));
+        assert_eq!(discs.elements.len(), MAX_INK_ELEMENTS);
+        assert_eq!(ids.0.borrow().len(), MAX_INK_ELEMENTS);
+    }
+
+    #[test]
+    fn test_ink_node_texture() {
+        let mut backend = HeadlessBackend::for_tests(64, 48);
+        let state = backend.state_mut();
+        let center = state.global_to_canvas((32.0, 24.0).into());
+        let points = [(center.x - 20.0, center.y), (center.x + 20.0, center.y)];
+        state
+            .canvas
+            .add_ink_stroke(stroke([1.0, 0.0, 0.0, 1.0], 6.0, &points), None)
+            .unwrap();
+
+        backend.render().unwrap();
+        let [r, g, b, a] = backend.frame(0).unwrap().pixel(32, 24).unwrap();
+        assert_eq!((r, g, b, a), (255, 0, 0, 255));
+        assert_eq!(backend.state().ink_buffers.0.borrow().len(), 1);
+
+        // Panned out of view, the texture is dropped
+        backend
+            .state_mut()
+            .pan_view_at((32.0, 24.0).into(), 500.0, 0.0);
+        backend.render().unwrap();
+        assert!(backend.state().ink_buffers.0.borrow().is_empty());
+    }
+}
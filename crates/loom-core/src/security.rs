@@ -3,6 +3,13 @@
 //! This module defines security-related constants that prevent resource exhaustion
 //! and other denial-of-service attacks. All limits are conservative defaults that
 //! can be adjusted via configuration.
+//!
+//! Privileged protocols (screen capture, clipboard access) are granted by
+//! the executable of the client's process, see [`peer_executable`].
+
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
 
 /// Maximum number of simultaneous Wayland clients
 pub const MAX_CLIENTS: usize = 256;
@@ -29,9 +36,25 @@ pub const MAX_CLIPBOARD_SIZE: usize = 16 * 1024 * 1024;
 /// Maximum number of keyboard shortcuts
 pub const MAX_KEYBINDINGS: usize = 500;
 
+/// Maximum number of keybinding modes, including the default one
+pub const MAX_BINDING_MODES: usize = 32;
+
+/// Maximum number of touchpad gesture bindings
+pub const MAX_GESTURE_BINDINGS: usize = 64;
+
+/// Maximum number of layer surfaces (panels, wallpapers, ...) over all
+/// outputs
+pub const MAX_LAYER_SURFACES: usize = 64;
+
 /// Maximum window title length in bytes
 pub const MAX_TITLE_LENGTH: usize = 4096;
 
+/// Maximum number of screen captures waiting for a frame
+pub const MAX_PENDING_CAPTURES: usize = 64;
+
+/// Maximum number of idle inhibitors across all clients
+pub const MAX_IDLE_INHIBITORS: usize = 64;
+
 /// Maximum app ID length in bytes
 pub const MAX_APP_ID_LENGTH: usize = 512;
 
@@ -58,10 +81,47 @@ pub fn is_valid_app_id(app_id: &str) -> bool {
     app_id.len() <= MAX_APP_ID_LENGTH && app_id.chars().all(|c| c.is_ascii_graphic() || c == ' ')
 }
 
+/// Executable of the process at the other end of a client socket
+///
+/// Taken from the peer's credentials when it connected, so a client can't
+/// claim to be another program. `None` if the process is gone or its
+/// executable can't be read (e.g. another user's process).
+pub fn peer_executable(stream: &UnixStream) -> Option<PathBuf> {
+    let mut credentials = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: `credentials` and `len` describe a valid, writable `ucred`
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&raw mut credentials).cast(),
+            &mut len,
+        )
+    };
+    if result != 0 || credentials.pid <= 0 {
+        return None;
+    }
+    std::fs::read_link(format!("/proc/{}/exe", credentials.pid)).ok()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_peer_executable() {
+        let (server, _client) = UnixStream::pair().unwrap();
+        assert_eq!(
+            peer_executable(&server),
+            Some(std::env::current_exe().unwrap())
+        );
+    }
+
     #[test]
     fn test_valid_buffer_size() {
         // Normal HD buffer
@@ -8,11 +8,31 @@
 //! Resource limits from [`crate::security`] are enforced here to prevent
 //! denial of service attacks from malicious clients.
 
-use crate::input::Keybindings;
+use crate::capture::CaptureState;
+use crate::decorations::Decorations;
+use crate::desktop::{DesktopIndex, DesktopScanner};
+use crate::dnd::DndState;
+use crate::handlers::data_control_filter;
+use crate::idle::IdleState;
+use crate::input::{
+    GestureState, Keybindings, KeyboardState, Switches, TabletState, TouchState, xkb_config,
+};
+use crate::launcher::PendingLaunch;
+use crate::lock::LockState;
+use crate::navigation::NavigationState;
+use crate::nodes::ManagedWindow;
+use crate::render::{CardBuffers, FrameBuffers, InkBuffers, InkElementIds};
 use crate::security;
+use crate::types::WindowId;
+use crate::views::OutputView;
 use loom_canvas::Canvas;
+use loom_config::config::{AiConfig, CanvasConfig, GeneralConfig, OutputConfig};
+use loom_config::{
+    CaptureConfig, ClipboardConfig, Config, IdleConfig, InputConfig, SwitchConfig, Theme,
+};
+use slotmap::SlotMap;
 use smithay::{
-    desktop::{Space, Window},
+    desktop::{PopupManager, Space, Window},
     input::{Seat, SeatState, pointer::CursorImageStatus},
     reexports::{
         calloop::{Interest, LoopHandle, Mode, PostAction, generic::Generic},
@@ -21,16 +41,32 @@ use smithay::{
             backend::{ClientData, ClientId, DisconnectReason},
         },
     },
-    utils::{Logical, Point},
+    utils::{Clock, Logical, Monotonic, Point},
     wayland::{
         compositor::{CompositorClientState, CompositorState},
+        foreign_toplevel_list::ForeignToplevelListState,
+        idle_inhibit::IdleInhibitManagerState,
+        idle_notify::IdleNotifierState,
         output::OutputManagerState,
-        shell::xdg::XdgShellState,
+        pointer_gestures::PointerGesturesState,
+        selection::{
+            data_device::DataDeviceState, primary_selection::PrimarySelectionState,
+            wlr_data_control::DataControlState,
+        },
+        session_lock::SessionLockManagerState,
+        shell::{
+            wlr_layer::WlrLayerShellState,
+            xdg::{XdgShellState, decoration::XdgDecorationState},
+        },
         shm::ShmState,
         socket::ListeningSocketSource,
+        tablet_manager::TabletManagerState,
     },
 };
-use std::sync::Arc;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use tracing::{debug, info, warn};
 
 /// Client-specific data stored by Smithay
@@ -40,6 +76,18 @@ pub struct ClientState {
     pub compositor_state: CompositorClientState,
     /// Number of surfaces created by this client (for DoS protection)
     pub surface_count: usize,
+    /// Executable of the client's process, for permissions
+    pub executable: Option<PathBuf>,
+}
+
+impl ClientState {
+    /// State of a client connecting over a socket
+    pub fn new(stream: &UnixStream) -> Self {
+        Self {
+            executable: security::peer_executable(stream),
+            ..Self::default()
+        }
+    }
 }
 
 impl ClientData for ClientState {
@@ -60,33 +108,119 @@ pub struct LoomState {
     /// The infinite canvas where nodes live
     pub canvas: Canvas,
 
+    /// General settings (terminal and launcher commands)
+    pub general_config: GeneralConfig,
+
+    /// Canvas settings (zoom/pan sensitivity, viewport mode)
+    pub canvas_config: CanvasConfig,
+
+    /// AI settings
+    pub ai_config: AiConfig,
+
+    /// Configured output positions
+    pub output_config: Vec<OutputConfig>,
+
+    /// Keyboard layout and input device settings
+    pub input_config: InputConfig,
+
+    /// Lid and tablet-mode switch actions
+    pub switch_config: SwitchConfig,
+
+    /// Clients allowed to capture the screen
+    pub capture_config: CaptureConfig,
+
+    /// Clients allowed to access the clipboard without the keyboard focus,
+    /// shared with the data control global's filter
+    pub clipboard_config: Arc<RwLock<ClipboardConfig>>,
+
+    /// Idle timeouts
+    pub idle_config: IdleConfig,
+
+    /// Colors of compositor drawn elements
+    pub theme: Theme,
+
+    /// Node frame style and title font, from the theme
+    pub decorations: Decorations,
+
+    /// Views of the outputs into the canvas
+    pub views: Vec<OutputView>,
+
+    /// Pan and zoom interaction state
+    pub navigation: NavigationState,
+
     /// Wayland display handle
     pub display_handle: DisplayHandle,
 
     /// Event loop handle for registering sources
     pub loop_handle: LoopHandle<'static, Self>,
 
+    /// Monotonic clock for event timestamps
+    pub clock: Clock<Monotonic>,
+
     /// Compositor protocol state (wl_compositor)
     pub compositor_state: CompositorState,
 
     /// XDG shell state (xdg_wm_base)
     pub xdg_shell_state: XdgShellState,
 
+    /// XDG decoration state (zxdg_decoration_manager_v1)
+    pub xdg_decoration_state: XdgDecorationState,
+
+    /// Layer shell state (zwlr_layer_shell_v1)
+    pub layer_shell_state: WlrLayerShellState,
+
+    /// Clipboard and drag and drop state (wl_data_device_manager)
+    pub data_device_state: DataDeviceState,
+
+    /// Primary selection state (zwp_primary_selection_device_manager_v1)
+    pub primary_selection_state: PrimarySelectionState,
+
+    /// Clipboard manager state (zwlr_data_control_manager_v1)
+    pub data_control_state: DataControlState,
+
+    /// Window list state (ext_foreign_toplevel_list_v1)
+    pub foreign_toplevel_list_state: ForeignToplevelListState,
+
+    /// Screen capture (zwlr_screencopy_manager_v1,
+    /// ext_image_copy_capture_manager_v1)
+    pub capture: CaptureState,
+
+    /// Session lock state (ext_session_lock_manager_v1)
+    pub session_lock_state: SessionLockManagerState,
+
+    /// Idle notification state (ext_idle_notifier_v1)
+    pub idle_notifier_state: IdleNotifierState<Self>,
+
+    /// Idle inhibition state (zwp_idle_inhibit_manager_v1)
+    pub idle_inhibit_state: IdleInhibitManagerState,
+
     /// Shared memory state (wl_shm)
     pub shm_state: ShmState,
 
     /// Output manager state
     pub output_manager_state: OutputManagerState,
 
+    /// Pointer gestures state (zwp_pointer_gestures_v1)
+    pub pointer_gestures_state: PointerGesturesState,
+
+    /// Drawing tablet protocol state
+    pub tablet_manager_state: TabletManagerState,
+
     /// Seat state (input devices)
     pub seat_state: SeatState<Self>,
 
     /// The primary seat
     pub seat: Seat<Self>,
 
-    /// 2D space for window management
+    /// Toplevel windows, each shown by a canvas node
+    pub windows: SlotMap<WindowId, ManagedWindow>,
+
+    /// 2D space for window management (locations derived from the canvas)
     pub space: Space<Window>,
 
+    /// Popups of all toplevels
+    pub popups: PopupManager,
+
     /// Current cursor image status
     pub cursor_status: CursorImageStatus,
 
@@ -96,12 +230,74 @@ pub struct LoomState {
     /// Keybindings manager
     pub keybindings: Keybindings,
 
+    /// Intercepted keys and keybinding repeat
+    pub keyboard_state: KeyboardState,
+
+    /// Touches navigating the canvas
+    pub touch_state: TouchState,
+
+    /// Touchpad gesture bindings and the gesture in progress
+    pub gestures: GestureState,
+
+    /// Annotation mode and the pen stroke being drawn
+    pub tablet_state: TabletState,
+
+    /// Drag in progress and its icon
+    pub dnd: DndState,
+
+    /// Lid and tablet-mode switch states
+    pub switches: Switches,
+
+    /// Element ids of drawn ink, reused across frames
+    pub ink_ids: InkElementIds,
+
+    /// Textures of drawn ink nodes
+    pub ink_buffers: InkBuffers,
+
+    /// Buffers and element ids of drawn node frames
+    pub frame_buffers: FrameBuffers,
+
+    /// Element ids and labels of drawn note and media cards
+    pub card_buffers: CardBuffers,
+
+    /// libinput devices, configured again when the config is reloaded
+    #[cfg(feature = "backend-drm")]
+    pub libinput_devices: Vec<smithay::reexports::input::Device>,
+
+    /// Whether the session is locked (only lock surfaces are drawn and get
+    /// input, and only bindings allowed when locked work)
+    pub session_locked: bool,
+
+    /// Locker of the session and its surfaces
+    pub lock: LockState,
+
+    /// Idle timers and inhibitors
+    pub idle: IdleState,
+
     /// Whether the compositor should keep running
     pub running: bool,
 
+    /// VT switch requested by a keybinding, performed by the DRM backend
+    pub vt_switch: Option<i32>,
+
+    /// Something on screen changed since the outputs were last rendered
+    ///
+    /// The DRM backend only redraws its outputs while this is set.
+    pub redraw_needed: bool,
+
     /// Socket name for clients to connect
     pub socket_name: Option<String>,
 
+    /// Installed applications, empty until first indexed
+    pub apps: DesktopIndex,
+
+    /// Worker thread indexing the installed applications
+    pub desktop_scanner: Option<DesktopScanner>,
+
+    /// Launched programs whose first window is placed where they were
+    /// launched
+    pub pending_launches: Vec<PendingLaunch>,
+
     /// Number of connected clients (for DoS protection)
     client_count: usize,
 }
@@ -111,55 +307,140 @@ impl LoomState {
     ///
     /// # Arguments
     ///
-    /// * `display` - The Wayland display
+    /// * `display` - The Wayland display (the backend keeps ownership so it
+    ///   can dispatch client requests with `&mut LoomState`)
     /// * `loop_handle` - The event loop handle
+    /// * `config` - The user configuration; invalid keybindings are logged
+    ///   and skipped
     ///
     /// # Returns
     ///
     /// A new `LoomState` instance, or an error if initialization fails
     pub fn new(
-        display: Display<Self>,
+        display: &Display<Self>,
         loop_handle: LoopHandle<'static, Self>,
+        config: &Config,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         let display_handle = display.handle();
 
         // Initialize Smithay protocol handlers
         let compositor_state = CompositorState::new::<Self>(&display_handle);
         let xdg_shell_state = XdgShellState::new::<Self>(&display_handle);
+        let xdg_decoration_state = XdgDecorationState::new::<Self>(&display_handle);
+        let layer_shell_state = WlrLayerShellState::new::<Self>(&display_handle);
+        let data_device_state = DataDeviceState::new::<Self>(&display_handle);
+        let primary_selection_state = PrimarySelectionState::new::<Self>(&display_handle);
+        let clipboard_config = Arc::new(RwLock::new(config.clipboard.clone()));
+        let data_control_state = DataControlState::new::<Self, _>(
+            &display_handle,
+            Some(&primary_selection_state),
+            data_control_filter(clipboard_config.clone()),
+        );
+        let foreign_toplevel_list_state = ForeignToplevelListState::new::<Self>(&display_handle);
+        let capture = CaptureState::new(&display_handle);
+        let session_lock_state = SessionLockManagerState::new::<Self, _>(&display_handle, |_| true);
+        let idle_notifier_state = IdleNotifierState::new(&display_handle, loop_handle.clone());
+        let idle_inhibit_state = IdleInhibitManagerState::new::<Self>(&display_handle);
         let shm_state = ShmState::new::<Self>(&display_handle, vec![]);
         let output_manager_state = OutputManagerState::new_with_xdg_output::<Self>(&display_handle);
+        let pointer_gestures_state = PointerGesturesState::new::<Self>(&display_handle);
+        let tablet_manager_state = TabletManagerState::new::<Self>(&display_handle);
 
         // Initialize seat (input devices)
         let mut seat_state = SeatState::new();
         let mut seat = seat_state.new_wl_seat(&display_handle, "seat0");
 
-        // Add keyboard capability with default XKB config
-        seat.add_keyboard(Default::default(), 200, 25)
-            .map_err(|e| format!("Failed to add keyboard: {e}"))?;
+        // Add keyboard capability with the configured keymap, falling back
+        // to the default one if it doesn't compile
+        let (repeat_delay, repeat_rate) = config.input.repeat_info();
+        if let Err(e) = seat.add_keyboard(xkb_config(&config.input), repeat_delay, repeat_rate) {
+            warn!("Invalid keymap, using the default: {:?}", e);
+            seat.add_keyboard(Default::default(), repeat_delay, repeat_rate)
+                .map_err(|e| format!("Failed to add keyboard: {e}"))?;
+        }
 
-        // Add pointer capability
+        // Add pointer and touch capabilities
         seat.add_pointer();
+        seat.add_touch();
+
+        let (mut keybindings, errors) = Keybindings::from_config(&config.keybindings);
+        for e in errors {
+            warn!("Ignoring keybinding: {}", e);
+        }
+        keybindings.set_chord_timeout(Duration::from_millis(config.general.chord_timeout_ms));
 
-        info!("Compositor state initialized with keyboard and pointer");
+        info!("Compositor state initialized with keyboard, pointer and touch");
 
-        Ok(Self {
+        let mut state = Self {
             canvas: Canvas::new(),
+            general_config: config.general.clone(),
+            canvas_config: config.canvas.clone(),
+            ai_config: config.ai.clone(),
+            output_config: config.outputs.clone(),
+            input_config: config.input.clone(),
+            switch_config: config.switches.clone(),
+            capture_config: config.capture.clone(),
+            clipboard_config,
+            idle_config: config.idle.clone(),
+            theme: config.theme.clone(),
+            decorations: Decorations::from_theme(&config.theme),
+            views: Vec::new(),
+            navigation: NavigationState::default(),
             display_handle,
             loop_handle,
+            clock: Clock::new(),
             compositor_state,
             xdg_shell_state,
+            xdg_decoration_state,
+            layer_shell_state,
+            data_device_state,
+            primary_selection_state,
+            data_control_state,
+            foreign_toplevel_list_state,
+            capture,
+            session_lock_state,
+            idle_notifier_state,
+            idle_inhibit_state,
             shm_state,
             output_manager_state,
+            pointer_gestures_state,
+            tablet_manager_state,
             seat_state,
             seat,
+            windows: SlotMap::with_key(),
             space: Space::default(),
+            popups: PopupManager::default(),
             cursor_status: CursorImageStatus::default_named(),
             pointer_location: Point::from((0.0, 0.0)),
-            keybindings: Keybindings::new(),
+            keybindings,
+            keyboard_state: KeyboardState::default(),
+            touch_state: TouchState::default(),
+            gestures: GestureState::from_config(&config.gestures),
+            tablet_state: TabletState::default(),
+            dnd: DndState::default(),
+            switches: Switches::default(),
+            ink_ids: InkElementIds::default(),
+            ink_buffers: InkBuffers::default(),
+            frame_buffers: FrameBuffers::default(),
+            card_buffers: CardBuffers::default(),
+            #[cfg(feature = "backend-drm")]
+            libinput_devices: Vec::new(),
+            session_locked: false,
+            lock: LockState::default(),
+            idle: IdleState::default(),
             running: true,
+            vt_switch: None,
+            redraw_needed: true,
             socket_name: None,
+            apps: DesktopIndex::default(),
+            desktop_scanner: None,
+            pending_launches: Vec::new(),
             client_count: 0,
-        })
+        };
+        state.start_desktop_scanner();
+        state.start_idle_timer();
+
+        Ok(state)
     }
 
     /// Register a Wayland socket for client connections
@@ -187,9 +468,10 @@ impl LoomState {
                 }
 
                 // Accept the client
+                let client_state = Arc::new(ClientState::new(&client_stream));
                 if let Err(e) = state
                     .display_handle
-                    .insert_client(client_stream, Arc::new(ClientState::default()))
+                    .insert_client(client_stream, client_state)
                 {
                     warn!("Failed to insert client: {}", e);
                 } else {
@@ -222,6 +504,72 @@ impl LoomState {
         Ok(socket_name)
     }
 
+    /// Load the config file again and apply it
+    ///
+    /// Errors are logged and the current config kept.
+    pub fn reload_config(&mut self) {
+        match Config::load() {
+            Ok(config) => {
+                self.apply_config(&config);
+                info!("Config reloaded");
+            }
+            Err(e) => warn!("Failed to reload config: {}", e),
+        }
+    }
+
+    /// Apply a new config
+    ///
+    /// Keybindings, keyboard and device settings and the theme take effect
+    /// immediately, output positions when an output is next connected, and
+    /// capture and clipboard permissions when a client next binds a capture
+    /// or data control global. The keybinding mode is reset, and idle
+    /// timeouts count from the last input.
+    pub fn apply_config(&mut self, config: &Config) {
+        self.general_config = config.general.clone();
+        self.canvas_config = config.canvas.clone();
+        self.ai_config = config.ai.clone();
+        self.output_config = config.outputs.clone();
+        self.input_config = config.input.clone();
+        self.switch_config = config.switches.clone();
+        self.capture_config = config.capture.clone();
+        if let Ok(mut clipboard_config) = self.clipboard_config.write() {
+            *clipboard_config = config.clipboard.clone();
+        }
+        self.idle_config = config.idle.clone();
+        self.theme = config.theme.clone();
+        self.decorations.set_theme(&config.theme);
+
+        self.stop_key_repeat();
+        let (mut keybindings, errors) = Keybindings::from_config(&config.keybindings);
+        for e in errors {
+            warn!("Ignoring keybinding: {}", e);
+        }
+        keybindings.set_chord_timeout(Duration::from_millis(config.general.chord_timeout_ms));
+        self.keybindings = keybindings;
+        self.gestures = GestureState::from_config(&config.gestures);
+
+        self.apply_keyboard_config();
+        self.start_idle_timer();
+        self.queue_redraw();
+        #[cfg(feature = "backend-drm")]
+        self.configure_libinput_devices();
+    }
+
+    /// Drop state of destroyed surfaces
+    ///
+    /// Called by the backends after dispatching client requests.
+    pub fn refresh(&mut self) {
+        self.space.refresh();
+        self.popups.cleanup();
+        self.cleanup_layers();
+        self.refresh_idle_inhibitors();
+    }
+
+    /// Redraw the outputs, as something on screen changed
+    pub fn queue_redraw(&mut self) {
+        self.redraw_needed = true;
+    }
+
     /// Called when a client disconnects
     pub fn client_disconnected(&mut self) {
         self.client_count = self.client_count.saturating_sub(1);
@@ -0,0 +1,318 @@
+//! Output views into the canvas
+//!
+//! Outputs look at the canvas through [`OutputView`]s, each backed by a
+//! [`Viewport`] on the [`Canvas`](loom_canvas::Canvas). Depending on the
+//! configured [`ViewportMode`], every output has its own view, panned and
+//! zoomed independently, or a single view spans the whole output layout.
+//!
+//! Global (output layout) points are converted to canvas coordinates
+//! through the view under them, so the pointer crossing from one output to
+//! the next continues in the other output's view. Canvas points have a
+//! global location per view; nodes are placed in the [`Space`] through the
+//! view that shows them best (see [`LoomState::node_view`]).
+//!
+//! [`Space`]: smithay::desktop::Space
+
+use crate::state::LoomState;
+use crate::types::SmallVec4;
+use loom_canvas::{Viewport, ViewportId};
+use loom_config::config::ViewportMode;
+use smithay::{
+    output::Output,
+    utils::{Logical, Point, Rectangle},
+};
+use tracing::{debug, warn};
+
+/// A viewport shown on one or more outputs
+#[derive(Debug, Clone)]
+pub struct OutputView {
+    /// Viewport on the canvas
+    pub viewport: ViewportId,
+    /// Layout area covered by the view, in global coordinates
+    pub geometry: Rectangle<i32, Logical>,
+    /// Outputs showing the view, with their geometry at the last update
+    pub outputs: SmallVec4<(Output, Rectangle<i32, Logical>)>,
+}
+
+impl OutputView {
+    /// Whether the view is shown on an output
+    pub fn shows(&self, output: &Output) -> bool {
+        self.outputs.iter().any(|(o, _)| o == output)
+    }
+
+    /// Whether a global point is on one of the view's outputs
+    pub fn contains(&self, point: Point<f64, Logical>) -> bool {
+        self.outputs
+            .iter()
+            .any(|(_, geometry)| geometry.to_f64().contains(point))
+    }
+}
+
+impl LoomState {
+    /// Rebuild the views from the current output layout
+    ///
+    /// Must be called whenever outputs are added, removed, moved or change
+    /// mode. Views keep showing the same canvas region at the top-left of
+    /// their first output; new outputs continue the canvas of the first
+    /// existing view, as if it spanned them. Layer and lock surfaces are
+    /// arranged again first, as the output sizes may have changed.
+    pub fn update_views(&mut self) {
+        let mut outputs: Vec<(Output, Rectangle<i32, Logical>)> = self
+            .space
+            .outputs()
+            .filter_map(|output| Some((output.clone(), self.space.output_geometry(output)?)))
+            .collect();
+        outputs.sort_by_key(|(_, geometry)| (geometry.loc.x, geometry.loc.y));
+
+        self.queue_redraw();
+        self.arrange_layers();
+        self.configure_lock_surfaces();
+        self.update_fullscreen_windows();
+
+        // Without outputs the old views are kept, so the canvas position
+        // survives until a monitor comes back
+        if outputs.is_empty() {
+            debug!("No outputs, keeping {} view(s)", self.views.len());
+            return;
+        }
+
+        let groups: Vec<SmallVec4<(Output, Rectangle<i32, Logical>)>> =
+            match self.canvas_config.viewports {
+                ViewportMode::PerOutput => outputs
+                    .into_iter()
+                    .map(|output| SmallVec4::from_iter([output]))
+                    .collect(),
+                ViewportMode::Spanning => vec![outputs.into_iter().collect()],
+            };
+
+        let old_views = std::mem::take(&mut self.views);
+        let mut claimed = Vec::with_capacity(groups.len());
+
+        for outputs in groups {
+            let geometry = outputs
+                .iter()
+                .map(|(_, geometry)| *geometry)
+                .reduce(|acc, geometry| acc.merge(geometry))
+                .unwrap_or_default();
+
+            let viewport = self.view_viewport_for(&old_views, &claimed, &outputs, geometry);
+            let Some(viewport) = viewport else {
+                continue;
+            };
+
+            claimed.push(viewport);
+            self.views.push(OutputView {
+                viewport,
+                geometry,
+                outputs,
+            });
+        }
+
+        for view in old_views {
+            if !claimed.contains(&view.viewport) {
+                self.canvas.remove_viewport(view.viewport);
+            }
+        }
+
+        self.sync_space_to_canvas();
+    }
+
+    /// Viewport for a new view, reusing the one of an old view if possible
+    fn view_viewport_for(
+        &mut self,
+        old_views: &[OutputView],
+        claimed: &[ViewportId],
+        outputs: &[(Output, Rectangle<i32, Logical>)],
+        geometry: Rectangle<i32, Logical>,
+    ) -> Option<ViewportId> {
+        // An old view showing one of the outputs keeps the canvas point at
+        // that output's top-left corner in place
+        let reused = outputs.iter().find_map(|(output, new_geometry)| {
+            let old = old_views
+                .iter()
+                .filter(|view| !claimed.contains(&view.viewport))
+                .find(|view| view.shows(output))?;
+            let (_, old_geometry) = old.outputs.iter().find(|(o, _)| o == output)?;
+            let anchor = self.view_to_canvas(old, old_geometry.loc.to_f64());
+            Some((old.viewport, anchor, new_geometry.loc))
+        });
+
+        if let Some((id, anchor, anchor_location)) = reused {
+            let viewport = self.canvas.viewport_mut(id)?;
+            viewport.screen_width = geometry.size.w as f64;
+            viewport.screen_height = geometry.size.h as f64;
+
+            let local = (anchor_location - geometry.loc).to_f64();
+            let (x, y) = viewport.screen_to_canvas(local.x, local.y);
+            viewport.x += anchor.x - x;
+            viewport.y += anchor.y - y;
+            return Some(id);
+        }
+
+        // Continue the canvas of the first view, or start at the origin
+        let mut viewport = Viewport::new(geometry.size.w as f64, geometry.size.h as f64);
+        let reference = self
+            .views
+            .first()
+            .or_else(|| old_views.iter().find(|v| !claimed.contains(&v.viewport)));
+        if let Some(reference) = reference
+            && let Some(zoom) = self.canvas.viewport(reference.viewport).map(|v| v.zoom)
+        {
+            let center = geometry.to_f64().loc
+                + Point::from((geometry.size.w as f64 / 2.0, geometry.size.h as f64 / 2.0));
+            let center = self.view_to_canvas(reference, center);
+            viewport.x = center.x;
+            viewport.y = center.y;
+            viewport.zoom = zoom;
+        }
+
+        match self.canvas.add_viewport(viewport) {
+            Ok(id) => Some(id),
+            Err(e) => {
+                warn!("Cannot create viewport: {}", e);
+                None
+            }
+        }
+    }
+
+    /// View showing an output
+    pub fn view_for_output(&self, output: &Output) -> Option<&OutputView> {
+        self.views.iter().find(|view| view.shows(output))
+    }
+
+    /// View under a global point, or the nearest one
+    pub fn view_at(&self, point: Point<f64, Logical>) -> Option<&OutputView> {
+        self.views
+            .iter()
+            .find(|view| view.contains(point))
+            .or_else(|| {
+                self.views.iter().min_by(|a, b| {
+                    let a = distance_to_rect(a.geometry.to_f64(), point);
+                    let b = distance_to_rect(b.geometry.to_f64(), point);
+                    a.total_cmp(&b)
+                })
+            })
+    }
+
+    /// Viewport of the view under a global point
+    pub fn viewport_at(&self, point: Point<f64, Logical>) -> Option<&Viewport> {
+        self.view_at(point)
+            .and_then(|view| self.canvas.viewport(view.viewport))
+    }
+
+    /// View that shows a node best
+    ///
+    /// That is the first view containing the node's center, or else the
+    /// one showing the largest part of it, or else the first view.
+    pub fn node_view(&self, rect: Rectangle<f64, Logical>) -> Option<&OutputView> {
+        let center = rect.loc + Point::from((rect.size.w / 2.0, rect.size.h / 2.0));
+        let mut best = None;
+        let mut best_area = 0.0;
+
+        for view in &self.views {
+            let visible = self.view_canvas_rect(view);
+            if visible.contains(center) {
+                return Some(view);
+            }
+
+            if let Some(area) = rect.intersection(visible).map(|r| r.size.w * r.size.h)
+                && area > best_area
+            {
+                best = Some(view);
+                best_area = area;
+            }
+        }
+
+        best.or_else(|| self.views.first())
+    }
+
+    /// Convert a global (output layout) point to canvas coordinates
+    ///
+    /// Uses the view under the point; without outputs, global and canvas
+    /// coordinates are the same.
+    #[inline]
+    pub fn global_to_canvas(&self, point: Point<f64, Logical>) -> Point<f64, Logical> {
+        match self.view_at(point) {
+            Some(view) => self.view_to_canvas(view, point),
+            None => point,
+        }
+    }
+
+    /// Convert a global point to canvas coordinates through a view
+    #[inline]
+    pub fn view_to_canvas(
+        &self,
+        view: &OutputView,
+        point: Point<f64, Logical>,
+    ) -> Point<f64, Logical> {
+        let Some(viewport) = self.canvas.viewport(view.viewport) else {
+            return point;
+        };
+
+        let screen = point - view.geometry.loc.to_f64();
+        let (x, y) = viewport.screen_to_canvas(screen.x, screen.y);
+        Point::from((x, y))
+    }
+
+    /// Convert a canvas point to global coordinates through a view
+    #[inline]
+    pub fn canvas_to_view(
+        &self,
+        view: &OutputView,
+        point: Point<f64, Logical>,
+    ) -> Point<f64, Logical> {
+        let Some(viewport) = self.canvas.viewport(view.viewport) else {
+            return point;
+        };
+
+        let (x, y) = viewport.canvas_to_screen(point.x, point.y);
+        view.geometry.loc.to_f64() + Point::from((x, y))
+    }
+
+    /// Part of the canvas shown by a view, in canvas coordinates
+    pub fn view_canvas_rect(&self, view: &OutputView) -> Rectangle<f64, Logical> {
+        self.global_rect_to_canvas(view, view.geometry)
+    }
+
+    /// Convert a global rectangle to canvas coordinates through a view
+    pub fn global_rect_to_canvas(
+        &self,
+        view: &OutputView,
+        rect: Rectangle<i32, Logical>,
+    ) -> Rectangle<f64, Logical> {
+        let zoom = self
+            .canvas
+            .viewport(view.viewport)
+            .map_or(1.0, |viewport| viewport.zoom);
+
+        Rectangle::new(
+            self.view_to_canvas(view, rect.loc.to_f64()),
+            (rect.size.w as f64 / zoom, rect.size.h as f64 / zoom).into(),
+        )
+    }
+}
+
+/// Distance from a point to a rectangle (zero inside)
+fn distance_to_rect(rect: Rectangle<f64, Logical>, point: Point<f64, Logical>) -> f64 {
+    let dx = (rect.loc.x - point.x)
+        .max(point.x - (rect.loc.x + rect.size.w))
+        .max(0.0);
+    let dy = (rect.loc.y - point.y)
+        .max(point.y - (rect.loc.y + rect.size.h))
+        .max(0.0);
+    dx.hypot(dy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance_to_rect() {
+        let rect = Rectangle::new((0.0, 0.0).into(), (100.0, 50.0).into());
+
+        assert_eq!(distance_to_rect(rect, (10.0, 10.0).into()), 0.0);
+        assert_eq!(distance_to_rect(rect, (130.0, 20.0).into()), 30.0);
+        assert_eq!(distance_to_rect(rect, (-3.0, 54.0).into()), 5.0);
+    }
+}
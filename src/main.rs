@@ -42,8 +42,8 @@ fn main() {
     }
 }
 
-fn run(_config: loom_config::Config) -> Result<(), Box<dyn std::error::Error>> {
+fn run(config: loom_config::Config) -> Result<(), Box<dyn std::error::Error>> {
     // Run compositor with auto-detected backend
-    loom_core::backend::run_auto()?;
+    loom_core::backend::run_auto(&config)?;
     Ok(())
 }